@@ -196,6 +196,10 @@ impl LineEditor {
         self.secondary = self.primary;
     }
 
+    pub fn selected_text(&self) -> &str {
+        &self.buf[self.selection_range()]
+    }
+
     pub fn paste_from_cut(&mut self) {
         let range = self.selection_range();
         let text = &self.cut_buffer;
@@ -534,6 +538,8 @@ pub fn draw_editor_into_console(
 
     let initial_cursor = grid.cursor;
     grid.scrolled_rows = 0;
+    // The cursor may be left past the end of a full line
+    grid.check_wrap();
 
     for (i, char) in editor.buf.char_indices() {
         match char {
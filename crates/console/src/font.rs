@@ -0,0 +1,64 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use gfx::format::{pcf, ttf};
+
+/// Where an outline font is looked for, if none is given.
+pub const DEFAULT_FONT_PATH: &str = "/fonts/mono.ttf";
+
+/// The font the grid is drawn with: the built-in bitmap font, scaled up
+/// by whole pixels, or an outline font at any size.
+pub enum Font<'a> {
+    Bitmap {
+        font: Box<pcf::LoadedPCF<'a>>,
+        scale: usize,
+    },
+    Outline(ttf::ScaledFont<'a>),
+}
+
+impl Font<'_> {
+    /// The size of a character cell in pixels, as (height, width).
+    pub fn cell_dims(&mut self) -> (usize, usize) {
+        match self {
+            Font::Bitmap { font, scale } => {
+                let (height, width) = font.dimensions();
+                (height * *scale, width * *scale)
+            }
+            Font::Outline(font) => font.dimensions(),
+        }
+    }
+
+    /// Draw a character in the cell with its top left corner at `(x, y)`.
+    pub fn draw_char(
+        &mut self,
+        ch: char,
+        data: &mut [u32],
+        row_stride: usize,
+        (x, y): (usize, usize),
+        color: u32,
+    ) {
+        match self {
+            Font::Bitmap { font, scale } => {
+                font.draw_char(ch, data, y * row_stride + x, row_stride, *scale, color);
+            }
+            Font::Outline(font) => {
+                font.draw_char(ch, data, row_stride, (x, y), color);
+            }
+        }
+    }
+}
+
+pub fn read_file(path: &str) -> Option<Vec<u8>> {
+    let fd = ulib::sys::openat(3, path.as_bytes(), 0, 0).ok()?;
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    let result = loop {
+        match ulib::sys::pread(fd, &mut buf, data.len() as u64) {
+            Ok(0) => break Some(data),
+            Ok(len) => data.extend_from_slice(&buf[..len]),
+            Err(_) => break None,
+        }
+    };
+    ulib::sys::close(fd).ok();
+    result
+}
@@ -67,3 +67,49 @@ pub fn remap_input(
         None
     }
 }
+
+/// Append the bytes a terminal sends for a key press, for programs that
+/// read the keyboard directly instead of through the line editor.
+pub fn key_bytes(event: &editor::KeyEvent, out: &mut alloc::vec::Vec<u8>) {
+    use editor::{FuncKey, KeyEvent, Keypress};
+    let (KeyEvent::Press(key) | KeyEvent::Repeat(key)) = event else {
+        return;
+    };
+    match key {
+        Keypress::Char(m, c, resolved) => {
+            if m.alt {
+                out.push(0x1b);
+            }
+            let upper = c.to_ascii_uppercase();
+            if m.ctrl && ('@'..='_').contains(&upper) {
+                out.push(upper as u8 & 0x1f);
+            } else if m.ctrl && *c == ' ' {
+                out.push(0);
+            } else {
+                let mut buf = [0; 4];
+                out.extend_from_slice(resolved.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+        Keypress::Function(_, key) => {
+            let seq: &[u8] = match key {
+                FuncKey::Up => b"\x1b[A",
+                FuncKey::Down => b"\x1b[B",
+                FuncKey::Right => b"\x1b[C",
+                FuncKey::Left => b"\x1b[D",
+                FuncKey::Home => b"\x1b[H",
+                FuncKey::End => b"\x1b[F",
+                FuncKey::Insert => b"\x1b[2~",
+                FuncKey::Delete => b"\x1b[3~",
+                FuncKey::PageUp => b"\x1b[5~",
+                FuncKey::PageDown => b"\x1b[6~",
+                FuncKey::Backspace => b"\x7f",
+                FuncKey::Enter => b"\r",
+                FuncKey::Tab => b"\t",
+                FuncKey::Escape => b"\x1b",
+                FuncKey::Func(_) => b"",
+            };
+            out.extend_from_slice(seq);
+        }
+        Keypress::Invalid(_) => (),
+    }
+}
@@ -8,6 +8,7 @@ extern crate display_client;
 extern crate ulib;
 
 pub mod editor;
+mod font;
 mod grid;
 mod input;
 mod vt100;
@@ -15,10 +16,14 @@ mod vt100;
 use display_client::proto;
 use gfx::{color, format};
 
-use ulib::sys::{pipe, pwrite_all, spawn_elf, ArgStr, FileDesc, SpawnArgs};
+use ulib::sys::{pty_create, pwrite_all, spawn_elf, ArgStr, FileDesc, SpawnArgs};
 
 #[no_mangle]
 fn main(argc: usize, argv: *const *const u8) {
+    // crashes of the console, and of the programs it runs (which inherit
+    // the limit), leave a core file in the serial log
+    ulib::sys::set_core_limit(1 << 20);
+
     let argv_array = unsafe { core::slice::from_raw_parts(argv, argc) };
     let args = argv_array
         .iter()
@@ -27,18 +32,29 @@ fn main(argc: usize, argv: *const *const u8) {
         .map(|arg| core::str::from_utf8(arg).unwrap())
         .collect::<alloc::vec::Vec<_>>();
 
-    let remaining_args;
+    let mut remaining_args = args.get(1..).unwrap_or(&[]);
     let mut scale = 1;
-
-    if let (Some("--scale"), Some(scale_str)) = (args.get(1).map(|s| *s), args.get(2)) {
-        if let Ok(s) = scale_str.parse::<usize>() {
-            scale = s;
+    let mut font_path = None;
+    let mut font_size = None;
+
+    loop {
+        match remaining_args {
+            ["--scale", scale_str, rest @ ..] => {
+                if let Ok(s) = scale_str.parse::<usize>() {
+                    scale = s;
+                }
+                remaining_args = rest;
+            }
+            ["--font", path, rest @ ..] => {
+                font_path = Some(*path);
+                remaining_args = rest;
+            }
+            ["--font-size", size_str, rest @ ..] => {
+                font_size = size_str.parse::<u32>().ok();
+                remaining_args = rest;
+            }
+            _ => break,
         }
-        remaining_args = &args[3..];
-    } else if args.len() > 0 {
-        remaining_args = &args[1..];
-    } else {
-        remaining_args = &[];
     }
 
     // Useful font resources:
@@ -53,19 +69,42 @@ fn main(argc: usize, argv: *const *const u8) {
         .unwrap();
     let mut font_data = alloc::vec![0; size as usize];
     let font_data = lz4::decode_into(compressed_font, &mut font_data).unwrap();
-    let font = format::pcf::load_pcf(font_data).unwrap();
+    let bitmap_font = format::pcf::load_pcf(font_data).unwrap();
+
+    // An outline font is used if there is one, at a size matching the
+    // scale of the bitmap font unless told otherwise
+    let font_file = font::read_file(font_path.unwrap_or(font::DEFAULT_FONT_PATH));
+    let outline_font = font_file.as_deref().and_then(|data| {
+        format::ttf::load_ttf(data)
+            .inspect_err(|err| println!("Failed to load font: {err:?}"))
+            .ok()
+    });
+    let mut font = match outline_font {
+        Some(outline) => {
+            let size = font_size.unwrap_or(16 * scale as u32);
+            font::Font::Outline(format::ttf::ScaledFont::new(outline, size))
+        }
+        None => font::Font::Bitmap {
+            font: alloc::boxed::Box::new(bitmap_font),
+            scale,
+        },
+    };
 
-    let mut buf = display_client::connect(320 * scale as u16, 240 * scale as u16);
+    let mut buf = display_client::connect_resizable(
+        (320 * scale as u16, 240 * scale as u16),
+        (80 * scale as u16, 40 * scale as u16),
+        (u16::MAX, u16::MAX),
+    );
 
     let (width, height) = (
         buf.video_meta.width as usize,
         buf.video_meta.height as usize,
     );
-    let row_stride = buf.video_meta.row_stride as usize / 4;
+    let mut row_stride = buf.video_meta.row_stride as usize / 4;
 
-    buf.video_mem().fill(color::rgba(0, 0, 0, 255));
+    buf.video_mem()[..height * row_stride].fill(color::rgba(0, 0, 0, 255));
 
-    let char_dims = font.dimensions();
+    let char_dims = font.cell_dims();
     let hpad = 2;
     let vpad = 0;
 
@@ -73,16 +112,19 @@ fn main(argc: usize, argv: *const *const u8) {
         fg: color::rgba(255, 255, 255, 255),
         bg: color::rgba(0, 0, 0, 0),
     };
-    let mut grid = grid::CharGrid::new(
-        (width, height),
-        font.dimensions(),
-        scale,
+    let mut grid = grid::CharGrid::new((width, height), char_dims, 1, hpad, vpad, fill_color);
+
+    let layout = CellLayout {
+        cell_width: char_dims.1,
+        cell_height: char_dims.0,
         hpad,
         vpad,
-        fill_color,
-    );
+    };
+    // Whether the left button is held down over the window, selecting text
+    let mut selecting = false;
 
     let mut emulator = vt100::EmulatorState::new(grid.rows, grid.cols);
+    buf.set_term_meta(grid.rows as u16, grid.cols as u16);
 
     let mut modifiers = editor::Modifiers::NONE;
     let mut editor = editor::LineEditor::new();
@@ -103,9 +145,9 @@ fn main(argc: usize, argv: *const *const u8) {
         }
     };
 
-    let (shell, shell_stdin_tx, shell_stdout_rx) = {
-        let (shell_stdin_rx, shell_stdin_tx) = pipe(0).unwrap();
-        let (shell_stdout_rx, shell_stdout_tx) = pipe(0).unwrap();
+    let (shell, pty) = {
+        let (pty, pty_slave) = pty_create().unwrap();
+        set_pty_size(pty, &grid, (width, height));
 
         let shell = spawn_elf(&SpawnArgs {
             fd,
@@ -116,12 +158,19 @@ fn main(argc: usize, argv: *const *const u8) {
                     ptr: a.as_ptr(),
                 })
                 .collect::<alloc::vec::Vec<_>>(),
-            stdin: Some(shell_stdin_rx),
-            stdout: Some(shell_stdout_tx),
-            stderr: Some(shell_stdout_tx),
+            stdin: Some(pty_slave),
+            stdout: Some(pty_slave),
+            stderr: Some(pty_slave),
+            pgid: Some(0),
         })
         .unwrap();
-        (shell, shell_stdin_tx, shell_stdout_rx)
+        ulib::sys::close(pty_slave).ok();
+
+        // The shell starts out in the foreground, in its own group
+        if let Ok(pid) = ulib::sys::wait_fd_pid(shell) {
+            ulib::sys::tcsetpgrp(pty, pid).ok();
+        }
+        (shell, pty)
     };
 
     'outer: loop {
@@ -132,10 +181,60 @@ fn main(argc: usize, argv: *const *const u8) {
             break;
         }
 
+        // Programs that turn off canonical mode (such as editors) read
+        // keys directly, rather than lines from the line editor
+        let raw_input = ulib::sys::tcgetattr(pty).is_ok_and(|t| t.lflag & ulib::sys::ICANON == 0);
+
+        let mut resize = None;
         while let Some(ev) = buf.server_to_client_queue().try_recv() {
             match ev.kind {
                 proto::EventKind::INPUT => {
-                    handle_input(ev, &mut modifiers, &mut editor, shell_stdin_tx, time_us)
+                    if !handle_view_input(
+                        &ev,
+                        modifiers,
+                        &mut emulator,
+                        &layout,
+                        &mut selecting,
+                        &mut buf,
+                        &mut editor,
+                    ) {
+                        handle_input(
+                            ev,
+                            &mut buf,
+                            &mut modifiers,
+                            &mut editor,
+                            pty,
+                            raw_input,
+                            time_us,
+                        )
+                    }
+                }
+                proto::EventKind::CLIPBOARD_DATA => {
+                    use proto::EventData;
+                    let Some(proto::ClipboardDataEvent(event)) =
+                        proto::ClipboardDataEvent::parse(&ev)
+                    else {
+                        continue;
+                    };
+                    let data = display_client::receive_clipboard(&buf, &event);
+                    if let Some(text) = data.and_then(|d| alloc::string::String::from_utf8(d).ok())
+                    {
+                        if emulator.bracketed_paste {
+                            // The program asked to receive pastes directly
+                            pwrite_all(pty, b"\x1b[200~", 0).unwrap();
+                            pwrite_all(pty, text.as_bytes(), 0).unwrap();
+                            pwrite_all(pty, b"\x1b[201~", 0).unwrap();
+                        } else if raw_input {
+                            pwrite_all(pty, text.as_bytes(), 0).unwrap();
+                        } else {
+                            editor.cut_buffer = text;
+                            editor.paste_from_cut();
+                        }
+                    }
+                }
+                proto::EventKind::RESIZE => {
+                    use proto::EventData;
+                    resize = proto::ResizeEvent::parse(&ev);
                 }
                 proto::EventKind::REQUEST_CLOSE => {
                     println!("Close requested, exiting.");
@@ -146,35 +245,59 @@ fn main(argc: usize, argv: *const *const u8) {
             }
         }
 
+        // Only the latest size matters, if several arrived at once
+        if let Some(resize) = resize {
+            if buf.ack_resize(resize) {
+                let (width, height) = (resize.width as usize, resize.height as usize);
+                row_stride = buf.video_meta.row_stride as usize / 4;
+                grid = grid::CharGrid::new((width, height), char_dims, 1, hpad, vpad, fill_color);
+                emulator.resize(grid.rows, grid.cols);
+                buf.set_term_meta(grid.rows as u16, grid.cols as u16);
+                set_pty_size(pty, &grid, (width, height));
+            }
+        }
+
         {
-            let data = buf.video_mem();
+            let frame_len = buf.video_meta.height as usize * row_stride;
+            let data = &mut buf.video_mem()[..frame_len];
             data.fill(color::rgba(0, 0, 0, 255));
 
             let mut buf = [0; 4096];
-            while let Ok(n @ 1..) = ulib::sys::pread(shell_stdout_rx, &mut buf, 0) {
+            while let Ok(n @ 1..) = ulib::sys::pread(pty, &mut buf, 0) {
                 emulator.input(&buf[..n]);
             }
+            let replies = emulator.take_replies();
+            if !replies.is_empty() {
+                pwrite_all(pty, &replies, 0).unwrap();
+            }
 
             emulator.update(grid.region(0, 0, grid.rows, grid.cols));
 
-            let blink = ((time_us - editor.last_keypress) < 0_600_000
-                || ((time_us - editor.last_keypress) % 1_200_000 < 600_000))
-                && editor.selection_range().len() == 0;
+            if raw_input {
+                // No line editor to draw a cursor; show the terminal's,
+                // unless it's scrolled out of view
+                let row =
+                    emulator.cursor.row.min(grid.rows.saturating_sub(1)) + emulator.view_offset();
+                if emulator.cursor_visible && row < grid.rows {
+                    let col = emulator.cursor.col.min(grid.cols.saturating_sub(1));
+                    let cell = &mut grid.colors[row * grid.stride + col];
+                    *cell = grid::Colors {
+                        fg: color::rgba(0, 0, 0, 255),
+                        bg: color::rgba(255, 255, 255, 255),
+                    };
+                }
+            } else {
+                let blink = ((time_us - editor.last_keypress) < 0_600_000
+                    || ((time_us - editor.last_keypress) % 1_200_000 < 600_000))
+                    && editor.selection_range().len() == 0;
 
-            editor::draw_editor_into_console(&editor, &mut emulator, fill_color.fg, blink);
+                editor::draw_editor_into_console(&editor, &mut emulator, fill_color.fg, blink);
+            }
 
-            render_grid(&grid, char_dims, scale, vpad, hpad, data, row_stride, &font);
+            render_grid(&grid, char_dims, vpad, hpad, data, row_stride, &mut font);
         }
 
-        buf.client_to_server_queue()
-            .try_send(proto::Event {
-                kind: proto::EventKind::PRESENT,
-                data: [0; 7],
-            })
-            .ok();
-
-        // signal(video)? for sync
-        ulib::sys::sem_down(buf.get_sem_fd(buf.present_sem)).unwrap();
+        display_client::present(&mut buf, None);
     }
 
     buf.client_to_server_queue()
@@ -183,29 +306,41 @@ fn main(argc: usize, argv: *const *const u8) {
             data: [0; 7],
         })
         .ok();
+    display_client::notify_server(&buf);
+}
+
+/// Tell programs on the terminal its size, from the same dimensions
+/// as the [`proto::TermMeta`] sent to the display server.
+fn set_pty_size(pty: FileDesc, grid: &grid::CharGrid, (width, height): (usize, usize)) {
+    let size = ulib::sys::WinSize {
+        rows: grid.rows as u16,
+        cols: grid.cols as u16,
+        width: width as u16,
+        height: height as u16,
+    };
+    ulib::sys::set_winsize(pty, &size).ok();
 }
 
 fn render_grid(
     grid: &grid::CharGrid,
     char_dims: (usize, usize),
-    scale: usize,
     vpad: usize,
     hpad: usize,
     data: &mut [u32],
     row_stride: usize,
-    font: &format::pcf::LoadedPCF<'_>,
+    font: &mut font::Font<'_>,
 ) {
     for r in 0..grid.rows {
         for c in 0..grid.cols {
             let ch = grid.chars[r * grid.cols + c];
             let colors = grid.colors[r * grid.cols + c];
 
-            let y = char_dims.0 * scale * r + vpad;
-            let x = char_dims.1 * scale * c + hpad;
+            let y = char_dims.0 * r + vpad;
+            let x = char_dims.1 * c + hpad;
 
             if (colors.bg & 0xFF000000) != 0 {
-                for pr in 0..char_dims.0 * scale {
-                    for pc in 0..char_dims.1 * scale {
+                for pr in 0..char_dims.0 {
+                    for pc in 0..char_dims.1 {
                         let pixel = &mut data[(y + pr) * row_stride + x + pc];
                         *pixel = color::blend(colors.bg, *pixel);
                     }
@@ -213,17 +348,141 @@ fn render_grid(
             }
 
             if ch != ' ' && (colors.fg & 0xFF000000) != 0 {
-                font.draw_char(ch, data, y * row_stride + x, row_stride, scale, colors.fg);
+                font.draw_char(ch, data, row_stride, (x, y), colors.fg);
+            }
+        }
+    }
+}
+
+const TEXT_MIME: &str = "text/plain;charset=utf-8";
+
+/// Rows scrolled through the history per notch of the mouse wheel.
+const WHEEL_ROWS: isize = 3;
+
+/// Where the character cells are in the window.
+struct CellLayout {
+    cell_width: usize,
+    cell_height: usize,
+    hpad: usize,
+    vpad: usize,
+}
+
+impl CellLayout {
+    /// The row under a point, and the boundary between cells nearest to it.
+    fn boundary_at(&self, x: u32, y: u32) -> vt100::GridCoords {
+        let x = (x as usize).saturating_sub(self.hpad) + self.cell_width / 2;
+        let y = (y as usize).saturating_sub(self.vpad);
+        vt100::GridCoords {
+            row: y / self.cell_height,
+            col: x / self.cell_width,
+        }
+    }
+}
+
+/// Scroll through the history and select text, which the terminal does
+/// itself instead of passing the input on to the program. Returns
+/// whether the event was used up.
+fn handle_view_input(
+    ev: &proto::Event,
+    modifiers: editor::Modifiers,
+    emulator: &mut vt100::EmulatorState,
+    layout: &CellLayout,
+    selecting: &mut bool,
+    buf: &mut proto::BufferHandle,
+    editor: &mut editor::LineEditor,
+) -> bool {
+    use proto::{EventData, InputEvent, ScanCode};
+    let Some(data) = InputEvent::parse(ev) else {
+        return false;
+    };
+    match data.kind {
+        InputEvent::KIND_SCROLL => {
+            // Rolling the wheel away from you goes back in the history
+            emulator.scroll_view(data.data1 as i32 as isize * WHEEL_ROWS);
+            true
+        }
+        InputEvent::KIND_MOUSE => {
+            let pos = layout.boundary_at(data.data2, data.data3);
+            match data.data1 {
+                InputEvent::MODE_MOUSE_DOWN if data.data4 == 1 => {
+                    emulator.start_selection(pos);
+                    *selecting = true;
+                }
+                InputEvent::MODE_MOUSE_MOVE if *selecting => emulator.extend_selection(pos),
+                InputEvent::MODE_MOUSE_UP if data.data4 == 1 && *selecting => {
+                    emulator.extend_selection(pos);
+                    *selecting = false;
+                    // A click without dragging deselects
+                    if !copy_selection(emulator, buf, editor) {
+                        emulator.clear_selection();
+                    }
+                }
+                _ => (),
+            }
+            true
+        }
+        InputEvent::KIND_KEY => {
+            let pressed = data.data1 != 2;
+            let page = emulator.rows.saturating_sub(1).max(1) as isize;
+            match ScanCode(data.data2) {
+                ScanCode::PAGE_UP if modifiers.shift => {
+                    if pressed {
+                        emulator.scroll_view(page);
+                    }
+                    true
+                }
+                ScanCode::PAGE_DOWN if modifiers.shift => {
+                    if pressed {
+                        emulator.scroll_view(-page);
+                    }
+                    true
+                }
+                // Copying prefers the terminal's selection to the editor's
+                ScanCode::C if modifiers.ctrl && modifiers.shift && pressed => {
+                    copy_selection(emulator, buf, editor)
+                }
+                ScanCode::LEFT_SHIFT
+                | ScanCode::RIGHT_SHIFT
+                | ScanCode::LEFT_CTRL
+                | ScanCode::RIGHT_CTRL
+                | ScanCode::LEFT_ALT
+                | ScanCode::RIGHT_ALT => false,
+                _ => {
+                    // Typing goes back to the bottom, where the input is
+                    if pressed {
+                        emulator.scroll_to_bottom();
+                    }
+                    false
+                }
             }
         }
+        _ => false,
+    }
+}
+
+/// Copy the text selected in the terminal to the clipboard and the line
+/// editor's cut buffer, returning whether there was any.
+fn copy_selection(
+    emulator: &vt100::EmulatorState,
+    buf: &mut proto::BufferHandle,
+    editor: &mut editor::LineEditor,
+) -> bool {
+    let text = emulator.selected_text();
+    if text.is_empty() {
+        return false;
     }
+    display_client::set_clipboard(buf, TEXT_MIME, text.as_bytes());
+    editor.cut_buffer = text;
+    true
 }
 
 fn handle_input(
     ev: proto::Event,
+    buf: &mut proto::BufferHandle,
     modifiers: &mut editor::Modifiers,
     editor: &mut editor::LineEditor,
-    shell_stdin_tx: FileDesc,
+    pty: FileDesc,
+    raw_input: bool,
     time_us: u64,
 ) {
     use proto::EventData;
@@ -240,6 +499,58 @@ fn handle_input(
         };
 
         if let Some(ev) = input::remap_input(data, *modifiers) {
+            use editor::{KeyEvent, Keypress};
+            if raw_input {
+                let mut bytes = alloc::vec::Vec::new();
+                input::key_bytes(&ev, &mut bytes);
+                if !bytes.is_empty() {
+                    pwrite_all(pty, &bytes, 0).unwrap();
+                }
+                return;
+            }
+
+            // Ctrl+Shift+C/V copy and paste through the system
+            // clipboard, as do Ctrl+K/Y (cut and yank)
+            let clipboard_key = match &ev {
+                KeyEvent::Press(Keypress::Char(m, c, _)) if m.ctrl && !m.alt && !m.meta => {
+                    Some((c.to_ascii_lowercase(), m.shift))
+                }
+                _ => None,
+            };
+            // Job control characters go straight to the terminal, as does
+            // end-of-file on an empty line
+            let control = match clipboard_key {
+                Some(('c', false)) => Some(0x03),
+                Some(('z', false)) => Some(0x1a),
+                Some(('\\', false)) => Some(0x1c),
+                Some(('d', false)) if editor.buf.is_empty() => Some(0x04),
+                _ => None,
+            };
+            if let Some(control) = control {
+                editor.clear();
+                pwrite_all(pty, &[control], 0).unwrap();
+                return;
+            }
+            match clipboard_key {
+                Some(('c', true)) => {
+                    let text = alloc::string::String::from(editor.selected_text());
+                    if !text.is_empty() {
+                        display_client::set_clipboard(buf, TEXT_MIME, text.as_bytes());
+                        editor.cut_buffer = text;
+                    }
+                    return;
+                }
+                Some(('v', true) | ('y', false)) => {
+                    if !display_client::request_clipboard(buf, "text/*") {
+                        editor.paste_from_cut();
+                    }
+                    editor.last_keypress = time_us;
+                    return;
+                }
+                _ => (),
+            }
+
+            let cut = matches!(clipboard_key, Some(('k', false)));
             if matches!(
                 ev,
                 editor::KeyEvent::Press(editor::Keypress::Function(
@@ -247,11 +558,14 @@ fn handle_input(
                     editor::FuncKey::Enter
                 ))
             ) {
-                pwrite_all(shell_stdin_tx, editor.buf.as_bytes(), 0).unwrap();
-                pwrite_all(shell_stdin_tx, b"\r", 0).unwrap();
+                pwrite_all(pty, editor.buf.as_bytes(), 0).unwrap();
+                pwrite_all(pty, b"\r", 0).unwrap();
                 editor.clear();
             } else {
                 editor::editor_input(editor, ev, time_us);
+                if cut {
+                    display_client::set_clipboard(buf, TEXT_MIME, editor.cut_buffer.as_bytes());
+                }
             }
         }
     }
@@ -1,9 +1,22 @@
 use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::string::String;
 use alloc::vec;
+use alloc::vec::Vec;
 
 use crate::color::rgba;
 use crate::grid::{Colors, GridRef};
 
+mod parser;
+
+use parser::{Action, Csi, Parser};
+
+/// The characters and colors of a whole screen.
+type Screen = (Box<[char]>, Box<[Colors]>);
+
+/// The most rows kept in the scrollback history.
+const SCROLLBACK_LIMIT: usize = 2000;
+
 // TODO: line/row separation
 // TODO: handling double width chars (emoji)
 pub struct EmulatorState {
@@ -16,7 +29,54 @@ pub struct EmulatorState {
     changed: bool,
 
     pub cursor: GridCoords,
-    cur_mode: Colors,
+    attrs: Attrs,
+    saved: SavedCursor,
+
+    parser: Parser,
+    /// The rows scrolled by line feeds, `scroll_top..scroll_bottom`.
+    scroll_top: usize,
+    scroll_bottom: usize,
+    autowrap: bool,
+    /// The main screen, while full-screen programs use the alternate one.
+    main_screen: Option<Screen>,
+    /// Whether pasted text should be wrapped in `ESC [200~`/`ESC [201~`.
+    pub bracketed_paste: bool,
+    pub cursor_visible: bool,
+    /// Answers to status requests, to be sent back to the program.
+    replies: Vec<u8>,
+
+    /// Rows scrolled off the top of the main screen, oldest first, each
+    /// as wide as the screen was at the time.
+    scrollback: VecDeque<Screen>,
+    /// How many rows have been dropped from the front of `scrollback`.
+    /// Rows are numbered from the first one ever kept, so a selection
+    /// stays on the same text as more rows scroll off.
+    scrollback_dropped: usize,
+    /// How many rows of history are shown above the screen.
+    view_offset: usize,
+    selection: Option<Selection>,
+}
+
+/// A position between two cells, numbered from the first row of the
+/// history.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct TextPos {
+    line: usize,
+    col: usize,
+}
+
+/// Text selected with the mouse, from where the button was pressed to
+/// where it is now.
+#[derive(Copy, Clone, Debug)]
+struct Selection {
+    anchor: TextPos,
+    end: TextPos,
+}
+
+impl Selection {
+    fn range(&self) -> core::ops::Range<TextPos> {
+        self.anchor.min(self.end)..self.anchor.max(self.end)
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -30,6 +90,124 @@ const DEFAULT_COLOR: Colors = Colors {
     bg: rgba(0, 0, 0, 0),
 };
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Color {
+    Default,
+    Indexed(u8),
+    Rgb(u32),
+}
+
+/// Character attributes set by SGR sequences.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Attrs {
+    fg: Color,
+    bg: Color,
+    bold: bool,
+    inverse: bool,
+}
+
+impl Attrs {
+    const DEFAULT: Attrs = Attrs {
+        fg: Color::Default,
+        bg: Color::Default,
+        bold: false,
+        inverse: false,
+    };
+
+    fn fg(&self) -> u32 {
+        match self.fg {
+            Color::Default => DEFAULT_COLOR.fg,
+            // Bold text is drawn in the bright variant of the first 8 colors
+            Color::Indexed(i @ 0..8) if self.bold => palette(i + 8),
+            Color::Indexed(i) => palette(i),
+            Color::Rgb(c) => c,
+        }
+    }
+
+    fn bg(&self) -> u32 {
+        match self.bg {
+            Color::Default => DEFAULT_COLOR.bg,
+            Color::Indexed(i) => palette(i),
+            Color::Rgb(c) => c,
+        }
+    }
+
+    fn colors(&self) -> Colors {
+        if self.inverse {
+            // The default background is transparent, which doesn't work
+            // as a text color
+            let fg = match self.bg {
+                Color::Default => rgba(0, 0, 0, 255),
+                _ => self.bg(),
+            };
+            Colors { fg, bg: self.fg() }
+        } else {
+            Colors {
+                fg: self.fg(),
+                bg: self.bg(),
+            }
+        }
+    }
+
+    /// Colors for erased cells, which keep the current background.
+    fn blank(&self) -> Colors {
+        Colors {
+            fg: DEFAULT_COLOR.fg,
+            bg: self.bg(),
+        }
+    }
+}
+
+/// The xterm 256-color palette: 16 system colors, a 6x6x6 color cube and
+/// a grayscale ramp.
+const fn palette(index: u8) -> u32 {
+    const SYSTEM: [(u8, u8, u8); 16] = [
+        (0x00, 0x00, 0x00),
+        (0xcd, 0x00, 0x00),
+        (0x00, 0xcd, 0x00),
+        (0xcd, 0xcd, 0x00),
+        (0x00, 0x00, 0xee),
+        (0xcd, 0x00, 0xcd),
+        (0x00, 0xcd, 0xcd),
+        (0xe5, 0xe5, 0xe5),
+        (0x7f, 0x7f, 0x7f),
+        (0xff, 0x00, 0x00),
+        (0x00, 0xff, 0x00),
+        (0xff, 0xff, 0x00),
+        (0x5c, 0x5c, 0xff),
+        (0xff, 0x00, 0xff),
+        (0x00, 0xff, 0xff),
+        (0xff, 0xff, 0xff),
+    ];
+    const fn level(l: u8) -> u8 {
+        if l == 0 {
+            0
+        } else {
+            55 + 40 * l
+        }
+    }
+    match index {
+        0..16 => {
+            let (r, g, b) = SYSTEM[index as usize];
+            rgba(r, g, b, 255)
+        }
+        16..232 => {
+            let i = index - 16;
+            rgba(level(i / 36), level(i / 6 % 6), level(i % 6), 255)
+        }
+        232.. => {
+            let l = 8 + 10 * (index - 232);
+            rgba(l, l, l, 255)
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct SavedCursor {
+    cursor: GridCoords,
+    attrs: Attrs,
+}
+
 impl EmulatorState {
     pub fn new(rows: usize, cols: usize) -> Self {
         let stride = cols.next_multiple_of(4);
@@ -42,36 +220,255 @@ impl EmulatorState {
             colors: vec![DEFAULT_COLOR; rows * stride].into_boxed_slice(),
             changed: true,
             cursor: GridCoords { row: 0, col: 0 },
-            cur_mode: DEFAULT_COLOR,
+            attrs: Attrs::DEFAULT,
+            saved: SavedCursor {
+                cursor: GridCoords { row: 0, col: 0 },
+                attrs: Attrs::DEFAULT,
+            },
+            parser: Parser::new(),
+            scroll_top: 0,
+            scroll_bottom: rows,
+            autowrap: true,
+            main_screen: None,
+            bracketed_paste: false,
+            cursor_visible: true,
+            replies: Vec::new(),
+            scrollback: VecDeque::new(),
+            scrollback_dropped: 0,
+            view_offset: 0,
+            selection: None,
         }
     }
 
-    fn scroll(&mut self, distance: usize) {
+    /// Change the size of the screen, keeping as much of the current
+    /// contents as fits; if the cursor would end up below the screen,
+    /// the top rows are dropped instead.
+    pub fn resize(&mut self, rows: usize, cols: usize) {
+        let stride = cols.next_multiple_of(4);
+        let skip = (self.cursor.row + 1).saturating_sub(rows);
+        if self.main_screen.is_none() {
+            self.push_scrollback(skip);
+        }
+        let (chars, colors) = self.resized_screen(&self.chars, &self.colors, skip, rows, cols);
+
+        if let Some((main_chars, main_colors)) = self.main_screen.take() {
+            self.main_screen = Some(self.resized_screen(&main_chars, &main_colors, 0, rows, cols));
+        }
+
+        self.rows = rows;
+        self.cols = cols;
+        self.stride = stride;
+        self.chars = chars;
+        self.colors = colors;
+        self.cursor.row -= skip;
+        self.cursor.col = self.cursor.col.min(cols);
+        self.saved.cursor.row = self.saved.cursor.row.min(rows.saturating_sub(1));
+        self.saved.cursor.col = self.saved.cursor.col.min(cols.saturating_sub(1));
+        self.scroll_top = 0;
+        self.scroll_bottom = rows;
+        self.scrolled_rows += skip;
+        self.changed = true;
+    }
+
+    fn resized_screen(
+        &self,
+        old_chars: &[char],
+        old_colors: &[Colors],
+        skip: usize,
+        rows: usize,
+        cols: usize,
+    ) -> Screen {
+        let stride = cols.next_multiple_of(4);
+        let mut chars = vec![' '; rows * stride].into_boxed_slice();
+        let mut colors = vec![DEFAULT_COLOR; rows * stride].into_boxed_slice();
+
+        for row in 0..self.rows.saturating_sub(skip).min(rows) {
+            let src = (row + skip) * self.stride;
+            let dst = row * stride;
+            let len = self.cols.min(cols);
+            chars[dst..dst + len].copy_from_slice(&old_chars[src..src + len]);
+            colors[dst..dst + len].copy_from_slice(&old_colors[src..src + len]);
+        }
+        (chars, colors)
+    }
+
+    /// Move the rows of the scroll region up by `distance`, clearing the
+    /// ones at the bottom.
+    fn scroll_up(&mut self, distance: usize) {
+        let (top, bottom) = (self.scroll_top, self.scroll_bottom);
+        let distance = distance.min(bottom - top);
+        if distance == 0 {
+            return;
+        }
+        // Rows leaving the top of the main screen go to the history
+        if top == 0 && self.main_screen.is_none() {
+            self.push_scrollback(distance);
+        }
+        let src = (top + distance) * self.stride..bottom * self.stride;
+        self.chars.copy_within(src.clone(), top * self.stride);
+        self.colors.copy_within(src, top * self.stride);
+        self.clear_rows(bottom - distance..bottom);
+    }
+
+    /// Move the rows of the scroll region down by `distance`, clearing
+    /// the ones at the top.
+    fn scroll_down(&mut self, distance: usize) {
+        let (top, bottom) = (self.scroll_top, self.scroll_bottom);
+        let distance = distance.min(bottom - top);
         if distance == 0 {
             return;
         }
-        for row in 0..self.rows.saturating_sub(distance) {
-            let dst_start = row * self.stride;
-            let dst_end = dst_start + self.stride;
-            let src_start = (row + distance) * self.stride - dst_end;
-            let src_end = src_start + self.stride;
+        let src = top * self.stride..(bottom - distance) * self.stride;
+        self.chars
+            .copy_within(src.clone(), (top + distance) * self.stride);
+        self.colors.copy_within(src, (top + distance) * self.stride);
+        self.clear_rows(top..top + distance);
+    }
+
+    /// Copy the top `count` rows of the screen to the end of the history.
+    fn push_scrollback(&mut self, count: usize) {
+        for row in 0..count.min(self.rows) {
+            let range = row * self.stride..row * self.stride + self.cols;
+            let line = (self.chars[range.clone()].into(), self.colors[range].into());
+            if self.scrollback.len() == SCROLLBACK_LIMIT {
+                self.scrollback.pop_front();
+                self.scrollback_dropped += 1;
+            }
+            self.scrollback.push_back(line);
+            // Keep showing the same rows while looking at the history
+            if self.view_offset > 0 {
+                self.view_offset = (self.view_offset + 1).min(self.scrollback.len());
+            }
+        }
+    }
+
+    fn clear_scrollback(&mut self) {
+        self.scrollback_dropped += self.scrollback.len();
+        self.scrollback.clear();
+        self.view_offset = 0;
+        self.changed = true;
+    }
+
+    /// How many rows of history are shown above the screen.
+    pub fn view_offset(&self) -> usize {
+        self.view_offset
+    }
+
+    /// Move the view through the history by `rows`, back towards older
+    /// rows if positive.
+    pub fn scroll_view(&mut self, rows: isize) {
+        // The alternate screen has no history
+        let history = match self.main_screen {
+            Some(_) => 0,
+            None => self.scrollback.len(),
+        };
+        let offset = self.view_offset.saturating_add_signed(rows).min(history);
+        if offset != self.view_offset {
+            self.view_offset = offset;
+            self.changed = true;
+        }
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll_view(-(self.view_offset as isize));
+    }
+
+    /// The cells of a row, numbered from the first row of the history.
+    fn line(&self, line: usize) -> Option<(&[char], &[Colors])> {
+        let index = line.checked_sub(self.scrollback_dropped)?;
+        if let Some((chars, colors)) = self.scrollback.get(index) {
+            return Some((chars, colors));
+        }
+        let row = index - self.scrollback.len();
+        if row >= self.rows {
+            return None;
+        }
+        let range = row * self.stride..row * self.stride + self.cols;
+        Some((&self.chars[range.clone()], &self.colors[range]))
+    }
+
+    /// The number of the row of the history shown at `row` of the view.
+    fn view_line(&self, row: usize) -> usize {
+        self.scrollback_dropped + self.scrollback.len() - self.view_offset + row
+    }
+
+    fn text_pos(&self, pos: GridCoords) -> TextPos {
+        TextPos {
+            line: self.view_line(pos.row.min(self.rows.saturating_sub(1))),
+            col: pos.col.min(self.cols),
+        }
+    }
+
+    /// Start selecting text at a position in the view, which is the
+    /// boundary before the cell at `pos.col`.
+    pub fn start_selection(&mut self, pos: GridCoords) {
+        let pos = self.text_pos(pos);
+        self.selection = Some(Selection {
+            anchor: pos,
+            end: pos,
+        });
+        self.changed = true;
+    }
 
-            let (dst, src) = self.chars.split_at_mut(dst_end);
-            dst[dst_start..dst_end].copy_from_slice(&src[src_start..src_end]);
-            let (dst, src) = self.colors.split_at_mut(dst_end);
-            dst[dst_start..dst_end].copy_from_slice(&src[src_start..src_end]);
+    /// Move the end of the selection to a position in the view.
+    pub fn extend_selection(&mut self, pos: GridCoords) {
+        let pos = self.text_pos(pos);
+        if let Some(selection) = &mut self.selection {
+            selection.end = pos;
+            self.changed = true;
         }
+    }
 
-        let clear_start = self.rows.saturating_sub(distance);
-        let clear_end = self.rows;
-        let clear_idx_range = clear_start * self.stride..clear_end * self.stride;
+    pub fn clear_selection(&mut self) {
+        if self.selection.take().is_some() {
+            self.changed = true;
+        }
+    }
 
-        let fill_color = DEFAULT_COLOR;
-        self.chars[clear_idx_range.clone()].fill(' ');
-        self.colors[clear_idx_range].fill(fill_color);
+    /// The selected text, with the trailing blanks of each row removed.
+    pub fn selected_text(&self) -> String {
+        let mut text = String::new();
+        let Some(selection) = self.selection else {
+            return text;
+        };
+        let range = selection.range();
+        for line in range.start.line..=range.end.line {
+            let Some((chars, _)) = self.line(line) else {
+                continue;
+            };
+            let start = if line == range.start.line {
+                range.start.col
+            } else {
+                0
+            };
+            let end = if line == range.end.line {
+                range.end.col
+            } else {
+                chars.len()
+            };
+            let row = &chars[start.min(chars.len())..end.min(chars.len())];
+            let len = row.iter().rposition(|&c| c != ' ').map_or(0, |i| i + 1);
+            text.extend(&row[..len]);
+            if line != range.end.line {
+                text.push('\n');
+            }
+        }
+        text
+    }
 
-        self.cursor.row = self.cursor.row.saturating_sub(distance);
-        self.scrolled_rows += distance;
+    fn clear_rows(&mut self, rows: core::ops::Range<usize>) {
+        let range = rows.start * self.stride..rows.end * self.stride;
+        let blank = self.attrs.blank();
+        self.chars[range.clone()].fill(' ');
+        self.colors[range].fill(blank);
+    }
+
+    fn clear_cols(&mut self, row: usize, cols: core::ops::Range<usize>) {
+        let start = row * self.stride + cols.start.min(self.cols);
+        let end = row * self.stride + cols.end.min(self.cols);
+        let blank = self.attrs.blank();
+        self.chars[start..end].fill(' ');
+        self.colors[start..end].fill(blank);
     }
 
     pub fn should_wrap(&self) -> bool {
@@ -84,9 +481,29 @@ impl EmulatorState {
     }
     pub fn wrap(&mut self) {
         self.cursor.col = 0;
-        self.cursor.row += 1;
-        if self.cursor.row == self.rows {
-            self.scroll(1);
+        self.index();
+    }
+
+    /// Move the cursor down a row, scrolling if it's at the bottom of the
+    /// scroll region.
+    fn index(&mut self) {
+        if self.cursor.row + 1 == self.scroll_bottom {
+            self.scroll_up(1);
+            if self.scroll_top == 0 && self.scroll_bottom == self.rows {
+                self.scrolled_rows += 1;
+            }
+        } else if self.cursor.row + 1 < self.rows {
+            self.cursor.row += 1;
+        }
+    }
+
+    /// Move the cursor up a row, scrolling if it's at the top of the
+    /// scroll region.
+    fn reverse_index(&mut self) {
+        if self.cursor.row == self.scroll_top {
+            self.scroll_down(1);
+        } else {
+            self.cursor.row = self.cursor.row.saturating_sub(1);
         }
     }
 
@@ -97,46 +514,358 @@ impl EmulatorState {
         }
     }
 
+    /// Answers to status requests since the last call, which should be
+    /// written back to the program as if they were typed.
+    pub fn take_replies(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.replies)
+    }
+
     pub fn input(&mut self, text: &[u8]) {
-        for chunk in text.utf8_chunks() {
-            for c in chunk.valid().chars() {
-                match c {
-                    '\r' => self.cursor.col = 0,
-                    '\n' => self.wrap(),
-                    '\t' => {
-                        self.cursor.col = (self.cursor.col + 1).next_multiple_of(8);
-                        self.check_wrap();
-                    }
-                    _ => {
-                        self.set_char_color(self.cursor, c, self.cur_mode);
-                        self.cursor.col += 1;
-                        self.check_wrap();
+        let mut parser = core::mem::replace(&mut self.parser, Parser::new());
+        parser.feed(text, |action| match action {
+            Action::Print(c) => self.print(c),
+            Action::Control(c) => self.control(c),
+            Action::Csi(csi) => self.csi(&csi),
+            Action::Esc {
+                intermediate,
+                action,
+            } => self.esc(intermediate, action),
+        });
+        self.parser = parser;
+        self.changed = true;
+    }
+
+    fn print(&mut self, c: char) {
+        if self.should_wrap() {
+            if self.autowrap {
+                self.wrap();
+            } else {
+                self.cursor.col = self.cols - 1;
+            }
+        }
+        self.set_char_color(self.cursor, c, self.attrs.colors());
+        // The cursor is left past the last column until the next
+        // character, so that the last column can be written without
+        // scrolling
+        self.cursor.col += 1;
+    }
+
+    fn control(&mut self, c: u8) {
+        match c {
+            b'\r' => self.cursor.col = 0,
+            // Output isn't post-processed, so line feeds also return
+            // the cursor to the start of the line
+            b'\n' | 0x0b | 0x0c => self.wrap(),
+            b'\t' => {
+                let next_stop = (self.cursor.col + 1).next_multiple_of(8);
+                self.cursor.col = next_stop.min(self.cols.saturating_sub(1));
+            }
+            0x08 => self.cursor.col = self.cursor.col.min(self.cols).saturating_sub(1),
+            _ => (),
+        }
+    }
+
+    fn esc(&mut self, intermediate: Option<u8>, action: u8) {
+        if intermediate.is_some() {
+            // Character set selection, which we don't support
+            return;
+        }
+        match action {
+            b'7' => self.save_cursor(),
+            b'8' => self.restore_cursor(),
+            b'D' => self.index(),
+            b'E' => self.wrap(),
+            b'M' => self.reverse_index(),
+            b'c' => self.reset(),
+            _ => (),
+        }
+    }
+
+    fn csi(&mut self, csi: &Csi) {
+        if csi.intermediate.is_some() {
+            return;
+        }
+        match csi.private {
+            None => (),
+            Some(b'?') => {
+                match csi.action {
+                    b'h' => csi.params().iter().for_each(|&m| self.set_mode(m, true)),
+                    b'l' => csi.params().iter().for_each(|&m| self.set_mode(m, false)),
+                    _ => (),
+                }
+                return;
+            }
+            Some(_) => return,
+        }
+
+        let n = csi.param(0, 1) as usize;
+        let last_row = self.rows.saturating_sub(1);
+        let last_col = self.cols.saturating_sub(1);
+        let cursor = GridCoords {
+            row: self.cursor.row,
+            col: self.cursor.col.min(last_col),
+        };
+
+        match csi.action {
+            // Cursor movement, which stops at the edges of the scroll
+            // region if the cursor starts within it
+            b'A' => {
+                let top = if cursor.row >= self.scroll_top {
+                    self.scroll_top
+                } else {
+                    0
+                };
+                self.cursor.row = cursor.row.saturating_sub(n).max(top);
+            }
+            b'B' => {
+                let bottom = if cursor.row < self.scroll_bottom {
+                    self.scroll_bottom - 1
+                } else {
+                    last_row
+                };
+                self.cursor.row = cursor.row.saturating_add(n).min(bottom);
+            }
+            b'C' => self.cursor.col = cursor.col.saturating_add(n).min(last_col),
+            b'D' => self.cursor.col = cursor.col.saturating_sub(n),
+            b'E' => {
+                self.cursor.row = cursor.row.saturating_add(n).min(last_row);
+                self.cursor.col = 0;
+            }
+            b'F' => {
+                self.cursor.row = cursor.row.saturating_sub(n);
+                self.cursor.col = 0;
+            }
+            b'G' | b'`' => self.cursor.col = (n - 1).min(last_col),
+            b'd' => self.cursor.row = (n - 1).min(last_row),
+            b'H' | b'f' => {
+                self.cursor.row = (n - 1).min(last_row);
+                self.cursor.col = (csi.param(1, 1) as usize - 1).min(last_col);
+            }
+
+            // Erasing
+            b'J' => match csi.param(0, 0) {
+                0 => {
+                    self.clear_cols(cursor.row, cursor.col..self.cols);
+                    self.clear_rows(cursor.row + 1..self.rows);
+                }
+                1 => {
+                    self.clear_rows(0..cursor.row);
+                    self.clear_cols(cursor.row, 0..cursor.col + 1);
+                }
+                2 => self.clear_rows(0..self.rows),
+                3 => {
+                    self.clear_rows(0..self.rows);
+                    self.clear_scrollback();
+                }
+                _ => (),
+            },
+            b'K' => match csi.param(0, 0) {
+                0 => self.clear_cols(cursor.row, cursor.col..self.cols),
+                1 => self.clear_cols(cursor.row, 0..cursor.col + 1),
+                2 => self.clear_cols(cursor.row, 0..self.cols),
+                _ => (),
+            },
+            b'X' => self.clear_cols(cursor.row, cursor.col..cursor.col.saturating_add(n)),
+
+            // Inserting and deleting characters within the line
+            b'@' | b'P' => {
+                let n = n.min(self.cols - cursor.col);
+                let line = cursor.row * self.stride;
+                let (start, end) = (line + cursor.col, line + self.cols);
+                if csi.action == b'@' {
+                    self.chars.copy_within(start..end - n, start + n);
+                    self.colors.copy_within(start..end - n, start + n);
+                    self.clear_cols(cursor.row, cursor.col..cursor.col + n);
+                } else {
+                    self.chars.copy_within(start + n..end, start);
+                    self.colors.copy_within(start + n..end, start);
+                    self.clear_cols(cursor.row, self.cols - n..self.cols);
+                }
+                self.cursor.col = cursor.col;
+            }
+
+            // Inserting and deleting lines, which scrolls the part of
+            // the scroll region below the cursor
+            b'L' | b'M' => {
+                if (self.scroll_top..self.scroll_bottom).contains(&cursor.row) {
+                    let top = self.scroll_top;
+                    self.scroll_top = cursor.row;
+                    if csi.action == b'L' {
+                        self.scroll_down(n);
+                    } else {
+                        self.scroll_up(n);
                     }
+                    self.scroll_top = top;
+                    self.cursor.col = 0;
                 }
             }
-            for _ in chunk.invalid() {
-                self.set_char_color(self.cursor, ' ', self.cur_mode);
-                self.cursor.col += 1;
-                self.check_wrap();
+            b'S' => self.scroll_up(n),
+            b'T' => self.scroll_down(n),
+            b'r' => {
+                let top = csi.param(0, 1) as usize - 1;
+                let bottom = (csi.param(1, self.rows as u16) as usize).min(self.rows);
+                if top + 1 < bottom {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.cursor = GridCoords { row: 0, col: 0 };
+                }
+            }
+            b's' => self.save_cursor(),
+            b'u' => self.restore_cursor(),
+            b'm' => self.sgr(csi.params()),
+
+            // Status reports
+            b'n' => match csi.param(0, 0) {
+                5 => self.replies.extend_from_slice(b"\x1b[0n"),
+                6 => {
+                    let report = alloc::format!("\x1b[{};{}R", cursor.row + 1, cursor.col + 1);
+                    self.replies.extend_from_slice(report.as_bytes());
+                }
+                _ => (),
+            },
+            // Identify as a VT100 with advanced video
+            b'c' if csi.param(0, 0) == 0 => self.replies.extend_from_slice(b"\x1b[?1;2c"),
+            _ => (),
+        }
+    }
+
+    fn set_mode(&mut self, mode: u16, enable: bool) {
+        match mode {
+            7 => self.autowrap = enable,
+            25 => self.cursor_visible = enable,
+            47 | 1047 => self.set_alternate_screen(enable),
+            1049 => {
+                if enable {
+                    self.save_cursor();
+                    self.set_alternate_screen(true);
+                } else {
+                    self.set_alternate_screen(false);
+                    self.restore_cursor();
+                }
+            }
+            2004 => self.bracketed_paste = enable,
+            _ => (),
+        }
+    }
+
+    fn set_alternate_screen(&mut self, enable: bool) {
+        if enable == self.main_screen.is_some() {
+            return;
+        }
+        if enable {
+            let chars = vec![' '; self.rows * self.stride].into_boxed_slice();
+            let colors = vec![DEFAULT_COLOR; self.rows * self.stride].into_boxed_slice();
+            let chars = core::mem::replace(&mut self.chars, chars);
+            let colors = core::mem::replace(&mut self.colors, colors);
+            self.main_screen = Some((chars, colors));
+            self.view_offset = 0;
+        } else if let Some((chars, colors)) = self.main_screen.take() {
+            self.chars = chars;
+            self.colors = colors;
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved = SavedCursor {
+            cursor: GridCoords {
+                row: self.cursor.row,
+                col: self.cursor.col.min(self.cols.saturating_sub(1)),
+            },
+            attrs: self.attrs,
+        };
+    }
+
+    fn restore_cursor(&mut self) {
+        self.cursor = self.saved.cursor;
+        self.attrs = self.saved.attrs;
+    }
+
+    fn reset(&mut self) {
+        self.set_alternate_screen(false);
+        self.attrs = Attrs::DEFAULT;
+        self.saved = SavedCursor {
+            cursor: GridCoords { row: 0, col: 0 },
+            attrs: Attrs::DEFAULT,
+        };
+        self.scroll_top = 0;
+        self.scroll_bottom = self.rows;
+        self.autowrap = true;
+        self.bracketed_paste = false;
+        self.cursor_visible = true;
+        self.clear_rows(0..self.rows);
+        self.clear_scrollback();
+        self.cursor = GridCoords { row: 0, col: 0 };
+    }
+
+    /// Select graphic rendition: colors and text attributes.
+    fn sgr(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            self.attrs = Attrs::DEFAULT;
+            return;
+        }
+        let mut params = params.iter().copied();
+        while let Some(p) = params.next() {
+            match p {
+                0 => self.attrs = Attrs::DEFAULT,
+                1 => self.attrs.bold = true,
+                7 => self.attrs.inverse = true,
+                22 => self.attrs.bold = false,
+                27 => self.attrs.inverse = false,
+                30..=37 => self.attrs.fg = Color::Indexed(p as u8 - 30),
+                38 => self.attrs.fg = extended_color(&mut params).unwrap_or(self.attrs.fg),
+                39 => self.attrs.fg = Color::Default,
+                40..=47 => self.attrs.bg = Color::Indexed(p as u8 - 40),
+                48 => self.attrs.bg = extended_color(&mut params).unwrap_or(self.attrs.bg),
+                49 => self.attrs.bg = Color::Default,
+                90..=97 => self.attrs.fg = Color::Indexed(p as u8 - 90 + 8),
+                100..=107 => self.attrs.bg = Color::Indexed(p as u8 - 100 + 8),
+                // Other attributes, like underline and italics, can't be
+                // drawn and are ignored
+                _ => (),
             }
         }
-        self.changed = true;
     }
 
     pub fn update(&mut self, grid: GridRef<'_>) {
         if self.changed {
+            let selected = self.selection.map(|s| s.range());
             for row in 0..self.rows.min(grid.rows) {
+                let line = self.view_line(row);
+                let (chars, colors) = self.line(line).unwrap_or((&[], &[]));
                 for col in 0..self.cols.min(grid.cols) {
-                    let src = row * self.stride + col;
                     let dst = row * grid.stride + col;
-                    grid.chars[dst] = self.chars[src];
-                    grid.colors[dst] = self.colors[src];
+                    // History rows from a narrower screen are padded
+                    grid.chars[dst] = chars.get(col).copied().unwrap_or(' ');
+                    grid.colors[dst] = colors.get(col).copied().unwrap_or(DEFAULT_COLOR);
+                    if selected
+                        .as_ref()
+                        .is_some_and(|s| s.contains(&TextPos { line, col }))
+                    {
+                        grid.colors[dst] = Colors {
+                            fg: rgba(0, 0, 0, 255),
+                            bg: rgba(255, 255, 255, 255),
+                        };
+                    }
                 }
             }
         }
     }
 }
 
+/// The color of a `38;5;<index>` or `38;2;<r>;<g>;<b>` SGR parameter,
+/// whose parameters after the 38 (or 48) come from `params`.
+fn extended_color(params: &mut impl Iterator<Item = u16>) -> Option<Color> {
+    match params.next()? {
+        5 => Some(Color::Indexed(params.next()?.min(255) as u8)),
+        2 => {
+            let mut channel = || params.next().map(|c| c.min(255) as u8);
+            let (r, g, b) = (channel()?, channel()?, channel()?);
+            Some(Color::Rgb(rgba(r, g, b, 255)))
+        }
+        _ => None,
+    }
+}
+
 // #[test]
 // fn term_test() {
 //     let width = 1280;
@@ -162,3 +891,214 @@ impl EmulatorState {
 //         // emulator.update(&mut grid, rows - 2, cols - 2, cols + 1, cols);
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use alloc::string::String;
+
+    use super::{palette, EmulatorState, GridCoords, DEFAULT_COLOR};
+    use crate::color::rgba;
+
+    fn row_text(term: &EmulatorState, row: usize) -> String {
+        let start = row * term.stride;
+        term.chars[start..start + term.cols].iter().collect()
+    }
+
+    fn screen(term: &EmulatorState) -> std::vec::Vec<String> {
+        (0..term.rows).map(|r| row_text(term, r)).collect()
+    }
+
+    fn colors_at(term: &EmulatorState, row: usize, col: usize) -> (u32, u32) {
+        let c = term.colors[row * term.stride + col];
+        (c.fg, c.bg)
+    }
+
+    #[test]
+    fn test_plain_text() {
+        let mut term = EmulatorState::new(3, 5);
+        term.input(b"ab\ncd\r\nxyz\x1b[31");
+        term.input(b"m!");
+        assert_eq!(screen(&term), ["ab   ", "cd   ", "xyz! "]);
+        assert_eq!(colors_at(&term, 2, 3).0, palette(1));
+    }
+
+    #[test]
+    fn test_wrap_and_scroll() {
+        let mut term = EmulatorState::new(2, 3);
+        term.input(b"abcdef");
+        // Filling the last column doesn't scroll until the next character
+        assert_eq!(screen(&term), ["abc", "def"]);
+        assert_eq!(term.scrolled_rows, 0);
+        term.input(b"g");
+        assert_eq!(screen(&term), ["def", "g  "]);
+        assert_eq!(term.scrolled_rows, 1);
+
+        term.input(b"\x1b[?7l\rxyzw");
+        assert_eq!(screen(&term), ["def", "xyw"]);
+    }
+
+    #[test]
+    fn test_cursor_movement() {
+        let mut term = EmulatorState::new(4, 6);
+        term.input(b"\x1b[3;4Ha\x1b[2Ab\x1b[10Dc\x1b[Bd\x1b[99;99He\x1b[1;2Hf");
+        assert_eq!(screen(&term), ["cf  b ", " d    ", "   a  ", "     e"]);
+        term.input(b"\x1b[3Gg\x1b[4dh\x1b[Ei");
+        assert_eq!(screen(&term), ["cfg b ", " d    ", "   a  ", "i  h e"]);
+        assert_eq!((term.cursor.row, term.cursor.col), (3, 1));
+    }
+
+    #[test]
+    fn test_erase() {
+        let mut term = EmulatorState::new(3, 4);
+        term.input(b"abcdefghijkl\x1b[2;2H\x1b[K");
+        assert_eq!(screen(&term), ["abcd", "e   ", "ijkl"]);
+        term.input(b"\x1b[1;3H\x1b[1K");
+        assert_eq!(screen(&term), ["   d", "e   ", "ijkl"]);
+        term.input(b"\x1b[3;2H\x1b[1J");
+        assert_eq!(screen(&term), ["    ", "    ", "  kl"]);
+        term.input(b"\x1b[2J");
+        assert_eq!(screen(&term), ["    ", "    ", "    "]);
+    }
+
+    #[test]
+    fn test_insert_delete() {
+        let mut term = EmulatorState::new(4, 5);
+        term.input(b"abcde\x1b[1;2H\x1b[2@");
+        assert_eq!(row_text(&term, 0), "a  bc");
+        term.input(b"\x1b[3P");
+        assert_eq!(row_text(&term, 0), "ac   ");
+        term.input(b"\x1b[1;1H\x1b[3X");
+        assert_eq!(row_text(&term, 0), "     ");
+
+        let mut term = EmulatorState::new(4, 2);
+        term.input(b"1\r\n2\r\n3\r\n4\x1b[2;1H\x1b[L");
+        assert_eq!(screen(&term), ["1 ", "  ", "2 ", "3 "]);
+        term.input(b"\x1b[2M");
+        assert_eq!(screen(&term), ["1 ", "3 ", "  ", "  "]);
+    }
+
+    #[test]
+    fn test_scroll_region() {
+        let mut term = EmulatorState::new(5, 2);
+        term.input(b"1\r\n2\r\n3\r\n4\r\n5\x1b[2;4r");
+        assert_eq!((term.cursor.row, term.cursor.col), (0, 0));
+        term.input(b"\x1b[4;1Hx\ny");
+        assert_eq!(screen(&term), ["1 ", "3 ", "x ", "y ", "5 "]);
+        term.input(b"\x1b[2;1H\x1bM");
+        assert_eq!(screen(&term), ["1 ", "  ", "3 ", "x ", "5 "]);
+        term.input(b"\x1b[2S");
+        assert_eq!(screen(&term), ["1 ", "x ", "  ", "  ", "5 "]);
+        // Scrolling within a region doesn't move what's above the screen
+        assert_eq!(term.scrolled_rows, 0);
+    }
+
+    #[test]
+    fn test_sgr() {
+        let mut term = EmulatorState::new(1, 8);
+        term.input(b"\x1b[1;32ma\x1b[0;97;41mb\x1b[38;5;196mc\x1b[38;2;1;2;3;48;5;240md");
+        term.input(b"\x1b[39;49me\x1b[7mf\x1b[27;22mg\x1b[mh");
+        assert_eq!(colors_at(&term, 0, 0), (palette(10), DEFAULT_COLOR.bg));
+        assert_eq!(colors_at(&term, 0, 1), (palette(15), palette(1)));
+        assert_eq!(colors_at(&term, 0, 2), (rgba(0xff, 0, 0, 255), palette(1)));
+        assert_eq!(colors_at(&term, 0, 3), (rgba(1, 2, 3, 255), palette(240)));
+        assert_eq!(colors_at(&term, 0, 4), (DEFAULT_COLOR.fg, DEFAULT_COLOR.bg));
+        assert_eq!(
+            colors_at(&term, 0, 5),
+            (rgba(0, 0, 0, 255), DEFAULT_COLOR.fg)
+        );
+        assert_eq!(colors_at(&term, 0, 6), (DEFAULT_COLOR.fg, DEFAULT_COLOR.bg));
+        assert_eq!(colors_at(&term, 0, 7), (DEFAULT_COLOR.fg, DEFAULT_COLOR.bg));
+        assert_eq!(palette(232), rgba(8, 8, 8, 255));
+        assert_eq!(palette(16 + 36 + 6 * 2 + 5), rgba(95, 135, 255, 255));
+    }
+
+    #[test]
+    fn test_alternate_screen() {
+        let mut term = EmulatorState::new(2, 3);
+        term.input(b"abc\r\nde");
+        term.input(b"\x1b[?1049h\x1b[Hxy");
+        assert_eq!(screen(&term), ["xy ", "   "]);
+        term.input(b"\x1b[?1049l!");
+        assert_eq!(screen(&term), ["abc", "de!"]);
+    }
+
+    #[test]
+    fn test_modes_and_replies() {
+        let mut term = EmulatorState::new(3, 3);
+        term.input(b"\x1b[?2004h");
+        assert!(term.bracketed_paste);
+        term.input(b"\x1b[2;3H\x1b[6n\x1b[5n");
+        assert_eq!(term.take_replies(), b"\x1b[2;3R\x1b[0n");
+        assert!(term.take_replies().is_empty());
+        term.input(b"\x1b[?2004l\x1b[?25l");
+        assert!(!term.bracketed_paste);
+        assert!(!term.cursor_visible);
+    }
+
+    #[test]
+    fn test_save_restore_cursor() {
+        let mut term = EmulatorState::new(3, 3);
+        term.input(b"\x1b[2;2H\x1b[31m\x1b7\x1b[m\x1b[Ha\x1b8b");
+        assert_eq!(screen(&term), ["a  ", " b ", "   "]);
+        assert_eq!(colors_at(&term, 1, 1).0, palette(1));
+    }
+
+    fn view(term: &EmulatorState) -> std::vec::Vec<String> {
+        (0..term.rows)
+            .map(|r| term.line(term.view_line(r)).unwrap().0.iter().collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_scrollback() {
+        let mut term = EmulatorState::new(2, 3);
+        term.input(b"1\r\n2\r\n3\r\n4");
+        assert_eq!(screen(&term), ["3  ", "4  "]);
+        assert_eq!(term.scrollback.len(), 2);
+
+        term.scroll_view(1);
+        assert_eq!(view(&term), ["2  ", "3  "]);
+        // New output doesn't move the view
+        term.input(b"\r\n5");
+        assert_eq!(view(&term), ["2  ", "3  "]);
+        term.scroll_view(10);
+        assert_eq!(view(&term), ["1  ", "2  "]);
+        term.scroll_to_bottom();
+        assert_eq!(view(&term), ["4  ", "5  "]);
+
+        // Nothing scrolled off the alternate screen is kept
+        term.input(b"\x1b[?1049h\r\n\r\n\r\n");
+        assert_eq!(term.scrollback.len(), 3);
+        term.scroll_view(1);
+        assert_eq!(term.view_offset(), 0);
+        term.input(b"\x1b[?1049l\x1b[3J");
+        assert!(term.scrollback.is_empty());
+    }
+
+    #[test]
+    fn test_selection() {
+        let mut term = EmulatorState::new(2, 4);
+        term.input(b"ab\r\ncd\r\nefgh");
+        term.scroll_view(1);
+        // From the middle of "ab" to the middle of "efgh", across the
+        // history and the screen
+        term.start_selection(GridCoords { row: 0, col: 1 });
+        term.extend_selection(GridCoords { row: 1, col: 0 });
+        term.scroll_to_bottom();
+        term.extend_selection(GridCoords { row: 1, col: 2 });
+        assert_eq!(term.selected_text(), "b\ncd\nef");
+
+        // Selecting backwards works the same
+        term.start_selection(GridCoords { row: 1, col: 3 });
+        term.extend_selection(GridCoords { row: 1, col: 1 });
+        assert_eq!(term.selected_text(), "fg");
+
+        // The selection follows its text as more rows scroll off
+        term.input(b"\r\n");
+        assert_eq!(term.selected_text(), "fg");
+        term.clear_selection();
+        assert_eq!(term.selected_text(), "");
+    }
+}
@@ -0,0 +1,438 @@
+//! Splits a stream of bytes into printable characters, control codes and
+//! escape sequences, following the state machine of a DEC VT500-series
+//! terminal (see https://vt100.net/emu/dec_ansi_parser), minus DCS and
+//! the other string sequences we have no use for.
+//!
+//! The parser keeps its state between calls, so sequences split across
+//! several reads are still recognized.
+
+const ESC: u8 = 0x1b;
+const CAN: u8 = 0x18;
+const SUB: u8 = 0x1a;
+const BEL: u8 = 0x07;
+
+pub const MAX_PARAMS: usize = 16;
+
+/// A control sequence, `ESC [ <private> <params> <intermediate> <action>`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Csi {
+    params: [u16; MAX_PARAMS],
+    len: usize,
+    // Whether there were more parameters than we have room for
+    overflow: bool,
+    /// The marker of a private sequence, one of `<=>?`.
+    pub private: Option<u8>,
+    pub intermediate: Option<u8>,
+    pub action: u8,
+}
+
+impl Csi {
+    const EMPTY: Csi = Csi {
+        params: [0; MAX_PARAMS],
+        len: 0,
+        overflow: false,
+        private: None,
+        intermediate: None,
+        action: 0,
+    };
+
+    pub fn params(&self) -> &[u16] {
+        &self.params[..self.len]
+    }
+
+    /// The `i`th parameter, where a missing or zero parameter means
+    /// `default`, as it does for most sequences.
+    pub fn param(&self, i: usize, default: u16) -> u16 {
+        match self.params().get(i) {
+            Some(0) | None => default,
+            Some(&p) => p,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Print(char),
+    /// A C0 control code, such as `\n` or backspace.
+    Control(u8),
+    Csi(Csi),
+    /// An escape sequence, `ESC <intermediate> <action>`.
+    Esc {
+        intermediate: Option<u8>,
+        action: u8,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    Ground,
+    /// Inside a multi-byte UTF-8 character.
+    Utf8 {
+        remaining: u8,
+        codepoint: u32,
+    },
+    Escape,
+    EscapeIntermediate,
+    CsiEntry,
+    CsiParam,
+    CsiIntermediate,
+    /// A malformed control sequence, dropped up to its final byte.
+    CsiIgnore,
+    /// Operating system commands, such as setting the window title,
+    /// which are skipped.
+    Osc,
+    OscEscape,
+}
+
+pub struct Parser {
+    state: State,
+    csi: Csi,
+    intermediate: Option<u8>,
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Parser {
+            state: State::Ground,
+            csi: Csi::EMPTY,
+            intermediate: None,
+        }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8], mut emit: impl FnMut(Action)) {
+        for &b in bytes {
+            self.advance(b, &mut emit);
+        }
+    }
+
+    fn advance(&mut self, b: u8, emit: &mut impl FnMut(Action)) {
+        if let State::Utf8 {
+            remaining,
+            codepoint,
+        } = self.state
+        {
+            if b & 0xc0 == 0x80 {
+                let codepoint = (codepoint << 6) | (b & 0x3f) as u32;
+                if remaining == 1 {
+                    let c = char::from_u32(codepoint).unwrap_or(char::REPLACEMENT_CHARACTER);
+                    emit(Action::Print(c));
+                    self.state = State::Ground;
+                } else {
+                    self.state = State::Utf8 {
+                        remaining: remaining - 1,
+                        codepoint,
+                    };
+                }
+                return;
+            }
+            // The character was cut short; this byte starts something new
+            emit(Action::Print(char::REPLACEMENT_CHARACTER));
+            self.state = State::Ground;
+        }
+
+        // These apply in the middle of any sequence
+        match b {
+            ESC if self.state == State::Osc => {
+                self.state = State::OscEscape;
+                return;
+            }
+            ESC => {
+                self.intermediate = None;
+                self.state = State::Escape;
+                return;
+            }
+            CAN | SUB => {
+                self.state = State::Ground;
+                return;
+            }
+            BEL if self.state == State::Osc => {
+                self.state = State::Ground;
+                return;
+            }
+            0x00..0x20 if self.state != State::Osc => {
+                emit(Action::Control(b));
+                return;
+            }
+            _ => (),
+        }
+
+        match self.state {
+            State::Ground => self.ground(b, emit),
+            State::Utf8 { .. } => unreachable!(),
+            State::Escape => match b {
+                b'[' => {
+                    self.csi = Csi::EMPTY;
+                    self.state = State::CsiEntry;
+                }
+                b']' => self.state = State::Osc,
+                0x20..0x30 => {
+                    self.intermediate = Some(b);
+                    self.state = State::EscapeIntermediate;
+                }
+                0x7f => (),
+                _ => self.esc_dispatch(b, emit),
+            },
+            State::EscapeIntermediate => match b {
+                0x20..0x30 => self.intermediate = Some(b),
+                0x7f => (),
+                _ => self.esc_dispatch(b, emit),
+            },
+            State::CsiEntry => match b {
+                b'<'..=b'?' => {
+                    self.csi.private = Some(b);
+                    self.state = State::CsiParam;
+                }
+                _ => {
+                    self.state = State::CsiParam;
+                    self.csi_param(b, emit);
+                }
+            },
+            State::CsiParam => self.csi_param(b, emit),
+            State::CsiIntermediate => match b {
+                0x20..0x30 => self.csi.intermediate = Some(b),
+                0x30..0x40 => self.state = State::CsiIgnore,
+                _ => self.csi_dispatch(b, emit),
+            },
+            State::CsiIgnore => {
+                if let 0x40..0x7f = b {
+                    self.state = State::Ground;
+                }
+            }
+            State::Osc => (),
+            State::OscEscape => {
+                // Either the string terminator `ESC \`, or the start of
+                // another sequence
+                self.state = State::Escape;
+                if b != b'\\' {
+                    self.advance(b, emit);
+                } else {
+                    self.state = State::Ground;
+                }
+            }
+        }
+    }
+
+    fn ground(&mut self, b: u8, emit: &mut impl FnMut(Action)) {
+        let (remaining, codepoint) = match b {
+            0x00..0x80 => {
+                if b != 0x7f {
+                    emit(Action::Print(b as char));
+                }
+                return;
+            }
+            0xc2..0xe0 => (1, b & 0x1f),
+            0xe0..0xf0 => (2, b & 0x0f),
+            0xf0..0xf5 => (3, b & 0x07),
+            _ => {
+                emit(Action::Print(char::REPLACEMENT_CHARACTER));
+                return;
+            }
+        };
+        self.state = State::Utf8 {
+            remaining,
+            codepoint: codepoint as u32,
+        };
+    }
+
+    fn csi_param(&mut self, b: u8, emit: &mut impl FnMut(Action)) {
+        let csi = &mut self.csi;
+        match b {
+            b'0'..=b'9' => {
+                if csi.len == 0 {
+                    csi.len = 1;
+                }
+                if csi.overflow {
+                    return;
+                }
+                if let Some(p) = csi.params.get_mut(csi.len - 1) {
+                    *p = p.saturating_mul(10).saturating_add((b - b'0') as u16);
+                }
+            }
+            // Sub-parameters are treated like parameters
+            b';' | b':' => {
+                if csi.len == 0 {
+                    csi.len = 1;
+                }
+                // Anything past the last parameter we have room for is dropped
+                if csi.len == MAX_PARAMS {
+                    csi.overflow = true;
+                } else {
+                    csi.len += 1;
+                }
+            }
+            b'<'..=b'?' => self.state = State::CsiIgnore,
+            0x20..0x30 => {
+                csi.intermediate = Some(b);
+                self.state = State::CsiIntermediate;
+            }
+            _ => self.csi_dispatch(b, emit),
+        }
+    }
+
+    fn csi_dispatch(&mut self, b: u8, emit: &mut impl FnMut(Action)) {
+        self.state = State::Ground;
+        if b != 0x7f {
+            self.csi.action = b;
+            emit(Action::Csi(self.csi));
+        }
+    }
+
+    fn esc_dispatch(&mut self, b: u8, emit: &mut impl FnMut(Action)) {
+        self.state = State::Ground;
+        emit(Action::Esc {
+            intermediate: self.intermediate,
+            action: b,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use alloc::vec::Vec;
+
+    use super::{Action, Csi, Parser};
+
+    fn parse(parser: &mut Parser, bytes: &[u8]) -> Vec<Action> {
+        let mut actions = Vec::new();
+        parser.feed(bytes, |a| actions.push(a));
+        actions
+    }
+
+    fn csi(bytes: &[u8]) -> Csi {
+        match parse(&mut Parser::new(), bytes)[..] {
+            [Action::Csi(csi)] => csi,
+            ref other => panic!("expected one CSI, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_print() {
+        let actions = parse(&mut Parser::new(), "a\u{e9}\u{2500}\r\n".as_bytes());
+        assert_eq!(
+            actions,
+            [
+                Action::Print('a'),
+                Action::Print('\u{e9}'),
+                Action::Print('\u{2500}'),
+                Action::Control(b'\r'),
+                Action::Control(b'\n'),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let actions = parse(&mut Parser::new(), b"\xe2\x94a\xff");
+        assert_eq!(
+            actions,
+            [
+                Action::Print(char::REPLACEMENT_CHARACTER),
+                Action::Print('a'),
+                Action::Print(char::REPLACEMENT_CHARACTER),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_csi_params() {
+        let seq = csi(b"\x1b[12;34H");
+        assert_eq!(seq.params(), [12, 34]);
+        assert_eq!(seq.action, b'H');
+        assert_eq!(seq.private, None);
+
+        let seq = csi(b"\x1b[;5H");
+        assert_eq!(seq.params(), [0, 5]);
+        assert_eq!(seq.param(0, 1), 1);
+        assert_eq!(seq.param(1, 1), 5);
+        assert_eq!(seq.param(2, 1), 1);
+
+        let seq = csi(b"\x1b[m");
+        assert_eq!(seq.params(), []);
+        assert_eq!(seq.action, b'm');
+
+        let seq = csi(b"\x1b[?1049h");
+        assert_eq!(seq.private, Some(b'?'));
+        assert_eq!(seq.params(), [1049]);
+        assert_eq!(seq.action, b'h');
+
+        let seq = csi(b"\x1b[38;2;255;128;0m");
+        assert_eq!(seq.params(), [38, 2, 255, 128, 0]);
+
+        let seq = csi(b"\x1b[99999A");
+        assert_eq!(seq.params(), [u16::MAX]);
+
+        let seq = csi(b"\x1b[1;2;3;4;5;6;7;8;9;10;11;12;13;14;15;16;17;18m");
+        assert_eq!(
+            seq.params(),
+            [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]
+        );
+    }
+
+    #[test]
+    fn test_split_sequence() {
+        let mut parser = Parser::new();
+        assert_eq!(parse(&mut parser, b"x\x1b["), [Action::Print('x')]);
+        assert_eq!(parse(&mut parser, b"3"), []);
+        assert_eq!(
+            parse(&mut parser, b"1m\xe2\x94"),
+            [Action::Csi(csi(b"\x1b[31m"))]
+        );
+        assert_eq!(parse(&mut parser, b"\x80"), [Action::Print('\u{2500}')]);
+    }
+
+    #[test]
+    fn test_control_inside_csi() {
+        let actions = parse(&mut Parser::new(), b"\x1b[1\n2A");
+        assert_eq!(actions[0], Action::Control(b'\n'));
+        let Action::Csi(seq) = actions[1] else {
+            panic!("expected a CSI, got {:?}", actions[1]);
+        };
+        assert_eq!(seq.params(), [12]);
+        assert_eq!(seq.action, b'A');
+    }
+
+    #[test]
+    fn test_esc() {
+        let actions = parse(&mut Parser::new(), b"\x1b7\x1b(B\x1bM");
+        assert_eq!(
+            actions,
+            [
+                Action::Esc {
+                    intermediate: None,
+                    action: b'7'
+                },
+                Action::Esc {
+                    intermediate: Some(b'('),
+                    action: b'B'
+                },
+                Action::Esc {
+                    intermediate: None,
+                    action: b'M'
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_osc_skipped() {
+        let mut parser = Parser::new();
+        let actions = parse(&mut parser, b"\x1b]0;title\x07a\x1b]2;other\x1b\\b");
+        assert_eq!(actions, [Action::Print('a'), Action::Print('b')]);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let actions = parse(&mut Parser::new(), b"\x1b[12\x18a\x1b[1\x1b[2J");
+        assert_eq!(actions[0], Action::Print('a'));
+        assert_eq!(actions[1], Action::Csi(csi(b"\x1b[2J")));
+        assert_eq!(actions.len(), 2);
+    }
+
+    #[test]
+    fn test_malformed_csi_ignored() {
+        let actions = parse(&mut Parser::new(), b"\x1b[1?2ha");
+        assert_eq!(actions, [Action::Print('a')]);
+    }
+}
@@ -3,14 +3,31 @@
 
 pub extern crate display_proto as proto;
 
+extern crate alloc;
+
 #[allow(unused_imports)]
 #[macro_use]
 extern crate ulib;
 
-use proto::BufferHandle;
-use ulib::sys::{mmap, recv, send};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use proto::{BufferHandle, ClipboardEvent};
+use ulib::sys::{mmap, recv, recv_nonblock, send, send_nonblock};
+
+// Ids for clipboard contents sent by this client
+static CLIPBOARD_ID: AtomicU32 = AtomicU32::new(0);
 
 pub fn connect(width: u16, height: u16) -> BufferHandle {
+    connect_resizable((width, height), (width, height), (width, height))
+}
+
+/// Connect with a window that the user can resize between `min` and
+/// `max`; the client must then handle `RESIZE` events.
+pub fn connect_resizable(
+    (width, height): (u16, u16),
+    (min_width, min_height): (u16, u16),
+    (max_width, max_height): (u16, u16),
+) -> BufferHandle {
     let server_socket = 12;
     let message = ulib::sys::Message {
         tag: 0x101,
@@ -20,10 +37,10 @@ pub fn connect(width: u16, height: u16) -> BufferHandle {
     let buffer = proto::ConnRequest {
         width,
         height,
-        min_width: width,
-        min_height: height,
-        max_width: width,
-        max_height: height,
+        min_width,
+        min_height,
+        max_width,
+        max_height,
     };
 
     send(server_socket, &message, bytemuck::bytes_of(&buffer), 0);
@@ -41,6 +58,77 @@ pub fn connect(width: u16, height: u16) -> BufferHandle {
     handle
 }
 
+/// Wake the server to handle the events sent since the last call.
+pub fn notify_server(buf: &BufferHandle) {
+    ulib::sys::notify_signal(buf.get_sem_fd(buf.server_notify)).ok();
+}
+
+/// Present a frame (see [`BufferHandle::present`]), and wait until the
+/// server has copied it out of the video buffer.
+pub fn present(buf: &mut BufferHandle, damage: Option<&[proto::DamageRect]>) {
+    buf.present(damage);
+    notify_server(buf);
+    ulib::sys::sem_down(buf.get_sem_fd(buf.present_sem)).unwrap();
+}
+
+/// Replace the clipboard with `data`, of the given MIME type.  Returns
+/// false if it couldn't be sent, or is longer than
+/// [`proto::MAX_CLIPBOARD`].
+pub fn set_clipboard(buf: &mut BufferHandle, mime: &str, data: &[u8]) -> bool {
+    use proto::ClipboardSetEvent;
+
+    if data.len() > proto::MAX_CLIPBOARD {
+        return false;
+    }
+    let id = CLIPBOARD_ID.fetch_add(1, Ordering::Relaxed);
+    let Some(event) = ClipboardEvent::new(mime, id, data.len() as u32) else {
+        return false;
+    };
+    let message = ulib::sys::Message {
+        tag: proto::clipboard_tag(id),
+        objects: [u32::MAX; 4],
+    };
+    if send_nonblock(buf.get_sem_fd(buf.transfer_channel), &message, data) < 0 {
+        return false;
+    }
+    let sent = buf
+        .client_to_server_queue()
+        .try_send_data(ClipboardSetEvent(event))
+        .is_ok();
+    notify_server(buf);
+    sent
+}
+
+/// Ask for the clipboard contents, if they match the MIME type `accept`
+/// (see [`proto::mime_matches`]).  The server replies with a
+/// `CLIPBOARD_DATA` event, to be passed to [`receive_clipboard`].
+/// Returns false if the request couldn't be sent.
+pub fn request_clipboard(buf: &mut BufferHandle, accept: &str) -> bool {
+    use proto::ClipboardGetEvent;
+
+    let Some(event) = ClipboardEvent::new(accept, 0, 0) else {
+        return false;
+    };
+    let sent = buf
+        .client_to_server_queue()
+        .try_send_data(ClipboardGetEvent(event))
+        .is_ok();
+    notify_server(buf);
+    sent
+}
+
+/// Get the contents sent along with a `CLIPBOARD_DATA` event, or `None`
+/// if the clipboard was empty or of the wrong type.
+pub fn receive_clipboard(buf: &BufferHandle, event: &ClipboardEvent) -> Option<Vec<u8>> {
+    if event.is_empty() {
+        return None;
+    }
+    let transfer = buf.get_sem_fd(buf.transfer_channel);
+    proto::recv_clipboard(event, |data| {
+        recv_nonblock(transfer, data).map(|(len, message)| (len, message.tag))
+    })
+}
+
 pub unsafe fn disconnect(_buf: *mut proto::BufferHeader) {
     // TODO: disconnect?
 }
@@ -1,11 +1,13 @@
 #![no_std]
 
+extern crate alloc;
 extern crate core;
 
 mod local;
 
 pub use local::BufferHandle;
 
+use alloc::vec::Vec;
 use core::mem::MaybeUninit;
 use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
 
@@ -48,6 +50,12 @@ pub struct BufferHeader {
     pub term_meta: TermMeta,
 
     pub present_sem: SemDescriptor,
+    /// Raised by the client after sending events to the server, so the
+    /// server can sleep while nothing is happening.
+    pub server_notify: SemDescriptor,
+    /// A channel for data that doesn't fit in events, such as clipboard
+    /// contents.
+    pub transfer_channel: SemDescriptor,
 }
 
 #[repr(C)]
@@ -59,6 +67,7 @@ pub struct GlobalMeta {
 }
 
 #[repr(C)]
+#[derive(Copy, Clone)]
 pub struct VideoMeta {
     pub width: u16,
     pub height: u16,
@@ -70,6 +79,7 @@ pub struct VideoMeta {
 }
 
 #[repr(C)]
+#[derive(Copy, Clone)]
 pub struct TermMeta {
     pub rows: u16,
     pub cols: u16,
@@ -219,11 +229,134 @@ impl EventKind {
     pub const DISCONNECT: EventKind = EventKind(3);
     pub const TITLE: EventKind = EventKind(4);
     pub const REQUEST_CLOSE: EventKind = EventKind(5);
+    pub const RESIZE: EventKind = EventKind(6);
+    pub const CLIPBOARD_SET: EventKind = EventKind(7);
+    pub const CLIPBOARD_GET: EventKind = EventKind(8);
+    pub const CLIPBOARD_DATA: EventKind = EventKind(9);
+}
+
+/// Message tag for clipboard contents on the transfer channel.  The
+/// upper 32 bits hold the id of the event that refers to them (see
+/// [`clipboard_tag`]).
+pub const TRANSFER_CLIPBOARD: u64 = 0x200;
+
+/// The largest clipboard contents that can be sent, in bytes.
+pub const MAX_CLIPBOARD: usize = 1 << 20;
+
+/// The transfer channel tag for the clipboard contents of the event
+/// with the given id.
+pub fn clipboard_tag(id: u32) -> u64 {
+    TRANSFER_CLIPBOARD | (id as u64) << 32
+}
+
+/// Receive the clipboard contents for `event` from a transfer channel,
+/// discarding any left behind by earlier events that were never sent.
+/// `recv` receives one message without blocking, returning its length
+/// and tag.  Returns `None` if they're missing, of the wrong length, or
+/// longer than [`MAX_CLIPBOARD`].
+pub fn recv_clipboard<E>(
+    event: &ClipboardEvent,
+    mut recv: impl FnMut(&mut [u8]) -> Result<(usize, u64), E>,
+) -> Option<Vec<u8>> {
+    if event.len as usize > MAX_CLIPBOARD {
+        return None;
+    }
+    let mut data = alloc::vec![0; event.len as usize];
+    loop {
+        let (len, tag) = recv(&mut data).ok()?;
+        if tag == clipboard_tag(event.id) {
+            return (len == data.len()).then_some(data);
+        }
+    }
+}
+
+/// A rectangle of a window's video buffer, in pixels.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct DamageRect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl DamageRect {
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// The smallest rectangle containing both rectangles.
+    pub fn union(&self, other: &DamageRect) -> DamageRect {
+        if self.is_empty() {
+            return *other;
+        } else if other.is_empty() {
+            return *self;
+        }
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x.saturating_add(self.width)).max(other.x.saturating_add(other.width));
+        let bottom = (self.y.saturating_add(self.height)).max(other.y.saturating_add(other.height));
+        DamageRect {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        }
+    }
 }
 
+/// Sent by the client once a new frame is ready in the video buffer.
+///
+/// Unless `FLAG_PARTIAL` is set, the whole frame is treated as changed
+/// (which is what an all-zero event means); otherwise only the first
+/// `damage_count` rectangles have to be redrawn, and a partial present
+/// without any rectangles just waits for the server to acknowledge.
 #[derive(Copy, Clone)]
 #[repr(C)]
-pub struct PresentEvent;
+pub struct PresentEvent {
+    pub flags: u32,
+    pub damage_count: u32,
+    pub damage: [DamageRect; PresentEvent::MAX_DAMAGE],
+}
+
+impl PresentEvent {
+    pub const MAX_DAMAGE: usize = 6;
+    pub const FLAG_PARTIAL: u32 = 1 << 0;
+
+    pub fn full() -> Self {
+        PresentEvent {
+            flags: 0,
+            damage_count: 0,
+            damage: [DamageRect::default(); Self::MAX_DAMAGE],
+        }
+    }
+
+    /// A present of only the given regions; if there are too many, they
+    /// are merged into one.
+    pub fn partial(damage: &[DamageRect]) -> Self {
+        let mut event = PresentEvent {
+            flags: Self::FLAG_PARTIAL,
+            ..Self::full()
+        };
+        if damage.len() <= Self::MAX_DAMAGE {
+            event.damage[..damage.len()].copy_from_slice(damage);
+            event.damage_count = damage.len() as u32;
+        } else {
+            event.damage[0] = damage.iter().fold(DamageRect::default(), |a, b| a.union(b));
+            event.damage_count = 1;
+        }
+        event
+    }
+
+    /// Get the damaged regions, or `None` if the whole frame changed.
+    pub fn damage(&self) -> Option<&[DamageRect]> {
+        if self.flags & Self::FLAG_PARTIAL == 0 {
+            return None;
+        }
+        let count = (self.damage_count as usize).min(Self::MAX_DAMAGE);
+        Some(&self.damage[..count])
+    }
+}
 
 unsafe impl bytemuck::Zeroable for PresentEvent {}
 unsafe impl bytemuck::AnyBitPattern for PresentEvent {}
@@ -233,10 +366,13 @@ impl EventData for PresentEvent {
     fn parse_data(data: &[u64; 7]) -> Option<Self> {
         const _ASSERT: () = assert!(size_of::<PresentEvent>() <= size_of::<[u64; 7]>());
         let bytes = &bytemuck::bytes_of(data)[..size_of::<Self>()];
-        Some(*bytemuck::from_bytes(bytes))
+        Some(bytemuck::pod_read_unaligned(bytes))
     }
     fn serialize_data(&self) -> [u64; 7] {
-        [0; 7]
+        let mut out = [0u64; 7];
+        let data: [u8; size_of::<Self>()] = unsafe { core::mem::transmute(*self) };
+        bytemuck::cast_slice_mut(&mut out)[..size_of::<Self>()].copy_from_slice(&data);
+        out
     }
 }
 
@@ -323,6 +459,152 @@ impl EventData for RequestCloseEvent {
     }
 }
 
+/// Sent by the server to ask the client to switch to a new window
+/// size, and echoed back by the client once it has done so.
+///
+/// The video buffer is allocated for the largest size the client asked
+/// for when connecting, so only `VideoMeta` changes: after the client
+/// acknowledges, both sides use the new width and height (with a row
+/// stride of `width * bytes_per_pixel`) starting from the next present.
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub struct ResizeEvent {
+    pub width: u16,
+    pub height: u16,
+}
+
+unsafe impl bytemuck::Zeroable for ResizeEvent {}
+unsafe impl bytemuck::AnyBitPattern for ResizeEvent {}
+impl EventData for ResizeEvent {
+    const KIND: EventKind = EventKind::RESIZE;
+    fn parse_data(data: &[u64; 7]) -> Option<Self> {
+        const _ASSERT: () = assert!(size_of::<ResizeEvent>() <= size_of::<[u64; 7]>());
+        let bytes = &bytemuck::bytes_of(data)[..size_of::<Self>()];
+        Some(*bytemuck::from_bytes(bytes))
+    }
+    fn serialize_data(&self) -> [u64; 7] {
+        let mut out = [0u64; 7];
+        let data: [u8; size_of::<Self>()] = unsafe { core::mem::transmute(*self) };
+        bytemuck::cast_slice_mut(&mut out)[..size_of::<Self>()].copy_from_slice(&data);
+        out
+    }
+}
+
+/// Clipboard requests and replies, tagged with a MIME type such as
+/// `text/plain;charset=utf-8`.  The contents themselves are sent as a
+/// `TRANSFER_CLIPBOARD` message on the transfer channel, just before
+/// the event that refers to them, and tagged with the event's `id`.  If
+/// the event then can't be queued, the contents are left behind on the
+/// channel; receivers discard messages with a different id.
+///
+/// - `CLIPBOARD_SET` (client to server): replace the clipboard with
+///   `len` bytes of contents of type `mime`, at most [`MAX_CLIPBOARD`].
+/// - `CLIPBOARD_GET` (client to server): ask for the clipboard, if its
+///   type matches `mime` (see [`mime_matches`]); `len` is unused.
+/// - `CLIPBOARD_DATA` (server to client): the reply to a get, with the
+///   actual type of the contents; an empty type means the clipboard
+///   was empty or didn't match, and no contents were sent.
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct ClipboardEvent {
+    pub len: u32,
+    pub id: u32,
+    pub mime_len: u8,
+    pub mime: [u8; 47],
+}
+
+unsafe impl bytemuck::Zeroable for ClipboardEvent {}
+unsafe impl bytemuck::AnyBitPattern for ClipboardEvent {}
+
+impl ClipboardEvent {
+    pub const MAX_MIME_LEN: usize = 47;
+
+    /// Returns `None` if `mime` is too long.
+    pub fn new(mime: &str, id: u32, len: u32) -> Option<Self> {
+        let mut event = ClipboardEvent {
+            len,
+            id,
+            mime_len: mime.len() as u8,
+            mime: [0; Self::MAX_MIME_LEN],
+        };
+        event
+            .mime
+            .get_mut(..mime.len())?
+            .copy_from_slice(mime.as_bytes());
+        Some(event)
+    }
+
+    pub fn empty() -> Self {
+        ClipboardEvent {
+            len: 0,
+            id: 0,
+            mime_len: 0,
+            mime: [0; Self::MAX_MIME_LEN],
+        }
+    }
+
+    pub fn mime(&self) -> &str {
+        let len = (self.mime_len as usize).min(Self::MAX_MIME_LEN);
+        core::str::from_utf8(&self.mime[..len]).unwrap_or("")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mime_len == 0
+    }
+}
+
+pub struct ClipboardSetEvent(pub ClipboardEvent);
+pub struct ClipboardGetEvent(pub ClipboardEvent);
+pub struct ClipboardDataEvent(pub ClipboardEvent);
+
+macro_rules! impl_clipboard_event {
+    ($($name:ident => $kind:ident),* $(,)?) => {
+        $(impl EventData for $name {
+            const KIND: EventKind = EventKind::$kind;
+            fn parse_data(data: &[u64; 7]) -> Option<Self> {
+                const _ASSERT: () =
+                    assert!(size_of::<ClipboardEvent>() <= size_of::<[u64; 7]>());
+                let bytes = &bytemuck::bytes_of(data)[..size_of::<ClipboardEvent>()];
+                Some($name(bytemuck::pod_read_unaligned(bytes)))
+            }
+            fn serialize_data(&self) -> [u64; 7] {
+                let mut out = [0u64; 7];
+                let data: [u8; size_of::<ClipboardEvent>()] =
+                    unsafe { core::mem::transmute(self.0) };
+                bytemuck::cast_slice_mut(&mut out)[..size_of::<ClipboardEvent>()]
+                    .copy_from_slice(&data);
+                out
+            }
+        })*
+    };
+}
+
+impl_clipboard_event! {
+    ClipboardSetEvent => CLIPBOARD_SET,
+    ClipboardGetEvent => CLIPBOARD_GET,
+    ClipboardDataEvent => CLIPBOARD_DATA,
+}
+
+/// Whether contents of type `mime` are acceptable for a request of
+/// type `accept`.  Parameters (after `;`) are ignored, and `accept`
+/// can be empty or `*/*` to match anything, or end in `/*` to match a
+/// whole category such as `text/*`.
+pub fn mime_matches(accept: &str, mime: &str) -> bool {
+    fn essence(mime: &str) -> &str {
+        mime.split(';').next().unwrap_or("").trim()
+    }
+    let (accept, mime) = (essence(accept), essence(mime));
+    if accept.is_empty() || accept == "*/*" {
+        return true;
+    }
+    match accept.strip_suffix("/*") {
+        Some(category) => mime
+            .split_once('/')
+            .is_some_and(|(c, _)| c.eq_ignore_ascii_case(category)),
+        None => accept.eq_ignore_ascii_case(mime),
+    }
+}
+
 #[derive(PartialEq, Eq, Copy, Clone)]
 pub struct ScanCode(pub u32);
 
@@ -1,10 +1,15 @@
-use super::{BufferHeader, EventQueue, GlobalMeta, SemDescriptor, VideoMeta};
+use super::{
+    BufferHeader, DamageRect, EventQueue, GlobalMeta, PresentEvent, ResizeEvent, SemDescriptor,
+    TermMeta, VideoMeta,
+};
 
 pub struct BufferHandle {
     buf: *mut BufferHeader,
     pub global_meta: GlobalMeta,
     pub video_meta: VideoMeta,
     pub present_sem: SemDescriptor,
+    pub server_notify: SemDescriptor,
+    pub transfer_channel: SemDescriptor,
     pub fds: [u32; 8],
 }
 
@@ -16,6 +21,8 @@ impl BufferHandle {
         let global_meta = unsafe { (&raw const (*buf).meta).read_volatile() };
         let video_meta = unsafe { (&raw const (*buf).video_meta).read_volatile() };
         let present_sem = unsafe { (&raw const (*buf).present_sem).read_volatile() };
+        let server_notify = unsafe { (&raw const (*buf).server_notify).read_volatile() };
+        let transfer_channel = unsafe { (&raw const (*buf).transfer_channel).read_volatile() };
 
         // TODO: ensure this wasn't changed before connection started...
         // TODO: track actual size of the buffer (don't trust values from the buffer itself)
@@ -29,6 +36,8 @@ impl BufferHandle {
             global_meta,
             video_meta,
             present_sem,
+            server_notify,
+            transfer_channel,
             fds: local_fds,
         }
     }
@@ -71,10 +80,58 @@ impl BufferHandle {
         unsafe { core::slice::from_raw_parts_mut(ptr, len_u128) }
     }
 
+    /// Whether the video buffer can hold a frame of the given size.
+    pub fn fits(&self, width: u16, height: u16) -> bool {
+        let bytes = width as usize * height as usize * self.video_meta.bytes_per_pixel as usize;
+        bytes <= self.global_meta.vmem_size as usize
+    }
+
+    /// Switch the local view of the video buffer to a new size, and
+    /// publish it in the shared header.
+    pub fn set_video_size(&mut self, width: u16, height: u16) {
+        assert!(self.fits(width, height));
+        self.video_meta.width = width;
+        self.video_meta.height = height;
+        self.video_meta.row_stride = width * self.video_meta.bytes_per_pixel as u16;
+        unsafe { (&raw mut (*self.buf).video_meta).write_volatile(self.video_meta) };
+    }
+
+    /// Acknowledge a resize requested by the server, and apply it.
+    /// Returns false (and leaves the size unchanged) if the buffer is
+    /// too small for the requested size or the acknowledgement can't
+    /// be sent.
+    pub fn ack_resize(&mut self, resize: ResizeEvent) -> bool {
+        if !self.fits(resize.width, resize.height) {
+            return false;
+        }
+        if self.client_to_server_queue().try_send_data(resize).is_err() {
+            return false;
+        }
+        self.set_video_size(resize.width, resize.height);
+        true
+    }
+
+    /// Publish the size of the terminal shown in this window.
+    pub fn set_term_meta(&mut self, rows: u16, cols: u16) {
+        let meta = TermMeta { rows, cols };
+        unsafe { (&raw mut (*self.buf).term_meta).write_volatile(meta) };
+    }
+
     pub fn get_sem_fd(&self, sem: SemDescriptor) -> u32 {
         self.fds[sem.0 as usize]
     }
 
+    /// Tell the server that a new frame is ready; `damage` lists the
+    /// regions that changed since the last present, or `None` if the
+    /// whole frame should be redrawn.
+    pub fn present(&mut self, damage: Option<&[DamageRect]>) {
+        let event = match damage {
+            Some(damage) => PresentEvent::partial(damage),
+            None => PresentEvent::full(),
+        };
+        self.client_to_server_queue().try_send_data(event).ok();
+    }
+
     pub fn set_title(&mut self, title: &[u8]) {
         let truncated_len = title.len().min(7 * 8 - 1);
         let mut title_event = super::TitleEvent {
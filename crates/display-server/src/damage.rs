@@ -0,0 +1,150 @@
+use alloc::vec::Vec;
+
+/// A rectangle of the screen, in pixels.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    fn right(&self) -> usize {
+        self.x + self.width
+    }
+
+    fn bottom(&self) -> usize {
+        self.y + self.height
+    }
+
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        Rect::new(x, y, right.saturating_sub(x), bottom.saturating_sub(y))
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        !self.intersect(other).is_empty()
+    }
+
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        } else if other.is_empty() {
+            return *self;
+        }
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = self.right().max(other.right());
+        let bottom = self.bottom().max(other.bottom());
+        Rect::new(x, y, right - x, bottom - y)
+    }
+}
+
+/// Regions of the screen that have to be recomposited.
+pub struct Damage {
+    screen: Rect,
+    rects: Vec<Rect>,
+}
+
+impl Damage {
+    /// Past this many separate regions, they're merged into one.
+    const MAX_RECTS: usize = 16;
+
+    pub fn new(width: usize, height: usize) -> Self {
+        Damage {
+            screen: Rect::new(0, 0, width, height),
+            rects: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, rect: Rect) {
+        let mut rect = rect.intersect(&self.screen);
+        if rect.is_empty() {
+            return;
+        }
+        // Overlapping regions are merged, so that nothing is drawn twice
+        while let Some(i) = self.rects.iter().position(|r| r.intersects(&rect)) {
+            rect = rect.union(&self.rects.swap_remove(i));
+        }
+        self.rects.push(rect);
+
+        if self.rects.len() > Self::MAX_RECTS {
+            let bounds = self
+                .rects
+                .drain(..)
+                .fold(Rect::default(), |a, b| a.union(&b));
+            self.rects.push(bounds);
+        }
+    }
+
+    pub fn add_all(&mut self) {
+        self.add(self.screen);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects
+    }
+
+    pub fn clear(&mut self) {
+        self.rects.clear();
+    }
+}
+
+/// Copy the part of `src`, placed at (`x`, `y`) in `dst`, that lies
+/// within `clip`, optionally blending it with what's already there.
+#[allow(clippy::too_many_arguments)]
+pub fn blit_clipped(
+    dst: &mut [u32],
+    dst_stride: usize,
+    clip: Rect,
+    (x, y): (usize, usize),
+    src: &[u32],
+    src_w: usize,
+    src_h: usize,
+    src_stride: usize,
+    blend: bool,
+) {
+    let area = Rect::new(x, y, src_w, src_h).intersect(&clip);
+    if area.is_empty() {
+        return;
+    }
+    let src = &src[(area.y - y) * src_stride + (area.x - x)..];
+    let blit = if blend {
+        gfx::blit_buffer_blend
+    } else {
+        gfx::blit_buffer
+    };
+    blit(
+        dst,
+        area.right(),
+        area.bottom(),
+        dst_stride,
+        area.x,
+        area.y,
+        src,
+        area.width,
+        area.height,
+        src_stride,
+    );
+}
@@ -1,3 +1,5 @@
+use crate::damage::Rect;
+
 pub struct Framebuffer {
     #[allow(unused)]
     pub fd: usize,
@@ -7,8 +9,24 @@ pub struct Framebuffer {
     pub data: &'static mut [u128],
 }
 
-pub fn present(fb: &mut Framebuffer, buf: &[u128]) {
-    proto::memcpy128(&mut fb.data, &buf);
+/// Copy the given regions of `buf` to the screen.
+pub fn present_rects(fb: &mut Framebuffer, buf: &[u128], rects: &[Rect]) {
+    if let [rect] = rects {
+        if rect.width == fb.width && rect.height == fb.height {
+            proto::memcpy128(fb.data, buf);
+            core::hint::black_box(&mut *fb);
+            return;
+        }
+    }
+
+    let src = bytemuck::cast_slice::<_, u32>(buf);
+    let dst = bytemuck::cast_slice_mut::<_, u32>(fb.data);
+    for rect in rects {
+        for r in rect.y..rect.y + rect.height {
+            let row = r * fb.stride + rect.x;
+            dst[row..][..rect.width].copy_from_slice(&src[row..][..rect.width]);
+        }
+    }
     // Force writes to go through
     core::hint::black_box(&mut *fb);
 }
@@ -11,10 +11,13 @@ use alloc::string::String;
 use alloc::vec::Vec;
 use proto::BufferHandle;
 use thunderdome::{Arena, Index};
-use ulib::sys::{dup3, mmap, recv_nonblock, send, FileDesc};
+use ulib::sys::{dup3, mmap, recv_nonblock, send, send_nonblock, FileDesc};
 
+mod damage;
 mod framebuffer;
 
+use damage::{Damage, Rect};
+
 struct BufferInfo {
     fd: u32,
     present_sem_fd: u32,
@@ -24,8 +27,13 @@ struct BufferInfo {
 
 struct Client {
     handle: BufferHandle,
+    window: Index,
     title: String,
+    /// Whether the client has presented a frame yet.
     present_ready: bool,
+    /// Whether a present is waiting to be acknowledged, once it has
+    /// been composited.
+    present_pending: bool,
 }
 
 #[no_mangle]
@@ -39,6 +47,7 @@ fn handle_incoming(
     _msg: ulib::sys::Message,
     buf: &[u8],
     resp_socket: FileDesc,
+    notify: FileDesc,
     manager: &mut WindowManager,
 ) -> (Index, Client) {
     // TODO: proper listen + connect sockets
@@ -50,18 +59,19 @@ fn handle_incoming(
     let max_size = U16Vec2::new(buf.max_width, buf.max_height);
     let pref_size = U16Vec2::new(buf.width, buf.height);
     let (window, size) = manager.request_window(min_size, max_size, pref_size);
-    let (width, height) = (size.x, size.y);
+    let capacity = manager.windows[window].max_size;
 
-    let buffer = init_buffer(width as usize, height as usize);
+    let buffer = init_buffer(size, capacity);
 
-    let fds = [buffer.fd, buffer.present_sem_fd];
+    let (transfer, client_transfer) = ulib::sys::channel();
+    let fds = [buffer.fd, buffer.present_sem_fd, notify, transfer];
     let handle = unsafe { proto::BufferHandle::new(buffer.mapped, &fds) };
 
     let objects = [
         dup3(fds[0], u32::MAX, 0).unwrap(),
         dup3(fds[1], u32::MAX, 0).unwrap(),
-        u32::MAX,
-        u32::MAX,
+        dup3(fds[2], u32::MAX, 0).unwrap(),
+        client_transfer,
     ];
 
     let buf = u64::to_le_bytes(buffer.size as u64);
@@ -77,15 +87,20 @@ fn handle_incoming(
 
     let client = Client {
         handle,
+        window,
         present_ready: false,
+        present_pending: false,
         title: String::new(),
     };
     (window, client)
 }
 
-fn init_buffer(width: usize, height: usize) -> BufferInfo {
+/// Create the shared buffer for a client, with video memory for frames
+/// of up to `capacity` pixels, initially of the given size.
+fn init_buffer(size: U16Vec2, capacity: U16Vec2) -> BufferInfo {
     // println!("[disp] init_buffer({}, {})", width, height);
-    let vmem_size = width * height * 4;
+    let (width, height) = (size.x as usize, size.y as usize);
+    let vmem_size = capacity.x as usize * capacity.y as usize * 4;
 
     let header_size = size_of::<proto::BufferHeader>().next_multiple_of(4096);
     let total_size = header_size + vmem_size;
@@ -124,6 +139,8 @@ fn init_buffer(width: usize, height: usize) -> BufferInfo {
         term_meta: proto::TermMeta { rows: 0, cols: 0 },
 
         present_sem,
+        server_notify: proto::SemDescriptor(2),
+        transfer_channel: proto::SemDescriptor(3),
     };
 
     // println!("Writing header");
@@ -144,10 +161,47 @@ use glam::{I16Vec2, U16Vec2};
 struct Window {
     pos: U16Vec2,
     size: U16Vec2,
-    _resizable: bool,
+    min_size: U16Vec2,
+    max_size: U16Vec2,
+    /// The size most recently sent to the client in a resize event.
+    requested_size: U16Vec2,
     client: Index,
 }
 
+impl Window {
+    fn resizable(&self) -> bool {
+        self.min_size != self.max_size
+    }
+
+    /// The area of the screen covered by the window and its title bar.
+    fn bounds(&self) -> Rect {
+        Rect::new(
+            self.pos.x as usize,
+            self.pos.y as usize,
+            self.size.x as usize,
+            self.size.y as usize + TITLE_HEIGHT as usize,
+        )
+    }
+
+    fn title_bounds(&self) -> Rect {
+        Rect::new(
+            self.pos.x as usize,
+            self.pos.y as usize,
+            self.size.x as usize,
+            TITLE_HEIGHT as usize,
+        )
+    }
+
+    fn content_bounds(&self) -> Rect {
+        Rect::new(
+            self.pos.x as usize,
+            self.pos.y as usize + TITLE_HEIGHT as usize,
+            self.size.x as usize,
+            self.size.y as usize,
+        )
+    }
+}
+
 struct WindowManager {
     default_pos: U16Vec2,
     screen_dims: U16Vec2,
@@ -159,8 +213,14 @@ struct WindowManager {
     mouse_down: bool,
 
     dragging: Option<(Index, U16Vec2)>,
+    /// Window being resized, and the offset from the cursor to the
+    /// window's bottom right corner.
+    resizing: Option<(Index, U16Vec2)>,
 
     request_close: Vec<Index>,
+    request_resize: Vec<Index>,
+
+    damage: Damage,
 }
 
 enum HoveredState {
@@ -168,8 +228,12 @@ enum HoveredState {
     Window(Index, U16Vec2),
     Titlebar(Index, U16Vec2),
     CloseButton(Index),
+    ResizeHandle(Index, U16Vec2),
 }
 
+const TITLE_HEIGHT: u16 = 12;
+const RESIZE_HANDLE_SIZE: u16 = 8;
+
 impl WindowManager {
     fn new(screen_dims: U16Vec2) -> Self {
         WindowManager {
@@ -179,18 +243,23 @@ impl WindowManager {
             layering: Vec::new(),
             active: None,
             dragging: None,
+            resizing: None,
             mouse_down: false,
             request_close: Vec::new(),
+            request_resize: Vec::new(),
+            damage: Damage::new(screen_dims.x as usize, screen_dims.y as usize),
         }
     }
 
     fn request_window(
         &mut self,
         min_size: U16Vec2,
-        _max_size: U16Vec2,
+        max_size: U16Vec2,
         pref_size: U16Vec2,
     ) -> (Index, U16Vec2) {
         let size = pref_size.min(self.screen_dims).max(min_size);
+        let max_size = max_size.min(self.screen_dims).max(size);
+        let min_size = min_size.min(size);
 
         let overflow = self
             .default_pos
@@ -207,27 +276,59 @@ impl WindowManager {
         let idx = self.windows.insert(Window {
             pos,
             size,
-            _resizable: false,
+            min_size,
+            max_size,
+            requested_size: size,
             client: Index::DANGLING, // TODO
         });
+        self.damage_active();
         self.layering.push(idx);
         self.active = Some(idx);
+        self.damage.add(self.windows[idx].bounds());
         (idx, size)
     }
 
+    /// Damage the title bar of the active window, which is drawn
+    /// differently from the others.
+    fn damage_active(&mut self) {
+        if let Some(active) = self.active {
+            self.damage.add(self.windows[active].title_bounds());
+        }
+    }
+
+    fn move_window(&mut self, idx: Index, pos: U16Vec2) {
+        let window = &mut self.windows[idx];
+        if window.pos != pos {
+            self.damage.add(window.bounds());
+            window.pos = pos;
+            self.damage.add(window.bounds());
+        }
+    }
+
     fn remove_window(&mut self, window: Index) {
         // TODO: alt-f4 can leave windows active, but receiving no input until alt-tab
+        self.damage.add(self.windows[window].bounds());
         self.windows.remove(window);
+        self.request_resize.retain(|f| *f != window);
+        if matches!(self.resizing, Some((idx, _)) if idx == window) {
+            self.resizing = None;
+        }
         self.layering.retain(|f| *f != window);
         if self.active == Some(window) {
             self.active = self.layering.last().map(|i| *i);
+            self.damage_active();
         }
     }
 
     fn select_window(&mut self, window: Index) {
+        if self.layering.last() != Some(&window) {
+            self.damage.add(self.windows[window].bounds());
+        }
+        self.damage_active();
         self.layering.retain(|f| *f != window);
         self.layering.push(window);
         self.active = Some(window);
+        self.damage_active();
     }
 
     fn alt_tab(&mut self, reverse: bool) {
@@ -260,7 +361,7 @@ impl WindowManager {
     }
 
     fn hovered(&self, cursor: U16Vec2) -> HoveredState {
-        let title_height = 12;
+        let title_height = TITLE_HEIGHT;
         let close_width = 12;
 
         for (idx, window) in self.iter_windows_draw_order_rev() {
@@ -282,6 +383,11 @@ impl WindowManager {
                 }
             } else if x_range.contains(&cursor.x) && window_y_range.contains(&cursor.y) {
                 let local_pos = cursor - window.pos - U16Vec2::new(0, title_height);
+                let from_corner = window.size - local_pos;
+                if window.resizable() && from_corner.cmple(U16Vec2::splat(RESIZE_HANDLE_SIZE)).all()
+                {
+                    return HoveredState::ResizeHandle(idx, from_corner);
+                }
                 return HoveredState::Window(idx, local_pos);
             }
         }
@@ -290,10 +396,45 @@ impl WindowManager {
 
     fn mouse_move(&mut self, new_cursor: U16Vec2) {
         if let Some((idx, offset)) = self.dragging {
-            self.windows[idx].pos = new_cursor.saturating_sub(offset);
+            self.move_window(idx, new_cursor.saturating_sub(offset));
+        }
+        if let Some((idx, offset)) = self.resizing {
+            let window = &self.windows[idx];
+            let content_pos = window.pos + U16Vec2::new(0, TITLE_HEIGHT);
+            let size = (new_cursor + offset).saturating_sub(content_pos);
+            self.resize_window(idx, size);
         }
     }
 
+    /// Ask the client of a window to switch to a new size; the window
+    /// keeps its current size until the client acknowledges it.
+    fn resize_window(&mut self, idx: Index, size: U16Vec2) {
+        let window = &mut self.windows[idx];
+        let size = size.clamp(window.min_size, window.max_size);
+        if size != window.requested_size {
+            window.requested_size = size;
+            if !self.request_resize.contains(&idx) {
+                self.request_resize.push(idx);
+            }
+        }
+    }
+
+    /// Handle a client acknowledging a resize; sizes outside of the
+    /// window's limits are ignored.
+    fn resize_acked(&mut self, idx: Index, size: U16Vec2) -> bool {
+        let window = &mut self.windows[idx];
+        if !window.resizable()
+            || size.cmplt(window.min_size).any()
+            || size.cmpgt(window.max_size).any()
+        {
+            return false;
+        }
+        self.damage.add(window.bounds());
+        window.size = size;
+        self.damage.add(window.bounds());
+        true
+    }
+
     fn iter_windows_draw_order(&self) -> impl Iterator<Item = (Index, &Window)> {
         self.layering.iter().map(|i| (*i, &self.windows[*i]))
     }
@@ -303,13 +444,9 @@ impl WindowManager {
 }
 
 fn load_image(img: &[u8]) -> (usize, usize, Vec<u32>) {
-    let (header, data) = gfx::format::qoi::read_qoi_header(img).unwrap();
-    let width = header.width as usize;
-    let height = header.height as usize;
     // Decode image into a bitmap buffer
-    let mut buf = alloc::vec![0u32; width * height];
-    gfx::format::qoi::decode_qoi(&header, data, &mut buf, width);
-    (width, height, buf)
+    let (info, buf) = gfx::format::image::decode_image_to_vec(img).unwrap();
+    (info.width, info.height, buf)
 }
 
 bitflags::bitflags! {
@@ -346,21 +483,49 @@ impl Modifiers {
     }
 }
 
+/// Where an outline font for window titles is looked for.
+const TITLE_FONT_PATH: &[u8] = b"/fonts/ui.ttf";
+
+/// The font window titles are drawn with: the built-in bitmap font, or
+/// an outline font sized to fit the title bar.
+enum TitleFont<'a> {
+    Bitmap(alloc::boxed::Box<gfx::format::pcf::LoadedPCF<'a>>),
+    Outline(gfx::format::ttf::ScaledFont<'a>),
+}
+
+impl TitleFont<'_> {
+    fn draw(&mut self, title: &str, buf: &mut [u32], width: usize, color: u32) {
+        match self {
+            TitleFont::Bitmap(font) => {
+                font.draw_string(
+                    title,
+                    buf,
+                    (width * 2) + 2,
+                    Some(width.saturating_sub(2)),
+                    width,
+                    1,
+                    color,
+                );
+            }
+            TitleFont::Outline(font) => {
+                let y = (TITLE_HEIGHT as usize).saturating_sub(font.line_height()) / 2;
+                let wrap_at = Some(width.saturating_sub(2));
+                font.draw_string(title, buf, width, (2, y), wrap_at, color);
+            }
+        }
+    }
+}
+
 fn handle_conns(mut fb: framebuffer::Framebuffer, server_socket: FileDesc) {
     let mut clients = Arena::new();
 
     let mut window_manager = WindowManager::new(U16Vec2::new(fb.width as u16, fb.height as u16));
 
-    // TODO: synchronization approach
-    // Server:
-    // - should be notified when clients send events
-    // - should be able to multiplex clients on a single thread
-    // Client
-    // - must have a way to wait until the server has copied a frame
-    //   out before updating it
-    // - should have some way to wait for server events (+ present
-    //   requests?)
-    // TODO: vsync / rate management
+    // Raised by new connections, input events, and clients once they
+    // have sent events; the server sleeps on it while idle.
+    let notify = ulib::sys::notify_create().unwrap();
+    ulib::sys::notify_subscribe(notify, server_socket).unwrap();
+    ulib::sys::notify_input(notify).unwrap();
 
     // Load image (included directly in the binary)
     let img = include_bytes!("../assets/cursor.qoi");
@@ -379,34 +544,72 @@ fn handle_conns(mut fb: framebuffer::Framebuffer, server_socket: FileDesc) {
         .unwrap();
     let mut font_data = alloc::vec![0; size as usize];
     let font_data = lz4::decode_into(compressed_font, &mut font_data).unwrap();
-    let font = gfx::format::pcf::load_pcf(font_data).unwrap();
+    let bitmap_font = gfx::format::pcf::load_pcf(font_data).unwrap();
+
+    let font_file = read_file(TITLE_FONT_PATH);
+    let mut font = match font_file.as_deref().map(gfx::format::ttf::load_ttf) {
+        Some(Ok(outline)) => {
+            // The largest size that fits in the title bar
+            let mut font = gfx::format::ttf::ScaledFont::new(outline, TITLE_HEIGHT as u32);
+            while font.size() > 1 && font.line_height() > TITLE_HEIGHT as usize {
+                font.set_size(font.size() - 1);
+            }
+            TitleFont::Outline(font)
+        }
+        Some(Err(err)) => {
+            println!("Failed to load title font: {err:?}");
+            TitleFont::Bitmap(alloc::boxed::Box::new(bitmap_font))
+        }
+        None => TitleFont::Bitmap(alloc::boxed::Box::new(bitmap_font)),
+    };
 
     let mut to_remove = Vec::<Index>::new();
 
+    let title_height = TITLE_HEIGHT as usize;
+    let title_fg_color = 0xFF000000;
+    let title_bg_color_active = 0xFFFFFFFF;
+    let title_bg_color_inactive = 0xFFCCCCCC;
+    let bg_color: u32 = 0xFFC8FFFF;
+
     let mut intermediate_fb = alloc::vec![0u128; fb.data.len()];
+    intermediate_fb.fill(0x00000001000000010000000100000001 * bg_color as u128);
+    window_manager.damage.add_all();
+    let mut title_buf = Vec::<u32>::new();
+
     let mut cursor = U16Vec2::new(0, 0);
+    let cursor_bounds = |cursor: U16Vec2| {
+        Rect::new(
+            cursor.x as usize,
+            cursor.y as usize,
+            cursor_width,
+            cursor_height,
+        )
+    };
+    let mut close_pressed = None;
 
-    let mut any_updated = true;
+    // The MIME type and contents of the clipboard
+    let mut clipboard: Option<(String, Vec<u8>)> = None;
+    let mut clipboard_id = 0u32;
 
     let mut modifiers = Modifiers::empty();
 
     loop {
+        // Whether there could be more events that weren't handled yet
+        let mut busy = false;
+
         let mut buf = [0u64; 32];
         while let Ok((len, msg)) = recv_nonblock(server_socket, bytemuck::bytes_of_mut(&mut buf)) {
             let (window, client) = handle_incoming(
                 msg,
                 &bytemuck::bytes_of(&buf)[..len],
                 server_socket,
+                notify,
                 &mut window_manager,
             );
             let idx = clients.insert(client);
             window_manager.windows[window].client = idx;
         }
 
-        let bg_color = 0xFFC8FFFF;
-        intermediate_fb.fill(0x00000001000000010000000100000001 * bg_color);
-
-        // TODO: better scheduling for this
         loop {
             use proto::ScanCode;
 
@@ -443,7 +646,7 @@ fn handle_conns(mut fb: framebuffer::Framebuffer, server_socket: FileDesc) {
             // TODO: key repeat???
 
             if let Some(active) = window_manager.active {
-                let window = &mut window_manager.windows[active];
+                let pos = window_manager.windows[active].pos;
 
                 let move_scale = if modifiers.shift_pressed() {
                     128
@@ -453,27 +656,29 @@ fn handle_conns(mut fb: framebuffer::Framebuffer, server_socket: FileDesc) {
                     16
                 };
 
-                match (code, pressed) {
+                let new_pos = match (code, pressed) {
                     (ScanCode::LEFT, true) if modifiers.super_pressed() => {
-                        window.pos.x = window.pos.x.saturating_sub(move_scale);
-                        continue;
-                    }
-                    (ScanCode::RIGHT, true) if modifiers.super_pressed() => {
-                        window.pos.x = (window.pos.x)
-                            .saturating_add(move_scale)
-                            .min(fb.width as u16 - 1);
-                        continue;
+                        Some(U16Vec2::new(pos.x.saturating_sub(move_scale), pos.y))
                     }
+                    (ScanCode::RIGHT, true) if modifiers.super_pressed() => Some(U16Vec2::new(
+                        pos.x.saturating_add(move_scale).min(fb.width as u16 - 1),
+                        pos.y,
+                    )),
                     (ScanCode::UP, true) if modifiers.super_pressed() => {
-                        window.pos.y = window.pos.y.saturating_sub(move_scale);
-                        continue;
-                    }
-                    (ScanCode::DOWN, true) if modifiers.super_pressed() => {
-                        window.pos.y = (window.pos.y)
-                            .saturating_add(move_scale)
-                            .min(fb.height as u16 - 1);
-                        continue;
+                        Some(U16Vec2::new(pos.x, pos.y.saturating_sub(move_scale)))
                     }
+                    (ScanCode::DOWN, true) if modifiers.super_pressed() => Some(U16Vec2::new(
+                        pos.x,
+                        pos.y.saturating_add(move_scale).min(fb.height as u16 - 1),
+                    )),
+                    _ => None,
+                };
+                if let Some(new_pos) = new_pos {
+                    window_manager.move_window(active, new_pos);
+                    continue;
+                }
+
+                match (code, pressed) {
                     (ScanCode::F4, true) if modifiers.alt_pressed() => {
                         window_manager.request_close.push(active);
                         continue;
@@ -497,8 +702,6 @@ fn handle_conns(mut fb: framebuffer::Framebuffer, server_socket: FileDesc) {
             }
         }
 
-        let mut cursor_moved = false;
-
         while let Some(ev) = ulib::sys::poll_mouse_event() {
             if ev.kind == ulib::sys::EVENT_KEY {
                 match ev.code {
@@ -517,6 +720,10 @@ fn handle_conns(mut fb: framebuffer::Framebuffer, server_socket: FileDesc) {
                                 HoveredState::Window(idx, _local_pos) => {
                                     window_manager.select_window(idx);
                                 }
+                                HoveredState::ResizeHandle(idx, offset) => {
+                                    window_manager.resizing = Some((idx, offset));
+                                    window_manager.select_window(idx);
+                                }
                                 HoveredState::CloseButton(_idx) => {
                                     // window_manager.request_close.push(idx);
                                 }
@@ -530,6 +737,7 @@ fn handle_conns(mut fb: framebuffer::Framebuffer, server_socket: FileDesc) {
                                 _ => (),
                             }
                             window_manager.dragging = None;
+                            window_manager.resizing = None;
                             window_manager.mouse_down = false;
                         }
 
@@ -563,14 +771,15 @@ fn handle_conns(mut fb: framebuffer::Framebuffer, server_socket: FileDesc) {
                     let x = (ev.value & 0xFFFF) as i16;
                     // println!("Mouse move: {}, {}", x, y);
 
+                    window_manager.damage.add(cursor_bounds(cursor));
                     cursor = (cursor.as_i16vec2() + I16Vec2::new(x, y))
                         .clamp(
                             I16Vec2::ZERO,
                             I16Vec2::new(fb.width as i16 - 1, fb.height as i16 - 1),
                         )
                         .as_u16vec2();
+                    window_manager.damage.add(cursor_bounds(cursor));
                     window_manager.mouse_move(cursor);
-                    cursor_moved = true;
 
                     // TODO: dragging off window, still send mouse up to original window
                     let hovered = window_manager.hovered(cursor);
@@ -625,19 +834,51 @@ fn handle_conns(mut fb: framebuffer::Framebuffer, server_socket: FileDesc) {
             // TODO: if queue is full / etc, track non-acknowledgement and kill anyways
         }
 
+        for to_resize in window_manager.request_resize.drain(..) {
+            let window = &window_manager.windows[to_resize];
+            let client = &mut clients[window.client];
+            let event = proto::ResizeEvent {
+                width: window.requested_size.x,
+                height: window.requested_size.y,
+            };
+            client
+                .handle
+                .server_to_client_queue()
+                .try_send_data(event)
+                .ok();
+        }
+
         for (i, client) in clients.iter_mut() {
             use proto::EventData;
-            let mut ev_limit = 10;
-            while let Some(msg) = client.handle.client_to_server_queue().try_recv() {
-                if ev_limit == 0 {
+            let ev_limit = 10;
+            for ev in 0..=ev_limit {
+                let Some(msg) = client.handle.client_to_server_queue().try_recv() else {
                     break;
+                };
+                if ev == ev_limit {
+                    busy = true;
                 }
-                ev_limit -= 1;
                 match msg.kind {
                     proto::EventKind::PRESENT => {
-                        let proto::PresentEvent = proto::PresentEvent::parse(&msg).unwrap();
+                        let present = proto::PresentEvent::parse(&msg).unwrap();
+                        let content = window_manager.windows[client.window].content_bounds();
+                        match present.damage() {
+                            // Nothing was drawn before the first present
+                            Some(damage) if client.present_ready => {
+                                for rect in damage {
+                                    let rect = Rect::new(
+                                        content.x + rect.x as usize,
+                                        content.y + rect.y as usize,
+                                        rect.width as usize,
+                                        rect.height as usize,
+                                    );
+                                    window_manager.damage.add(rect.intersect(&content));
+                                }
+                            }
+                            _ => window_manager.damage.add(content),
+                        }
                         client.present_ready = true;
-                        any_updated = true;
+                        client.present_pending = true;
                     }
                     proto::EventKind::TITLE => {
                         let proto::TitleEvent { len, data } =
@@ -645,7 +886,60 @@ fn handle_conns(mut fb: framebuffer::Framebuffer, server_socket: FileDesc) {
                         let buf = &data[..(len as usize).min(data.len())];
                         client.title = alloc::string::String::from_utf8_lossy(buf).into_owned();
                         println!("Updated title: {:?}", client.title);
-                        any_updated = true;
+                        let title = window_manager.windows[client.window].title_bounds();
+                        window_manager.damage.add(title);
+                    }
+                    proto::EventKind::RESIZE => {
+                        let proto::ResizeEvent { width, height } =
+                            proto::ResizeEvent::parse(&msg).unwrap();
+                        let size = U16Vec2::new(width, height);
+                        if client.handle.fits(width, height)
+                            && window_manager.resize_acked(client.window, size)
+                        {
+                            client.handle.set_video_size(width, height);
+                        }
+                    }
+                    proto::EventKind::CLIPBOARD_SET => {
+                        let proto::ClipboardSetEvent(event) =
+                            proto::ClipboardSetEvent::parse(&msg).unwrap();
+                        let transfer = client.handle.get_sem_fd(client.handle.transfer_channel);
+                        let contents = proto::recv_clipboard(&event, |data| {
+                            recv_nonblock(transfer, data).map(|(len, message)| (len, message.tag))
+                        });
+                        match contents {
+                            Some(data) => clipboard = Some((String::from(event.mime()), data)),
+                            None => println!("[disp] client {:?} sent bad clipboard contents", i),
+                        }
+                    }
+                    proto::EventKind::CLIPBOARD_GET => {
+                        let proto::ClipboardGetEvent(event) =
+                            proto::ClipboardGetEvent::parse(&msg).unwrap();
+                        let transfer = client.handle.get_sem_fd(client.handle.transfer_channel);
+                        let reply = match &clipboard {
+                            Some((mime, data)) if proto::mime_matches(event.mime(), mime) => {
+                                clipboard_id = clipboard_id.wrapping_add(1);
+                                let message = ulib::sys::Message {
+                                    tag: proto::clipboard_tag(clipboard_id),
+                                    objects: [u32::MAX; 4],
+                                };
+                                if send_nonblock(transfer, &message, data) >= 0 {
+                                    proto::ClipboardEvent::new(
+                                        mime,
+                                        clipboard_id,
+                                        data.len() as u32,
+                                    )
+                                    .unwrap()
+                                } else {
+                                    proto::ClipboardEvent::empty()
+                                }
+                            }
+                            _ => proto::ClipboardEvent::empty(),
+                        };
+                        client
+                            .handle
+                            .server_to_client_queue()
+                            .try_send_data(proto::ClipboardDataEvent(reply))
+                            .ok();
                     }
                     proto::EventKind::DISCONNECT => {
                         // TODO: auto-disconnect on process exit?
@@ -660,119 +954,132 @@ fn handle_conns(mut fb: framebuffer::Framebuffer, server_socket: FileDesc) {
             }
         }
 
-        let title_height = 12;
-        let title_fg_color = 0xFF000000;
-        let title_bg_color_active = 0xFFFFFFFF;
-        let title_bg_color_inactive = 0xFFCCCCCC;
-
         let hovered = window_manager.hovered(cursor);
+        let new_close_pressed = match hovered {
+            HoveredState::CloseButton(i) if window_manager.mouse_down => Some(i),
+            _ => None,
+        };
+        if new_close_pressed != close_pressed {
+            for idx in [close_pressed, new_close_pressed].into_iter().flatten() {
+                if let Some(window) = window_manager.windows.get(idx) {
+                    window_manager.damage.add(window.title_bounds());
+                }
+            }
+            close_pressed = new_close_pressed;
+        }
 
-        for (idx, window) in window_manager.iter_windows_draw_order() {
-            let client = &mut clients[window.client];
-            let active = window_manager.active == Some(idx);
-
-            let window_x = window.pos.x as usize;
-            let window_y = window.pos.y as usize;
-
-            if client.present_ready {
-                let out = bytemuck::cast_slice_mut(&mut intermediate_fb);
-                let client_width = client.handle.video_meta.width as usize;
-                let client_height = client.handle.video_meta.height as usize;
-                let client_row_stride =
-                    client.handle.video_meta.row_stride as usize / size_of::<u32>();
-                let client_fb = &*client.handle.video_mem();
-                gfx::blit_buffer(
-                    out,
-                    fb.width,
-                    fb.height,
-                    fb.stride,
-                    window_x,
-                    window_y + title_height,
-                    client_fb,
-                    client_width,
-                    client_height,
-                    client_row_stride,
-                );
-
-                // TODO: better sync system here? this will accumulate if client
-                // isn't waiting for acks
-                ulib::sys::sem_up(client.handle.get_sem_fd(client.handle.present_sem)).unwrap();
+        let out = bytemuck::cast_slice_mut::<_, u32>(&mut intermediate_fb);
+        for &clip in window_manager.damage.rects() {
+            for r in clip.y..clip.y + clip.height {
+                out[r * fb.stride + clip.x..][..clip.width].fill(bg_color);
             }
 
-            // TODO: draw title bar, borders
+            for (idx, window) in window_manager.iter_windows_draw_order() {
+                if !window.bounds().intersects(&clip) {
+                    continue;
+                }
+                let client = &mut clients[window.client];
+                let active = window_manager.active == Some(idx);
+
+                let window_x = window.pos.x as usize;
+                let window_y = window.pos.y as usize;
+
+                if client.present_ready {
+                    let client_width = client.handle.video_meta.width as usize;
+                    let client_height = client.handle.video_meta.height as usize;
+                    let client_row_stride =
+                        client.handle.video_meta.row_stride as usize / size_of::<u32>();
+                    let client_fb = &*client.handle.video_mem();
+                    damage::blit_clipped(
+                        out,
+                        fb.stride,
+                        clip,
+                        (window_x, window_y + title_height),
+                        client_fb,
+                        client_width,
+                        client_height,
+                        client_row_stride,
+                        false,
+                    );
+                }
 
-            let bg_color = if active {
-                title_bg_color_active
-            } else {
-                title_bg_color_inactive
-            };
+                // TODO: draw borders
 
-            let width = client.handle.video_meta.width as usize;
-            let effective_width = width.min(fb.width.saturating_sub(window_x));
-            for r in window_y..(window_y + title_height).min(fb.height) {
-                let out = bytemuck::cast_slice_mut::<_, u32>(&mut intermediate_fb);
-                let dst_row = &mut out[r * fb.stride + window_x..][..effective_width];
-                dst_row.fill(bg_color);
-            }
+                if !window.title_bounds().intersects(&clip) {
+                    continue;
+                }
 
-            let out = bytemuck::cast_slice_mut::<_, u32>(&mut intermediate_fb);
-            let buf = &mut out
-                [window_y * fb.stride..(window_y + title_height).min(fb.height) * fb.stride];
-            let title_start = (fb.stride * 2) + window_x + 2;
-            font.draw_string(
-                &client.title,
-                buf,
-                title_start,
-                Some(effective_width.saturating_sub(2)),
-                fb.stride,
-                1,
-                title_fg_color,
-            );
+                let bg_color = if active {
+                    title_bg_color_active
+                } else {
+                    title_bg_color_inactive
+                };
 
-            // Draw close button
+                let width = window.size.x as usize;
+                title_buf.clear();
+                title_buf.resize(width * title_height, bg_color);
 
-            let close_pressed = match hovered {
-                HoveredState::CloseButton(i) => i == idx && window_manager.mouse_down,
-                _ => false,
-            };
+                font.draw(&client.title, &mut title_buf, width, title_fg_color);
 
-            let buf = if close_pressed {
-                &close2_buf
-            } else {
-                &close_buf
-            };
-            gfx::blit_buffer_blend(
+                // Draw close button
+
+                let buf = if close_pressed == Some(idx) {
+                    &close2_buf
+                } else {
+                    &close_buf
+                };
+                gfx::blit_buffer_blend(
+                    &mut title_buf,
+                    width,
+                    title_height,
+                    width,
+                    width.saturating_sub(close_width),
+                    0,
+                    buf,
+                    close_width,
+                    close_height,
+                    close_width,
+                );
+
+                damage::blit_clipped(
+                    out,
+                    fb.stride,
+                    clip,
+                    (window_x, window_y),
+                    &title_buf,
+                    width,
+                    title_height,
+                    width,
+                    false,
+                );
+            }
+
+            damage::blit_clipped(
                 out,
-                fb.width,
-                fb.height,
                 fb.stride,
-                window_x + width - close_width,
-                window_y,
-                buf,
-                close_width,
-                close_height,
-                close_width,
+                clip,
+                (cursor.x as usize, cursor.y as usize),
+                &cursor_buf,
+                cursor_width,
+                cursor_height,
+                cursor_width,
+                true,
             );
         }
 
-        let out = bytemuck::cast_slice_mut(&mut intermediate_fb);
-        gfx::blit_buffer_blend(
-            out,
-            fb.width,
-            fb.height,
-            fb.stride,
-            cursor.x as usize,
-            cursor.y as usize,
-            &cursor_buf,
-            cursor_width,
-            cursor_height,
-            cursor_width,
-        );
+        let presented = !window_manager.damage.is_empty();
+        if presented {
+            framebuffer::present_rects(&mut fb, &intermediate_fb, window_manager.damage.rects());
+            window_manager.damage.clear();
+        }
 
-        if any_updated || cursor_moved {
-            framebuffer::present(&mut fb, &intermediate_fb);
+        // Only acknowledge presents once their contents have been copied
+        // out, so clients can't tear frames by drawing the next one early
+        for (_, client) in clients.iter_mut() {
+            if core::mem::take(&mut client.present_pending) {
+                ulib::sys::sem_up(client.handle.get_sem_fd(client.handle.present_sem)).unwrap();
+            }
         }
-        any_updated = false;
 
         if !to_remove.is_empty() {
             to_remove.sort_by(|a, b| a.cmp(b).reverse());
@@ -787,17 +1094,33 @@ fn handle_conns(mut fb: framebuffer::Framebuffer, server_socket: FileDesc) {
                 window_manager.remove_window(window);
             }
             clients.remove(remove);
-            any_updated = true;
         }
 
-        // TODO: composite and present
-        // TODO: sleep until frame
         // TODO: intermediate buffers to prevent flickering for apps
         // that render slowly
-        unsafe { ulib::sys::sys_sleep_ms(10) };
+        if presented || busy || !window_manager.damage.is_empty() {
+            unsafe { ulib::sys::sys_wait_vsync() };
+        } else {
+            ulib::sys::notify_wait(notify).unwrap();
+        }
     }
 }
 
+fn read_file(path: &[u8]) -> Option<Vec<u8>> {
+    let fd = ulib::sys::openat(3, path, 0, 0).ok()?;
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    let result = loop {
+        match ulib::sys::pread(fd, &mut buf, data.len() as u64) {
+            Ok(0) => break Some(data),
+            Ok(len) => data.extend_from_slice(&buf[..len]),
+            Err(_) => break None,
+        }
+    };
+    ulib::sys::close(fd).ok();
+    result
+}
+
 fn spawn_console() {
     let path = b"/console";
     let file = ulib::sys::openat(3, path, 0, 0).unwrap();
@@ -806,6 +1129,7 @@ fn spawn_console() {
         stdin: None,
         stdout: None,
         stderr: None,
+        pgid: None,
         args: &[ulib::sys::ArgStr {
             len: path.len(),
             ptr: path.as_ptr(),
@@ -822,6 +1146,7 @@ fn spawn_paint() {
         stdin: None,
         stdout: None,
         stderr: None,
+        pgid: None,
         args: &[ulib::sys::ArgStr {
             len: path.len(),
             ptr: path.as_ptr(),
@@ -1,7 +1,8 @@
 use std::fs;
 
+use elf::note::{self, AArch64PrStatus};
 use elf::section_header::SectionHeaderError;
-use elf::{section_header, Elf, ElfError};
+use elf::{elf_header, section_header, Elf, ElfError};
 
 // Readelf-like tool to output the parsed elf structs
 
@@ -86,7 +87,15 @@ fn output_elf_file_header(elf: &Elf) {
     );
 }
 
+fn has_sections(elf: &Elf) -> bool {
+    elf.elf_header().e_shoff() != 0
+}
+
 fn output_section_headers(elf: &Elf) -> Result<(), ElfError> {
+    if !has_sections(elf) {
+        println!("There are no sections in this file.");
+        return Ok(());
+    }
     let section_string_table = match elf
         .section_headers()?
         .nth(elf.elf_header().e_shstrndx() as usize)
@@ -202,7 +211,62 @@ fn output_program_headers(elf: &Elf) -> Result<(), ElfError> {
     Ok(())
 }
 
+fn output_notes(elf: &Elf) -> Result<(), ElfError> {
+    let Some(program_headers) = elf.program_headers() else {
+        return Ok(());
+    };
+    let is_aarch64 = matches!(elf.elf_header().e_machine(), elf_header::Machine::AArch64);
+
+    for header in program_headers {
+        let header = header?;
+        let Some(notes) = elf.notes(&header) else {
+            continue;
+        };
+        println!(
+            "Displaying notes found at file offset 0x{:08x} with length 0x{:08x}:",
+            header.p_offset, header.p_filesz
+        );
+        println!("  Owner                Data size \tDescription");
+        for note in notes {
+            let note = note?;
+            println!(
+                "  {:20} 0x{:08x}\t{}",
+                note.name,
+                note.desc.len(),
+                note.n_type
+            );
+            if !is_aarch64 || note.name != note::CORE_NOTE_NAME {
+                continue;
+            }
+            if let (note::Type::PrStatus, Some(status)) =
+                (note.n_type, AArch64PrStatus::from_desc(note.desc))
+            {
+                println!("    pid: {}  signal: {}", status.pr_pid, status.pr_cursig);
+                println!(
+                    "    pc: 0x{:016x}  sp: 0x{:016x}  pstate: 0x{:08x}",
+                    status.pc(),
+                    status.sp(),
+                    status.pstate()
+                );
+                for (i, regs) in status.pr_reg[..31].chunks(3).enumerate() {
+                    print!("   ");
+                    for (j, reg) in regs.iter().enumerate() {
+                        print!(" {:>3}: 0x{:016x}", format!("x{}", i * 3 + j), reg);
+                    }
+                    println!();
+                }
+            }
+        }
+        println!();
+    }
+    Ok(())
+}
+
 fn output_relocations(elf: &Elf) -> Result<(), ElfError> {
+    if !has_sections(elf) {
+        println!("There are no relocations in this file.");
+        return Ok(());
+    }
     let section_string_table = elf
         .section_headers()?
         .nth(elf.elf_header().e_shstrndx() as usize)
@@ -340,6 +404,7 @@ fn display_elf_file<'a>(elf: &'a Elf) -> Result<(), Box<dyn core::error::Error +
     println!();
     output_program_headers(elf)?;
     println!();
+    output_notes(elf)?;
     output_relocations(elf)?;
     println!();
     output_symbols(elf)?;
@@ -348,8 +413,11 @@ fn display_elf_file<'a>(elf: &'a Elf) -> Result<(), Box<dyn core::error::Error +
 }
 
 fn main() {
-    // /lib/libc.so.6
-    let data: Vec<u8> = match fs::read("crates/elf/examples/x64/simple") {
+    // /lib/libc.so.6, or a core file dumped by the kernel
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| String::from("crates/elf/examples/x64/simple"));
+    let data: Vec<u8> = match fs::read(&path) {
         Ok(data) => data,
         Err(e) => {
             eprintln!("Error: {}", e);
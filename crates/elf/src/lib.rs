@@ -13,6 +13,7 @@ use core::{
 
 pub mod elf_header;
 pub mod identity;
+pub mod note;
 pub mod program_header;
 pub mod relocation;
 pub mod section_header;
@@ -50,6 +51,7 @@ pub enum ElfError {
     ProgramHeaderError(program_header::ProgramHeaderError),
     RelocationError(relocation::RelocationError),
     SymbolError(symbol::SymbolError),
+    NoteError(note::NoteError),
 }
 
 impl Display for ElfError {
@@ -60,6 +62,7 @@ impl Display for ElfError {
             Self::ProgramHeaderError(e) => write!(f, "Error parsing program header: {}", e),
             Self::RelocationError(e) => write!(f, "Error parsing relocation: {}", e),
             Self::SymbolError(e) => write!(f, "Error parsing symbol: {}", e),
+            Self::NoteError(e) => write!(f, "Error parsing note: {}", e),
         }
     }
 }
@@ -94,6 +97,12 @@ impl From<symbol::SymbolError> for ElfError {
     }
 }
 
+impl From<note::NoteError> for ElfError {
+    fn from(e: note::NoteError) -> Self {
+        Self::NoteError(e)
+    }
+}
+
 impl Error for ElfError {}
 
 impl<'a> Elf<'a> {
@@ -123,7 +132,10 @@ impl<'a> Elf<'a> {
     {
         let table_start = self.elf_header.e_shoff() as usize;
         let entry_size = self.elf_header.e_shentsize() as usize;
-        let entry_count = if self.elf_header.e_shnum() == section_header::SHN_UNDEF {
+        let entry_count = if table_start == 0 {
+            // No section header table, as in core files
+            0
+        } else if self.elf_header.e_shnum() == section_header::SHN_UNDEF {
             let first_section_header = section_header::SectionHeader::new(self, table_start)?;
             first_section_header.sh_size as usize
         } else {
@@ -193,4 +205,12 @@ impl<'a> Elf<'a> {
         let end = usize::try_from(phdr.p_offset.checked_add(phdr.p_filesz)?).ok()?;
         self.file_data.get(start..end)
     }
+
+    /// Iterate over the notes in a `PT_NOTE` segment.
+    pub fn notes(&self, phdr: &program_header::ProgramHeader) -> Option<note::Notes<'a>> {
+        if !matches!(phdr.p_type, program_header::Type::Note) {
+            return None;
+        }
+        Some(note::Notes::new(self.segment_data(phdr)?, phdr.p_align))
+    }
 }
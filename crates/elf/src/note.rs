@@ -0,0 +1,239 @@
+// https://refspecs.linuxfoundation.org/elf/gabi4+/ch5.pheader.html#note_section
+
+use core::{
+    error::Error,
+    fmt::{Display, Formatter},
+};
+
+use super::types::*;
+
+pub const NT_PRSTATUS: u32 = 1;
+pub const NT_FPREGSET: u32 = 2;
+pub const NT_PRPSINFO: u32 = 3;
+pub const NT_AUXV: u32 = 6;
+pub const NT_SIGINFO: u32 = 0x5349_4749;
+pub const NT_FILE: u32 = 0x4649_4c45;
+
+/// Owner name used for the notes in Linux style core files.
+pub const CORE_NOTE_NAME: &str = "CORE";
+
+#[derive(Debug, Copy, Clone)]
+pub struct Note<'a> {
+    pub n_type: Type,
+    pub name: &'a str,
+    pub desc: &'a [u8],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct ElfNhdr {
+    n_namesz: Elf64Word,
+    n_descsz: Elf64Word,
+    n_type: Elf64Word,
+}
+
+unsafe impl bytemuck::Zeroable for ElfNhdr {}
+unsafe impl bytemuck::AnyBitPattern for ElfNhdr {}
+
+#[derive(Debug, Copy, Clone)]
+pub enum Type {
+    PrStatus,
+    FpRegSet,
+    PrPsInfo,
+    Auxv,
+    SigInfo,
+    File,
+    Other(u32),
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        match self {
+            Self::PrStatus => write!(f, "NT_PRSTATUS (prstatus structure)"),
+            Self::FpRegSet => write!(f, "NT_FPREGSET (floating point registers)"),
+            Self::PrPsInfo => write!(f, "NT_PRPSINFO (prpsinfo structure)"),
+            Self::Auxv => write!(f, "NT_AUXV (auxiliary vector)"),
+            Self::SigInfo => write!(f, "NT_SIGINFO (siginfo_t data)"),
+            Self::File => write!(f, "NT_FILE (mapped files)"),
+            Self::Other(value) => write!(f, "Unknown note type: (0x{:08x})", value),
+        }
+    }
+}
+
+impl From<u32> for Type {
+    fn from(value: u32) -> Self {
+        match value {
+            NT_PRSTATUS => Self::PrStatus,
+            NT_FPREGSET => Self::FpRegSet,
+            NT_PRPSINFO => Self::PrPsInfo,
+            NT_AUXV => Self::Auxv,
+            NT_SIGINFO => Self::SigInfo,
+            NT_FILE => Self::File,
+            value => Self::Other(value),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum NoteError {
+    InvalidLength,
+    InvalidName,
+}
+
+impl Display for NoteError {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        match self {
+            Self::InvalidLength => write!(f, "Invalid length"),
+            Self::InvalidName => write!(f, "Invalid name"),
+        }
+    }
+}
+
+impl Error for NoteError {}
+
+/// Iterator over the notes in a `PT_NOTE` segment or `SHT_NOTE` section.
+pub struct Notes<'a> {
+    data: &'a [u8],
+    align: usize,
+}
+
+impl<'a> Notes<'a> {
+    /// Names and descriptors are padded to `align` bytes, which is 4 for
+    /// everything except `.note.gnu.property`.
+    pub fn new(data: &'a [u8], align: u64) -> Self {
+        let align = if align == 8 { 8 } else { 4 };
+        Self { data, align }
+    }
+
+    fn next_note(&mut self) -> Result<Note<'a>, NoteError> {
+        let header = self
+            .data
+            .get(..size_of::<ElfNhdr>())
+            .ok_or(NoteError::InvalidLength)?;
+        let header: ElfNhdr = bytemuck::pod_read_unaligned(header);
+
+        let name_start = size_of::<ElfNhdr>();
+        let name_end = name_start + header.n_namesz as usize;
+        let desc_start = name_end.next_multiple_of(self.align);
+        let desc_end = desc_start + header.n_descsz as usize;
+
+        let name = self
+            .data
+            .get(name_start..name_end)
+            .ok_or(NoteError::InvalidLength)?;
+        let name = name.strip_suffix(&[0]).unwrap_or(name);
+        let name = core::str::from_utf8(name).map_err(|_| NoteError::InvalidName)?;
+        let desc = self
+            .data
+            .get(desc_start..desc_end)
+            .ok_or(NoteError::InvalidLength)?;
+
+        let next = desc_end.next_multiple_of(self.align).min(self.data.len());
+        self.data = &self.data[next..];
+
+        Ok(Note {
+            n_type: Type::from(header.n_type),
+            name,
+            desc,
+        })
+    }
+}
+
+impl<'a> Iterator for Notes<'a> {
+    type Item = Result<Note<'a>, NoteError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let note = self.next_note();
+        if note.is_err() {
+            self.data = &[];
+        }
+        Some(note)
+    }
+}
+
+/// Get the size of a note once written, including padding.
+pub fn note_size(name: &str, desc_len: usize) -> usize {
+    size_of::<ElfNhdr>() + (name.len() + 1).next_multiple_of(4) + desc_len.next_multiple_of(4)
+}
+
+/// Write the header and name of a note, which must be followed by
+/// `desc_len` bytes of descriptor and padding up to a multiple of 4.
+/// Returns the number of bytes written to `out`.
+pub fn write_note_header(out: &mut [u8], name: &str, n_type: u32, desc_len: usize) -> usize {
+    let namesz = name.len() + 1;
+    let header_len = size_of::<ElfNhdr>() + namesz.next_multiple_of(4);
+    let out = &mut out[..header_len];
+    out.fill(0);
+    out[0..4].copy_from_slice(&(namesz as u32).to_le_bytes());
+    out[4..8].copy_from_slice(&(desc_len as u32).to_le_bytes());
+    out[8..12].copy_from_slice(&n_type.to_le_bytes());
+    out[12..12 + name.len()].copy_from_slice(name.as_bytes());
+    header_len
+}
+
+/// `struct elf_prstatus` as used in AArch64 Linux core files.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct AArch64PrStatus {
+    pub si_signo: i32,
+    pub si_code: i32,
+    pub si_errno: i32,
+    pub pr_cursig: i16,
+    _pad0: i16,
+    pub pr_sigpend: u64,
+    pub pr_sighold: u64,
+    pub pr_pid: i32,
+    pub pr_ppid: i32,
+    pub pr_pgrp: i32,
+    pub pr_sid: i32,
+    pub pr_utime: [i64; 2],
+    pub pr_stime: [i64; 2],
+    pub pr_cutime: [i64; 2],
+    pub pr_cstime: [i64; 2],
+    /// x0 to x30, followed by sp, pc and pstate.
+    pub pr_reg: [u64; 34],
+    pub pr_fpvalid: i32,
+    _pad1: i32,
+}
+
+unsafe impl bytemuck::Zeroable for AArch64PrStatus {}
+unsafe impl bytemuck::AnyBitPattern for AArch64PrStatus {}
+unsafe impl bytemuck::NoUninit for AArch64PrStatus {}
+
+impl AArch64PrStatus {
+    pub fn new(pid: i32, signal: i32, pr_reg: [u64; 34]) -> Self {
+        Self {
+            si_signo: signal,
+            pr_cursig: signal as i16,
+            pr_pid: pid,
+            pr_reg,
+            ..bytemuck::Zeroable::zeroed()
+        }
+    }
+
+    pub fn from_desc(desc: &[u8]) -> Option<Self> {
+        let desc = desc.get(..size_of::<Self>())?;
+        Some(bytemuck::pod_read_unaligned(desc))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        bytemuck::bytes_of(self)
+    }
+
+    pub fn sp(&self) -> u64 {
+        self.pr_reg[31]
+    }
+
+    pub fn pc(&self) -> u64 {
+        self.pr_reg[32]
+    }
+
+    pub fn pstate(&self) -> u64 {
+        self.pr_reg[33]
+    }
+}
+
+const _: () = assert!(size_of::<AArch64PrStatus>() == 392);
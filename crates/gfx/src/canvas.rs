@@ -0,0 +1,471 @@
+//! Drawing shapes into a buffer of pixels, clipped to a rectangle and
+//! blended by their alpha.
+//!
+//! Rectangles with whole pixel coordinates are filled directly; anything
+//! else goes through the anti-aliasing rasterizer, with points given in
+//! pixels as `(x, y)`, where `(0.0, 0.0)` is the top left corner of the
+//! first pixel.
+
+use alloc::vec::Vec;
+
+use crate::color::composite;
+use crate::raster::{Point, Raster, ceil, floor, round, sin_cos, sqrt};
+
+/// A rectangle of pixels, which may be partly or wholly outside the canvas.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn right(&self) -> i32 {
+        self.x.saturating_add_unsigned(self.width)
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y.saturating_add_unsigned(self.height)
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        (self.x..self.right()).contains(&x) && (self.y..self.bottom()).contains(&y)
+    }
+
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        Rect::new(
+            x,
+            y,
+            right.saturating_sub(x).max(0) as u32,
+            bottom.saturating_sub(y).max(0) as u32,
+        )
+    }
+
+    /// This rectangle shrunk by `amount` on every side.
+    pub fn inset(&self, amount: u32) -> Rect {
+        Rect::new(
+            self.x.saturating_add_unsigned(amount),
+            self.y.saturating_add_unsigned(amount),
+            self.width.saturating_sub(2 * amount),
+            self.height.saturating_sub(2 * amount),
+        )
+    }
+}
+
+/// A buffer of `u32` pixels to draw into, with rows `stride` pixels apart.
+pub struct Canvas<'a> {
+    buffer: &'a mut [u32],
+    width: usize,
+    height: usize,
+    stride: usize,
+    clip: Rect,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(buffer: &'a mut [u32], width: usize, height: usize, stride: usize) -> Self {
+        assert!(width <= stride || height <= 1);
+        assert!(height == 0 || (height - 1) * stride + width <= buffer.len());
+        Canvas {
+            buffer,
+            width,
+            height,
+            stride,
+            clip: Rect::new(0, 0, width as u32, height as u32),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width as u32, self.height as u32)
+    }
+
+    pub fn clip(&self) -> Rect {
+        self.clip
+    }
+
+    /// Only draw inside `rect`, from now on.
+    pub fn set_clip(&mut self, rect: Rect) {
+        self.clip = rect.intersect(&self.bounds());
+    }
+
+    pub fn reset_clip(&mut self) {
+        self.clip = self.bounds();
+    }
+
+    pub fn pixel(&self, x: i32, y: i32) -> Option<u32> {
+        if !self.bounds().contains(x, y) {
+            return None;
+        }
+        Some(self.buffer[y as usize * self.stride + x as usize])
+    }
+
+    /// Blend a pixel, if it's inside the clip rectangle.
+    pub fn draw_pixel(&mut self, x: i32, y: i32, color: u32) {
+        if self.clip.contains(x, y) {
+            let pixel = &mut self.buffer[y as usize * self.stride + x as usize];
+            *pixel = composite(color, *pixel);
+        }
+    }
+
+    /// The rows of the canvas inside `rect`, which must be clipped already.
+    fn rows(&mut self, rect: Rect) -> impl Iterator<Item = &mut [u32]> {
+        let (x, width) = (rect.x as usize, rect.width as usize);
+        let start = rect.y as usize * self.stride;
+        self.buffer[start..]
+            .chunks_mut(self.stride)
+            .take(rect.height as usize)
+            .map(move |row| &mut row[x..][..width])
+    }
+
+    /// Replace every pixel inside the clip rectangle, without blending.
+    pub fn clear(&mut self, color: u32) {
+        let clip = self.clip;
+        for row in self.rows(clip) {
+            row.fill(color);
+        }
+    }
+
+    pub fn fill_rect(&mut self, rect: Rect, color: u32) {
+        let rect = rect.intersect(&self.clip);
+        if rect.is_empty() {
+            return;
+        }
+        for row in self.rows(rect) {
+            blend_span(row, color);
+        }
+    }
+
+    /// Draw the outline of a rectangle, `width` pixels wide and inside it.
+    pub fn stroke_rect(&mut self, rect: Rect, width: u32, color: u32) {
+        let inner = rect.inset(width);
+        if inner.is_empty() {
+            return self.fill_rect(rect, color);
+        }
+        let bottom = Rect::new(rect.x, inner.bottom(), rect.width, width);
+        self.fill_rect(Rect::new(rect.x, rect.y, rect.width, width), color);
+        self.fill_rect(bottom, color);
+        self.fill_rect(Rect::new(rect.x, inner.y, width, inner.height), color);
+        self.fill_rect(
+            Rect::new(inner.right(), inner.y, width, inner.height),
+            color,
+        );
+    }
+
+    /// Fill a polygon, anti-aliased.  The last point joins up with the
+    /// first.
+    pub fn fill_polygon(&mut self, points: &[(f32, f32)], color: u32) {
+        self.fill_contours(&[points], color);
+    }
+
+    /// Draw a line `width` pixels wide, with square ends at `from` and
+    /// `to`.
+    pub fn draw_line(&mut self, from: (f32, f32), to: (f32, f32), width: f32, color: u32) {
+        if let Some(quad) = line_quad(from, to, width) {
+            self.fill_polygon(&quad, color);
+        }
+    }
+
+    /// Draw connected lines `width` pixels wide, with rounded joints and
+    /// ends.
+    pub fn draw_polyline(&mut self, points: &[(f32, f32)], width: f32, color: u32) {
+        let radius = width / 2.0;
+        let mut contours: Vec<Vec<(f32, f32)>> = points
+            .windows(2)
+            .filter_map(|pair| line_quad(pair[0], pair[1], width))
+            .map(Vec::from)
+            .collect();
+        contours.extend(points.iter().map(|&p| ellipse(p, radius, radius)));
+        let contours: Vec<&[(f32, f32)]> = contours.iter().map(Vec::as_slice).collect();
+        self.fill_contours(&contours, color);
+    }
+
+    pub fn fill_rounded_rect(&mut self, rect: Rect, radius: f32, color: u32) {
+        if rect.is_empty() {
+            return;
+        }
+        let (x0, y0) = (rect.x as f32, rect.y as f32);
+        let (x1, y1) = (rect.right() as f32, rect.bottom() as f32);
+        let radius = radius.min((x1 - x0) / 2.0).min((y1 - y0) / 2.0);
+        if radius <= 0.0 {
+            return self.fill_rect(rect, color);
+        }
+        // The centers of the corners, clockwise from the bottom right
+        let corners = [
+            (x1 - radius, y1 - radius),
+            (x0 + radius, y1 - radius),
+            (x0 + radius, y0 + radius),
+            (x1 - radius, y0 + radius),
+        ];
+        let segments = arc_segments(radius);
+        let mut points = Vec::with_capacity(4 * (segments + 1));
+        for (quarter, (cx, cy)) in corners.into_iter().enumerate() {
+            points.extend((0..=segments).map(|step| {
+                let (x, y) = arc_point(quarter, step, segments);
+                (cx + radius * x, cy + radius * y)
+            }));
+        }
+        self.fill_polygon(&points, color);
+    }
+
+    pub fn fill_circle(&mut self, center: (f32, f32), radius: f32, color: u32) {
+        self.fill_ellipse(center, radius, radius, color);
+    }
+
+    pub fn fill_ellipse(&mut self, center: (f32, f32), rx: f32, ry: f32, color: u32) {
+        if rx > 0.0 && ry > 0.0 {
+            self.fill_polygon(&ellipse(center, rx, ry), color);
+        }
+    }
+
+    /// Blend an image onto the canvas with its top left corner at `(x, y)`.
+    pub fn draw_image(
+        &mut self,
+        (x, y): (i32, i32),
+        image: &[u32],
+        width: usize,
+        height: usize,
+        stride: usize,
+    ) {
+        let dest = Rect::new(x, y, width as u32, height as u32);
+        let visible = dest.intersect(&self.clip);
+        if visible.is_empty() {
+            return;
+        }
+        let (skip_x, skip_y) = ((visible.x - x) as usize, (visible.y - y) as usize);
+        let src_rows = image[skip_y * stride..].chunks(stride);
+        for (dst, src) in self.rows(visible).zip(src_rows) {
+            for (d, &s) in dst.iter_mut().zip(&src[skip_x..]) {
+                *d = composite(s, *d);
+            }
+        }
+    }
+
+    /// Fill the union of several closed contours, anti-aliased.
+    fn fill_contours(&mut self, contours: &[&[(f32, f32)]], color: u32) {
+        let points = contours.iter().flat_map(|c| c.iter());
+        let (mut min_x, mut min_y) = (f32::MAX, f32::MAX);
+        let (mut max_x, mut max_y) = (f32::MIN, f32::MIN);
+        for &(x, y) in points {
+            (min_x, min_y) = (min_x.min(x), min_y.min(y));
+            (max_x, max_y) = (max_x.max(x), max_y.max(y));
+        }
+        if min_x > max_x {
+            return;
+        }
+        let clip = self.clip;
+        let left = (floor(min_x).max(clip.x as f32) as i32).min(clip.right());
+        let top = (floor(min_y).max(clip.y as f32) as i32).min(clip.bottom());
+        let right = (ceil(max_x).min(clip.right() as f32) as i32).max(left);
+        let bottom = (ceil(max_y).min(clip.bottom() as f32) as i32).max(top);
+        let area = Rect::new(left, top, (right - left) as u32, (bottom - top) as u32);
+        if area.is_empty() {
+            return;
+        }
+
+        let mut raster = Raster::new(area.width as usize, area.height as usize);
+        let at = |(x, y): (f32, f32)| Point {
+            x: x - left as f32,
+            y: y - top as f32,
+        };
+        for &contour in contours {
+            // Wind every contour the same way, so overlaps don't cancel
+            // out
+            let reverse = signed_area(contour) < 0.0;
+            let Some(&last) = contour.last() else {
+                continue;
+            };
+            let mut prev = at(last);
+            for &p in contour {
+                let p = at(p);
+                let (from, to) = if reverse { (p, prev) } else { (prev, p) };
+                raster.draw_line(from, to);
+                prev = p;
+            }
+        }
+        let coverage = raster.coverage();
+        let rows = coverage.chunks(area.width as usize);
+        for (row, coverage) in self.rows(area).zip(rows) {
+            blend_coverage(row, coverage, color);
+        }
+    }
+}
+
+/// Blend one color over every pixel of a row.
+fn blend_span(row: &mut [u32], color: u32) {
+    if color >> 24 == 255 {
+        row.fill(color);
+    } else {
+        for pixel in row {
+            *pixel = composite(color, *pixel);
+        }
+    }
+}
+
+/// Blend a color over a row, with each pixel's coverage scaling its alpha.
+fn blend_coverage(row: &mut [u32], coverage: &[u8], color: u32) {
+    let alpha = color >> 24;
+    let rgb = color & 0x00FF_FFFF;
+    for (pixel, &c) in row.iter_mut().zip(coverage) {
+        let a = alpha * c as u32 / 255;
+        *pixel = composite(rgb | (a << 24), *pixel);
+    }
+}
+
+/// Twice the area of a polygon, positive if it winds clockwise (with y
+/// pointing down).
+fn signed_area(points: &[(f32, f32)]) -> f32 {
+    let Some(&last) = points.last() else {
+        return 0.0;
+    };
+    let mut prev = last;
+    let mut area = 0.0;
+    for &p in points {
+        area += prev.0 * p.1 - p.0 * prev.1;
+        prev = p;
+    }
+    area
+}
+
+/// The corners of a line `width` wide from `from` to `to`.
+fn line_quad(from: (f32, f32), to: (f32, f32), width: f32) -> Option<[(f32, f32); 4]> {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let len = sqrt(dx * dx + dy * dy);
+    if len == 0.0 || width <= 0.0 {
+        return None;
+    }
+    // Half the width, across the line
+    let (nx, ny) = (-dy / len * width / 2.0, dx / len * width / 2.0);
+    Some([
+        (from.0 + nx, from.1 + ny),
+        (to.0 + nx, to.1 + ny),
+        (to.0 - nx, to.1 - ny),
+        (from.0 - nx, from.1 - ny),
+    ])
+}
+
+/// How many lines to draw each quarter of a circle or ellipse with, to
+/// stay within a fraction of a pixel of the curve.
+fn arc_segments(radius: f32) -> usize {
+    2 + 2 * (round(radius) as usize).isqrt().min(64)
+}
+
+/// The point `step` of `segments` along a quarter of the unit circle,
+/// clockwise from the positive x axis in the given quarter, with y
+/// pointing down.
+fn arc_point(quarter: usize, step: usize, segments: usize) -> (f32, f32) {
+    let angle = core::f32::consts::FRAC_PI_2 * step as f32 / segments as f32;
+    let (sin, cos) = sin_cos(angle);
+    match quarter % 4 {
+        0 => (cos, sin),
+        1 => (-sin, cos),
+        2 => (-cos, -sin),
+        _ => (sin, -cos),
+    }
+}
+
+fn ellipse((cx, cy): (f32, f32), rx: f32, ry: f32) -> Vec<(f32, f32)> {
+    let segments = arc_segments(rx.max(ry));
+    (0..4 * segments)
+        .map(|i| {
+            let (x, y) = arc_point(i / segments, i % segments, segments);
+            (cx + rx * x, cy + ry * y)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Canvas, Rect};
+
+    const WHITE: u32 = 0xFFFF_FFFF;
+    const BLACK: u32 = 0xFF00_0000;
+
+    #[test]
+    fn test_rect_clipping() {
+        let mut buf = [0; 4 * 3];
+        let mut canvas = Canvas::new(&mut buf, 3, 3, 4);
+        canvas.fill_rect(Rect::new(-5, 1, 100, 1), WHITE);
+        canvas.set_clip(Rect::new(1, 0, 1, 10));
+        canvas.fill_rect(Rect::new(0, 0, 3, 3), BLACK);
+        #[rustfmt::skip]
+        assert_eq!(buf, [
+            0, BLACK, 0, 0,
+            WHITE, BLACK, WHITE, 0,
+            0, BLACK, 0, 0,
+        ]);
+
+        let mut buf = [0; 4 * 4];
+        let mut canvas = Canvas::new(&mut buf, 4, 4, 4);
+        canvas.stroke_rect(Rect::new(0, 0, 4, 4), 1, WHITE);
+        assert_eq!(&buf[4..8], [WHITE, 0, 0, WHITE]);
+        assert_eq!(&buf[12..], [WHITE; 4]);
+    }
+
+    #[test]
+    fn test_polygon_coverage() {
+        let mut buf = [BLACK; 4];
+        let mut canvas = Canvas::new(&mut buf, 4, 1, 4);
+        // Covers all of the second pixel and half of the third, winding
+        // either way
+        canvas.fill_polygon(&[(1.0, 0.0), (2.5, 0.0), (2.5, 1.0), (1.0, 1.0)], WHITE);
+        assert_eq!(buf, [BLACK, WHITE, 0xFF80_8080, BLACK]);
+
+        let mut buf = [BLACK; 4];
+        let mut canvas = Canvas::new(&mut buf, 4, 1, 4);
+        canvas.fill_polygon(&[(1.0, 1.0), (2.5, 1.0), (2.5, 0.0), (1.0, 0.0)], WHITE);
+        assert_eq!(buf, [BLACK, WHITE, 0xFF80_8080, BLACK]);
+    }
+
+    #[test]
+    fn test_overlaps_blend_once() {
+        let mut buf = [BLACK; 5];
+        let mut canvas = Canvas::new(&mut buf, 5, 1, 5);
+        let color = 0x80FF_FFFF;
+        canvas.draw_polyline(&[(0.0, 0.5), (3.0, 0.5), (5.0, 0.5)], 1.0, color);
+        assert!(buf.iter().all(|&p| p == buf[2]), "{buf:x?}");
+    }
+
+    #[test]
+    fn test_rounded_corners() {
+        let mut buf = [0; 10 * 10];
+        let mut canvas = Canvas::new(&mut buf, 10, 10, 10);
+        canvas.fill_rounded_rect(Rect::new(0, 0, 10, 10), 4.0, WHITE);
+        assert_eq!(buf[0], 0);
+        assert_eq!(buf[9 * 10 + 9], 0);
+        assert_eq!(buf[5 * 10], WHITE);
+        assert_eq!(buf[5 * 10 + 5], WHITE);
+
+        let mut buf = [0; 10 * 10];
+        let mut canvas = Canvas::new(&mut buf, 10, 10, 10);
+        canvas.fill_circle((5.0, 5.0), 5.0, WHITE);
+        assert_eq!(buf[0], 0);
+        assert_eq!(buf[5 * 10 + 5], WHITE);
+        // An edge of the circle
+        assert!(buf[5 * 10] >> 24 > 0 && buf[5 * 10] >> 24 < 255);
+    }
+}
@@ -7,6 +7,18 @@ pub const fn de_rgba(color: u32) -> (u8, u8, u8, u8) {
     (r, g, b, a)
 }
 
+/// Multiply the color channels of a pixel by its alpha.
+pub fn premultiply(pixel: u32) -> u32 {
+    // TODO: sRGB conversions
+    let (r, g, b, a) = de_rgba(pixel);
+    let (r, g, b) = (
+        r as u32 * a as u32 / 255,
+        g as u32 * a as u32 / 255,
+        b as u32 * a as u32 / 255,
+    );
+    rgba(r as u8, g as u8, b as u8, a)
+}
+
 pub fn blend(one: u32, two: u32) -> u32 {
     let [a, r, g, b] = one.to_be_bytes().map(u32::from);
     if a == 255 {
@@ -21,3 +33,19 @@ pub fn blend(one: u32, two: u32) -> u32 {
     );
     u32::from_be_bytes([a, r, g, b].map(|b| b as u8))
 }
+
+/// Blend `src` over `dst` by its alpha, keeping the opacity of what's
+/// underneath.  Without branches, so loops over rows of pixels can be
+/// vectorized.
+pub fn composite(src: u32, dst: u32) -> u32 {
+    let [a, r, g, b] = src.to_be_bytes().map(u32::from);
+    let [bg_a, bg_r, bg_g, bg_b] = dst.to_be_bytes().map(u32::from);
+    let inv = 255 - a;
+    let (r, g, b) = (
+        (r * a + bg_r * inv) / 255,
+        (g * a + bg_g * inv) / 255,
+        (b * a + bg_b * inv) / 255,
+    );
+    let a = a + bg_a * inv / 255;
+    u32::from_be_bytes([a, r, g, b].map(|b| b as u8))
+}
@@ -0,0 +1,259 @@
+//! Windows bitmaps, uncompressed or with bitfields, at any depth.  The
+//! run-length encoded variants aren't supported.
+
+use alloc::vec::Vec;
+
+use super::image::ImageError;
+use crate::color::{premultiply, rgba};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BmpHeader {
+    pub width: u32,
+    pub height: u32,
+    /// Whether the first row in the file is the top of the image; they're
+    /// stored bottom up otherwise.
+    pub top_down: bool,
+    pub bits_per_pixel: u16,
+    /// Where the pixels start, from the start of the file.
+    pub data_offset: u32,
+    /// The size of the DIB header after the file header.
+    pub dib_size: u32,
+    pub compression: u32,
+}
+
+const FILE_HEADER_SIZE: usize = 14;
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+fn u16_at(data: &[u8], pos: usize) -> Result<u16, ImageError> {
+    data.get(pos..pos + 2)
+        .map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(ImageError::Truncated)
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Result<u32, ImageError> {
+    data.get(pos..pos + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(ImageError::Truncated)
+}
+
+pub fn read_bmp_header(data: &[u8]) -> Result<BmpHeader, ImageError> {
+    if !data.starts_with(b"BM") {
+        return Err(ImageError::Unrecognized);
+    }
+    let data_offset = u32_at(data, 10)?;
+    let dib = FILE_HEADER_SIZE;
+    let dib_size = u32_at(data, dib)?;
+    let (width, height, bits_per_pixel, compression) = match dib_size {
+        // BITMAPCOREHEADER, from OS/2
+        12 => (
+            u16_at(data, dib + 4)? as i32,
+            u16_at(data, dib + 6)? as i16 as i32,
+            u16_at(data, dib + 10)?,
+            BI_RGB,
+        ),
+        // BITMAPINFOHEADER and its extensions
+        40 | 52 | 56 | 108 | 124 => (
+            u32_at(data, dib + 4)? as i32,
+            u32_at(data, dib + 8)? as i32,
+            u16_at(data, dib + 14)?,
+            u32_at(data, dib + 16)?,
+        ),
+        _ => return Err(ImageError::Unsupported),
+    };
+    if width < 0 || height == i32::MIN {
+        return Err(ImageError::Invalid);
+    }
+    if !matches!(bits_per_pixel, 1 | 4 | 8 | 16 | 24 | 32)
+        || !matches!(compression, BI_RGB | BI_BITFIELDS | BI_ALPHABITFIELDS)
+    {
+        return Err(ImageError::Unsupported);
+    }
+    Ok(BmpHeader {
+        width: width as u32,
+        height: height.unsigned_abs(),
+        top_down: height < 0,
+        bits_per_pixel,
+        data_offset,
+        dib_size,
+        compression,
+    })
+}
+
+/// A channel packed into a pixel by a mask, such as 0x7C00 for the red of
+/// a 5-5-5 pixel.
+#[derive(Copy, Clone)]
+struct Mask {
+    shift: u32,
+    max: u32,
+}
+
+impl Mask {
+    fn new(mask: u32) -> Self {
+        if mask == 0 {
+            return Mask { shift: 0, max: 0 };
+        }
+        let shift = mask.trailing_zeros();
+        Mask {
+            shift,
+            max: mask >> shift,
+        }
+    }
+
+    /// The channel from a pixel, scaled to 8 bits; `default` if the mask
+    /// is empty.
+    fn extract(&self, pixel: u32, default: u8) -> u8 {
+        if self.max == 0 {
+            return default;
+        }
+        // Masks can be up to 32 bits wide, so this doesn't fit in a u32
+        ((pixel >> self.shift & self.max) as u64 * 255 / self.max as u64) as u8
+    }
+}
+
+/// How the pixels of a row are turned into colors.
+enum Pixels {
+    Indexed(Vec<u32>),
+    Masked([Mask; 4]),
+}
+
+fn read_masks(header: &BmpHeader, data: &[u8]) -> Result<[Mask; 4], ImageError> {
+    let dib = FILE_HEADER_SIZE;
+    let masks = match header.compression {
+        BI_BITFIELDS | BI_ALPHABITFIELDS => {
+            // The masks follow the BITMAPINFOHEADER, inside the larger
+            // headers or after it
+            let count = if header.compression == BI_ALPHABITFIELDS || header.dib_size >= 56 {
+                4
+            } else {
+                3
+            };
+            let mut masks = [0; 4];
+            for (i, mask) in masks.iter_mut().enumerate().take(count) {
+                *mask = u32_at(data, dib + 40 + 4 * i)?;
+            }
+            masks
+        }
+        _ => match header.bits_per_pixel {
+            16 => [0x7C00, 0x03E0, 0x001F, 0],
+            // The fourth byte of 32-bit pixels is usually unused, and
+            // sometimes garbage
+            _ => [0xFF_0000, 0x00_FF00, 0x00_00FF, 0],
+        },
+    };
+    Ok(masks.map(Mask::new))
+}
+
+fn read_palette(header: &BmpHeader, data: &[u8]) -> Result<Vec<u32>, ImageError> {
+    let entry_size = if header.dib_size == 12 { 3 } else { 4 };
+    let mut count = 1usize << header.bits_per_pixel;
+    if header.dib_size >= 40 {
+        let used = u32_at(data, FILE_HEADER_SIZE + 32)? as usize;
+        if used != 0 {
+            count = count.min(used);
+        }
+    }
+    let start = FILE_HEADER_SIZE + header.dib_size as usize;
+    let table = data
+        .get(start..start + count * entry_size)
+        .ok_or(ImageError::Truncated)?;
+    Ok(table
+        .chunks_exact(entry_size)
+        .map(|c| rgba(c[2], c[1], c[0], 255))
+        .collect())
+}
+
+/// Decode a bitmap into `output`, with rows `out_stride` pixels apart; the
+/// buffer must be large enough for the image.
+pub fn decode_bmp(data: &[u8], output: &mut [u32], out_stride: usize) -> Result<(), ImageError> {
+    let header = read_bmp_header(data)?;
+    let (width, height) = (header.width as usize, header.height as usize);
+    let bpp = header.bits_per_pixel as usize;
+    if width == 0 || height == 0 {
+        return Ok(());
+    }
+
+    let pixels = if bpp <= 8 {
+        Pixels::Indexed(read_palette(&header, data)?)
+    } else {
+        Pixels::Masked(read_masks(&header, data)?)
+    };
+
+    // Rows are padded to a multiple of four bytes
+    let row_bytes = (width * bpp).div_ceil(32) * 4;
+    let start = header.data_offset as usize;
+    let end = row_bytes
+        .checked_mul(height)
+        .and_then(|len| len.checked_add(start))
+        .ok_or(ImageError::TooLarge)?;
+    let rows = data.get(start..end).ok_or(ImageError::Truncated)?;
+
+    for (i, row) in rows.chunks_exact(row_bytes).enumerate() {
+        let y = if header.top_down { i } else { height - 1 - i };
+        let out_row = &mut output[y * out_stride..][..width];
+        for (x, out) in out_row.iter_mut().enumerate() {
+            let color = match &pixels {
+                Pixels::Indexed(palette) => {
+                    // Packed from the highest bits of each byte
+                    let bit = x * bpp;
+                    let shift = 8 - bpp - bit % 8;
+                    let index = (row[bit / 8] >> shift) as usize & ((1 << bpp) - 1);
+                    palette.get(index).copied().unwrap_or(rgba(0, 0, 0, 255))
+                }
+                Pixels::Masked([r, g, b, a]) => {
+                    let bytes = &row[x * bpp / 8..][..bpp / 8];
+                    let mut value = [0; 4];
+                    value[..bytes.len()].copy_from_slice(bytes);
+                    let value = u32::from_le_bytes(value);
+                    rgba(
+                        r.extract(value, 0),
+                        g.extract(value, 0),
+                        b.extract(value, 0),
+                        a.extract(value, 255),
+                    )
+                }
+            };
+            *out = premultiply(color);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bottom_up() {
+        // 2x2 at 24 bits per pixel, with padded rows
+        let data = b"\x42\x4d\x46\x00\x00\x00\x00\x00\x00\x00\x36\x00\x00\x00\x28\x00\x00\x00\x02\x00\x00\x00\x02\x00\x00\x00\x01\x00\x18\x00\x00\x00\x00\x00\x10\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\xff\x00\x00\x00\xff\x00\x00\x00\x00\x00\xff\xff\xff\xff\x00\x00";
+        let header = read_bmp_header(data).unwrap();
+        assert_eq!((header.width, header.height), (2, 2));
+        assert!(!header.top_down);
+
+        let mut output = [0; 4];
+        decode_bmp(data, &mut output, 2).unwrap();
+        assert_eq!(
+            output,
+            [
+                rgba(255, 0, 0, 255),
+                rgba(255, 255, 255, 255),
+                rgba(0, 0, 255, 255),
+                rgba(0, 255, 0, 255),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mask_extract() {
+        let red = Mask::new(0x7c00);
+        assert_eq!(red.extract(0x7c00, 0), 255);
+        assert_eq!(red.extract(0x03ff, 0), 0);
+        let wide = Mask::new(u32::MAX);
+        assert_eq!(wide.extract(u32::MAX, 0), 255);
+        assert_eq!(wide.extract(u32::MAX / 2, 0), 127);
+        assert_eq!(Mask::new(0).extract(0x1234, 9), 9);
+    }
+}
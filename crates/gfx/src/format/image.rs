@@ -0,0 +1,120 @@
+//! Decoding images in any of the supported formats, recognized by their
+//! contents, into buffers of premultiplied `u32` pixels.
+
+use super::{bmp, png, qoi};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Qoi,
+    Png,
+    Bmp,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageError {
+    /// Not an image in any supported format.
+    Unrecognized,
+    /// The data ends before the image does.
+    Truncated,
+    /// The image is corrupt.
+    Invalid,
+    /// A valid image using a feature that isn't supported.
+    Unsupported,
+    /// The output buffer is too small for the image.
+    TooLarge,
+}
+
+/// The most pixels [`decode_image_to_vec`] will allocate for, so a
+/// corrupt header can't ask for gigabytes.
+pub const MAX_PIXELS: usize = 1 << 24;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub format: ImageFormat,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Recognize the format of an image from its first bytes.
+pub fn detect_format(data: &[u8]) -> Option<ImageFormat> {
+    if data.starts_with(b"qoif") {
+        Some(ImageFormat::Qoi)
+    } else if data.starts_with(&png::SIGNATURE) {
+        Some(ImageFormat::Png)
+    } else if data.starts_with(b"BM") {
+        Some(ImageFormat::Bmp)
+    } else {
+        None
+    }
+}
+
+/// Read the format and dimensions of an image, without decoding it.
+pub fn read_image_info(data: &[u8]) -> Result<ImageInfo, ImageError> {
+    let format = detect_format(data).ok_or(ImageError::Unrecognized)?;
+    let (width, height) = match format {
+        ImageFormat::Qoi => {
+            let (header, _) = qoi::read_qoi_header(data).ok_or(ImageError::Truncated)?;
+            (header.width as usize, header.height as usize)
+        }
+        ImageFormat::Png => {
+            let header = png::read_png_header(data)?;
+            (header.width as usize, header.height as usize)
+        }
+        ImageFormat::Bmp => {
+            let header = bmp::read_bmp_header(data)?;
+            (header.width as usize, header.height as usize)
+        }
+    };
+    Ok(ImageInfo {
+        format,
+        width,
+        height,
+    })
+}
+
+/// Decode an image into `output`, with rows `out_stride` pixels apart.
+pub fn decode_image(
+    data: &[u8],
+    output: &mut [u32],
+    out_stride: usize,
+) -> Result<ImageInfo, ImageError> {
+    let info = read_image_info(data)?;
+    if !fits(&info, output.len(), out_stride) {
+        return Err(ImageError::TooLarge);
+    }
+    match info.format {
+        ImageFormat::Qoi => {
+            let (header, stream) = qoi::read_qoi_header(data).ok_or(ImageError::Truncated)?;
+            qoi::decode_qoi(&header, stream, output, out_stride);
+        }
+        ImageFormat::Png => png::decode_png(data, output, out_stride)?,
+        ImageFormat::Bmp => bmp::decode_bmp(data, output, out_stride)?,
+    }
+    Ok(info)
+}
+
+/// Decode an image into a new buffer, with no space between rows.  Images
+/// of more than [`MAX_PIXELS`] pixels are rejected as too large.
+pub fn decode_image_to_vec(data: &[u8]) -> Result<(ImageInfo, alloc::vec::Vec<u32>), ImageError> {
+    let info = read_image_info(data)?;
+    let len = info
+        .width
+        .checked_mul(info.height)
+        .filter(|&len| len <= MAX_PIXELS)
+        .ok_or(ImageError::TooLarge)?;
+    let mut output = alloc::vec![0u32; len];
+    decode_image(data, &mut output, info.width)?;
+    Ok((info, output))
+}
+
+/// Whether an image fits in a buffer with the given stride.
+fn fits(info: &ImageInfo, len: usize, stride: usize) -> bool {
+    if info.width == 0 || info.height == 0 {
+        return true;
+    }
+    let last_row = (info.height - 1).checked_mul(stride);
+    info.width <= stride
+        && last_row
+            .and_then(|r| r.checked_add(info.width))
+            .is_some_and(|end| end <= len)
+}
@@ -1,2 +1,6 @@
+pub mod bmp;
+pub mod image;
 pub mod pcf;
+pub mod png;
 pub mod qoi;
+pub mod ttf;
@@ -0,0 +1,362 @@
+//! PNG images (<https://www.w3.org/TR/png/>), in every color type and
+//! bit depth, interlaced or not.  Chunk checksums aren't verified, and
+//! color space chunks (gamma, ICC profiles) are ignored.
+
+mod inflate;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::image::ImageError;
+use crate::color::{premultiply, rgba};
+
+pub const SIGNATURE: [u8; 8] = *b"\x89PNG\r\n\x1a\n";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PngHeader {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: u8,
+    pub interlaced: bool,
+}
+
+const GRAY: u8 = 0;
+const RGB: u8 = 2;
+const PALETTE: u8 = 3;
+const GRAY_ALPHA: u8 = 4;
+const RGBA: u8 = 6;
+
+impl PngHeader {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            GRAY | PALETTE => 1,
+            GRAY_ALPHA => 2,
+            RGB => 3,
+            _ => 4,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    /// The bytes in a row of `width` pixels, without its filter type.
+    fn row_bytes(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel()).div_ceil(8)
+    }
+}
+
+/// Iterate over the chunks of a PNG, as (type, data).
+fn chunks(data: &[u8]) -> impl Iterator<Item = Result<(&[u8; 4], &[u8]), ImageError>> {
+    let mut pos = SIGNATURE.len();
+    core::iter::from_fn(move || {
+        if pos >= data.len() {
+            return None;
+        }
+        let chunk = (|| {
+            let header = data.get(pos..pos + 8)?;
+            let len = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+            let kind = header[4..8].try_into().unwrap();
+            let body = data.get(pos + 8..(pos + 8).checked_add(len)?)?;
+            // Skip the checksum too
+            pos += 12 + len;
+            Some((kind, body))
+        })();
+        if chunk.is_none() {
+            pos = data.len();
+        }
+        Some(chunk.ok_or(ImageError::Truncated))
+    })
+}
+
+pub fn read_png_header(data: &[u8]) -> Result<PngHeader, ImageError> {
+    if !data.starts_with(&SIGNATURE) {
+        return Err(ImageError::Unrecognized);
+    }
+    let (kind, ihdr) = chunks(data).next().ok_or(ImageError::Truncated)??;
+    if kind != b"IHDR" || ihdr.len() < 13 {
+        return Err(ImageError::Invalid);
+    }
+    let header = PngHeader {
+        width: u32::from_be_bytes(ihdr[0..4].try_into().unwrap()),
+        height: u32::from_be_bytes(ihdr[4..8].try_into().unwrap()),
+        bit_depth: ihdr[8],
+        color_type: ihdr[9],
+        interlaced: match ihdr[12] {
+            0 => false,
+            1 => true,
+            _ => return Err(ImageError::Unsupported),
+        },
+    };
+    let valid_depth = match header.color_type {
+        GRAY => matches!(header.bit_depth, 1 | 2 | 4 | 8 | 16),
+        PALETTE => matches!(header.bit_depth, 1 | 2 | 4 | 8),
+        RGB | GRAY_ALPHA | RGBA => matches!(header.bit_depth, 8 | 16),
+        _ => false,
+    };
+    // Compression and filter methods other than 0 don't exist yet
+    if !valid_depth || ihdr[10] != 0 || ihdr[11] != 0 {
+        return Err(ImageError::Unsupported);
+    }
+    Ok(header)
+}
+
+/// The sub-images of an Adam7 interlaced image, as (x, y) of the first
+/// pixel and the spacing between pixels.
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// Everything needed to turn samples into pixels.
+struct Colors {
+    palette: Vec<u32>,
+    /// The color that's transparent, for images without alpha, as
+    /// samples at the image's depth.
+    transparent: Option<[u16; 3]>,
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Undo the filter of a row, given the row above it (all zeros for the
+/// first row) and the bytes per pixel.
+fn unfilter(filter: u8, row: &mut [u8], prev: &[u8], bpp: usize) -> Result<(), ImageError> {
+    match filter {
+        0 => (),
+        1 => {
+            for i in bpp..row.len() {
+                row[i] = row[i].wrapping_add(row[i - bpp]);
+            }
+        }
+        2 => {
+            for (x, &up) in row.iter_mut().zip(prev) {
+                *x = x.wrapping_add(up);
+            }
+        }
+        3 => {
+            for i in 0..row.len() {
+                let left = if i >= bpp { row[i - bpp] } else { 0 };
+                row[i] = row[i].wrapping_add(((left as u16 + prev[i] as u16) / 2) as u8);
+            }
+        }
+        4 => {
+            for i in 0..row.len() {
+                let (left, up_left) = if i >= bpp {
+                    (row[i - bpp], prev[i - bpp])
+                } else {
+                    (0, 0)
+                };
+                row[i] = row[i].wrapping_add(paeth(left, prev[i], up_left));
+            }
+        }
+        _ => return Err(ImageError::Invalid),
+    }
+    Ok(())
+}
+
+/// The `channel`th sample of the `x`th pixel of a row.
+fn sample(header: &PngHeader, row: &[u8], x: usize, channel: usize) -> u16 {
+    let i = x * header.channels() + channel;
+    match header.bit_depth {
+        16 => u16::from_be_bytes([row[2 * i], row[2 * i + 1]]),
+        8 => row[i] as u16,
+        depth => {
+            // Packed from the highest bits of each byte
+            let bit = i * depth as usize;
+            let byte = row[bit / 8];
+            let shift = 8 - depth as usize - bit % 8;
+            ((byte >> shift) & ((1 << depth) - 1)) as u16
+        }
+    }
+}
+
+fn pixel(header: &PngHeader, colors: &Colors, row: &[u8], x: usize) -> u32 {
+    let depth = header.bit_depth;
+    // Scale a sample to 8 bits
+    let to_u8 = |s: u16| match depth {
+        16 => (s >> 8) as u8,
+        8 => s as u8,
+        _ => (s as u32 * 255 / ((1 << depth) - 1)) as u8,
+    };
+    let s = |channel| sample(header, row, x, channel);
+    let opaque_unless = |samples: [u16; 3]| match colors.transparent {
+        Some(t) if t == samples => 0,
+        _ => 255,
+    };
+    match header.color_type {
+        GRAY => {
+            let v = s(0);
+            let g = to_u8(v);
+            rgba(g, g, g, opaque_unless([v, 0, 0]))
+        }
+        RGB => {
+            let (r, g, b) = (s(0), s(1), s(2));
+            rgba(to_u8(r), to_u8(g), to_u8(b), opaque_unless([r, g, b]))
+        }
+        PALETTE => colors
+            .palette
+            .get(s(0) as usize)
+            .copied()
+            .unwrap_or(rgba(0, 0, 0, 255)),
+        GRAY_ALPHA => {
+            let g = to_u8(s(0));
+            rgba(g, g, g, to_u8(s(1)))
+        }
+        _ => rgba(to_u8(s(0)), to_u8(s(1)), to_u8(s(2)), to_u8(s(3))),
+    }
+}
+
+/// Decode a PNG into `output`, with rows `out_stride` pixels apart; the
+/// buffer must be large enough for the image.
+pub fn decode_png(data: &[u8], output: &mut [u32], out_stride: usize) -> Result<(), ImageError> {
+    let header = read_png_header(data)?;
+    let (width, height) = (header.width as usize, header.height as usize);
+
+    let mut colors = Colors {
+        palette: Vec::new(),
+        transparent: None,
+    };
+    let mut compressed = Vec::new();
+    for chunk in chunks(data) {
+        let (kind, body) = chunk?;
+        match kind {
+            b"PLTE" => {
+                colors.palette = body
+                    .chunks_exact(3)
+                    .map(|c| rgba(c[0], c[1], c[2], 255))
+                    .collect();
+            }
+            b"tRNS" => match header.color_type {
+                PALETTE => {
+                    for (color, &alpha) in colors.palette.iter_mut().zip(body) {
+                        *color = (*color & 0x00FF_FFFF) | ((alpha as u32) << 24);
+                    }
+                }
+                GRAY | RGB => {
+                    let mut t = [0; 3];
+                    for (t, s) in t.iter_mut().zip(body.chunks_exact(2)) {
+                        *t = u16::from_be_bytes([s[0], s[1]]);
+                    }
+                    colors.transparent = Some(t);
+                }
+                _ => (),
+            },
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => (),
+        }
+    }
+    if header.color_type == PALETTE && colors.palette.is_empty() {
+        return Err(ImageError::Invalid);
+    }
+
+    let passes: &[_] = if header.interlaced {
+        &ADAM7
+    } else {
+        &[(0, 0, 1, 1)]
+    };
+    let pass_size = |&(x0, y0, dx, dy): &(usize, usize, usize, usize)| {
+        (
+            width.saturating_sub(x0).div_ceil(dx),
+            height.saturating_sub(y0).div_ceil(dy),
+        )
+    };
+    let expected: usize = passes
+        .iter()
+        .map(pass_size)
+        .filter(|&(w, h)| w > 0 && h > 0)
+        .map(|(w, h)| (1 + header.row_bytes(w)) * h)
+        .sum();
+
+    let raw = inflate::zlib_decompress(&compressed, expected).map_err(|e| match e {
+        inflate::InflateError::Truncated => ImageError::Truncated,
+        _ => ImageError::Invalid,
+    })?;
+    if raw.len() < expected {
+        return Err(ImageError::Truncated);
+    }
+
+    let bpp = header.bits_per_pixel().div_ceil(8);
+    let mut raw = &raw[..];
+    for pass in passes {
+        let (pass_width, pass_height) = pass_size(pass);
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+        let &(x0, y0, dx, dy) = pass;
+        let row_bytes = header.row_bytes(pass_width);
+        let mut prev = vec![0; row_bytes];
+        let mut row = vec![0; row_bytes];
+        for r in 0..pass_height {
+            let (filter, rest) = raw.split_first().ok_or(ImageError::Truncated)?;
+            row.copy_from_slice(&rest[..row_bytes]);
+            raw = &rest[row_bytes..];
+            unfilter(*filter, &mut row, &prev, bpp)?;
+
+            let out_row = (y0 + r * dy) * out_stride;
+            for c in 0..pass_width {
+                let color = pixel(&header, &colors, &row, c);
+                output[out_row + x0 + c * dx] = premultiply(color);
+            }
+            core::mem::swap(&mut prev, &mut row);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rgba_filtered() {
+        // 2x2, with the rows filtered by Sub and Up
+        let data = b"\x89\x50\x4e\x47\x0d\x0a\x1a\x0a\x00\x00\x00\x0d\x49\x48\x44\x52\x00\x00\x00\x02\x00\x00\x00\x02\x08\x06\x00\x00\x00\x72\xb6\x0d\x24\x00\x00\x00\x16\x49\x44\x41\x54\x78\x9c\x63\xfc\xcf\xc0\xf0\x9f\xf1\x3f\x43\x03\x13\x90\x66\x00\x01\x00\x36\x12\x04\x81\x99\x48\xe5\x12\x00\x00\x00\x00\x49\x45\x4e\x44\xae\x42\x60\x82";
+        let header = read_png_header(data).unwrap();
+        assert_eq!((header.width, header.height), (2, 2));
+        assert_eq!((header.bit_depth, header.color_type), (8, RGBA));
+
+        let mut output = [0; 6];
+        decode_png(data, &mut output, 3).unwrap();
+        assert_eq!(
+            output,
+            [
+                rgba(255, 0, 0, 255),
+                rgba(0, 127, 0, 127),
+                0,
+                rgba(255, 0, 255, 255),
+                rgba(0, 127, 0, 127),
+                0,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_packed_palette() {
+        // 3x1, at two bits per pixel, with the first entry transparent
+        let data = b"\x89\x50\x4e\x47\x0d\x0a\x1a\x0a\x00\x00\x00\x0d\x49\x48\x44\x52\x00\x00\x00\x03\x00\x00\x00\x01\x02\x03\x00\x00\x00\x66\x8e\xfc\x27\x00\x00\x00\x09\x50\x4c\x54\x45\xff\x00\x00\x00\xff\x00\x00\x00\xff\x2d\x4a\xcd\x8a\x00\x00\x00\x01\x74\x52\x4e\x53\x00\x40\xe6\xd8\x66\x00\x00\x00\x0a\x49\x44\x41\x54\x78\x9c\x63\x90\x00\x00\x00\x1a\x00\x19\x2d\x88\xf4\x36\x00\x00\x00\x00\x49\x45\x4e\x44\xae\x42\x60\x82";
+        let mut output = [1; 3];
+        decode_png(data, &mut output, 3).unwrap();
+        assert_eq!(output, [0, rgba(0, 255, 0, 255), rgba(0, 0, 255, 255)]);
+    }
+}
@@ -0,0 +1,396 @@
+//! A decoder for DEFLATE streams (RFC 1951), inside the zlib wrapper
+//! (RFC 1950) that PNG uses.
+
+use alloc::vec::Vec;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InflateError {
+    /// The stream ends before its last block does.
+    Truncated,
+    /// A bad zlib header, block type or Huffman code.
+    Invalid,
+    /// The output would be larger than the limit.
+    TooLarge,
+}
+
+type Result<T> = core::result::Result<T, InflateError>;
+
+/// Reads bits from a byte stream, least significant bit first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            pos: 0,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    /// Buffer at least `n` bits, if there are that many left.
+    fn fill(&mut self, n: u32) {
+        while self.count < n && self.pos < self.data.len() {
+            self.bits |= (self.data[self.pos] as u64) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+    }
+
+    /// The next `n` bits, padded with zeros past the end of the stream.
+    fn peek(&mut self, n: u32) -> u32 {
+        self.fill(n);
+        (self.bits & ((1 << n) - 1)) as u32
+    }
+
+    fn consume(&mut self, n: u32) -> Result<()> {
+        if self.count < n {
+            return Err(InflateError::Truncated);
+        }
+        self.bits >>= n;
+        self.count -= n;
+        Ok(())
+    }
+
+    fn read(&mut self, n: u32) -> Result<u32> {
+        let value = self.peek(n);
+        self.consume(n)?;
+        Ok(value)
+    }
+
+    /// Skip to the next byte boundary, and give back whole bytes that
+    /// were buffered, so they can be read directly.
+    fn align(&mut self) {
+        let whole_bytes = self.count / 8;
+        self.pos -= whole_bytes as usize;
+        self.bits = 0;
+        self.count = 0;
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or(InflateError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+}
+
+/// Codes up to this long are decoded with one table lookup.
+const FAST_BITS: u32 = 9;
+const MAX_BITS: usize = 15;
+
+/// A canonical Huffman code.
+struct Huffman {
+    /// The number of codes of each length.
+    counts: [u16; MAX_BITS + 1],
+    /// Symbols ordered by code.
+    symbols: [u16; 288],
+    /// `(symbol << 4) | length` for codes of up to `FAST_BITS`, indexed
+    /// by the next bits of the stream; zero for longer codes.
+    fast: [u16; 1 << FAST_BITS],
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self> {
+        let mut code = Huffman {
+            counts: [0; MAX_BITS + 1],
+            symbols: [0; 288],
+            fast: [0; 1 << FAST_BITS],
+        };
+        for &len in lengths {
+            code.counts[len as usize] += 1;
+        }
+        code.counts[0] = 0;
+
+        // More codes of some length than there's room for
+        let mut left = 1i32;
+        for len in 1..=MAX_BITS {
+            left = (left << 1) - code.counts[len] as i32;
+            if left < 0 {
+                return Err(InflateError::Invalid);
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + code.counts[len];
+        }
+        // The first code of each length
+        let mut next_code = [0u32; MAX_BITS + 1];
+        let mut c = 0u32;
+        for (next, &count) in next_code.iter_mut().zip(&code.counts).skip(1) {
+            *next = c;
+            c = (c + count as u32) << 1;
+        }
+
+        for (symbol, &len) in lengths.iter().enumerate() {
+            let len = len as usize;
+            if len == 0 {
+                continue;
+            }
+            code.symbols[offsets[len] as usize] = symbol as u16;
+            offsets[len] += 1;
+
+            let bits = next_code[len];
+            next_code[len] += 1;
+            if len as u32 <= FAST_BITS {
+                // Codes are stored starting from their first bit, which
+                // the reader gives as the lowest
+                let reversed = bits.reverse_bits() >> (32 - len);
+                let entry = ((symbol as u16) << 4) | len as u16;
+                for fill in (reversed..1 << FAST_BITS).step_by(1 << len) {
+                    code.fast[fill as usize] = entry;
+                }
+            }
+        }
+        Ok(code)
+    }
+
+    fn decode(&self, reader: &mut BitReader<'_>) -> Result<u16> {
+        let entry = self.fast[reader.peek(FAST_BITS) as usize];
+        if entry != 0 {
+            reader.consume((entry & 0xf) as u32)?;
+            return Ok(entry >> 4);
+        }
+
+        // One bit at a time: codes of each length are consecutive,
+        // following the last code of the length before
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for len in 1..=MAX_BITS {
+            code |= reader.read(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(InflateError::Invalid)
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// The order code lengths for the code length code are sent in.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn fixed_codes() -> Result<(Huffman, Huffman)> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes(reader: &mut BitReader<'_>) -> Result<(Huffman, Huffman)> {
+    let literals = reader.read(5)? as usize + 257;
+    let distances = reader.read(5)? as usize + 1;
+    let code_lengths = reader.read(4)? as usize + 4;
+    if literals > 286 || distances > 30 {
+        return Err(InflateError::Invalid);
+    }
+
+    let mut lengths = [0u8; 19];
+    for &i in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[i] = reader.read(3)? as u8;
+    }
+    let length_code = Huffman::new(&lengths)?;
+
+    // Both codes' lengths are sent together, and repeats can cross
+    // from one to the other
+    let mut lengths = [0u8; 286 + 30];
+    let mut i = 0;
+    while i < literals + distances {
+        let symbol = length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let prev = *i
+                    .checked_sub(1)
+                    .map(|p| &lengths[p])
+                    .ok_or(InflateError::Invalid)?;
+                (prev, 3 + reader.read(2)? as usize)
+            }
+            17 => (0, 3 + reader.read(3)? as usize),
+            18 => (0, 11 + reader.read(7)? as usize),
+            _ => return Err(InflateError::Invalid),
+        };
+        if i + repeat > literals + distances {
+            return Err(InflateError::Invalid);
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    // A block that can't end
+    if lengths[256] == 0 {
+        return Err(InflateError::Invalid);
+    }
+    Ok((
+        Huffman::new(&lengths[..literals])?,
+        Huffman::new(&lengths[literals..literals + distances])?,
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader<'_>,
+    out: &mut Vec<u8>,
+    limit: usize,
+    (literals, distances): &(Huffman, Huffman),
+) -> Result<()> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        match symbol {
+            0..=255 => {
+                if out.len() >= limit {
+                    return Err(InflateError::TooLarge);
+                }
+                out.push(symbol as u8);
+            }
+            256 => return Ok(()),
+            257..=285 => {
+                let i = symbol - 257;
+                let len = LENGTH_BASE[i] as usize + reader.read(LENGTH_EXTRA[i] as u32)? as usize;
+                let d = distances.decode(reader)? as usize;
+                if d >= 30 {
+                    return Err(InflateError::Invalid);
+                }
+                let dist = DIST_BASE[d] as usize + reader.read(DIST_EXTRA[d] as u32)? as usize;
+                if dist > out.len() {
+                    return Err(InflateError::Invalid);
+                }
+                if out.len() + len > limit {
+                    return Err(InflateError::TooLarge);
+                }
+                // The copy may overlap what it's writing
+                let start = out.len() - dist;
+                for i in 0..len {
+                    out.push(out[start + i]);
+                }
+            }
+            _ => return Err(InflateError::Invalid),
+        }
+    }
+}
+
+/// Decompress a zlib stream of at most `limit` bytes.  The checksum at
+/// the end isn't verified.
+pub fn zlib_decompress(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    let [cmf, flg, ..] = *data else {
+        return Err(InflateError::Truncated);
+    };
+    let deflate = cmf & 0x0f == 8;
+    let preset_dictionary = flg & 0x20 != 0;
+    if !deflate || preset_dictionary || (cmf as u16 * 256 + flg as u16) % 31 != 0 {
+        return Err(InflateError::Invalid);
+    }
+    inflate(&data[2..], limit)
+}
+
+/// Decompress a raw DEFLATE stream of at most `limit` bytes.
+pub fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    let mut reader = BitReader::new(data);
+    let mut out = Vec::new();
+    loop {
+        let last = reader.read(1)? == 1;
+        match reader.read(2)? {
+            0 => {
+                reader.align();
+                let header = reader.read_bytes(4)?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                let inverse = u16::from_le_bytes([header[2], header[3]]);
+                if len != !inverse {
+                    return Err(InflateError::Invalid);
+                }
+                if out.len() + len as usize > limit {
+                    return Err(InflateError::TooLarge);
+                }
+                out.extend_from_slice(reader.read_bytes(len as usize)?);
+            }
+            1 => inflate_block(&mut reader, &mut out, limit, &fixed_codes()?)?,
+            2 => {
+                let codes = dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut out, limit, &codes)?;
+            }
+            _ => return Err(InflateError::Invalid),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InflateError, inflate, zlib_decompress};
+
+    #[test]
+    fn test_stored() {
+        let data = [0x01, 0x05, 0x00, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o'];
+        assert_eq!(inflate(&data, 100).unwrap(), b"hello");
+        assert_eq!(inflate(&data, 4), Err(InflateError::TooLarge));
+        assert_eq!(inflate(&data[..8], 100), Err(InflateError::Truncated));
+    }
+
+    #[test]
+    fn test_fixed() {
+        // zlib.compress(b"hello hello hello hello!")
+        let data = [
+            0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0x15, 0x01, 0x70,
+            0xd5, 0x08, 0xd2,
+        ];
+        assert_eq!(
+            zlib_decompress(&data, 100).unwrap(),
+            b"hello hello hello hello!"
+        );
+        assert_eq!(zlib_decompress(&data[1..], 100), Err(InflateError::Invalid));
+    }
+
+    #[test]
+    fn test_dynamic() {
+        // zlib.compress(b" ".join(str(i * i).encode() for i in range(60)), 9)
+        let data = [
+            0x78, 0xda, 0x15, 0x8e, 0xc1, 0x01, 0xc0, 0x30, 0x08, 0x02, 0x57, 0x61, 0x04, 0x51,
+            0x6b, 0x74, 0xff, 0xc5, 0x0a, 0x2f, 0x12, 0x05, 0xbc, 0x00, 0xd1, 0x38, 0x70, 0x90,
+            0x1f, 0x6a, 0xd0, 0x87, 0x69, 0x2c, 0xc1, 0x08, 0x30, 0xa5, 0xdd, 0xda, 0xca, 0x71,
+            0xb2, 0xc8, 0x93, 0x9f, 0x74, 0x0f, 0x95, 0x2d, 0xbf, 0xd2, 0xf2, 0x75, 0x4b, 0xb7,
+            0xf1, 0xe5, 0xe1, 0x7b, 0x83, 0x91, 0x6f, 0xa4, 0x4f, 0xff, 0xa7, 0xf9, 0x6a, 0x7f,
+            0xf2, 0xdd, 0xb8, 0x57, 0x41, 0x86, 0x1a, 0x48, 0x55, 0xd1, 0x9d, 0x4c, 0x95, 0xb3,
+            0x7c, 0xa5, 0x7d, 0xee, 0xf3, 0xdd, 0x31, 0xc0, 0x98, 0xe4, 0x89, 0x88, 0xdb, 0x66,
+            0x10, 0x61, 0x86, 0x29, 0x68, 0xe2, 0x8c, 0x43, 0x56, 0x34, 0xb2, 0x83, 0x22, 0x53,
+            0x22, 0xc7, 0xaf, 0xe7, 0xd9, 0x7a, 0x7b, 0xf2, 0x95, 0x13, 0xa5, 0x7e, 0x43, 0x8b,
+            0xbc, 0xd4, 0x57, 0xbd, 0xfc, 0x01, 0xea, 0x2d, 0x2e, 0x92,
+        ];
+        let expected = (0..60)
+            .map(|i| alloc::format!("{}", i * i))
+            .collect::<alloc::vec::Vec<_>>()
+            .join(" ");
+        assert_eq!(zlib_decompress(&data, 1000).unwrap(), expected.as_bytes());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::color::{de_rgba, rgba};
+use crate::color::{de_rgba, premultiply, rgba};
 
 #[derive(Debug)]
 pub struct QoiHeader {
@@ -26,17 +26,6 @@ pub fn read_qoi_header(data: &[u8]) -> Option<(QoiHeader, &[u8])> {
         .filter(|(h, _)| h.magic == *b"qoif")
 }
 
-fn premultiply(pixel: u32) -> u32 {
-    // TODO: sRGB conversions
-    let (r, g, b, a) = de_rgba(pixel);
-    let (r, g, b) = (
-        r as u32 * a as u32 / 255,
-        g as u32 * a as u32 / 255,
-        b as u32 * a as u32 / 255,
-    );
-    rgba(r as u8, g as u8, b as u8, a)
-}
-
 pub fn decode_qoi(header: &QoiHeader, mut stream: &[u8], output: &mut [u32], out_stride: usize) {
     let mut history = [rgba(0, 0, 0, 0); 64];
 
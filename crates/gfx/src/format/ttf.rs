@@ -0,0 +1,801 @@
+//! TrueType fonts, and OpenType fonts with TrueType outlines, drawn
+//! anti-aliased at any size.
+//!
+//! Only the tables needed to lay out and draw simple text are read:
+//! `cmap`, `glyf`/`loca`, `hmtx` and the headers.  Hinting instructions
+//! and kerning are ignored, and fonts with CFF outlines (`OTTO`) aren't
+//! supported.
+//!
+//! Glyphs are rasterized by accumulating the signed area each outline
+//! segment covers in every pixel, then summing along the rows; see
+//! <https://medium.com/@raphlinus/inside-the-fastest-font-renderer-in-the-world-75ae5270c445>.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::raster::{Point, Raster, ceil, floor};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TtfError {
+    /// The data ends in the middle of a table.
+    Truncated,
+    /// Not a TrueType or OpenType font.
+    BadMagic,
+    MissingTable([u8; 4]),
+    /// A font collection, or CFF outlines.
+    Unsupported,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_i16(data: &[u8], offset: usize) -> Option<i16> {
+    read_u16(data, offset).map(|v| v as i16)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+#[derive(Copy, Clone)]
+enum Cmap<'a> {
+    /// Segments of 16-bit characters.
+    Format4(&'a [u8]),
+    /// Groups of 32-bit characters.
+    Format12(&'a [u8]),
+}
+
+impl Cmap<'_> {
+    fn lookup(&self, c: u32) -> Option<u16> {
+        match *self {
+            Cmap::Format4(table) => {
+                let c = u16::try_from(c).ok()?;
+                let seg_count = read_u16(table, 6)? as usize / 2;
+                let ends = 14;
+                let starts = ends + 2 * seg_count + 2;
+                let deltas = starts + 2 * seg_count;
+                let range_offsets = deltas + 2 * seg_count;
+
+                // The first segment ending at or after the character
+                let (mut lo, mut hi) = (0, seg_count);
+                while lo < hi {
+                    let mid = (lo + hi) / 2;
+                    if read_u16(table, ends + 2 * mid)? < c {
+                        lo = mid + 1;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                let seg = lo;
+                let start = read_u16(table, starts + 2 * seg)?;
+                if seg == seg_count || c < start {
+                    return None;
+                }
+                let delta = read_u16(table, deltas + 2 * seg)?;
+                let range_offset_pos = range_offsets + 2 * seg;
+                let range_offset = read_u16(table, range_offset_pos)? as usize;
+                let glyph = if range_offset == 0 {
+                    c.wrapping_add(delta)
+                } else {
+                    // The offset is relative to where it's stored
+                    let pos = range_offset_pos + range_offset + 2 * (c - start) as usize;
+                    match read_u16(table, pos)? {
+                        0 => 0,
+                        g => g.wrapping_add(delta),
+                    }
+                };
+                Some(glyph).filter(|&g| g != 0)
+            }
+            Cmap::Format12(table) => {
+                let groups = read_u32(table, 12)? as usize;
+                let (mut lo, mut hi) = (0, groups);
+                while lo < hi {
+                    let mid = (lo + hi) / 2;
+                    let group = 16 + 12 * mid;
+                    let (start, end) = (read_u32(table, group)?, read_u32(table, group + 4)?);
+                    if c < start {
+                        hi = mid;
+                    } else if c > end {
+                        lo = mid + 1;
+                    } else {
+                        let glyph = read_u32(table, group + 8)?.checked_add(c - start)?;
+                        return u16::try_from(glyph).ok().filter(|&g| g != 0);
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct TrueTypeFont<'a> {
+    units_per_em: u16,
+    num_glyphs: u16,
+    long_loca: bool,
+    loca: &'a [u8],
+    glyf: &'a [u8],
+    hmtx: &'a [u8],
+    num_h_metrics: u16,
+    cmap: Cmap<'a>,
+    ascent: i16,
+    descent: i16,
+    line_gap: i16,
+}
+
+/// The vertical metrics of a font at some size, in pixels.
+#[derive(Copy, Clone, Debug)]
+pub struct LineMetrics {
+    /// The height above the baseline.
+    pub ascent: f32,
+    /// The depth below the baseline, as a negative number.
+    pub descent: f32,
+    pub line_gap: f32,
+}
+
+/// The coverage of a rendered glyph, from 0 to 255.
+#[derive(Clone, Debug)]
+pub struct GlyphBitmap {
+    pub width: usize,
+    pub height: usize,
+    /// The position of the top left corner of the bitmap, relative to
+    /// the glyph's origin on the baseline, with y pointing down.
+    pub left: i32,
+    pub top: i32,
+    pub coverage: Vec<u8>,
+}
+
+/// A 2x3 matrix, for the parts of composite glyphs.
+#[derive(Copy, Clone)]
+struct Transform([f32; 6]);
+
+impl Transform {
+    fn apply(&self, x: f32, y: f32) -> Point {
+        let [a, b, c, d, e, f] = self.0;
+        Point {
+            x: a * x + c * y + e,
+            y: b * x + d * y + f,
+        }
+    }
+
+    /// This transform, after another one.
+    fn then(&self, outer: &Transform) -> Transform {
+        let [a, b, c, d, e, f] = self.0;
+        let p = outer.apply(e, f);
+        let o = outer.0;
+        Transform([
+            o[0] * a + o[2] * b,
+            o[1] * a + o[3] * b,
+            o[0] * c + o[2] * d,
+            o[1] * c + o[3] * d,
+            p.x,
+            p.y,
+        ])
+    }
+}
+
+/// The points of a glyph's contours, in pixels from its origin.
+#[derive(Default)]
+struct Outline {
+    points: Vec<(Point, bool)>,
+    /// The index after the last point of each contour.
+    ends: Vec<usize>,
+}
+
+/// How deeply composite glyphs may be nested.
+const MAX_COMPONENT_DEPTH: usize = 8;
+
+impl<'a> TrueTypeFont<'a> {
+    pub fn units_per_em(&self) -> u16 {
+        self.units_per_em
+    }
+
+    pub fn num_glyphs(&self) -> u16 {
+        self.num_glyphs
+    }
+
+    /// The glyph for a character, or glyph 0 (the "missing character"
+    /// box) if the font doesn't have one.
+    pub fn glyph_index(&self, c: char) -> u16 {
+        self.cmap.lookup(c as u32).unwrap_or(0)
+    }
+
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.cmap.lookup(c as u32).is_some()
+    }
+
+    fn scale(&self, size: f32) -> f32 {
+        size / self.units_per_em as f32
+    }
+
+    /// The vertical metrics for text `size` pixels to the em.
+    pub fn line_metrics(&self, size: f32) -> LineMetrics {
+        let scale = self.scale(size);
+        LineMetrics {
+            ascent: self.ascent as f32 * scale,
+            descent: self.descent as f32 * scale,
+            line_gap: self.line_gap as f32 * scale,
+        }
+    }
+
+    /// How far a glyph moves the pen, in pixels for text `size` pixels to
+    /// the em.
+    pub fn advance(&self, glyph: u16, size: f32) -> f32 {
+        // Glyphs past the last metric share its advance
+        let index = glyph.min(self.num_h_metrics.saturating_sub(1)) as usize;
+        let advance = read_u16(self.hmtx, 4 * index).unwrap_or(0);
+        advance as f32 * self.scale(size)
+    }
+
+    fn glyph_data(&self, glyph: u16) -> Option<&'a [u8]> {
+        if glyph >= self.num_glyphs {
+            return None;
+        }
+        let i = glyph as usize;
+        let (start, end) = if self.long_loca {
+            (read_u32(self.loca, 4 * i)?, read_u32(self.loca, 4 * i + 4)?)
+        } else {
+            let start = read_u16(self.loca, 2 * i)? as u32 * 2;
+            (start, read_u16(self.loca, 2 * i + 2)? as u32 * 2)
+        };
+        self.glyf.get(start as usize..end as usize)
+    }
+
+    fn outline(
+        &self,
+        glyph: u16,
+        transform: &Transform,
+        out: &mut Outline,
+        depth: usize,
+    ) -> Option<()> {
+        // Glyphs without contours, like spaces, have no data
+        let data = self.glyph_data(glyph)?;
+        if data.is_empty() {
+            return Some(());
+        }
+        let contours = read_i16(data, 0)?;
+        if contours >= 0 {
+            self.simple_outline(data, contours as usize, transform, out)
+        } else if depth < MAX_COMPONENT_DEPTH {
+            self.composite_outline(data, transform, out, depth)
+        } else {
+            None
+        }
+    }
+
+    fn simple_outline(
+        &self,
+        data: &[u8],
+        contours: usize,
+        transform: &Transform,
+        out: &mut Outline,
+    ) -> Option<()> {
+        const ON_CURVE: u8 = 0x01;
+        const X_SHORT: u8 = 0x02;
+        const Y_SHORT: u8 = 0x04;
+        const REPEAT: u8 = 0x08;
+        const X_SAME_OR_POSITIVE: u8 = 0x10;
+        const Y_SAME_OR_POSITIVE: u8 = 0x20;
+
+        let ends_base = 10;
+        let point_count = match contours {
+            0 => 0,
+            n => read_u16(data, ends_base + 2 * (n - 1))? as usize + 1,
+        };
+        let instructions = read_u16(data, ends_base + 2 * contours)? as usize;
+        let mut pos = ends_base + 2 * contours + 2 + instructions;
+
+        let mut flags = Vec::with_capacity(point_count);
+        while flags.len() < point_count {
+            let flag = *data.get(pos)?;
+            pos += 1;
+            let repeat = if flag & REPEAT != 0 {
+                pos += 1;
+                *data.get(pos - 1)? as usize
+            } else {
+                0
+            };
+            for _ in 0..=repeat {
+                flags.push(flag);
+            }
+        }
+        flags.truncate(point_count);
+
+        // Coordinates are deltas, first all the x values, then all the y
+        let mut read_coords = |short: u8, same_or_positive: u8| -> Option<Vec<i32>> {
+            let mut value = 0i32;
+            let mut coords = Vec::with_capacity(point_count);
+            for &flag in &flags {
+                if flag & short != 0 {
+                    let delta = *data.get(pos)? as i32;
+                    pos += 1;
+                    value += if flag & same_or_positive != 0 {
+                        delta
+                    } else {
+                        -delta
+                    };
+                } else if flag & same_or_positive == 0 {
+                    value += read_i16(data, pos)? as i32;
+                    pos += 2;
+                }
+                coords.push(value);
+            }
+            Some(coords)
+        };
+        let xs = read_coords(X_SHORT, X_SAME_OR_POSITIVE)?;
+        let ys = read_coords(Y_SHORT, Y_SAME_OR_POSITIVE)?;
+
+        let base = out.points.len();
+        for i in 0..point_count {
+            let point = transform.apply(xs[i] as f32, ys[i] as f32);
+            out.points.push((point, flags[i] & ON_CURVE != 0));
+        }
+        let mut last_end = 0;
+        for c in 0..contours {
+            let end = read_u16(data, ends_base + 2 * c)? as usize + 1;
+            if end <= last_end || end > point_count {
+                return None;
+            }
+            out.ends.push(base + end);
+            last_end = end;
+        }
+        Some(())
+    }
+
+    fn composite_outline(
+        &self,
+        data: &[u8],
+        transform: &Transform,
+        out: &mut Outline,
+        depth: usize,
+    ) -> Option<()> {
+        const ARGS_ARE_WORDS: u16 = 0x0001;
+        const ARGS_ARE_XY_VALUES: u16 = 0x0002;
+        const HAVE_SCALE: u16 = 0x0008;
+        const MORE_COMPONENTS: u16 = 0x0020;
+        const HAVE_XY_SCALE: u16 = 0x0040;
+        const HAVE_2X2: u16 = 0x0080;
+
+        let f2dot14 = |pos: usize| Some(read_i16(data, pos)? as f32 / 16384.0);
+
+        let mut pos = 10;
+        loop {
+            let flags = read_u16(data, pos)?;
+            let glyph = read_u16(data, pos + 2)?;
+            pos += 4;
+            let (dx, dy) = if flags & ARGS_ARE_WORDS != 0 {
+                pos += 4;
+                (
+                    read_i16(data, pos - 4)? as f32,
+                    read_i16(data, pos - 2)? as f32,
+                )
+            } else {
+                pos += 2;
+                (
+                    *data.get(pos - 2)? as i8 as f32,
+                    *data.get(pos - 1)? as i8 as f32,
+                )
+            };
+            // Components can also be placed by matching up points,
+            // which isn't supported; they're drawn where they are
+            let (dx, dy) = if flags & ARGS_ARE_XY_VALUES != 0 {
+                (dx, dy)
+            } else {
+                (0.0, 0.0)
+            };
+            let [a, b, c, d] = if flags & HAVE_SCALE != 0 {
+                pos += 2;
+                let s = f2dot14(pos - 2)?;
+                [s, 0.0, 0.0, s]
+            } else if flags & HAVE_XY_SCALE != 0 {
+                pos += 4;
+                [f2dot14(pos - 4)?, 0.0, 0.0, f2dot14(pos - 2)?]
+            } else if flags & HAVE_2X2 != 0 {
+                pos += 8;
+                [
+                    f2dot14(pos - 8)?,
+                    f2dot14(pos - 6)?,
+                    f2dot14(pos - 4)?,
+                    f2dot14(pos - 2)?,
+                ]
+            } else {
+                [1.0, 0.0, 0.0, 1.0]
+            };
+            let component = Transform([a, b, c, d, dx, dy]).then(transform);
+            self.outline(glyph, &component, out, depth + 1)?;
+
+            if flags & MORE_COMPONENTS == 0 {
+                return Some(());
+            }
+        }
+    }
+
+    /// Render a glyph for text `size` pixels to the em.  Glyphs with
+    /// nothing to draw, like spaces, give an empty bitmap.
+    pub fn rasterize(&self, glyph: u16, size: f32) -> GlyphBitmap {
+        let empty = GlyphBitmap {
+            width: 0,
+            height: 0,
+            left: 0,
+            top: 0,
+            coverage: Vec::new(),
+        };
+
+        let scale = self.scale(size);
+        // Flip y to point down, as it does on the screen
+        let transform = Transform([scale, 0.0, 0.0, -scale, 0.0, 0.0]);
+        let mut outline = Outline::default();
+        if self.outline(glyph, &transform, &mut outline, 0).is_none() || outline.points.is_empty() {
+            return empty;
+        }
+
+        let (mut min, mut max) = (outline.points[0].0, outline.points[0].0);
+        for (p, _) in &outline.points {
+            min = Point {
+                x: min.x.min(p.x),
+                y: min.y.min(p.y),
+            };
+            max = Point {
+                x: max.x.max(p.x),
+                y: max.y.max(p.y),
+            };
+        }
+        let (left, top) = (floor(min.x), floor(min.y));
+        let width = (ceil(max.x) - left) as usize;
+        let height = (ceil(max.y) - top) as usize;
+        if width == 0 || height == 0 {
+            return empty;
+        }
+
+        let mut raster = Raster::new(width, height);
+        let origin = Point { x: left, y: top };
+        let mut start = 0;
+        for &end in &outline.ends {
+            let contour = &outline.points[start..end];
+            start = end;
+            draw_contour(&mut raster, contour, origin);
+        }
+
+        GlyphBitmap {
+            width,
+            height,
+            left: left as i32,
+            top: top as i32,
+            coverage: raster.coverage(),
+        }
+    }
+}
+
+/// Draw a closed contour of on- and off-curve points, where two
+/// off-curve points in a row have an implied on-curve point between
+/// them.
+fn draw_contour(raster: &mut Raster, contour: &[(Point, bool)], origin: Point) {
+    let Some(&(last, last_on)) = contour.last() else {
+        return;
+    };
+    let at = |p: Point| Point {
+        x: p.x - origin.x,
+        y: p.y - origin.y,
+    };
+    // Start from an on-curve point
+    let (first, _) = contour[0];
+    let start = match (contour[0].1, last_on) {
+        (true, _) => first,
+        (false, true) => last,
+        (false, false) => first.lerp(last, 0.5),
+    };
+
+    let mut pen = at(start);
+    let mut control: Option<Point> = None;
+    for &(point, on_curve) in contour.iter().chain(core::iter::once(&(start, true))) {
+        let point = at(point);
+        match (control, on_curve) {
+            (None, true) => {
+                raster.draw_line(pen, point);
+                pen = point;
+            }
+            (None, false) => control = Some(point),
+            (Some(c), true) => {
+                raster.draw_quad(pen, c, point);
+                pen = point;
+                control = None;
+            }
+            (Some(c), false) => {
+                let mid = c.lerp(point, 0.5);
+                raster.draw_quad(pen, c, mid);
+                pen = mid;
+                control = Some(point);
+            }
+        }
+    }
+}
+
+pub fn load_ttf(data: &[u8]) -> Result<TrueTypeFont<'_>, TtfError> {
+    match read_u32(data, 0).ok_or(TtfError::Truncated)? {
+        0x0001_0000 | 0x7472_7565 /* true */ => (),
+        0x4f54_544f /* OTTO */ | 0x7474_6366 /* ttcf */ => return Err(TtfError::Unsupported),
+        _ => return Err(TtfError::BadMagic),
+    }
+    let num_tables = read_u16(data, 4).ok_or(TtfError::Truncated)? as usize;
+    let table = |tag: &[u8; 4]| -> Result<&[u8], TtfError> {
+        for i in 0..num_tables {
+            let record = 12 + 16 * i;
+            let record_tag = data.get(record..record + 4).ok_or(TtfError::Truncated)?;
+            if record_tag == tag {
+                let offset = read_u32(data, record + 8).ok_or(TtfError::Truncated)? as usize;
+                let len = read_u32(data, record + 12).ok_or(TtfError::Truncated)? as usize;
+                return data
+                    .get(offset..offset.saturating_add(len))
+                    .ok_or(TtfError::Truncated);
+            }
+        }
+        Err(TtfError::MissingTable(*tag))
+    };
+
+    let head = table(b"head")?;
+    let maxp = table(b"maxp")?;
+    let hhea = table(b"hhea")?;
+    let cmap = table(b"cmap")?;
+    let font = (|| {
+        Some(TrueTypeFont {
+            units_per_em: read_u16(head, 18).filter(|&u| u != 0)?,
+            num_glyphs: read_u16(maxp, 4)?,
+            long_loca: read_i16(head, 50)? != 0,
+            loca: table(b"loca").ok()?,
+            glyf: table(b"glyf").ok()?,
+            hmtx: table(b"hmtx").ok()?,
+            num_h_metrics: read_u16(hhea, 34)?,
+            cmap: find_cmap(cmap)?,
+            ascent: read_i16(hhea, 4)?,
+            descent: read_i16(hhea, 6)?,
+            line_gap: read_i16(hhea, 8)?,
+        })
+    })();
+    // Every font with TrueType outlines has a glyf table
+    match font {
+        Some(font) => Ok(font),
+        None if table(b"glyf").is_err() => Err(TtfError::MissingTable(*b"glyf")),
+        None => Err(TtfError::Truncated),
+    }
+}
+
+/// Pick the Unicode subtable of a `cmap`, preferring one covering
+/// characters past the Basic Multilingual Plane.
+fn find_cmap(cmap: &[u8]) -> Option<Cmap<'_>> {
+    let count = read_u16(cmap, 2)? as usize;
+    let mut best = None;
+    for i in 0..count {
+        let record = 4 + 8 * i;
+        let platform = read_u16(cmap, record)?;
+        let encoding = read_u16(cmap, record + 2)?;
+        let unicode = matches!((platform, encoding), (0, _) | (3, 1) | (3, 10));
+        if !unicode {
+            continue;
+        }
+        let subtable = cmap.get(read_u32(cmap, record + 4)? as usize..)?;
+        match read_u16(subtable, 0)? {
+            12 => return Some(Cmap::Format12(subtable)),
+            4 => best = Some(Cmap::Format4(subtable)),
+            _ => (),
+        }
+    }
+    best
+}
+
+/// A glyph rendered at the size of its [`ScaledFont`].
+struct CachedGlyph {
+    advance: f32,
+    bitmap: GlyphBitmap,
+}
+
+/// A font at one size, which keeps the glyphs it has rendered.
+pub struct ScaledFont<'a> {
+    font: TrueTypeFont<'a>,
+    size: f32,
+    glyphs: BTreeMap<char, CachedGlyph>,
+}
+
+impl<'a> ScaledFont<'a> {
+    /// Draw text `size` pixels to the em.
+    pub fn new(font: TrueTypeFont<'a>, size: u32) -> Self {
+        ScaledFont {
+            font,
+            size: size as f32,
+            glyphs: BTreeMap::new(),
+        }
+    }
+
+    pub fn font(&self) -> &TrueTypeFont<'a> {
+        &self.font
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size as u32
+    }
+
+    /// Change the size of the text, which renders every glyph again.
+    pub fn set_size(&mut self, size: u32) {
+        if size as f32 != self.size {
+            self.size = size as f32;
+            self.glyphs.clear();
+        }
+    }
+
+    /// The height above the baseline, in whole pixels.
+    pub fn ascent(&self) -> usize {
+        ceil(self.font.line_metrics(self.size).ascent) as usize
+    }
+
+    /// The distance between the tops of two lines of text.
+    pub fn line_height(&self) -> usize {
+        let metrics = self.font.line_metrics(self.size);
+        ceil(metrics.ascent - metrics.descent + metrics.line_gap) as usize
+    }
+
+    /// The size of a character cell for a monospace font, as (height,
+    /// width), like [`LoadedPCF::dimensions`](super::pcf::LoadedPCF::dimensions).
+    pub fn dimensions(&mut self) -> (usize, usize) {
+        let width = ceil(self.glyph('M').advance) as usize;
+        (self.line_height(), width.max(1))
+    }
+
+    fn glyph(&mut self, c: char) -> &CachedGlyph {
+        let (font, size) = (&self.font, self.size);
+        self.glyphs.entry(c).or_insert_with(|| {
+            let glyph = font.glyph_index(c);
+            CachedGlyph {
+                advance: font.advance(glyph, size),
+                bitmap: font.rasterize(glyph, size),
+            }
+        })
+    }
+
+    /// How wide a line of text is.
+    pub fn measure(&mut self, str: &str) -> usize {
+        let width: f32 = str.chars().map(|c| self.glyph(c).advance).sum();
+        ceil(width) as usize
+    }
+
+    /// Draw a character with the top of its line at `(x, y)`, blending
+    /// it into `buffer`; returns how far it moves the pen.
+    pub fn draw_char(
+        &mut self,
+        char: char,
+        buffer: &mut [u32],
+        row_stride: usize,
+        (x, y): (usize, usize),
+        color: u32,
+    ) -> f32 {
+        let baseline = (y + self.ascent()) as i32;
+        let glyph = self.glyph(char);
+        draw_bitmap(
+            &glyph.bitmap,
+            buffer,
+            row_stride,
+            (x as i32 + glyph.bitmap.left, baseline + glyph.bitmap.top),
+            color,
+        );
+        glyph.advance
+    }
+
+    /// Draw text with the top of its first line at `(x, y)`, wrapping
+    /// it before `wrap_at` pixels from the left of the buffer; returns
+    /// how far below `y` the text ends.
+    pub fn draw_string(
+        &mut self,
+        str: &str,
+        buffer: &mut [u32],
+        row_stride: usize,
+        (x, y): (usize, usize),
+        wrap_at: Option<usize>,
+        color: u32,
+    ) -> usize {
+        let line_height = self.line_height();
+        let wrap_at = wrap_at.unwrap_or(usize::MAX) as f32;
+        let mut pen_x = x as f32;
+        let mut pen_y = y;
+        for char in str.chars() {
+            match char {
+                '\r' => continue,
+                '\n' => {
+                    pen_x = x as f32;
+                    pen_y += line_height;
+                    continue;
+                }
+                _ => (),
+            }
+            let advance = self.glyph(char).advance;
+            if pen_x + advance >= wrap_at && pen_x > x as f32 {
+                pen_x = x as f32;
+                pen_y += line_height;
+            }
+            let pos = ((pen_x + 0.5) as usize, pen_y);
+            pen_x += self.draw_char(char, buffer, row_stride, pos, color);
+        }
+        pen_y - y + line_height
+    }
+}
+
+/// Blend `color` into the buffer with the bitmap's coverage as its
+/// opacity, clipping whatever falls outside.
+fn draw_bitmap(
+    bitmap: &GlyphBitmap,
+    buffer: &mut [u32],
+    row_stride: usize,
+    (x, y): (i32, i32),
+    color: u32,
+) {
+    let rows = buffer.len() / row_stride.max(1);
+    let alpha = color >> 24;
+    for r in 0..bitmap.height {
+        let Ok(dst_y) = usize::try_from(y + r as i32) else {
+            continue;
+        };
+        if dst_y >= rows {
+            break;
+        }
+        for c in 0..bitmap.width {
+            let coverage = bitmap.coverage[r * bitmap.width + c] as u32;
+            let Ok(dst_x) = usize::try_from(x + c as i32) else {
+                continue;
+            };
+            if coverage == 0 || dst_x >= row_stride {
+                continue;
+            }
+            let pixel = &mut buffer[dst_y * row_stride + dst_x];
+            let a = alpha * coverage / 255;
+            let src = (color & 0x00FF_FFFF) | (a << 24);
+            *pixel = crate::color::composite(src, *pixel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cmap;
+
+    #[test]
+    fn test_cmap_format4() {
+        #[rustfmt::skip]
+        let table: &[u8] = &[
+            0, 4, 0, 40, 0, 0,
+            0, 6, 0, 4, 0, 1, 0, 0, // 3 segments
+            0, b'C', 0, b'z', 0xff, 0xff, // ends
+            0, 0,
+            0, b'A', 0, b'x', 0xff, 0xff, // starts
+            0, 0, 0, 0, 0, 1, // deltas
+            0, 6, 0, 0, 0, 0, // range offsets
+            0, 7, 0, 0, 0, 9, // glyphs of 'A'..='C'
+        ];
+        let cmap = Cmap::Format4(table);
+        assert_eq!(cmap.lookup('A' as u32), Some(7));
+        assert_eq!(cmap.lookup('B' as u32), None);
+        assert_eq!(cmap.lookup('C' as u32), Some(9));
+        assert_eq!(cmap.lookup('D' as u32), None);
+        assert_eq!(cmap.lookup('y' as u32), Some(b'y' as u16));
+        assert_eq!(cmap.lookup(0x1f600), None);
+    }
+
+    #[test]
+    fn test_cmap_format12() {
+        #[rustfmt::skip]
+        let table: &[u8] = &[
+            0, 12, 0, 0, 0, 0, 0, 40, 0, 0, 0, 0,
+            0, 0, 0, 2, // 2 groups
+            0, 0, 0, b'a', 0, 0, 0, b'z', 0, 0, 0, 3,
+            0, 1, 0xf6, 0, 0, 1, 0xf6, 0xff, 0xff, 0xff, 0xff, 0xff,
+        ];
+        let cmap = Cmap::Format12(table);
+        assert_eq!(cmap.lookup('a' as u32), Some(3));
+        assert_eq!(cmap.lookup('c' as u32), Some(5));
+        assert_eq!(cmap.lookup('A' as u32), None);
+        // Glyph ids past u32::MAX don't wrap around
+        assert_eq!(cmap.lookup(0x1f600), None);
+        assert_eq!(cmap.lookup(0x1f601), None);
+    }
+}
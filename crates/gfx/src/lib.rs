@@ -1,7 +1,11 @@
 #![no_std]
 
+extern crate alloc;
+
+pub mod canvas;
 pub mod color;
 pub mod format;
+mod raster;
 
 pub fn blit_buffer(
     dst: &mut [u32],
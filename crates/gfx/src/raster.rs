@@ -0,0 +1,240 @@
+//! Anti-aliased filling of outlines, by accumulating the signed area each
+//! segment covers in every pixel, then summing along the rows; see
+//! <https://medium.com/@raphlinus/inside-the-fastest-font-renderer-in-the-world-75ae5270c445>.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+// core has no rounding functions for floats
+
+pub(crate) fn floor(x: f32) -> f32 {
+    let i = x as i32 as f32;
+    if i > x { i - 1.0 } else { i }
+}
+
+pub(crate) fn ceil(x: f32) -> f32 {
+    let i = x as i32 as f32;
+    if i < x { i + 1.0 } else { i }
+}
+
+pub(crate) fn abs(x: f32) -> f32 {
+    if x < 0.0 { -x } else { x }
+}
+
+pub(crate) fn round(x: f32) -> f32 {
+    floor(x + 0.5)
+}
+
+pub(crate) fn sqrt(x: f32) -> f32 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    // Halving the exponent gets within a few percent, and Newton's method
+    // does the rest
+    let mut y = f32::from_bits((x.to_bits() >> 1) + 0x1fbd_1df5);
+    for _ in 0..3 {
+        y = 0.5 * (y + x / y);
+    }
+    y
+}
+
+/// The sine and cosine of an angle between 0 and pi/2, from their Taylor
+/// series.
+pub(crate) fn sin_cos(angle: f32) -> (f32, f32) {
+    let x2 = angle * angle;
+    let sin = angle * (1.0 - x2 / 6.0 * (1.0 - x2 / 20.0 * (1.0 - x2 / 42.0 * (1.0 - x2 / 72.0))));
+    let cos = 1.0
+        - x2 / 2.0 * (1.0 - x2 / 12.0 * (1.0 - x2 / 30.0 * (1.0 - x2 / 56.0 * (1.0 - x2 / 90.0))));
+    (sin, cos)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Point {
+    pub fn lerp(self, other: Point, t: f32) -> Point {
+        Point {
+            x: self.x + (other.x - self.x) * t,
+            y: self.y + (other.y - self.y) * t,
+        }
+    }
+}
+
+/// Accumulates the signed area outline segments cover in each pixel.
+pub(crate) struct Raster {
+    width: usize,
+    height: usize,
+    acc: Vec<f32>,
+}
+
+impl Raster {
+    pub fn new(width: usize, height: usize) -> Self {
+        Raster {
+            width,
+            height,
+            // A line ending on the right edge touches the first pixel of
+            // the next row, or just past the end
+            acc: vec![0.0; width * height + 2],
+        }
+    }
+
+    pub fn draw_quad(&mut self, p0: Point, p1: Point, p2: Point) {
+        // Enough segments to keep within about a quarter of a pixel of
+        // the curve
+        let dx = p0.x - 2.0 * p1.x + p2.x;
+        let dy = p0.y - 2.0 * p1.y + p2.y;
+        let deviation = abs(dx) + abs(dy);
+        let segments = (1 + ((deviation * 2.0) as usize).isqrt()).min(32);
+        let mut prev = p0;
+        for i in 1..=segments {
+            let t = i as f32 / segments as f32;
+            let next = p0.lerp(p1, t).lerp(p1.lerp(p2, t), t);
+            self.draw_line(prev, next);
+            prev = next;
+        }
+    }
+
+    pub fn draw_line(&mut self, p0: Point, p1: Point) {
+        if p0.y == p1.y {
+            return;
+        }
+        let (dir, p0, p1) = if p0.y < p1.y {
+            (1.0, p0, p1)
+        } else {
+            (-1.0, p1, p0)
+        };
+        let clamp_x = |p: Point| Point {
+            x: p.x.max(0.0).min(self.width as f32),
+            y: p.y,
+        };
+        let (p0, p1) = (clamp_x(p0), clamp_x(p1));
+        let dxdy = (p1.x - p0.x) / (p1.y - p0.y);
+
+        let mut x = p0.x;
+        let y_start = p0.y.max(0.0);
+        if p0.y < 0.0 {
+            x -= p0.y * dxdy;
+        }
+        let y_end = (ceil(p1.y) as usize).min(self.height);
+        for y in (y_start as usize)..y_end {
+            let row = y * self.width;
+            let dy = p1.y.min(y as f32 + 1.0) - p0.y.max(y as f32);
+            let x_next = x + dxdy * dy;
+            let d = dy * dir;
+
+            let (x0, x1) = if x < x_next { (x, x_next) } else { (x_next, x) };
+            let x0_floor = floor(x0);
+            let x0i = x0_floor as usize;
+            let x1_ceil = ceil(x1);
+            let x1i = x1_ceil as usize;
+
+            if x1i <= x0i + 1 {
+                // Within one pixel: split the area between it and the
+                // pixels to its right
+                let mid = 0.5 * (x + x_next) - x0_floor;
+                self.acc[row + x0i] += d - d * mid;
+                self.acc[row + x0i + 1] += d * mid;
+            } else {
+                let s = 1.0 / (x1 - x0);
+                let x0f = x0 - x0_floor;
+                let a0 = 0.5 * s * (1.0 - x0f) * (1.0 - x0f);
+                let x1f = x1 - x1_ceil + 1.0;
+                let am = 0.5 * s * x1f * x1f;
+
+                self.acc[row + x0i] += d * a0;
+                if x1i == x0i + 2 {
+                    self.acc[row + x0i + 1] += d * (1.0 - a0 - am);
+                } else {
+                    let a1 = s * (1.5 - x0f);
+                    self.acc[row + x0i + 1] += d * (a1 - a0);
+                    for xi in x0i + 2..x1i - 1 {
+                        self.acc[row + xi] += d * s;
+                    }
+                    let a2 = a1 + (x1i - x0i - 3) as f32 * s;
+                    self.acc[row + x1i - 1] += d * (1.0 - a2 - am);
+                }
+                self.acc[row + x1i] += d * am;
+            }
+            x = x_next;
+        }
+    }
+
+    /// Sum the accumulated areas along each row into the coverage of
+    /// each pixel.
+    pub fn coverage(&self) -> Vec<u8> {
+        let mut sum = 0.0;
+        self.acc[..self.width * self.height]
+            .iter()
+            .map(|&a| {
+                sum += a;
+                (abs(sum).min(1.0) * 255.0 + 0.5) as u8
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Point, Raster, sin_cos, sqrt};
+
+    fn square(raster: &mut Raster, (x0, y0): (f32, f32), (x1, y1): (f32, f32)) {
+        let corners = [(x0, y0), (x1, y0), (x1, y1), (x0, y1), (x0, y0)];
+        for pair in corners.windows(2) {
+            let p = |(x, y)| Point { x, y };
+            raster.draw_line(p(pair[0]), p(pair[1]));
+        }
+    }
+
+    #[test]
+    fn test_raster_coverage() {
+        let mut raster = Raster::new(4, 3);
+        square(&mut raster, (1.0, 0.0), (3.0, 2.0));
+        #[rustfmt::skip]
+        assert_eq!(raster.coverage(), [
+            0, 255, 255, 0,
+            0, 255, 255, 0,
+            0, 0, 0, 0,
+        ]);
+
+        // Half a pixel is half covered, whichever way the outline winds
+        let mut raster = Raster::new(2, 1);
+        square(&mut raster, (1.5, 1.0), (0.5, 0.0));
+        assert_eq!(raster.coverage(), [128, 128]);
+
+        // Overlapping contours don't add up past full coverage
+        let mut raster = Raster::new(1, 1);
+        square(&mut raster, (0.0, 0.0), (1.0, 1.0));
+        square(&mut raster, (0.0, 0.0), (1.0, 1.0));
+        assert_eq!(raster.coverage(), [255]);
+    }
+
+    #[test]
+    fn test_raster_curve() {
+        // A circle-ish shape, made of quadratic curves
+        let mut raster = Raster::new(10, 10);
+        let p = |x, y| Point { x, y };
+        raster.draw_quad(p(5.0, 0.0), p(10.0, 0.0), p(10.0, 5.0));
+        raster.draw_quad(p(10.0, 5.0), p(10.0, 10.0), p(5.0, 10.0));
+        raster.draw_quad(p(5.0, 10.0), p(0.0, 10.0), p(0.0, 5.0));
+        raster.draw_quad(p(0.0, 5.0), p(0.0, 0.0), p(5.0, 0.0));
+        let coverage = raster.coverage();
+        assert_eq!(coverage[5 * 10 + 5], 255);
+        assert_eq!(coverage[0], 0);
+        assert!(coverage[1] > 0 && coverage[1] < 255);
+    }
+
+    #[test]
+    fn test_float_helpers() {
+        for x in [0.25f32, 1.0, 2.0, 100.0, 12345.0] {
+            let root = sqrt(x);
+            assert!((root * root - x).abs() < x * 1e-5, "{x}");
+        }
+        let (sin, cos) = sin_cos(core::f32::consts::FRAC_PI_2);
+        assert!((sin - 1.0).abs() < 1e-5 && cos.abs() < 1e-5);
+        let (sin, cos) = sin_cos(core::f32::consts::FRAC_PI_6);
+        assert!((sin - 0.5).abs() < 1e-6 && (cos - 0.866_025_4).abs() < 1e-6);
+    }
+}
@@ -28,6 +28,7 @@ pub extern "C" fn main() {
         stdin: None,
         stdout: None,
         stderr: None,
+        pgid: None,
         args: &[ArgStr {
             len: path.len(),
             ptr: path.as_ptr(),
@@ -44,6 +45,7 @@ pub extern "C" fn main() {
             stdin: None,
             stdout: None,
             stderr: None,
+            pgid: None,
             args: &[
                 ArgStr {
                     len: console_path.len(),
@@ -68,6 +70,7 @@ pub extern "C" fn main() {
         stdin: None,
         stdout: None,
         stderr: None,
+        pgid: None,
         args: &[ArgStr {
             len: path.len(),
             ptr: path.as_ptr(),
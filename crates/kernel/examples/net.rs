@@ -47,7 +47,7 @@ extern "Rust" fn kernel_main(_device_tree: device_tree::DeviceTree) {
 
     // [tcp send test]
     let saddr = SocketAddr {
-        addr: Ipv4Address::new([11, 187, 10, 102]),
+        addr: Ipv4Address::new([11, 187, 10, 102]).into(),
         port: 1337,
     };
 
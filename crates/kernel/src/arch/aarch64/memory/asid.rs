@@ -0,0 +1,196 @@
+//! Address space identifiers, so that switching between user address
+//! spaces doesn't require invalidating the whole TLB.
+//!
+//! User page tables are identified by their physical address.  Each one
+//! is given an ASID the first time it is activated, which is tagged onto
+//! all of its (non-global) TLB entries.  Once the ASIDs run out, a new
+//! generation starts: every ASID is released except for those currently
+//! active on some core, and each core flushes its local TLB before it
+//! next switches address spaces, so that stale entries from the
+//! previous generation can't be hit by the new owner of an ASID.
+//!
+//! This follows the allocator used by Linux on arm64.
+
+use core::arch::asm;
+
+use super::machine::TtbrEl1;
+use crate::sync::InterruptSpinLock;
+
+/// Only the low 8 bits are used, which every implementation supports.
+const ASID_COUNT: usize = 256;
+const CORES: usize = 4;
+
+/// Owner of an ASID whose page table has been released while still
+/// loaded on some core; it is freed once no core is using it.
+const RELEASED: usize = usize::MAX;
+
+struct AsidAllocator {
+    /// The page table that owns each ASID in the current generation;
+    /// ASID 0 is reserved for when no user address space is active.
+    owners: [usize; ASID_COUNT],
+    next: usize,
+    /// The page table and ASID that each core is currently using.
+    active: [Option<(usize, u16)>; CORES],
+    /// Cores that have to flush their TLB before switching to an ASID.
+    flush_pending: [bool; CORES],
+}
+
+static ALLOCATOR: InterruptSpinLock<AsidAllocator> = InterruptSpinLock::new(AsidAllocator {
+    owners: [0; ASID_COUNT],
+    next: 1,
+    active: [None; CORES],
+    flush_pending: [false; CORES],
+});
+
+fn cur_core() -> usize {
+    (crate::arch::core_id() & 0b11) as usize
+}
+
+impl AsidAllocator {
+    fn lookup(&self, table: usize) -> Option<u16> {
+        (1..ASID_COUNT)
+            .find(|&asid| self.owners[asid] == table)
+            .map(|asid| asid as u16)
+    }
+
+    fn alloc(&mut self, table: usize) -> u16 {
+        if let Some(asid) = self.find_free() {
+            self.owners[asid] = table;
+            return asid as u16;
+        }
+
+        self.new_generation();
+        let asid = self
+            .find_free()
+            .expect("more active address spaces than ASIDs");
+        self.owners[asid] = table;
+        asid as u16
+    }
+
+    fn find_free(&mut self) -> Option<usize> {
+        let asid = (self.next..ASID_COUNT).find(|&asid| self.owners[asid] == 0)?;
+        self.next = asid + 1;
+        Some(asid)
+    }
+
+    fn new_generation(&mut self) {
+        let mut owners = [0; ASID_COUNT];
+        for (_, asid) in self.active.iter().flatten() {
+            owners[*asid as usize] = self.owners[*asid as usize];
+        }
+        self.owners = owners;
+        self.next = 1;
+        self.flush_pending = [true; CORES];
+    }
+
+    fn is_active(&self, asid: u16) -> bool {
+        self.active.iter().flatten().any(|(_, a)| *a == asid)
+    }
+
+    /// Make the ASID available for reuse, once nothing can refer to it.
+    fn free(&mut self, asid: u16) {
+        if self.is_active(asid) {
+            self.owners[asid as usize] = RELEASED;
+        } else {
+            self.owners[asid as usize] = 0;
+            flush_asid(asid);
+        }
+    }
+}
+
+fn flush_asid(asid: u16) {
+    let arg = (asid as usize) << 48;
+    unsafe {
+        asm!(
+            "dsb ishst",
+            "tlbi aside1is, {0}",
+            "dsb ish",
+            "isb",
+            in(reg) arg,
+            options(nostack, preserves_flags)
+        )
+    };
+}
+
+/// Get the `TTBR0_EL1` value for the given user page table, assigning
+/// it an ASID if it doesn't have one.
+fn ttbr0_for(allocator: &mut AsidAllocator, table: usize) -> usize {
+    let asid = match allocator.lookup(table) {
+        Some(asid) => asid,
+        None => allocator.alloc(table),
+    };
+    table | ((asid as usize) << 48)
+}
+
+/// Get the page table address from a `TTBR0_EL1` value.
+pub fn table_addr(ttbr0: usize) -> usize {
+    ttbr0 & TtbrEl1::BADDR.bits() as usize
+}
+
+/// Get the page table currently used for user addresses on this core.
+pub fn current_table() -> usize {
+    let ttbr0: usize;
+    unsafe { asm!("mrs {0}, TTBR0_EL1", out(reg) ttbr0) };
+    table_addr(ttbr0)
+}
+
+/// Switch the current core to the user page table at `table`.
+///
+/// # Safety
+///
+/// `table` must be a valid top level page table, which must not be
+/// freed without calling [`release`].
+pub unsafe fn switch_user_table(table: usize) {
+    let table = table_addr(table);
+    let core = cur_core();
+
+    let mut allocator = ALLOCATOR.lock();
+    let ttbr0 = ttbr0_for(&mut allocator, table);
+    let prev = allocator.active[core].replace((table, (ttbr0 >> 48) as u16));
+    if let Some((_, prev_asid)) = prev {
+        if allocator.owners[prev_asid as usize] == RELEASED {
+            allocator.free(prev_asid);
+        }
+    }
+    let flush = core::mem::take(&mut allocator.flush_pending[core]);
+
+    let cur_ttbr0: usize;
+    unsafe { asm!("mrs {0}, TTBR0_EL1", out(reg) cur_ttbr0) };
+    if cur_ttbr0 != ttbr0 {
+        unsafe { asm!("msr TTBR0_EL1, {0}", "isb", in(reg) ttbr0) };
+    }
+    if flush {
+        unsafe { asm!("dsb nshst", "tlbi vmalle1", "dsb nsh", "isb") };
+    }
+    drop(allocator);
+}
+
+/// Invalidate the TLB entries for a single user page on all cores.
+pub fn invalidate_page(table: usize, vaddr: usize) {
+    let Some(asid) = ALLOCATOR.lock().lookup(table_addr(table)) else {
+        // The table has never been active in this generation; any of
+        // its entries are flushed before their ASID is reused.
+        return;
+    };
+    let arg = ((asid as usize) << 48) | ((vaddr >> 12) & ((1 << 44) - 1));
+    unsafe {
+        asm!(
+            "dsb ishst",
+            "tlbi vae1is, {0}",
+            "dsb ish",
+            "isb",
+            in(reg) arg,
+            options(nostack, preserves_flags)
+        )
+    };
+}
+
+/// Give up the ASID of a page table that is about to be freed,
+/// invalidating its TLB entries on all cores.
+pub fn release(table: usize) {
+    let table = table_addr(table);
+    let mut allocator = ALLOCATOR.lock();
+    if let Some(asid) = allocator.lookup(table) {
+        allocator.free(asid);
+    }
+}
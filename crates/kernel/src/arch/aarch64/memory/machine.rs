@@ -138,6 +138,10 @@ bitflags! {
         const CONTIGUOUS = 0b1 << 52;
         const PXN = 0b1 << 53;
         const UXN = 0b1 << 54;
+        /// Software-defined (ignored by hardware); set once a private
+        /// file-backed user page has been written to, so it can no
+        /// longer be dropped and re-read from the file.
+        const SW_DIRTY = 0b1 << 55;
     }
 
     // TODO: Fill this in when multilevel translation is used
@@ -1,3 +1,4 @@
+pub mod asid;
 pub mod machine;
 pub mod palloc;
 pub mod table;
@@ -8,7 +9,8 @@ use core::arch::asm;
 use machine::{at_s1e1r, LeafDescriptor};
 pub use vmm::{
     init_physical_alloc, map_device, map_device_block, map_physical, map_physical_noncacheable,
-    map_va_to_pa, UnifiedTranslationTable, KERNEL_UNIFIED_TRANSLATION_TABLE,
+    map_physical_noncacheable_blocks, map_va_to_pa, UnifiedTranslationTable,
+    KERNEL_UNIFIED_TRANSLATION_TABLE,
 };
 
 pub use machine::at_s1e0r;
@@ -70,15 +70,42 @@ impl PageAllocator {
     }
 
     pub fn alloc_frame<S: PageClass>(&self) -> PhysicalPage<S> {
-        let paddr = self
-            .allocator
-            .lock()
-            .alloc(S::SIZE, S::SIZE)
-            .expect("Out of physical memory");
-        PhysicalPage {
+        self.try_alloc_frame().expect("Out of physical memory")
+    }
+
+    pub fn try_alloc_frame<S: PageClass>(&self) -> Option<PhysicalPage<S>> {
+        let paddr = self.allocator.lock().alloc(S::SIZE, S::SIZE)?;
+        Some(PhysicalPage {
             paddr,
             _marker: PhantomData,
+        })
+    }
+
+    /// Allocate a frame on behalf of userspace.
+    ///
+    /// Unlike [`alloc_frame`](Self::alloc_frame), this fails once free
+    /// memory drops below [`USER_RESERVE`], so that user processes
+    /// exhausting memory leave the kernel enough headroom to reclaim
+    /// pages or kill a process.
+    pub fn try_alloc_user_frame<S: PageClass>(&self) -> Option<PhysicalPage<S>> {
+        let mut allocator = self.allocator.lock();
+        if allocator.free_bytes() < USER_RESERVE + S::SIZE {
+            return None;
         }
+        let paddr = allocator.alloc(S::SIZE, S::SIZE)?;
+        Some(PhysicalPage {
+            paddr,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Whether a user allocation of a single 4 KiB page would currently succeed.
+    pub fn has_user_headroom(&self) -> bool {
+        self.free_bytes() >= USER_RESERVE + Size4KiB::SIZE
+    }
+
+    pub fn free_bytes(&self) -> usize {
+        self.allocator.lock().free_bytes()
     }
 
     pub fn alloc_mapped_frame<S: BasePageSize>(&self) -> PhysicalPage<S> {
@@ -137,10 +164,23 @@ impl PageAllocator {
             self.allocator.lock().free(frame.paddr, S::SIZE, S::SIZE);
         }
     }
+
+    /// Free a 2 MiB frame. These only ever come from the buddy allocator,
+    /// never the kernel heap, so unlike [`dealloc_frame`](Self::dealloc_frame)
+    /// this doesn't need to check where the frame is.
+    pub fn dealloc_huge_frame(&self, frame: PhysicalPage<Size2MiB>) {
+        self.allocator
+            .lock()
+            .free(frame.paddr, Size2MiB::SIZE, Size2MiB::SIZE);
+    }
 }
 
 pub static PAGE_ALLOCATOR: UnsafeInit<PageAllocator> = unsafe { UnsafeInit::uninit() };
 
+/// Physical memory held back from user allocations, for use by the kernel
+/// while it recovers from memory pressure.
+pub const USER_RESERVE: usize = 4 << 20;
+
 pub unsafe fn init_physical_alloc(paddr_start: usize, paddr_end: usize) {
     let allocator = PageAllocator::init(paddr_start, paddr_end);
     unsafe { PAGE_ALLOCATOR.init(allocator) };
@@ -302,6 +342,14 @@ impl Freelist {
         cur_freelist.remove(sibling_idx);
     }
 
+    fn free_bytes(&self, size_log2: usize) -> usize {
+        self.freelists
+            .iter()
+            .enumerate()
+            .map(|(level, list)| list.len() << (size_log2 - level))
+            .sum()
+    }
+
     fn print_freelists(&self) {
         for freelist in self.freelists.iter().enumerate() {
             println!("{:?}", freelist);
@@ -548,6 +596,11 @@ impl BuddyAllocator {
         Some(ptr)
     }
 
+    /// Free a block returned by [`alloc`](Self::alloc).
+    ///
+    /// An allocated block is indistinguishable from its two halves both
+    /// being allocated, so a block may also be freed piecewise, in any
+    /// smaller power-of-two sized parts.
     pub fn free(&mut self, ptr: usize, size: usize, align: usize) {
         let size = size.clamp(align, usize::MAX);
         // println!("free({ptr:#x}, {size})");
@@ -556,6 +609,11 @@ impl BuddyAllocator {
         self.free_block(idx);
     }
 
+    /// Total size of all blocks currently on the free lists.
+    pub fn free_bytes(&self) -> usize {
+        self.freelist.free_bytes(self.size_log2)
+    }
+
     pub fn print_state(&self) {
         let width = 64;
         for level in 0..self.levels - 1 {
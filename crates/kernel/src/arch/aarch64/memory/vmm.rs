@@ -2,7 +2,7 @@ use core::{
     arch::asm,
     fmt::{Display, Formatter},
     ptr::{addr_of, NonNull},
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
 
 use crate::arch::memory::palloc::Size4KiB;
@@ -17,6 +17,8 @@ use super::{
 
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PG_SZ: usize = 0x1000;
+/// Size of a level 2 block mapping
+pub const HUGE_PG_SZ: usize = 0x20_0000;
 const TRANSLATION_TABLE_SIZE: usize = PG_SZ / size_of::<TranslationDescriptor>();
 
 /// This translation table is used for the user and kernel page tables once the 48 bit address
@@ -82,6 +84,9 @@ pub fn alloc_page_table() -> PageTablePtr {
 
 pub const DIRECT_MAP_BASE: usize = 0xFFFF100000000000;
 pub const MAPPED_HEAP_BASE: usize = 0xFFFF200000000000;
+pub const BLOCK_MAP_BASE: usize = 0xFFFF300000000000;
+
+static BLOCK_MAP_NEXT: AtomicUsize = AtomicUsize::new(BLOCK_MAP_BASE);
 
 pub static VMEM_INIT_DONE: AtomicBool = AtomicBool::new(false);
 
@@ -90,6 +95,8 @@ pub unsafe fn init_kernel_48bit() {
     let table = PageTablePtr::from_ptr(table);
     let kernel_vmem_base = (&raw const __rpi_virt_base) as usize;
 
+    // The kernel's linear mappings are all blocks: 2 MiB blocks for the
+    // kernel image and heap here, and 1 GiB blocks for the direct map below.
     // TEMP: 13 x 2MB = 26MB for heap
     for idx in 0..14 {
         let paddr = 0x20_0000 * idx;
@@ -492,6 +499,37 @@ pub unsafe fn map_physical_noncacheable(pa_start: usize, size: usize) -> NonNull
     }
 }
 
+/// Map a large physical region with 2 MiB blocks, for mappings such as
+/// the framebuffer that would otherwise take up many leaf entries.
+///
+/// The blocks cover the whole surrounding 2 MiB aligned region, so
+/// neighbouring memory is mapped with the same attributes.
+unsafe fn map_physical_blocks(pa_start: usize, size: usize, mair: u8) -> NonNull<()> {
+    assert!(VMEM_INIT_DONE.load(Ordering::SeqCst));
+
+    let block_start = (pa_start / HUGE_PG_SZ) * HUGE_PG_SZ;
+    let block_end = (pa_start + size).next_multiple_of(HUGE_PG_SZ);
+    let va_start = BLOCK_MAP_NEXT.fetch_add(block_end - block_start, Ordering::Relaxed);
+
+    let table = &raw mut KERNEL_UNIFIED_TRANSLATION_TABLE;
+    let table = PageTablePtr::from_ptr(table);
+    for pa in (block_start..block_end).step_by(HUGE_PG_SZ) {
+        let leaf = LeafDescriptor::new(pa)
+            .set_mair(mair)
+            .set_global()
+            .difference(LeafDescriptor::IS_PAGE_DESCRIPTOR);
+        let va = va_start + (pa - block_start);
+        unsafe { set_translation_descriptor(table, va, 2, 0, leaf.into(), true).unwrap() };
+    }
+
+    NonNull::new((va_start + (pa_start - block_start)) as *mut ()).unwrap()
+}
+
+/// not thread safe
+pub unsafe fn map_physical_noncacheable_blocks(pa_start: usize, size: usize) -> NonNull<()> {
+    unsafe { map_physical_blocks(pa_start, size, 2) }
+}
+
 //This is two adjacent pages all filled with leaf descriptors
 #[unsafe(no_mangle)]
 static mut KERNEL_LEAF_TABLE: KernelLeafTable =
@@ -281,6 +281,9 @@ pub fn init_devices(tree: &DeviceTree<'_>) {
         println!("| timer initialized, time: {time}");
     }
 
+    // the loopback interface has to come before any network device's
+    crate::networking::iface::init();
+
     if ENABLE_USB {
         println!("| Initializing USB");
         let usb = discover_compatible(tree, b"brcm,bcm2835-usb")
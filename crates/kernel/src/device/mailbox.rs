@@ -10,7 +10,10 @@
 //! <https://github.com/raspberrypi/firmware/wiki/Mailbox-property-interface>
 //! (since this isn't properly documented anywhere...)
 
+use core::sync::atomic::Ordering;
+
 use crate::{
+    arch::memory::vmm::VMEM_INIT_DONE,
     memory::{self, physical_addr},
     sync::Volatile,
 };
@@ -534,7 +537,11 @@ impl VideoCoreMailbox {
 
     pub unsafe fn map_framebuffer_kernel(&mut self, width: usize, height: usize) -> Surface {
         let fb = unsafe { self.get_framebuffer_raw(width, height) };
-        let ptr = unsafe { memory::map_physical_noncacheable(fb.paddr, fb.size) };
+        let ptr = if VMEM_INIT_DONE.load(Ordering::SeqCst) {
+            unsafe { memory::map_physical_noncacheable_blocks(fb.paddr, fb.size) }
+        } else {
+            unsafe { memory::map_physical_noncacheable(fb.paddr, fb.size) }
+        };
         let ptr = ptr.as_ptr().cast::<u128>();
         assert!(ptr.is_aligned());
 
@@ -544,6 +551,19 @@ impl VideoCoreMailbox {
     }
 }
 
+/// Rate at which framebuffer contents are assumed to be scanned out;
+/// the firmware doesn't report the display's refresh rate, or provide
+/// vsync interrupts, so this is only an approximation.
+pub const FRAMERATE: usize = 30;
+const FRAME_TIME_US: usize = 1_000_000 / FRAMERATE;
+
+/// Sleep until the start of the next frame.
+pub async fn wait_for_frame() {
+    // TODO: proper vsync IRQs?
+    let now = crate::sync::get_time();
+    crate::sync::time::sleep_until(now.next_multiple_of(FRAME_TIME_US) as u64).await;
+}
+
 #[derive(Copy, Clone)]
 pub struct RawFB {
     pub paddr: usize,
@@ -556,8 +576,6 @@ pub struct RawFB {
 pub struct Surface {
     alternate: alloc::boxed::Box<[u128]>,
     pub buffer: &'static mut [u128],
-    framerate: usize,
-    time_step: usize,
     width: usize,
     height: usize,
     pub pitch_elems: usize,
@@ -599,9 +617,6 @@ fn memcpy128(dst: &mut [u128], src: &[u128]) {
 
 impl Surface {
     fn new(buffer: &'static mut [u128], width: usize, height: usize, pitch_elems: usize) -> Self {
-        let framerate = 30;
-        let time_step = 1_000_000 / framerate;
-
         let mut alternate = alloc::vec::Vec::new();
         alternate.reserve_exact(buffer.len());
         alternate.resize(height * pitch_elems / 4, 0);
@@ -612,15 +627,13 @@ impl Surface {
             width,
             height,
             pitch_elems,
-            framerate,
-            time_step,
         }
     }
     pub fn dimensions(&self) -> (usize, usize) {
         (self.width, self.height)
     }
     pub fn framerate(&self) -> usize {
-        self.framerate
+        FRAMERATE
     }
     pub fn buffer(&mut self) -> &mut [u32] {
         bytemuck::cast_slice_mut(&mut self.alternate)
@@ -643,9 +656,7 @@ impl Surface {
         core::hint::black_box(&mut *self.buffer);
     }
     pub async fn wait_for_frame(&self) {
-        // TODO: proper vsync IRQs?
-        let now = crate::sync::get_time();
-        crate::sync::time::sleep_until(now.next_multiple_of(self.time_step) as u64).await;
+        wait_for_frame().await
     }
 }
 
@@ -9,8 +9,8 @@ pub mod usbd;
 pub use device::hid::keyboard;
 pub use device::hid::mouse;
 
-use device::net::NetSendPacket;
 use device::net::RegisterNetReceiveCallback;
+use device::net::NET_QUEUES;
 
 use alloc::boxed::Box;
 use hcd::dwc::dwc_otg::*;
@@ -38,8 +38,8 @@ pub fn usb_register_net_callback(callback: fn(*mut u8, u32)) {
     RegisterNetReceiveCallback(callback);
 }
 
+// queues an already framed message to go out on the network device's bulk endpoint
 pub unsafe fn usb_send_packet(buffer: *mut u8, buffer_length: u32) {
-    unsafe {
-        NetSendPacket(buffer, buffer_length);
-    }
+    let message = unsafe { core::slice::from_raw_parts(buffer, buffer_length as usize) };
+    let _ = NET_QUEUES.tx.lock().push(message.to_vec());
 }
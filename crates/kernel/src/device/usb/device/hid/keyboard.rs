@@ -19,6 +19,8 @@ pub struct KeyEvent {
 pub struct KeyEventBuffer {
     recv_lock: SpinLock<()>,
     buffer: crate::ringbuffer::SpscOverwritingRingBuffer<4096, KeyEvent>,
+    /// Notified whenever a new event is available.
+    pub waiters: crate::sync::NotifyList,
 }
 
 impl KeyEventBuffer {
@@ -36,6 +38,7 @@ unsafe impl Sync for KeyEventBuffer {}
 pub static KEY_EVENTS: KeyEventBuffer = KeyEventBuffer {
     recv_lock: SpinLock::new(()),
     buffer: crate::ringbuffer::SpscOverwritingRingBuffer::new(),
+    waiters: crate::sync::NotifyList::new(),
 };
 
 static LAST_KEYBOARD_REPORT: AtomicU64 = AtomicU64::new(0);
@@ -70,6 +73,7 @@ pub unsafe fn KeyboardAnalyze(buffer: *mut u8, buffer_length: u32) {
             pressed,
         };
         unsafe { KEY_EVENTS.buffer.send_overwrite(event) };
+        KEY_EVENTS.waiters.notify();
     };
 
     let old_mods = old_report[0];
@@ -43,6 +43,8 @@ bitflags::bitflags! {
 pub struct MouseEventBuffer {
     recv_lock: SpinLock<()>,
     buffer: crate::ringbuffer::SpscOverwritingRingBuffer<4096, MouseEvent>,
+    /// Notified whenever a new event is available.
+    pub waiters: crate::sync::NotifyList,
 }
 
 impl MouseEventBuffer {
@@ -60,6 +62,7 @@ unsafe impl Sync for MouseEventBuffer {}
 pub static MOUSE_EVENTS: MouseEventBuffer = MouseEventBuffer {
     recv_lock: SpinLock::new(()),
     buffer: crate::ringbuffer::SpscOverwritingRingBuffer::new(),
+    waiters: crate::sync::NotifyList::new(),
 };
 
 pub static LAST_BUTTONS: AtomicU8 = AtomicU8::new(0);
@@ -84,7 +87,10 @@ pub unsafe fn MouseAnalyze(buffer: *mut u8, buffer_length: u32) {
 
     let old_buttons = LAST_BUTTONS.swap(buttons, Ordering::SeqCst);
 
-    let send = |ev| unsafe { MOUSE_EVENTS.buffer.send_overwrite(ev) };
+    let send = |ev| {
+        unsafe { MOUSE_EVENTS.buffer.send_overwrite(ev) };
+        MOUSE_EVENTS.waiters.notify();
+    };
     let button_state = MouseButtonState::from_bits_truncate(buttons);
 
     let emit_button = |button, state| {
@@ -6,40 +6,82 @@ use super::super::usbd::device::*;
  *  By Aaron Lo
  *   
  */
+use core::future::poll_fn;
 use core::slice;
 
 use crate::networking::iface::*;
 use crate::networking::repr::*;
+use crate::networking::utils::frame_queue::DeviceQueues;
 
 use crate::device::usb::types::*;
 use crate::device::usb::usbd::endpoint::register_interrupt_endpoint;
 use crate::device::usb::usbd::endpoint::*;
+use crate::device::usb::usbd::pipe::*;
 use crate::device::usb::usbd::request::*;
-use crate::shutdown;
+use crate::device::usb::usbd::usbd::{UsbGetDescriptor, UsbSendBulkMessage};
+use crate::device::usb::{PacketId, UsbControlMessage};
+use crate::event::task::spawn_async;
+use crate::event::thread;
 
 use alloc::boxed::Box;
 use alloc::vec;
+use alloc::vec::Vec;
 
 use super::rndis::*;
 
 pub static mut NET_DEVICE: NetDevice = NetDevice {
     receive_callback: None,
     device: None,
+    interface: None,
+    driver: None,
 };
 
-pub static mut INTERFACE: Option<Interface> = None;
-
-#[allow(static_mut_refs)]
-pub fn get_interface_mut() -> &'static mut Interface {
-    unsafe { INTERFACE.as_mut().expect("INTERFACE not initialized") }
+// frames between the usb device and its interface's Device (see networking::iface::rndis and
+// networking::iface::cdcecm). the usb side pushes what the bulk in endpoint receives and sends
+// what the interface transmits
+// WARN: like NET_DEVICE, this assumes there's only ever one usb network device
+pub static NET_QUEUES: DeviceQueues = DeviceQueues::new(NET_QUEUE_LEN, LinkState::Down);
+const NET_QUEUE_LEN: usize = 64;
+
+const NET_MTU: usize = 1500;
+
+// cdc subclass of ethernet control model devices, rndis devices report the abstract control model
+// subclass (2) instead
+const CDC_SUBCLASS_ECM: u8 = 0x06;
+// class specific interface descriptors, which describe a cdc device's functions
+const CS_INTERFACE: u8 = 0x24;
+const CDC_UNION_DESCRIPTOR: u8 = 0x06;
+const CDC_ETHERNET_DESCRIPTOR: u8 = 0x0F;
+const CDC_NETWORK_CONNECTION: u8 = 0x00;
+// directed, broadcast and all multicast (for ipv6 neighbor discovery)
+const ECM_PACKET_FILTER: u16 = 0x0E;
+const LANG_ID_EN_US: u16 = 0x0409;
+
+// the framing a usb network device uses on its bulk endpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetDriver {
+    Rndis,
+    CdcEcm,
 }
 
-pub static mut DHCPD: Option<dhcp::Dhcpd> = None;
+impl NetDriver {
+    // the biggest bulk in transfer a frame arrives in: a whole ethernet frame, inside an rndis
+    // packet message for rndis devices
+    fn receive_len(self) -> u32 {
+        let frame_len = NET_MTU + EthernetFrame::HEADER_LEN;
+        match self {
+            NetDriver::Rndis => (frame_len + size_of::<RndisPacketMsg>()) as u32,
+            NetDriver::CdcEcm => frame_len as u32,
+        }
+    }
+}
 
-#[allow(static_mut_refs)]
-pub fn get_dhcpd_mut() -> &'static mut dhcp::Dhcpd {
-    unsafe { DHCPD.as_mut().expect("DHCPD not initialized") }
+// the network interface for the usb ethernet device
+pub fn get_interface_mut() -> &'static mut Interface {
+    let index = unsafe { NET_DEVICE.interface }.expect("INTERFACE not initialized");
+    crate::networking::iface::get_interface_mut(index)
 }
+
 pub fn NetLoad(bus: &mut UsbBus) {
     bus.interface_class_attach[InterfaceClass::InterfaceClassCommunications as usize] =
         Some(NetAttach);
@@ -51,28 +93,25 @@ pub fn NetAttach(device: &mut UsbDevice, interface_number: u32) -> ResultCode {
     //     device.interfaces[interface_number as usize].subclass,
     //     device.interfaces[interface_number as usize].protocol
     // );
-    println!("| Net: Usb Hub Detected");
-    rndis_initialize_msg(device);
-
-    let mut buffer = [0u8; 52];
-
-    unsafe {
-        rndis_query_msg(
-            device,
-            OID::OID_GEN_CURRENT_PACKET_FILTER,
-            buffer.as_mut_ptr(),
-            30,
-        );
-
-        rndis_set_msg(device, OID::OID_GEN_CURRENT_PACKET_FILTER, 0xF);
-
-        rndis_query_msg(
-            device,
-            OID::OID_GEN_CURRENT_PACKET_FILTER,
-            buffer.as_mut_ptr(),
-            30,
-        );
-    }
+    let driver = if device.interfaces[interface_number as usize].subclass == CDC_SUBCLASS_ECM {
+        NetDriver::CdcEcm
+    } else {
+        NetDriver::Rndis
+    };
+    println!("| Net: Usb {:?} device detected", driver);
+
+    // 1. initialize the device, and get its mac address and link state
+    let dev: Box<dyn Device> = match driver {
+        NetDriver::Rndis => match RndisAttach(device) {
+            Some(mac_addr) => Box::new(rndis::Rndis::new(&NET_QUEUES, mac_addr, NET_MTU)),
+            None => return ResultCode::ErrorDevice,
+        },
+        NetDriver::CdcEcm => match EcmAttach(device, interface_number) {
+            Some(mac_addr) => Box::new(cdcecm::CDCECM::new(&NET_QUEUES, mac_addr, NET_MTU)),
+            None => return ResultCode::ErrorDevice,
+        },
+    };
+    println!("| Net: MAC Address: {:x?}", dev.mac_addr());
 
     let driver_data = Box::new(UsbEndpointDevice::new());
     device.driver_data = DriverData::new(driver_data);
@@ -107,85 +146,286 @@ pub fn NetAttach(device: &mut UsbDevice, interface_number: u32) -> ResultCode {
         10,
     );
 
-    // 1. new network interface
-    // 2. initialize mac address
-    // 3. initalize and statically set ip address and gateway
-    // 4. initialize arp table
-
-    let mac_addr: &mut [u8; 6];
+    // 2. new network interface, which takes its mac address from the device
+    let index = add_interface(Interface::new(dev));
     unsafe {
-        let mut b = vec![0u8; 30];
-        let query = rndis_query_msg(device, OID::OID_802_3_PERMANENT_ADDRESS, b.as_mut_ptr(), 30);
-
-        if query.0 != ResultCode::OK {
-            panic!("| Net: Error getting MAC address {:#?}", query.0);
-        }
-
-        let b_offset = query.1;
-        let b_len = query.2;
-        if b_len != 6 {
-            panic!("| Net: Error getting MAC address {}", b_len);
-        }
-        mac_addr = &mut *(b.as_mut_ptr().offset(b_offset as isize) as *mut [u8; 6]);
-    }
-
-    println!("| Net: MAC Address: {:x?}", mac_addr);
-    let DEFAULT_MAC = EthernetAddress::from_bytes(mac_addr).unwrap();
-
-    // initalize the interface
-    unsafe {
-        INTERFACE = Some(Interface::new());
+        NET_DEVICE.interface = Some(index);
+        NET_DEVICE.driver = Some(driver);
     }
-
-    // set the mac address
     let interface = get_interface_mut();
-    interface.ethernet_addr = DEFAULT_MAC;
 
-    // register receiving ethernet function
+    // register receiving function
     RegisterNetReceiveCallback(recv);
 
     unsafe {
         NET_DEVICE.device = Some(device);
     }
 
-    // begin socket send loop, this iterates through all existing sockets, and attempts to send as
-    // many packets as possible from each socket
-    socket::socket_send_loop();
-
     // begin receieve series, this queues a receive to be ran which will eventually propogate back
     // to us through the rgistered `recv` function which then queues another receive
-    let buf = vec![0u8; 1500];
+    let receive_len = driver.receive_len();
+    let buf = vec![0u8; receive_len as usize];
     unsafe {
-        rndis_receive_packet(device, buf.into_boxed_slice(), 1500); // TODO: ask aaron if I need to use another function?
+        NetReceivePacket(device, buf.into_boxed_slice(), receive_len);
     }
 
-    // start dhcp
+    // and send whatever the interface transmits
+    NetTransmitLoop();
+
+    // 3. start dhcp, which keeps the interface's address and routes up to date from now on
+    dhcp::spawn_client(index);
+
+    // and ipv6 autoconfiguration
+    let _ = ndp::start(interface);
+
+    return ResultCode::OK;
+}
+
+// initializes an rndis device, returning its mac address
+fn RndisAttach(device: &mut UsbDevice) -> Option<EthernetAddress> {
+    rndis_initialize_msg(device);
+
+    let mut buffer = [0u8; 52];
+
     unsafe {
-        DHCPD = Some(dhcp::Dhcpd::new());
+        rndis_query_msg(
+            device,
+            OID::OID_GEN_CURRENT_PACKET_FILTER,
+            buffer.as_mut_ptr(),
+            30,
+        );
+
+        rndis_set_msg(device, OID::OID_GEN_CURRENT_PACKET_FILTER, 0xF);
+
+        rndis_query_msg(
+            device,
+            OID::OID_GEN_CURRENT_PACKET_FILTER,
+            buffer.as_mut_ptr(),
+            30,
+        );
     }
-    let dhcpd = get_dhcpd_mut();
-    let _ = dhcpd.start(interface);
 
-    return ResultCode::OK;
+    let mut b = vec![0u8; 30];
+    let query =
+        unsafe { rndis_query_msg(device, OID::OID_802_3_PERMANENT_ADDRESS, b.as_mut_ptr(), 30) };
+    if query.0 != ResultCode::OK || query.2 != 6 {
+        println!(
+            "| Net: Error getting MAC address {:#?} {}",
+            query.0, query.2
+        );
+        return None;
+    }
+    let b_offset = query.1 as usize;
+    let Some(mac_bytes) = b.get(b_offset..b_offset + 6) else {
+        println!("| Net: MAC address out of bounds at offset {}", b_offset);
+        return None;
+    };
+    let mac_addr = EthernetAddress::from_bytes(mac_bytes).ok()?;
+
+    // after this, the device tells us about changes through the interrupt endpoint
+    let mut b = vec![0u8; 30];
+    let query = unsafe {
+        rndis_query_msg(
+            device,
+            OID::OID_GEN_MEDIA_CONNECT_STATUS,
+            b.as_mut_ptr(),
+            30,
+        )
+    };
+    let b_offset = query.1 as usize;
+    let connected = if query.0 == ResultCode::OK && query.2 == 4 {
+        let Some(status) = b.get(b_offset..b_offset + 4) else {
+            println!("| Net: Link state out of bounds at offset {}", b_offset);
+            return None;
+        };
+        status == [0, 0, 0, 0]
+    } else {
+        false
+    };
+    NET_QUEUES.set_link_state(if connected {
+        LinkState::Up
+    } else {
+        LinkState::Down
+    });
+
+    Some(mac_addr)
+}
+
+// initializes a cdc-ecm device, returning its mac address
+fn EcmAttach(device: &mut UsbDevice, interface_number: u32) -> Option<EthernetAddress> {
+    let mac_addr = EcmMacAddress(device)?;
+
+    // the data interface has no endpoints until its second alternate setting is picked, which
+    // is how ecm devices are told the host is ready for frames
+    let data_interface = CdcFunctionalDescriptor(device, CDC_UNION_DESCRIPTOR)
+        .and_then(|union| union.get(4).copied())
+        .unwrap_or(interface_number as u8 + 1);
+    let result = EcmControlMessage(
+        device,
+        0x01,
+        UsbDeviceRequestRequest::SetInterface,
+        1,
+        data_interface as u16,
+    );
+    if result != ResultCode::OK {
+        println!("| Net: Failed to set the ecm data interface {:#?}", result);
+        return None;
+    }
+
+    let result = EcmControlMessage(
+        device,
+        0x21,
+        convert_usb_device_request_cdc(UsbDeviceRequestCDC::SetEthernetPacketFilter),
+        ECM_PACKET_FILTER,
+        interface_number as u16,
+    );
+    if result != ResultCode::OK {
+        println!("| Net: Failed to set the ecm packet filter {:#?}", result);
+    }
+
+    // ecm devices only say when the connection changes, so assume it's there until told otherwise
+    NET_QUEUES.set_link_state(LinkState::Up);
+
+    Some(mac_addr)
+}
+
+// a class specific interface descriptor of the given subtype, from the device's configuration
+fn CdcFunctionalDescriptor(device: &UsbDevice, subtype: u8) -> Option<&[u8]> {
+    let mut rest = &device.full_configuration.as_ref()?[..];
+    while rest.len() >= 3 {
+        let len = rest[0] as usize;
+        if len < 3 || len > rest.len() {
+            return None;
+        }
+        let (descriptor, next) = rest.split_at(len);
+        if descriptor[1] == CS_INTERFACE && descriptor[2] == subtype {
+            return Some(descriptor);
+        }
+        rest = next;
+    }
+    None
 }
 
+// ecm devices give their mac address as a string descriptor of 12 hex digits, which the ethernet
+// functional descriptor points at
+fn EcmMacAddress(device: &mut UsbDevice) -> Option<EthernetAddress> {
+    let index = *CdcFunctionalDescriptor(device, CDC_ETHERNET_DESCRIPTOR)?.get(3)?;
+
+    // the length and type, then the digits in utf-16
+    let mut buffer = [0u8; 2 + 12 * 2];
+    let result = unsafe {
+        UsbGetDescriptor(
+            device,
+            DescriptorType::String,
+            index,
+            LANG_ID_EN_US,
+            buffer.as_mut_ptr(),
+            buffer.len() as u32,
+            buffer.len() as u32,
+            0,
+        )
+    };
+    if result != ResultCode::OK {
+        println!("| Net: Error getting MAC address {:#?}", result);
+        return None;
+    }
+
+    let mut digits = buffer[2..].chunks(2).map(|c| (c[0] as char).to_digit(16));
+    let mut mac_addr = [0u8; 6];
+    for byte in mac_addr.iter_mut() {
+        *byte = (digits.next()?? << 4 | digits.next()??) as u8;
+    }
+    EthernetAddress::from_bytes(&mac_addr).ok()
+}
+
+fn EcmControlMessage(
+    device: &mut UsbDevice,
+    request_type: u8,
+    request: UsbDeviceRequestRequest,
+    value: u16,
+    index: u16,
+) -> ResultCode {
+    unsafe {
+        UsbControlMessage(
+            device,
+            UsbPipeAddress {
+                transfer_type: UsbTransfer::Control,
+                speed: device.speed,
+                end_point: 0,
+                device: device.number as u8,
+                direction: UsbDirection::Out,
+                max_size: size_from_number(device.descriptor.max_packet_size0 as u32),
+                _reserved: 0,
+            },
+            core::ptr::null_mut(),
+            0,
+            &mut UsbDeviceRequest {
+                request_type,
+                request,
+                value,
+                index,
+                length: 0,
+            },
+            10,
+        )
+    }
+}
+
+// a bulk in transfer finished, so hand it to the interface and queue the next one
 pub unsafe fn recv(buf: *mut u8, buf_len: u32) {
-    // cast our buffer into a Vec<u8>
     let slice: &[u8] = unsafe { slice::from_raw_parts(buf, buf_len as usize) };
+    if NET_QUEUES.rx.lock().push(slice.to_vec()).is_err() {
+        println!("| Net: Receive queue full, dropping a frame");
+    }
 
-    let interface = get_interface_mut();
-    let _ = ethernet::recv_ethernet_frame(interface, slice, buf_len);
+    // queue another recv to be run in the future
+    thread::thread(move || {
+        let receive_len = unsafe { NET_DEVICE.driver }
+            .expect("receiving before the driver was attached")
+            .receive_len();
+        let buf = vec![0u8; receive_len as usize];
+        unsafe {
+            let device = &mut *NET_DEVICE.device.unwrap();
+            NetReceivePacket(device, buf.into_boxed_slice(), receive_len);
+        }
+    });
 }
 
+// notifications from the interrupt endpoint, which is how the link state changes are heard
 pub unsafe fn NetAnalyze(buffer: *mut u8, buffer_length: u32) {
     let buffer32 = unsafe { core::slice::from_raw_parts(buffer, buffer_length as usize) };
-    if buffer32.is_empty() {
+    if buffer32.len() < 8 {
         return;
     }
 
-    if buffer32[0] != 0 {
-        println!("| Net: Buffer1: {:?}", buffer32[0]);
-        shutdown();
+    match unsafe { NET_DEVICE.driver } {
+        // a network connection notification, with whether it's connected as the value
+        Some(NetDriver::CdcEcm) => {
+            if buffer32[0] == 0xA1 && buffer32[1] == CDC_NETWORK_CONNECTION {
+                NET_QUEUES.set_link_state(if buffer32[2] != 0 {
+                    LinkState::Up
+                } else {
+                    LinkState::Down
+                });
+            }
+        }
+        // rndis only says a response is available, which has to be read to see what it is
+        Some(NetDriver::Rndis) => {
+            if buffer32[0] != 1 {
+                return;
+            }
+            thread::thread(|| {
+                let device = unsafe { &mut *NET_DEVICE.device.unwrap() };
+                let status = rndis_read_status(device);
+                if status == Some(RndisStatusValue::RNDIS_STATUS_MEDIA_CONNECT as u32) {
+                    NET_QUEUES.set_link_state(LinkState::Up);
+                } else if status == Some(RndisStatusValue::RNDIS_STATUS_MEDIA_DISCONNECT as u32) {
+                    NET_QUEUES.set_link_state(LinkState::Down);
+                }
+            });
+        }
+        // not attached yet
+        None => {}
     }
 }
 
@@ -213,22 +453,89 @@ pub fn RegisterNetReceiveCallback(callback: unsafe fn(*mut u8, u32)) {
     }
 }
 
-pub unsafe fn NetSendPacket(buffer: *mut u8, buffer_length: u32) {
-    unsafe {
-        if let Some(device) = NET_DEVICE.device {
-            let usb_dev = &mut *device;
-            rndis_send_packet(usb_dev, buffer, buffer_length);
-        } else {
-            println!("| Net: No device found.");
-            shutdown();
+// sends everything the interface transmits, already framed by its Device, one bulk out transfer
+// each
+pub fn NetTransmitLoop() {
+    spawn_async(async {
+        loop {
+            let message = poll_fn(|cx| NET_QUEUES.tx.lock().poll_pop(cx)).await;
+            unsafe {
+                if let Some(device) = NET_DEVICE.device {
+                    NetSendPacket(&mut *device, message);
+                } else {
+                    println!("| Net: No device found.");
+                }
+            }
         }
+    });
+}
+
+pub unsafe fn NetSendPacket(device: &mut UsbDevice, message: Vec<u8>) -> ResultCode {
+    let size = message.len() as u32;
+    let result = unsafe {
+        UsbSendBulkMessage(
+            device,
+            UsbPipeAddress {
+                transfer_type: UsbTransfer::Bulk,
+                speed: device.speed,
+                end_point: 2,
+                device: device.number as u8,
+                direction: UsbDirection::Out,
+                max_size: size_from_number(64),
+                _reserved: 0,
+            },
+            message.into_boxed_slice(),
+            size,
+            PacketId::Data0,
+            1,
+            10,
+        )
+    };
+
+    if result != ResultCode::OK {
+        print!("| Net: Failed to send packet message.\n");
     }
+    result
+}
+
+pub unsafe fn NetReceivePacket(
+    device: &mut UsbDevice,
+    buffer: Box<[u8]>,
+    buffer_length: u32,
+) -> ResultCode {
+    let result = unsafe {
+        UsbSendBulkMessage(
+            device,
+            UsbPipeAddress {
+                transfer_type: UsbTransfer::Bulk,
+                speed: device.speed,
+                end_point: 2,
+                device: device.number as u8,
+                direction: UsbDirection::In,
+                max_size: size_from_number(64),
+                _reserved: 0,
+            },
+            buffer,
+            buffer_length,
+            PacketId::Data0,
+            2,
+            10,
+        )
+    };
+
+    if result != ResultCode::OK {
+        print!("| Net: Failed to receive packet message.\n");
+    }
+    result
 }
 
 pub struct NetDevice {
     pub receive_callback: Option<unsafe fn(*mut u8, u32)>,
     // pub receive_callback: Option<fn(&mut Interface, &[u8], u32)>,
     pub device: Option<*mut UsbDevice>,
+    // index of the interface this device sends and receives through
+    pub interface: Option<usize>,
+    pub driver: Option<NetDriver>,
     // pub default_interface: Option<Box<Interface>>,
 }
 
@@ -244,6 +551,7 @@ pub enum UsbDeviceRequestCDC {
     GetLineCoding = 0x21,
     SetControlLineState = 0x22,
     SendBreak = 0x23,
+    SetEthernetPacketFilter = 0x43,
 }
 
 pub const fn convert_usb_device_request_cdc(
@@ -258,5 +566,8 @@ pub const fn convert_usb_device_request_cdc(
         UsbDeviceRequestCDC::GetLineCoding => UsbDeviceRequestRequest::GetLineCoding,
         UsbDeviceRequestCDC::SetControlLineState => UsbDeviceRequestRequest::SetControlLineState,
         UsbDeviceRequestCDC::SendBreak => UsbDeviceRequestRequest::SendBreak,
+        UsbDeviceRequestCDC::SetEthernetPacketFilter => {
+            UsbDeviceRequestRequest::SetEthernetPacketFilter
+        }
     }
 }
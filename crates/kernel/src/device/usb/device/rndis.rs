@@ -10,18 +10,19 @@ use crate::device::usb::types::*;
 use crate::device::usb::usbd::device::*;
 use crate::device::usb::usbd::pipe::*;
 use crate::device::usb::usbd::request::*;
-use crate::device::usb::usbd::usbd::UsbSendBulkMessage;
 use crate::device::usb::UsbControlMessage;
 
 use crate::device::usb::device::net::*;
-use crate::device::usb::PacketId;
-use alloc::boxed::Box;
-use alloc::vec;
+use alloc::vec::Vec;
+use core::slice;
 
 const ControlTimeoutPeriod: u32 = 10;
 #[allow(dead_code)]
 const KeepAliveTimeoutPeriod: u32 = 5;
 
+const RNDIS_PACKET_MSG: u32 = 0x00000001;
+const RNDIS_INDICATE_STATUS_MSG: u32 = 0x00000007;
+
 pub fn rndis_initialize_msg(device: &mut UsbDevice) -> ResultCode {
     let buffer = &mut RndisInitializeMsg {
         message_type: 0x00000002,
@@ -350,18 +351,16 @@ pub fn rndis_set_msg(device: &mut UsbDevice, oid: OID, value: u32) -> ResultCode
     return ResultCode::OK;
 }
 
-pub unsafe fn rndis_send_packet(
-    device: &mut UsbDevice,
-    buffer: *mut u8,
-    buffer_length: u32,
-) -> ResultCode {
-    let size = size_of::<RndisPacketMsg>() as u32 + buffer_length;
+// puts an ethernet frame in a packet message, which is how rndis carries frames over the bulk
+// endpoints
+pub fn rndis_wrap_packet(frame: &[u8]) -> Vec<u8> {
+    let size = size_of::<RndisPacketMsg>() + frame.len();
 
-    let buffer_req = &mut RndisPacketMsg {
-        message_type: 0x1,
-        message_length: size,
+    let header = RndisPacketMsg {
+        message_type: RNDIS_PACKET_MSG,
+        message_length: size as u32,
         data_offset: size_of::<RndisPacketMsg>() as u32 - 8,
-        data_length: buffer_length,
+        data_length: frame.len() as u32,
         oob_data_offset: 0,
         oob_data_length: 0,
         num_oob_data_elements: 0,
@@ -371,83 +370,77 @@ pub unsafe fn rndis_send_packet(
         reserved: 0,
     };
 
-    let mut complete_buffer = vec![0u8; size as usize];
-
-    //copy buffer_req to complete_buffer
-    unsafe {
-        core::ptr::copy_nonoverlapping(
-            buffer_req as *mut RndisPacketMsg as *mut u8,
-            complete_buffer.as_mut_ptr(),
-            size_of::<RndisPacketMsg>() as usize,
-        );
-        core::ptr::copy_nonoverlapping(
-            buffer,
-            complete_buffer
-                .as_mut_ptr()
-                .offset(size_of::<RndisPacketMsg>() as isize),
-            buffer_length as usize,
-        );
-    }
-
-    let result = unsafe {
-        UsbSendBulkMessage(
-            device,
-            UsbPipeAddress {
-                transfer_type: UsbTransfer::Bulk,
-                speed: device.speed,
-                end_point: 2,
-                device: device.number as u8,
-                direction: UsbDirection::Out,
-                max_size: size_from_number(64),
-                _reserved: 0,
-            },
-            complete_buffer.into_boxed_slice(),
-            size as u32,
-            PacketId::Data0,
-            1,
-            10,
+    let mut message = Vec::with_capacity(size);
+    message.extend_from_slice(unsafe {
+        slice::from_raw_parts(
+            &header as *const RndisPacketMsg as *const u8,
+            size_of::<RndisPacketMsg>(),
         )
+    });
+    message.extend_from_slice(frame);
+    message
+}
+
+// the ethernet frame inside a packet message, None if the message isn't one or is cut short
+pub fn rndis_unwrap_packet(message: &[u8]) -> Option<&[u8]> {
+    let field = |i: usize| {
+        let bytes = message.get(i * 4..i * 4 + 4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
     };
 
-    if result != ResultCode::OK {
-        print!("| RNDIS: Failed to send packet message.\n");
-        return result;
+    if field(0)? != RNDIS_PACKET_MSG {
+        return None;
     }
-
-    return ResultCode::OK;
+    // the offset is counted from the data_offset field, 8 bytes in
+    let start = 8 + field(2)? as usize;
+    let len = field(3)? as usize;
+    message.get(start..start.checked_add(len)?)
 }
 
-pub unsafe fn rndis_receive_packet(
-    device: &mut UsbDevice,
-    buffer: Box<[u8]>,
-    buffer_length: u32,
-) -> ResultCode {
+// reads the response the device said was available (through the interrupt endpoint), returning
+// the status if it was an indicate status message (ie. media connect or disconnect)
+pub fn rndis_read_status(device: &mut UsbDevice) -> Option<u32> {
+    let mut buffer = [0u8; 64];
+
     let result = unsafe {
-        UsbSendBulkMessage(
+        UsbControlMessage(
             device,
             UsbPipeAddress {
-                transfer_type: UsbTransfer::Bulk,
+                transfer_type: UsbTransfer::Control,
                 speed: device.speed,
-                end_point: 2,
+                end_point: 0,
                 device: device.number as u8,
                 direction: UsbDirection::In,
-                max_size: size_from_number(64),
+                max_size: size_from_number(device.descriptor.max_packet_size0 as u32),
                 _reserved: 0,
             },
-            buffer,
-            buffer_length,
-            PacketId::Data0,
-            2,
-            10,
+            buffer.as_mut_ptr(),
+            buffer.len() as u32,
+            &mut UsbDeviceRequest {
+                request_type: 0xA1,
+                request: convert_usb_device_request_cdc(
+                    UsbDeviceRequestCDC::GetEncapsulatedResponse,
+                ),
+                value: 0,
+                index: 0,
+                length: buffer.len() as u16,
+            },
+            ControlTimeoutPeriod,
         )
     };
 
     if result != ResultCode::OK {
-        print!("| RNDIS: Failed to receive packet message.\n");
-        return result;
+        print!("| RNDIS: Failed to receive response message.\n");
+        return None;
     }
 
-    return ResultCode::OK;
+    let message_type = u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]);
+    if message_type != RNDIS_INDICATE_STATUS_MSG {
+        return None;
+    }
+    Some(u32::from_le_bytes([
+        buffer[8], buffer[9], buffer[10], buffer[11],
+    ]))
 }
 
 #[repr(C, packed)]
@@ -62,6 +62,7 @@ pub enum UsbDeviceRequestRequest {
     GetLineCoding = 0x21,
     SetControlLineState = 0x22,
     SendBreak = 0x23,
+    SetEthernetPacketFilter = 0x43,
 }
 
 impl Default for UsbDeviceRequestRequest {
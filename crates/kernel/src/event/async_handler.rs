@@ -1,4 +1,3 @@
-use core::arch::asm;
 use core::future::Future;
 use core::pin::Pin;
 use core::task::Poll;
@@ -9,6 +8,7 @@ use super::context::{enter_event_loop, Context, CORES};
 use super::scheduler::Priority;
 use super::thread::UserRegs;
 use super::{task, thread};
+use crate::arch::memory::asid;
 use crate::event;
 
 pub trait AsyncFnCustomSend<Args> {
@@ -252,16 +252,8 @@ impl<'outer> HandlerContext<'outer> {
             let thread = self.cur_thread();
             let user_ttbr0 = thread.user_regs.as_ref().unwrap().ttbr0_el1;
 
-            let cur_ttbr0: usize;
-            unsafe { asm!("mrs {0}, TTBR0_EL1", out(reg) cur_ttbr0) };
-
-            // If the page table has changed, switch back to this thread's
-            // address space.
-            if cur_ttbr0 != user_ttbr0 {
-                unsafe {
-                    asm!("msr TTBR0_EL1, {0}", "isb", "dsb sy", "tlbi vmalle1is", "dsb sy", in(reg) user_ttbr0)
-                };
-            }
+            // Switch back to this thread's address space, if it has changed.
+            unsafe { asid::switch_user_table(user_ttbr0) };
         }
     }
 
@@ -1,7 +1,8 @@
 use core::arch::{asm, global_asm};
 
-use super::context::Context;
+use super::context::{Context, CORES};
 use crate::arch::halt;
+use crate::process::coredump;
 use crate::sync::HandlerTableInner;
 use crate::syscall::proc::exit_current_user_thread;
 use crate::uart;
@@ -330,6 +331,17 @@ unsafe extern "C" fn exception_handler_user(
                 println!("{:#?}", ctx);
             }
 
+            let process = CORES.with_current(|core| {
+                let thread = core.thread.take();
+                let process = thread.as_ref().and_then(|t| t.process.clone());
+                core.thread.set(thread);
+                process
+            });
+            if let Some(process) = process {
+                let signal = coredump::signal_for_exception(exception_class);
+                coredump::dump_core(&process, ctx, signal);
+            }
+
             unsafe { exit_current_user_thread(ctx, (-2i32) as u32) }
         }
     }
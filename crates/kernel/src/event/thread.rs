@@ -1,7 +1,7 @@
 use alloc::boxed::Box;
-use core::arch::asm;
 use core::ptr::NonNull;
 
+use crate::arch::memory::asid;
 use crate::process::ProcessRef;
 
 use super::context::{context_switch, Context, SwitchAction, CORES};
@@ -69,6 +69,8 @@ impl Thread {
         };
         let priority = Priority::Normal;
 
+        crate::process::register(&process);
+
         let mut thread = Box::new(Thread {
             stack: (&mut [] as &mut [u128]).into(),
             last_context: NonNull::dangling(),
@@ -166,7 +168,25 @@ impl Thread {
     }
 
     /// Switch into the thread, restoring its context
-    pub unsafe fn enter_thread(self: Box<Self>) -> ! {
+    pub unsafe fn enter_thread(mut self: Box<Self>) -> ! {
+        if self.is_user_thread() && self.process.as_ref().is_some_and(|p| p.is_killed()) {
+            // The process was killed while this thread was descheduled;
+            // user threads are only descheduled in EL0, so it can be
+            // freed without unwinding any kernel state.
+            drop(self);
+            unsafe { super::context::enter_event_loop() };
+        }
+
+        let stopped = self.process.as_ref().filter(|p| p.job.is_stopped());
+        if let Some(job) = stopped.map(|p| p.job.clone()) {
+            // Likewise, the threads of a stopped process are set aside
+            // until it's continued.
+            match job.park(self) {
+                Ok(()) => unsafe { super::context::enter_event_loop() },
+                Err(thread) => self = thread,
+            }
+        }
+
         let next_ctx = self.last_context.as_ptr();
 
         // Disable interrupts (preemption) until context is
@@ -190,30 +210,14 @@ impl Thread {
 
     pub unsafe fn save_user_regs(&mut self) {
         if let Some(user) = &mut self.user_regs {
-            unsafe { core::arch::asm!("mrs {}, TTBR0_EL1", out(reg) user.ttbr0_el1) };
+            user.ttbr0_el1 = asid::current_table();
         }
     }
 
     pub unsafe fn restore_user_regs(user: &UserRegs, ctx: &mut Context) {
-        // TODO: proper tlb flush stuff for switching address spaces
-
-        let cur_ttbr0: usize;
-        unsafe { asm!("mrs {0}, TTBR0_EL1", out(reg) cur_ttbr0) };
-
-        // If the page table has changed, switch back to this thread's
-        // address space.
-        if cur_ttbr0 != user.ttbr0_el1 {
-            unsafe {
-                asm!(
-                    "msr TTBR0_EL1, {0}",
-                    "isb",
-                    "dsb sy",
-                    "tlbi vmalle1is",
-                    "dsb sy",
-                    in(reg) user.ttbr0_el1,
-                );
-            }
-        }
+        // Switch back to this thread's address space; address spaces
+        // are tagged with ASIDs, so this doesn't need a TLB flush.
+        unsafe { asid::switch_user_table(user.ttbr0_el1) };
 
         if user.usermode {
             let core_sp = CORES.with_current(|core| core.core_sp.get());
@@ -222,12 +226,7 @@ impl Thread {
     }
 
     pub fn set_exited(&mut self, status: u32) {
-        let exit_code = &self.process.as_ref().unwrap().exit_code;
-        exit_code
-            .try_set(crate::process::ExitStatus {
-                status: status as u32,
-            })
-            .ok();
+        self.process.as_ref().unwrap().set_exit_status(status);
     }
 }
 
@@ -3,7 +3,8 @@ use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use initfs::{Archive, ArchiveError};
 
-use crate::arch::memory::palloc::{Size4KiB, PAGE_ALLOCATOR};
+use crate::arch::memory::palloc::{PAddr, PhysicalPage, Size4KiB, PAGE_ALLOCATOR};
+use crate::memory::reclaim::{register_shrinker, Shrinker};
 use crate::process::fd::{
     boxed_future, ArcFd, DirEntry, FileDescResult, FileDescriptor, FileKind, SmallFuture,
 };
@@ -45,11 +46,13 @@ impl InitFs {
     pub fn new(data: &'static [u8]) -> Result<alloc::sync::Arc<Self>, ArchiveError> {
         let inner = Archive::load(data)?;
 
-        Ok(Arc::new_cyclic(|this| InitFs {
+        let fs = Arc::new_cyclic(|this| InitFs {
             this: this.clone(),
             inner,
             cache: SpinLock::new(BTreeMap::new()),
-        }))
+        });
+        register_shrinker(Arc::downgrade(&fs) as Weak<dyn Shrinker>);
+        Ok(fs)
     }
     fn construct_inode(&self, num: u64) -> Option<Arc<InitFsFile>> {
         let hdr = self.inner.get_file(num as usize)?;
@@ -85,6 +88,32 @@ impl InitFs {
     }
 }
 
+impl Shrinker for InitFs {
+    /// Drop the decompressed contents of open files; they are
+    /// decompressed again on the next read.
+    fn shrink(&self, target: usize) -> usize {
+        let Some(cache) = self.cache.try_lock() else {
+            return 0;
+        };
+        let files: Vec<_> = cache.values().filter_map(Weak::upgrade).collect();
+        drop(cache);
+
+        let mut freed = 0;
+        for file in files {
+            if freed >= target {
+                break;
+            }
+            let Some(mut data) = file.data.try_lock() else {
+                continue;
+            };
+            if let Some(data) = data.take() {
+                freed += data.len().div_ceil(4096);
+            }
+        }
+        freed
+    }
+}
+
 pub struct InitFsFile {
     fs: Arc<InitFs>,
     pub inode: Inode,
@@ -260,7 +289,7 @@ impl FileDescriptor for InitFsFile {
 
         //File case, need to call read
         boxed_future(async move {
-            let page = PAGE_ALLOCATOR.get().alloc_mapped_frame::<Size4KiB>();
+            let page = PAGE_ALLOCATOR.get().try_alloc_user_frame::<Size4KiB>()?;
             let page_paddr = page.paddr;
             let page_virt = PAGE_ALLOCATOR.get().get_mapped_frame::<Size4KiB>(page);
             let buf_ref = unsafe { core::slice::from_raw_parts_mut(page_virt as *mut u8, 4096) };
@@ -270,11 +299,17 @@ impl FileDescriptor for InitFsFile {
                 }
                 Err(_val) => {
                     println!("Read failed");
+                    PAGE_ALLOCATOR
+                        .get()
+                        .dealloc_frame(PhysicalPage::<Size4KiB>::new(PAddr(page_paddr)));
                     return None;
                 }
             }
         })
     }
+    fn mmap_is_private_copy(&self) -> bool {
+        true
+    }
     fn as_any(&self) -> &dyn core::any::Any {
         self
     }
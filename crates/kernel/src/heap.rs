@@ -1,21 +1,42 @@
 use alloc::alloc::{handle_alloc_error, GlobalAlloc, Layout};
 use core::sync::atomic::{AtomicUsize, Ordering};
 
-// TODO: implement a proper allocator
 // TODO: clarify safety requirements of heap initialization
 
 use crate::arch::memory::machine::{LeafDescriptor, TranslationDescriptor};
 use crate::arch::memory::palloc::{PhysicalPage, Size4KiB, PAGE_ALLOCATOR};
 use crate::arch::memory::table::PageTablePtr;
-use crate::arch::memory::vmm::PAGE_SIZE;
+use crate::arch::memory::vmm::{PAGE_SIZE, VMEM_INIT_DONE};
 use crate::sync::InterruptSpinLock;
 
+pub mod slab;
+
+pub use slab::SlabClassStats;
+
 pub struct HeapStats {
+    /// Bytes handed out to callers, rounded up to the size class or page.
     pub used: usize,
+    /// Physical pages backing the heap.
+    pub pages: usize,
+    pub slabs: [SlabClassStats; slab::SIZE_CLASSES.len()],
 }
 
 pub fn stats() -> HeapStats {
-    HeapStats { used: 0 }
+    let slabs = slab::stats();
+    let large_pages = match &*ALLOCATOR_HACK.lock() {
+        AllocatorHack::Virt(virt_allocator) => virt_allocator.mapped_pages.load(Ordering::Relaxed),
+        _ => 0,
+    };
+    let slab_used = slabs
+        .iter()
+        .map(|s| s.object_size * s.objects_in_use)
+        .sum::<usize>();
+    let slab_pages = slabs.iter().map(|s| s.pages).sum::<usize>();
+    HeapStats {
+        used: slab_used + large_pages * PAGE_SIZE,
+        pages: slab_pages + large_pages,
+        slabs,
+    }
 }
 
 pub enum AllocatorHack {
@@ -30,6 +51,13 @@ pub static ALLOCATOR_HACK: InterruptSpinLock<AllocatorHack> =
 
 unsafe impl GlobalAlloc for InterruptSpinLock<AllocatorHack> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // Small objects come from the slab caches once the direct map
+        // is available; they don't need the global lock.
+        if let Some(class) = slab::size_class(layout) {
+            if VMEM_INIT_DONE.load(Ordering::Relaxed) {
+                return unsafe { slab::alloc(class) };
+            }
+        }
         match &mut *self.lock() {
             AllocatorHack::Uninit => panic!(),
             AllocatorHack::Bump(bump_allocator) => unsafe { bump_allocator.alloc(layout) },
@@ -37,6 +65,10 @@ unsafe impl GlobalAlloc for InterruptSpinLock<AllocatorHack> {
         }
     }
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if slab::contains(ptr) {
+            let class = slab::size_class(layout).unwrap();
+            return unsafe { slab::dealloc(ptr, class) };
+        }
         match &mut *self.lock() {
             AllocatorHack::Uninit => panic!(),
             AllocatorHack::Bump(bump_allocator) => unsafe { bump_allocator.dealloc(ptr, layout) },
@@ -122,6 +154,7 @@ pub struct VirtAllocator {
     pub base: usize,
     pub offset: AtomicUsize,
     pub max: AtomicUsize,
+    pub mapped_pages: AtomicUsize,
 }
 unsafe impl Sync for VirtAllocator {}
 
@@ -132,6 +165,7 @@ impl VirtAllocator {
             base: base as usize,
             offset: AtomicUsize::new(base as usize),
             max: AtomicUsize::new(base as usize + size),
+            mapped_pages: AtomicUsize::new(0),
         }
     }
 }
@@ -191,6 +225,7 @@ unsafe impl GlobalAlloc for VirtAllocator {
                 .unwrap()
             };
         }
+        self.mapped_pages.fetch_add(pages, Ordering::Relaxed);
         // println!("Allocating ptr {:p}, layout {layout:?}", vaddr_base as *mut u8);
 
         vaddr_base as *mut u8
@@ -241,5 +276,6 @@ unsafe impl GlobalAlloc for VirtAllocator {
                 .get()
                 .dealloc_frame(PhysicalPage::<Size4KiB>::new(leaf.get_pa()));
         }
+        self.mapped_pages.fetch_sub(pages, Ordering::Relaxed);
     }
 }
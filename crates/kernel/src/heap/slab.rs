@@ -0,0 +1,286 @@
+//! Slab caches for small kernel allocations.
+//!
+//! Objects of up to [`MAX_SLAB_OBJECT`] bytes are carved out of single
+//! frames from the page allocator, accessed through the direct map.
+//! Each core keeps a magazine of free objects for every size class, so
+//! most allocations and frees don't touch any shared state; magazines
+//! are refilled from (and flushed back to) the per-class slab caches
+//! in batches.
+
+use core::alloc::Layout;
+use core::ptr::null_mut;
+
+use crate::arch::memory::palloc::{PAddr, PhysicalPage, Size4KiB, PAGE_ALLOCATOR};
+use crate::arch::memory::vmm::{DIRECT_MAP_BASE, MAPPED_HEAP_BASE, PAGE_SIZE};
+use crate::sync::{ConstInit, InterruptSpinLock, PerCore};
+
+pub const SIZE_CLASSES: [usize; 7] = [16, 32, 64, 128, 256, 512, 1024];
+pub const MAX_SLAB_OBJECT: usize = SIZE_CLASSES[SIZE_CLASSES.len() - 1];
+
+const MAGAZINE_SIZE: usize = 32;
+/// Number of objects moved between a magazine and its cache at once.
+const MAGAZINE_BATCH: usize = MAGAZINE_SIZE / 2;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SlabClassStats {
+    pub object_size: usize,
+    /// Frames currently owned by this size class.
+    pub pages: usize,
+    /// Objects handed out to callers.
+    pub objects_in_use: usize,
+    /// Free objects held in per-core magazines.
+    pub objects_cached: usize,
+}
+
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+/// Stored at the start of every slab page; objects follow, aligned to
+/// their size.
+struct SlabHeader {
+    prev: *mut SlabHeader,
+    next: *mut SlabHeader,
+    free: *mut FreeObject,
+    in_use: usize,
+}
+
+struct SlabCache {
+    /// Slabs with at least one free object (and at least one in use).
+    partial: *mut SlabHeader,
+    /// A single completely free slab, kept to avoid bouncing a page
+    /// back and forth with the page allocator.
+    empty: *mut SlabHeader,
+    pages: usize,
+    in_use: usize,
+    cached: usize,
+}
+
+unsafe impl Send for SlabCache {}
+
+struct Magazine {
+    len: usize,
+    objects: [*mut u8; MAGAZINE_SIZE],
+}
+
+struct Magazines([Magazine; SIZE_CLASSES.len()]);
+
+unsafe impl Send for Magazines {}
+
+impl ConstInit for Magazines {
+    const INIT: Self = Magazines(
+        [const {
+            Magazine {
+                len: 0,
+                objects: [null_mut(); MAGAZINE_SIZE],
+            }
+        }; SIZE_CLASSES.len()],
+    );
+}
+
+static CACHES: [InterruptSpinLock<SlabCache>; SIZE_CLASSES.len()] =
+    [const { InterruptSpinLock::new(SlabCache::new()) }; SIZE_CLASSES.len()];
+static MAGAZINES: PerCore<Magazines> = PerCore::new();
+
+/// Get the size class that would be used to allocate `layout`, if any.
+pub fn size_class(layout: Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align());
+    SIZE_CLASSES.iter().position(|&class| class >= size)
+}
+
+/// Whether `ptr` was allocated from a slab.
+pub fn contains(ptr: *mut u8) -> bool {
+    (DIRECT_MAP_BASE..MAPPED_HEAP_BASE).contains(&(ptr as usize))
+}
+
+/// Allocate an object of the given size class, returning null if no
+/// memory is available.
+///
+/// # Safety
+///
+/// The direct map must be initialized on the current core.
+pub unsafe fn alloc(class: usize) -> *mut u8 {
+    MAGAZINES.with_current(|mags| {
+        let mag = &mut mags.0[class];
+        if mag.len == 0 {
+            CACHES[class].lock().refill(class, mag);
+        }
+        if mag.len == 0 {
+            return null_mut();
+        }
+        mag.len -= 1;
+        mag.objects[mag.len]
+    })
+}
+
+/// Free an object that was allocated with [`alloc`] and the same size
+/// class.
+///
+/// # Safety
+///
+/// `ptr` must have come from [`alloc`] with the same class, and must not
+/// be used afterwards.
+pub unsafe fn dealloc(ptr: *mut u8, class: usize) {
+    MAGAZINES.with_current(|mags| {
+        let mag = &mut mags.0[class];
+        if mag.len == MAGAZINE_SIZE {
+            unsafe { CACHES[class].lock().flush(mag) };
+        }
+        mag.objects[mag.len] = ptr;
+        mag.len += 1;
+    })
+}
+
+pub fn stats() -> [SlabClassStats; SIZE_CLASSES.len()] {
+    core::array::from_fn(|class| {
+        let cache = CACHES[class].lock();
+        SlabClassStats {
+            object_size: SIZE_CLASSES[class],
+            pages: cache.pages,
+            objects_in_use: cache.in_use - cache.cached,
+            objects_cached: cache.cached,
+        }
+    })
+}
+
+fn first_object_offset(class: usize) -> usize {
+    size_of::<SlabHeader>().next_multiple_of(SIZE_CLASSES[class])
+}
+
+impl SlabCache {
+    const fn new() -> Self {
+        SlabCache {
+            partial: null_mut(),
+            empty: null_mut(),
+            pages: 0,
+            in_use: 0,
+            cached: 0,
+        }
+    }
+
+    /// Move up to a batch of objects into an empty magazine.
+    fn refill(&mut self, class: usize, mag: &mut Magazine) {
+        while mag.len < MAGAZINE_BATCH {
+            let Some(obj) = (unsafe { self.alloc_object(class) }) else {
+                break;
+            };
+            mag.objects[mag.len] = obj;
+            mag.len += 1;
+            self.cached += 1;
+        }
+    }
+
+    /// Move a batch of objects out of a full magazine.
+    unsafe fn flush(&mut self, mag: &mut Magazine) {
+        for _ in 0..MAGAZINE_BATCH {
+            mag.len -= 1;
+            unsafe { self.free_object(mag.objects[mag.len]) };
+            self.cached -= 1;
+        }
+    }
+
+    unsafe fn alloc_object(&mut self, class: usize) -> Option<*mut u8> {
+        if self.partial.is_null() {
+            let slab = match core::mem::replace(&mut self.empty, null_mut()) {
+                slab if !slab.is_null() => slab,
+                _ => unsafe { self.new_slab(class)? },
+            };
+            unsafe { self.push_partial(slab) };
+        }
+
+        let slab = unsafe { &mut *self.partial };
+        let obj = slab.free;
+        slab.free = unsafe { (*obj).next };
+        slab.in_use += 1;
+        self.in_use += 1;
+        if slab.free.is_null() {
+            // Full slabs aren't tracked; they're found again from
+            // their objects when those are freed.
+            unsafe { self.remove_partial(slab) };
+        }
+        Some(obj.cast())
+    }
+
+    unsafe fn free_object(&mut self, ptr: *mut u8) {
+        let slab = ptr.map_addr(|p| p & !(PAGE_SIZE - 1)).cast::<SlabHeader>();
+        let slab_ref = unsafe { &mut *slab };
+
+        let was_full = slab_ref.free.is_null();
+        let obj = ptr.cast::<FreeObject>();
+        unsafe {
+            obj.write(FreeObject {
+                next: slab_ref.free,
+            })
+        };
+        slab_ref.free = obj;
+        slab_ref.in_use -= 1;
+        self.in_use -= 1;
+
+        if was_full {
+            unsafe { self.push_partial(slab) };
+        }
+        if slab_ref.in_use == 0 {
+            unsafe { self.remove_partial(slab) };
+            if self.empty.is_null() {
+                self.empty = slab;
+            } else {
+                let paddr = slab as usize - DIRECT_MAP_BASE;
+                PAGE_ALLOCATOR
+                    .get()
+                    .dealloc_frame(PhysicalPage::<Size4KiB>::new(PAddr(paddr)));
+                self.pages -= 1;
+            }
+        }
+    }
+
+    unsafe fn new_slab(&mut self, class: usize) -> Option<*mut SlabHeader> {
+        let frame = PAGE_ALLOCATOR.get().try_alloc_frame::<Size4KiB>()?;
+        let base = (DIRECT_MAP_BASE + frame.paddr) as *mut u8;
+        self.pages += 1;
+
+        let size = SIZE_CLASSES[class];
+        let mut free = null_mut();
+        for offset in (first_object_offset(class)..PAGE_SIZE).step_by(size).rev() {
+            let obj = unsafe { base.add(offset) }.cast::<FreeObject>();
+            unsafe { obj.write(FreeObject { next: free }) };
+            free = obj;
+        }
+
+        let slab = base.cast::<SlabHeader>();
+        unsafe {
+            slab.write(SlabHeader {
+                prev: null_mut(),
+                next: null_mut(),
+                free,
+                in_use: 0,
+            })
+        };
+        Some(slab)
+    }
+
+    unsafe fn push_partial(&mut self, slab: *mut SlabHeader) {
+        unsafe {
+            (*slab).prev = null_mut();
+            (*slab).next = self.partial;
+            if let Some(next) = self.partial.as_mut() {
+                next.prev = slab;
+            }
+        }
+        self.partial = slab;
+    }
+
+    unsafe fn remove_partial(&mut self, slab: *mut SlabHeader) {
+        let (prev, next) = unsafe { ((*slab).prev, (*slab).next) };
+        if let Some(next) = unsafe { next.as_mut() } {
+            next.prev = prev;
+        }
+        match unsafe { prev.as_mut() } {
+            Some(prev) => prev.next = next,
+            None => self.partial = next,
+        }
+        unsafe {
+            (*slab).prev = null_mut();
+            (*slab).next = null_mut();
+        }
+    }
+}
@@ -1,11 +1,13 @@
-use core::arch::asm;
 use core::future::Future;
 use core::pin::Pin;
 use core::task::Poll;
 
 use crate::arch::memory;
 
+pub mod reclaim;
+
 pub use memory::init;
+pub use memory::map_physical_noncacheable_blocks;
 pub use memory::{
     clean_physical_buffer_for_device, invalidate_physical_buffer_for_device, physical_addr,
 };
@@ -13,15 +15,8 @@ pub use memory::{map_device, map_device_block, map_physical, map_physical_noncac
 
 unsafe fn enable_user_vmem(user_ttbr0: usize) {
     // TODO: restore old ttbr0?
-    let cur_ttbr0: usize;
-    unsafe { asm!("mrs {0}, TTBR0_EL1", out(reg) cur_ttbr0) };
-
     // If the page table has changed, switch back to this thread's address space.
-    if cur_ttbr0 != user_ttbr0 {
-        unsafe {
-            asm!("msr TTBR0_EL1, {0}", "isb", "dsb sy", "tlbi vmalle1is", "dsb sy", in(reg) user_ttbr0)
-        };
-    }
+    unsafe { memory::asid::switch_user_table(user_ttbr0) };
 }
 
 pub unsafe fn with_user_vmem<F, O>(ttbr0: usize, callback: F) -> O
@@ -0,0 +1,128 @@
+//! Handling for running out of physical memory.
+//!
+//! There is no swap, so the only memory that can be given back without
+//! losing data is memory that can be recreated: caches, and clean pages
+//! of private file mappings.  When that isn't enough, the largest
+//! process is killed.
+
+use alloc::sync::Weak;
+use alloc::vec::Vec;
+
+use crate::process::{all_processes, ProcessRef};
+use crate::sync::SpinLock;
+
+/// Exit status of a process killed to free memory.
+pub const OOM_EXIT_STATUS: u32 = -9i32 as u32;
+
+/// Number of pages to try to free whenever an allocation fails.
+const RECLAIM_BATCH: usize = 64;
+
+/// How long to wait for a killed process to exit before retrying a
+/// failed allocation, in microseconds.
+pub const OOM_WAIT: u64 = 10_000;
+
+/// What a process that failed to allocate should do next.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OomAction {
+    /// Memory was freed; retry the allocation right away.
+    Retry,
+    /// A killed process will free its memory once its threads have
+    /// exited; retry after [`OOM_WAIT`].
+    Wait,
+    /// The faulting process itself was chosen to be killed.
+    Killed,
+}
+
+/// A cache that can give back memory on request.
+pub trait Shrinker: Send + Sync {
+    /// Free up to `target` pages worth of memory, returning the number
+    /// of pages actually freed.
+    ///
+    /// This may be called from any context that fails to allocate, so
+    /// it must not block on locks that could be held by the caller.
+    fn shrink(&self, target: usize) -> usize;
+}
+
+static SHRINKERS: SpinLock<Vec<Weak<dyn Shrinker>>> = SpinLock::new(Vec::new());
+
+/// Register a cache to be shrunk under memory pressure; it is removed
+/// once the last strong reference to it is dropped.
+pub fn register_shrinker(shrinker: Weak<dyn Shrinker>) {
+    SHRINKERS.lock().push(shrinker);
+}
+
+/// Try to free `target` pages from caches and clean file mappings,
+/// returning the number of pages freed.
+pub fn reclaim(target: usize) -> usize {
+    let mut freed = 0;
+
+    let shrinkers: Vec<_> = {
+        let mut shrinkers = SHRINKERS.lock();
+        shrinkers.retain(|s| s.strong_count() > 0);
+        shrinkers.iter().filter_map(Weak::upgrade).collect()
+    };
+    for shrinker in shrinkers {
+        if freed >= target {
+            return freed;
+        }
+        freed += shrinker.shrink(target - freed);
+    }
+
+    for process in all_processes() {
+        if freed >= target {
+            break;
+        }
+        // Skip processes in the middle of changing their mappings
+        if let Some(mem) = process.mem.try_lock() {
+            freed += mem.reclaim_clean_pages(target - freed);
+        }
+    }
+
+    freed
+}
+
+/// Called when `faulting` could not allocate a page for itself.
+///
+/// The victim's memory isn't freed here, since its threads may still be
+/// running on other cores; it is freed with the process, once the last
+/// of them has exited.
+pub fn handle_out_of_memory(faulting: &ProcessRef) -> OomAction {
+    if reclaim(RECLAIM_BATCH) > 0 {
+        return OomAction::Retry;
+    }
+
+    // Wait for an earlier victim to go away before killing another.
+    let exiting = all_processes()
+        .into_iter()
+        .any(|p| p.is_killed() && p.mem.try_lock().is_none_or(|mem| mem.owned_pages() > 0));
+    if exiting {
+        return OomAction::Wait;
+    }
+
+    // Pid 1 is init; killing it would bring down everything else too.
+    let victim = all_processes()
+        .into_iter()
+        .filter(|p| p.pid != 1 && !p.is_killed())
+        .filter_map(|p| {
+            let pages = p.mem.try_lock()?.owned_pages();
+            Some((pages, p))
+        })
+        .max_by_key(|(pages, _)| *pages);
+
+    let (pages, victim) = match victim {
+        Some((pages, victim)) if pages > faulting.mem.lock().owned_pages() => (pages, victim),
+        _ => (faulting.mem.lock().owned_pages(), faulting.clone()),
+    };
+
+    println!(
+        "| oom: killing process {} ({} pages) to satisfy a fault in process {}",
+        victim.pid, pages, faulting.pid
+    );
+    victim.kill(OOM_EXIT_STATUS);
+
+    if alloc::sync::Arc::ptr_eq(&victim, faulting) {
+        OomAction::Killed
+    } else {
+        OomAction::Wait
+    }
+}
@@ -14,7 +14,7 @@ pub fn send_arp_packet(
     let arp_repr = ArpPacket {
         op,
         source_hw_addr: interface.ethernet_addr,
-        source_proto_addr: *interface.ipv4_addr,
+        source_proto_addr: *interface.ipv4_addr(),
         target_hw_addr,
         target_proto_addr,
     };
@@ -33,7 +33,7 @@ pub fn recv_arp_packet(interface: &mut Interface, eth_frame: EthernetFrame) -> R
 
     // if the target_protocol address isn't us, we'll ignore it for now. be selfish we don't give
     // out other people's numbers :(
-    if arp_repr.target_proto_addr != *interface.ipv4_addr {
+    if arp_repr.target_proto_addr != *interface.ipv4_addr() {
         return Err(Error::Ignored);
     }
 
@@ -61,7 +61,7 @@ pub fn eth_addr_for_ip(
     interface: &mut Interface,
     ipv4_addr: Ipv4Address,
 ) -> Result<EthernetAddress> {
-    if interface.ipv4_addr.is_broadcast(ipv4_addr) {
+    if ipv4_addr.is_limited_broadcast() || interface.ipv4_addr().is_broadcast(ipv4_addr) {
         return Ok(EthernetAddress::BROADCAST);
     }
 
@@ -80,7 +80,7 @@ pub fn eth_addr_for_ip(
                 EthernetAddress::BROADCAST,
                 ipv4_addr,
             )?;
-            Err(Error::MacResolution(ipv4_addr))
+            Err(Error::MacResolution(ipv4_addr.into()))
         }
     }
 }
@@ -0,0 +1,142 @@
+// packet capture: every frame an interface's device sends or receives is copied into a ring
+// buffer, which can be exported in pcap format for wireshark (see [`pcap`])
+use crate::networking::iface::{get_interface_mut, Interface};
+use crate::networking::repr::Medium;
+use crate::networking::utils::pcap::{self, LinkType};
+use crate::sync::SpinLock;
+
+use crate::device::system_timer;
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+// frames are cut to this many bytes
+pub const SNAP_LEN: usize = 65535;
+// the oldest frames are dropped to stay under these
+const MAX_FRAMES: usize = 1024;
+const MAX_BYTES: usize = 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+struct Frame {
+    interface: usize,
+    direction: Direction,
+    timestamp: u64,
+    orig_len: usize,
+    data: Vec<u8>,
+}
+
+pub struct Capture {
+    enabled: bool,
+    frames: VecDeque<Frame>,
+    bytes: usize,
+    // frames pushed out of the ring since it was last cleared
+    dropped: usize,
+}
+
+pub static CAPTURE: SpinLock<Capture> = SpinLock::new(Capture {
+    enabled: true,
+    frames: VecDeque::new(),
+    bytes: 0,
+    dropped: 0,
+});
+
+impl Capture {
+    fn push(&mut self, frame: Frame) {
+        while !self.frames.is_empty()
+            && (self.frames.len() >= MAX_FRAMES || self.bytes + frame.data.len() > MAX_BYTES)
+        {
+            if let Some(oldest) = self.frames.pop_front() {
+                self.bytes -= oldest.data.len();
+                self.dropped += 1;
+            }
+        }
+        self.bytes += frame.data.len();
+        self.frames.push_back(frame);
+    }
+
+    fn frames_on(&self, interface: usize) -> impl Iterator<Item = &Frame> {
+        self.frames
+            .iter()
+            .filter(move |frame| frame.interface == interface)
+    }
+}
+
+// the tap point, called with every frame as it goes to or comes from an interface's device
+pub fn record(interface: &Interface, direction: Direction, data: &[u8]) {
+    let mut capture = CAPTURE.lock();
+    if !capture.enabled {
+        return;
+    }
+
+    let len = data.len().min(SNAP_LEN);
+    capture.push(Frame {
+        interface: interface.index,
+        direction,
+        timestamp: system_timer::get_time(),
+        orig_len: data.len(),
+        data: data[..len].to_vec(),
+    });
+}
+
+pub fn set_enabled(enabled: bool) {
+    CAPTURE.lock().enabled = enabled;
+}
+
+pub fn clear() {
+    let mut capture = CAPTURE.lock();
+    capture.frames.clear();
+    capture.bytes = 0;
+    capture.dropped = 0;
+}
+
+fn link_type(interface: usize) -> u32 {
+    match get_interface_mut(interface).dev.medium() {
+        Medium::Ethernet => LinkType::ETHERNET,
+        Medium::Ip => LinkType::RAW,
+    }
+}
+
+// the frames captured on an interface, as a pcap file. a pcap file has one link type, so each
+// interface is exported on its own
+pub fn export_pcap(interface: usize) -> Vec<u8> {
+    let link_type = link_type(interface);
+    let capture = CAPTURE.lock();
+
+    let len = capture
+        .frames_on(interface)
+        .map(|frame| pcap::RECORD_HEADER_LEN + frame.data.len())
+        .sum::<usize>();
+    let mut buf = Vec::with_capacity(pcap::GLOBAL_HEADER_LEN + len);
+
+    pcap::write_global_header(&mut buf, link_type, SNAP_LEN as u32);
+    for frame in capture.frames_on(interface) {
+        pcap::write_record(&mut buf, frame.timestamp, &frame.data, frame.orig_len);
+    }
+    buf
+}
+
+// prints the frames captured on an interface as hex dumps, which get to the host over the uart
+// even when nothing else does
+pub fn dump_uart(interface: usize) {
+    let link_type = link_type(interface);
+    let capture = CAPTURE.lock();
+
+    println!(
+        "[!] capture on interface {} (link type {}, {} frames dropped)",
+        interface, link_type, capture.dropped
+    );
+    for frame in capture.frames_on(interface) {
+        let arrow = match frame.direction {
+            Direction::Sent => ">",
+            Direction::Received => "<",
+        };
+        println!("{} {} bytes", arrow, frame.orig_len);
+        print!("{}", pcap::hex_dump(frame.timestamp, &frame.data));
+    }
+    println!("[!] end of capture");
+}
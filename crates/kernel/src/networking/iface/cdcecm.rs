@@ -1,41 +1,50 @@
-use crate::networking::repr::dev::Device;
+use crate::networking::repr::{Device, EthernetAddress, LinkState};
+use crate::networking::utils::frame_queue::DeviceQueues;
+use crate::networking::Result;
 
-use crate::device::usb::device::net::{NetReceive, NetSendPacket};
+use alloc::vec::Vec;
+use core::task::{Context, Poll};
 
+// a usb cdc-ecm device. ecm bulk transfers are bare ethernet frames, so they go through as they
+// are, the usb side (see device::usb::device::net) moves them between the queues and the device
 pub struct CDCECM {
+    queues: &'static DeviceQueues,
+    mac_addr: EthernetAddress,
     max_transmission_unit: usize,
 }
 
 impl CDCECM {
-    pub fn new(mtu: usize) -> Self {
+    pub fn new(queues: &'static DeviceQueues, mac_addr: EthernetAddress, mtu: usize) -> Self {
         CDCECM {
+            queues,
+            mac_addr,
             max_transmission_unit: mtu,
         }
     }
 }
 
 impl Device for CDCECM {
-    // TODO: fn send(&mut self, buffer: &[u8], buffer_len: u32) -> Result<()> {
-    fn send(&mut self, buffer: &mut [u8], buffer_len: u32) {
-        unsafe {
-            NetSendPacket(buffer.as_mut_ptr(), buffer_len);
-        }
+    fn transmit(&mut self, frame: Vec<u8>) -> Result<()> {
+        self.queues.tx.lock().push(frame)
     }
 
-    // TODO: fn recv(&mut self, buffer: &mut [u8], buffer_len: u32) -> Result<usize> {
-    fn recv(&mut self, buffer: &mut [u8], buffer_len: u32) {
-        unsafe {
-            NetReceive(buffer.as_mut_ptr(), buffer_len);
-        }
+    fn poll_receive(&mut self, cx: &mut Context<'_>) -> Poll<Vec<u8>> {
+        self.queues.rx.lock().poll_pop(cx)
+    }
+
+    fn link_state(&self) -> LinkState {
+        self.queues.link_state()
+    }
+
+    fn poll_link(&mut self, cx: &mut Context<'_>, known: LinkState) -> Poll<LinkState> {
+        self.queues.poll_link(cx, known)
+    }
+
+    fn mac_addr(&self) -> Option<EthernetAddress> {
+        Some(self.mac_addr)
     }
 
     fn mtu(&self) -> usize {
         self.max_transmission_unit
     }
 }
-
-// impl Drop for CDCECM {
-//     fn drop(&mut self) {
-//
-//     }
-// }
@@ -1,10 +1,23 @@
+/* [`dhcp`] client
+* one runs on each ethernet interface (see [`spawn_client`]), as a task that waits for either a
+* reply on its socket or its next timer. the timers follow rfc 2131:
+*   1. discovers and requests are retransmitted with a backoff doubling from 4 up to 64 seconds
+*   2. at t1 (half the lease by default) the lease is renewed with the server that gave it
+*   3. at t2 (7/8 of the lease) any server is asked instead (rebinding)
+*   4. when the lease runs out, or a server says no (a nak), the address and routes are dropped and
+*      discovery starts over
+*/
 use crate::device::system_timer;
+use crate::event::task::spawn_async;
 
-use crate::networking::iface::Interface;
+use crate::networking::iface::route::{Route, ROUTES};
+use crate::networking::iface::{get_interface_mut, Interface};
 use crate::networking::repr::{
     DhcpMessageType, DhcpOption, DhcpPacket, DhcpParam, Ipv4Address, Ipv4Cidr,
 };
-use crate::networking::socket::{bind, send_to, SocketAddr, UdpSocket};
+use crate::networking::socket::{
+    bind, bind_interface, recv_from_async, recv_from_timeout, send_to, SocketAddr, UdpSocket,
+};
 use crate::networking::{Error, Result};
 
 use alloc::vec;
@@ -12,9 +25,18 @@ use alloc::vec::Vec;
 
 const DHCP_SERVER_PORT: u16 = 67;
 const DHCP_CLIENT_PORT: u16 = 68;
+// requests sent for an offer before giving up on it and discovering again
 const DEFAULT_LEASE_RETRY: usize = 3;
-// const DISCOVER_TIMEOUT: u64 = 2;
-// const REQUEST_TIMEOUT: u64 = 5;
+
+// times are in microseconds, like system_timer's, except lease times which are in seconds
+const MICROS_PER_SEC: u64 = 1_000_000;
+// how long to wait for a reply to a discover or request, doubled after every retransmission
+const INITIAL_RETRANSMIT: u64 = 4 * MICROS_PER_SEC;
+const MAX_RETRANSMIT: u64 = 64 * MICROS_PER_SEC;
+// renews and rebinds are retransmitted halfway to their deadline, but no more often than this
+const MIN_RENEW_RETRANSMIT: u64 = 60 * MICROS_PER_SEC;
+// a lease time that never runs out
+const INFINITE_LEASE: u32 = 0xFFFFFFFF;
 
 // Basic DHCP client state machine
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -32,7 +54,13 @@ pub struct Dhcpd {
     state: DhcpState,
     xid: u32,
     retries: usize,
+    // when the last message was sent, a lease's times count from the request that got it
     last_action_time: u64,
+    // when to retransmit, or move on to the next state
+    next_timeout: Option<u64>,
+    // the current wait for a reply to a discover or request
+    backoff: u64,
+    lease_start: u64,
     server_identifier: Option<Ipv4Address>,
     offered_ip: Option<Ipv4Address>,
     lease_time: Option<u32>,
@@ -50,9 +78,10 @@ impl Dhcpd {
             state: DhcpState::Idle,
             xid: 0,
             retries: DEFAULT_LEASE_RETRY,
-            // discover_timeout: DISCOVER_TIMEOUT,
-            // request_timeout: REQUEST_TIMEOUT,
             last_action_time: 0,
+            next_timeout: None,
+            backoff: INITIAL_RETRANSMIT,
+            lease_start: 0,
             server_identifier: None,
             offered_ip: None,
             lease_time: None,
@@ -65,10 +94,13 @@ impl Dhcpd {
         }
     }
 
-    pub fn is_transacting(&mut self) -> bool {
-        self.state == DhcpState::Idle
-            || self.state == DhcpState::Discovering
-            || self.state == DhcpState::Requesting
+    pub fn state(&self) -> DhcpState {
+        self.state
+    }
+
+    // when `poll` next has something to do, None if nothing will happen until a reply comes
+    pub fn next_timeout(&self) -> Option<u64> {
+        self.next_timeout
     }
 
     pub fn start(&mut self, interface: &mut Interface) -> Result<()> {
@@ -78,14 +110,10 @@ impl Dhcpd {
 
         self.udp_socket = UdpSocket::new();
         let _ = bind(self.udp_socket, DHCP_CLIENT_PORT);
+        // there's no route to anything until we have an address
+        let _ = bind_interface(self.udp_socket, interface.index);
 
-        let time = system_timer::get_time();
-        self.xid = time as u32 ^ 0xDEADBEEF;
-
-        self.state = DhcpState::Discovering;
-        self.last_action_time = time;
-
-        send_dhcp_discover(interface, self.udp_socket, self.xid)
+        self.discover(interface)
     }
 
     pub fn release(&mut self, interface: &mut Interface) -> Result<()> {
@@ -99,13 +127,73 @@ impl Dhcpd {
         if let (Some(server_id), Some(offered_ip)) = (self.server_identifier, self.offered_ip) {
             let result =
                 send_dhcp_release(interface, self.xid, offered_ip, server_id, self.udp_socket);
+            self.drop_lease(interface);
             self.state = DhcpState::Released;
+            self.next_timeout = None;
             result
         } else {
             Err(Error::Malformed)
         }
     }
 
+    // runs whatever timer is due: retransmitting, renewing, rebinding, or losing the lease
+    pub fn poll(&mut self, interface: &mut Interface, now: u64) -> Result<()> {
+        match self.next_timeout {
+            Some(timeout) if now >= timeout => {}
+            _ => return Ok(()),
+        }
+
+        match self.state {
+            DhcpState::Discovering => {
+                self.backoff = (self.backoff * 2).min(MAX_RETRANSMIT);
+                self.next_timeout = Some(now + self.backoff);
+                self.last_action_time = now;
+                send_dhcp_discover(interface, self.udp_socket, self.xid)
+            }
+            DhcpState::Requesting => {
+                let (Some(server_id), Some(offered_ip), 1..) =
+                    (self.server_identifier, self.offered_ip, self.retries)
+                else {
+                    println!("DHCP: No reply to REQUEST, restarting discovery");
+                    return self.discover(interface);
+                };
+                self.retries -= 1;
+                self.backoff = (self.backoff * 2).min(MAX_RETRANSMIT);
+                self.next_timeout = Some(now + self.backoff);
+                self.last_action_time = now;
+                send_dhcp_request(interface, self.xid, offered_ip, server_id, self.udp_socket)
+            }
+            DhcpState::Bound => {
+                self.state = DhcpState::Renewing;
+                self.xid = now as u32 ^ 0xDEADBEEF;
+                self.renew(interface, now)
+            }
+            DhcpState::Renewing => {
+                if self.deadline(self.rebind_time).is_some_and(|t2| now >= t2) {
+                    self.state = DhcpState::Rebinding;
+                    self.xid = now as u32 ^ 0xDEADBEEF;
+                    return self.rebind(interface, now);
+                }
+                self.renew(interface, now)
+            }
+            DhcpState::Rebinding => {
+                if self
+                    .deadline(self.lease_time)
+                    .is_some_and(|expiry| now >= expiry)
+                {
+                    println!("DHCP: Lease on {} expired", interface.ipv4_addr());
+                    self.drop_lease(interface);
+                    return self.discover(interface);
+                }
+                self.rebind(interface, now)
+            }
+            DhcpState::Idle | DhcpState::Released => {
+                self.next_timeout = None;
+                Ok(())
+            }
+        }
+    }
+
     pub fn process_dhcp_packet(
         &mut self,
         interface: &mut Interface,
@@ -128,9 +216,12 @@ impl Dhcpd {
                 if let (Some(server_id), Some(offered_ip)) =
                     (self.server_identifier, self.offered_ip)
                 {
+                    let now = system_timer::get_time();
                     self.state = DhcpState::Requesting;
-                    self.last_action_time = system_timer::get_time();
+                    self.last_action_time = now;
                     self.retries = DEFAULT_LEASE_RETRY;
+                    self.backoff = INITIAL_RETRANSMIT;
+                    self.next_timeout = Some(now + self.backoff);
 
                     send_dhcp_request(interface, self.xid, offered_ip, server_id, self.udp_socket)?;
                     // send_dhcp_packet_workaround(interface, self.xid, offered_ip, server_id, packet)?;
@@ -145,10 +236,16 @@ impl Dhcpd {
                     return Err(Error::Ignored);
                 }
 
-                // Process lease parameters
+                // Process lease parameters, which count from when we asked
+                self.lease_start = self.last_action_time;
                 self.lease_time = packet.get_lease_time();
+                self.offered_ip = Some(packet.yiaddr);
+                // rebinding may have been answered by a different server, which holds the lease now
+                if let Some(server_id) = packet.get_server_identifier() {
+                    self.server_identifier = Some(server_id);
+                }
 
-                let sub_mask = packet.get_subnet_mask().unwrap();
+                let sub_mask = packet.get_subnet_mask().ok_or(Error::Malformed)?;
                 let mask_bytes = sub_mask.as_bytes();
                 let mut count = 0;
 
@@ -164,28 +261,49 @@ impl Dhcpd {
                 self.router = packet.get_router();
                 self.dns_servers = packet.get_dns_servers();
 
-                // Calculate renewal and rebinding times
+                // Calculate renewal and rebinding times, unless the server gave them
+                self.renewal_time = packet.get_renewal_time();
+                self.rebind_time = packet.get_rebinding_time();
                 if let Some(lease_time) = self.lease_time {
-                    self.renewal_time = Some(lease_time / 2);
-                    self.rebind_time = Some((lease_time * 7) / 8);
+                    let renewal_time = (lease_time as u64 / 2) as u32;
+                    let rebind_time = (lease_time as u64 * 7 / 8) as u32;
+                    self.renewal_time = self.renewal_time.or(Some(renewal_time));
+                    self.rebind_time = self.rebind_time.or(Some(rebind_time));
                 }
 
-                // Update interface IP address
-                interface.ipv4_addr = Ipv4Cidr::new(packet.yiaddr, self.subnet_mask).unwrap();
-
-                // Update default gateway if provided
+                // Update interface IP address and name servers together
+                let cidr = Ipv4Cidr::new(packet.yiaddr, self.subnet_mask)?;
+                let mut addrs = interface.addrs.lock();
+                addrs.ipv4_addr = cidr;
+                addrs.dns_servers = self.dns_servers.clone();
+                drop(addrs);
+
+                // Route the subnet through this interface, and everything else through the
+                // gateway if provided
+                let mut routes = ROUTES.lock();
+                routes.remove_interface(interface.index);
+                routes.add(Route {
+                    cidr,
+                    gateway: None,
+                    interface: interface.index,
+                });
                 if let Some(router) = self.router {
-                    interface.default_gateway = router;
+                    routes.add(Route {
+                        cidr: Ipv4Cidr::empty(),
+                        gateway: Some(router),
+                        interface: interface.index,
+                    });
                 }
+                drop(routes);
 
                 self.state = DhcpState::Bound;
-                self.last_action_time = system_timer::get_time();
+                self.next_timeout = self.deadline(self.renewal_time);
 
                 println!(
                     "\t[+] DHCP: Bound to IP {} with lease time {} seconds on gateway {}",
-                    interface.ipv4_addr,
+                    cidr,
                     self.lease_time.unwrap_or(0),
-                    interface.default_gateway,
+                    self.router.unwrap_or(Ipv4Address::empty()),
                 );
             }
             (DhcpState::Requesting, DhcpMessageType::Nak)
@@ -197,13 +315,11 @@ impl Dhcpd {
 
                 println!("DHCP: Received NAK, restarting discovery");
 
-                // Reset and start over
-                self.state = DhcpState::Discovering;
-                self.last_action_time = system_timer::get_time();
-                self.retries = DEFAULT_LEASE_RETRY;
-                self.xid = system_timer::get_time() as u32 ^ 0xEFEF1212;
-
-                send_dhcp_discover(interface, self.udp_socket, self.xid)?;
+                // the address isn't ours anymore, if it ever was
+                if self.state != DhcpState::Requesting {
+                    self.drop_lease(interface);
+                }
+                self.discover(interface)?;
             }
             _ => {
                 // Ignore unexpected messages
@@ -217,6 +333,106 @@ impl Dhcpd {
 
         Ok(())
     }
+
+    // starts over from nothing, with a new transaction
+    fn discover(&mut self, interface: &mut Interface) -> Result<()> {
+        let now = system_timer::get_time();
+        self.xid = now as u32 ^ 0xDEADBEEF;
+        self.state = DhcpState::Discovering;
+        self.offered_ip = None;
+        self.server_identifier = None;
+        self.backoff = INITIAL_RETRANSMIT;
+        self.next_timeout = Some(now + self.backoff);
+        self.last_action_time = now;
+
+        send_dhcp_discover(interface, self.udp_socket, self.xid)
+    }
+
+    fn renew(&mut self, interface: &mut Interface, now: u64) -> Result<()> {
+        let (Some(server_id), Some(current_ip)) = (self.server_identifier, self.offered_ip) else {
+            return Err(Error::Malformed);
+        };
+        self.schedule_retransmit(now, self.deadline(self.rebind_time));
+        self.last_action_time = now;
+        send_dhcp_renew(interface, self.xid, current_ip, server_id, self.udp_socket)
+    }
+
+    fn rebind(&mut self, interface: &mut Interface, now: u64) -> Result<()> {
+        let Some(current_ip) = self.offered_ip else {
+            return Err(Error::Malformed);
+        };
+        self.schedule_retransmit(now, self.deadline(self.lease_time));
+        self.last_action_time = now;
+        send_dhcp_rebind(interface, self.xid, current_ip, self.udp_socket)
+    }
+
+    // halfway to the deadline (but not too soon), or the deadline itself when it's close
+    fn schedule_retransmit(&mut self, now: u64, deadline: Option<u64>) {
+        self.next_timeout = deadline.map(|deadline| {
+            let remaining = deadline.saturating_sub(now);
+            now + (remaining / 2).max(MIN_RENEW_RETRANSMIT).min(remaining)
+        });
+    }
+
+    // when a time in the lease (in seconds from its start) comes, None if it never does
+    fn deadline(&self, secs: Option<u32>) -> Option<u64> {
+        match (secs, self.lease_time) {
+            (_, None) | (_, Some(INFINITE_LEASE)) | (None, _) => None,
+            (Some(secs), _) => Some(self.lease_start + secs as u64 * MICROS_PER_SEC),
+        }
+    }
+
+    // forgets the address and everything that came with it
+    fn drop_lease(&mut self, interface: &mut Interface) {
+        let mut addrs = interface.addrs.lock();
+        addrs.ipv4_addr = Ipv4Cidr::empty();
+        addrs.dns_servers.clear();
+        drop(addrs);
+        ROUTES.lock().remove_interface(interface.index);
+
+        self.offered_ip = None;
+        self.server_identifier = None;
+        self.lease_time = None;
+        self.renewal_time = None;
+        self.rebind_time = None;
+        self.router = None;
+        self.dns_servers.clear();
+    }
+}
+
+// starts a dhcp client on an interface, which keeps it configured for as long as the system runs
+pub fn spawn_client(index: usize) {
+    spawn_async(async move {
+        let mut dhcpd = Dhcpd::new();
+        let _ = dhcpd.start(get_interface_mut(index));
+
+        loop {
+            let received = match dhcpd.next_timeout() {
+                Some(timeout) => {
+                    let remaining = timeout.saturating_sub(system_timer::get_time());
+                    recv_from_timeout(dhcpd.udp_socket, remaining).await
+                }
+                None => recv_from_async(dhcpd.udp_socket).await,
+            };
+
+            let interface = get_interface_mut(index);
+            match received {
+                Ok((payload, _)) => match DhcpPacket::deserialize(&payload) {
+                    Ok(packet) => {
+                        let _ = dhcpd.process_dhcp_packet(interface, packet);
+                    }
+                    Err(_) => println!("DHCP: Dropping malformed packet"),
+                },
+                Err(Error::Timeout) => {}
+                Err(e) => {
+                    println!("DHCP: Client stopped, {:?}", e);
+                    return;
+                }
+            }
+
+            let _ = dhcpd.poll(interface, system_timer::get_time());
+        }
+    });
 }
 
 pub fn send_dhcp_discover(interface: &mut Interface, socketfd: u16, xid: u32) -> Result<()> {
@@ -394,7 +610,7 @@ pub fn send_dhcp_release(
 fn send_dhcp_packet(interface: &mut Interface, socketfd: u16, packet: &DhcpPacket) -> Result<()> {
     let data = packet.serialize();
     let saddr = SocketAddr {
-        addr: interface.ipv4_addr.broadcast(),
+        addr: interface.ipv4_addr().broadcast().into(),
         port: DHCP_SERVER_PORT,
     };
 
@@ -409,7 +625,7 @@ fn send_dhcp_packet_unicast(
 ) -> Result<()> {
     let data = packet.serialize();
     let saddr = SocketAddr {
-        addr: server_ip,
+        addr: server_ip.into(),
         port: DHCP_SERVER_PORT,
     };
 
@@ -0,0 +1,220 @@
+/** stub resolver
+* sends recursive queries for A records to the name servers the interfaces were given (by dhcp),
+* over a udp socket, and caches the answers for as long as their ttl says
+*
+* see: https://datatracker.ietf.org/doc/html/rfc1035
+*/
+use crate::networking::iface::interfaces;
+use crate::networking::repr::{
+    DnsPacket, DnsRecord, DnsRecordData, DnsRecordTypes, DnsResponseCode, Ipv4Address,
+};
+use crate::networking::socket::{close, recv_from_timeout, send_to, SocketAddr, UdpSocket};
+use crate::networking::utils::dns_cache::DnsCache;
+use crate::networking::{Error, Result};
+
+use crate::sync::{self, SpinLock};
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::str::FromStr;
+use core::sync::atomic::{AtomicU16, Ordering};
+
+const DNS_PORT: u16 = 53;
+// how long to wait for an answer before asking again, in microseconds
+const QUERY_TIMEOUT: u64 = 2_000_000;
+// how many times each server is asked
+const QUERY_ATTEMPTS: usize = 2;
+// longest chain of aliases that will be followed
+const MAX_CNAME_DEPTH: usize = 8;
+const CACHE_ENTRIES: usize = 64;
+
+static CACHE: SpinLock<DnsCache> = SpinLock::new(DnsCache::new(CACHE_ENTRIES));
+static NEXT_ID: AtomicU16 = AtomicU16::new(1);
+
+fn now() -> u64 {
+    sync::get_time() as u64
+}
+
+// the name servers of every interface, in order
+pub fn dns_servers() -> Vec<Ipv4Address> {
+    let mut servers = Vec::new();
+    for interface in interfaces() {
+        for server in &interface.addrs.lock().dns_servers {
+            if !servers.contains(server) {
+                servers.push(*server);
+            }
+        }
+    }
+    servers
+}
+
+pub fn flush_cache() {
+    CACHE.lock().clear();
+}
+
+// resolves a host name to its ipv4 addresses
+pub async fn resolve(name: &str) -> Result<Vec<Ipv4Address>> {
+    // addresses resolve to themselves
+    if let Ok(addr) = Ipv4Address::from_str(name) {
+        return Ok(vec![addr]);
+    }
+
+    let name = name.strip_suffix('.').unwrap_or(name).to_ascii_lowercase();
+    if name.is_empty() {
+        return Err(Error::Malformed);
+    }
+    if name == "localhost" {
+        return Ok(vec![Ipv4Address::new([127, 0, 0, 1])]);
+    }
+
+    if let Some(addrs) = CACHE.lock().lookup(&name, now()) {
+        return Ok(addrs);
+    }
+
+    let servers = dns_servers();
+    if servers.is_empty() {
+        return Err(Error::NotConnected);
+    }
+
+    let mut target = name.clone();
+    for _ in 0..MAX_CNAME_DEPTH {
+        let response = query(&servers, &target).await?;
+        if response.response_code() == DnsResponseCode::NameError {
+            return Err(Error::UnknownHost);
+        }
+
+        let (addrs, ttl, canonical) = follow_answers(&response.answers, &target);
+        if !addrs.is_empty() {
+            CACHE.lock().insert(name, addrs.clone(), ttl, now());
+            return Ok(addrs);
+        }
+
+        // the server only gave us an alias, so ask about that instead
+        if canonical == target {
+            return Err(Error::UnknownHost);
+        }
+        target = canonical;
+    }
+
+    Err(Error::UnknownHost)
+}
+
+// finds the addresses for a name in a set of answers, going through any aliases. returns them
+// with the smallest ttl along the way, and the name they belong to
+fn follow_answers(answers: &[DnsRecord], name: &str) -> (Vec<Ipv4Address>, u32, String) {
+    let mut name = String::from(name);
+    let mut ttl = u32::MAX;
+
+    for _ in 0..MAX_CNAME_DEPTH {
+        let alias = answers.iter().find_map(|record| match &record.data {
+            DnsRecordData::Cname(alias) if record.name.eq_ignore_ascii_case(&name) => {
+                Some((alias.to_ascii_lowercase(), record.ttl))
+            }
+            _ => None,
+        });
+        match alias {
+            Some((alias, alias_ttl)) => {
+                name = alias;
+                ttl = ttl.min(alias_ttl);
+            }
+            None => break,
+        }
+    }
+
+    let mut addrs = Vec::new();
+    for record in answers {
+        if let DnsRecordData::A(addr) = record.data {
+            if record.name.eq_ignore_ascii_case(&name) {
+                addrs.push(addr);
+                ttl = ttl.min(record.ttl);
+            }
+        }
+    }
+
+    (addrs, ttl, name)
+}
+
+// asks each server in turn until one answers
+async fn query(servers: &[Ipv4Address], name: &str) -> Result<DnsPacket> {
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    let request = DnsPacket::query(id, name, DnsRecordTypes::A).serialize()?;
+
+    let socketfd = UdpSocket::new();
+    let mut result = Err(Error::Timeout);
+    'servers: for &server in servers {
+        for _ in 0..QUERY_ATTEMPTS {
+            result = exchange(socketfd, server, id, &request).await;
+            match &result {
+                // a server that couldn't answer might not be the only one
+                Ok(response)
+                    if matches!(
+                        response.response_code(),
+                        DnsResponseCode::NoError | DnsResponseCode::NameError
+                    ) =>
+                {
+                    break 'servers
+                }
+                Ok(_) => continue 'servers,
+                Err(Error::Timeout) => continue,
+                Err(_) => continue 'servers,
+            }
+        }
+    }
+    let _ = close(socketfd);
+
+    match result {
+        Ok(response)
+            if !matches!(
+                response.response_code(),
+                DnsResponseCode::NoError | DnsResponseCode::NameError
+            ) =>
+        {
+            println!(
+                "[!] dns: {} failed with {:?}",
+                name,
+                response.response_code()
+            );
+            Err(Error::UnknownHost)
+        }
+        result => result,
+    }
+}
+
+// sends one query, and waits for its answer
+async fn exchange(
+    socketfd: u16,
+    server: Ipv4Address,
+    id: u16,
+    request: &[u8],
+) -> Result<DnsPacket> {
+    let server_addr = SocketAddr {
+        addr: server.into(),
+        port: DNS_PORT,
+    };
+    send_to(socketfd, request.to_vec(), server_addr)?;
+
+    let deadline = now() + QUERY_TIMEOUT;
+    loop {
+        let remaining = deadline.saturating_sub(now());
+        match recv_from_timeout(socketfd, remaining).await {
+            Ok((data, sender)) => {
+                if sender != server_addr {
+                    continue;
+                }
+                // anything that isn't the answer to this query is ignored
+                match DnsPacket::deserialize(&data) {
+                    Ok(response) if response.id == id && response.is_response() => {
+                        if response.is_truncated() {
+                            // TODO: retry over tcp, for now whatever fit will do
+                            println!("[!] dns: truncated response from {}", server);
+                        }
+                        return Ok(response);
+                    }
+                    _ => continue,
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -1,12 +1,8 @@
-use crate::networking::iface::{arp, ipv4, Interface};
+use crate::networking::iface::capture::{self, Direction};
+use crate::networking::iface::{arp, ipv4, ipv6, Interface};
 use crate::networking::repr::{EthernetAddress, EthernetFrame, EthernetType};
 use crate::networking::{Error, Result};
 
-use crate::device::usb::device::net::NET_DEVICE;
-use crate::device::usb::device::rndis::rndis_receive_packet;
-use crate::event::thread;
-
-use alloc::vec;
 use alloc::vec::Vec;
 
 // serialize the ethernet packet, and send it out over our interface's device
@@ -23,21 +19,16 @@ pub fn send_ethernet_frame(
         payload,
     };
 
-    interface.dev.send(
-        &mut ethernet_packet.serialize(),
-        ethernet_packet.size() as u32,
-    );
-
-    Ok(())
+    interface.transmit(ethernet_packet.serialize())
 }
 
 // recv ethernet frame from interface: parsed -> fwd to socket -> propogated up stack
-pub fn recv_ethernet_frame(interface: &mut Interface, eth_buffer: &[u8], _len: u32) -> Result<()> {
+pub fn recv_ethernet_frame(interface: &mut Interface, eth_buffer: &[u8]) -> Result<()> {
     println!("[!] received ethernet frame");
     println!("\t{:x?}", eth_buffer);
 
-    // we will truncate the first 44 bytes from the RNDIS protocol
-    let eth_frame = EthernetFrame::deserialize(&eth_buffer[44..])?;
+    capture::record(interface, Direction::Received, eth_buffer);
+    let eth_frame = EthernetFrame::deserialize(eth_buffer)?;
 
     // if this frame is not broadcast/multicast or to us, ignore it
     if eth_frame.dst != interface.ethernet_addr
@@ -47,20 +38,10 @@ pub fn recv_ethernet_frame(interface: &mut Interface, eth_buffer: &[u8], _len: u
         return Err(Error::Ignored);
     }
 
-    let result = match eth_frame.ethertype {
+    match eth_frame.ethertype {
         EthernetType::ARP => arp::recv_arp_packet(interface, eth_frame),
         EthernetType::IPV4 => ipv4::recv_ip_packet(interface, eth_frame),
+        EthernetType::IPV6 => ipv6::recv_ipv6_frame(interface, eth_frame),
         _ => Err(Error::Ignored),
-    };
-
-    // queue another recv to be run in the future
-    thread::thread(move || {
-        let buf = vec![0u8; 1500];
-        unsafe {
-            let device = &mut *NET_DEVICE.device.unwrap();
-            rndis_receive_packet(device, buf.into_boxed_slice(), 1500);
-        }
-    });
-
-    return result;
+    }
 }
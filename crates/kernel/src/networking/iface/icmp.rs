@@ -1,5 +1,6 @@
 use crate::networking::iface::{ipv4, Interface};
 use crate::networking::repr::{IcmpMessage, IcmpPacket, Ipv4Address, Ipv4Packet, Ipv4Protocol};
+use crate::networking::socket::{SocketAddr, TaggedSocket, SOCKETS};
 use crate::networking::{Error, Result};
 
 pub fn send_icmp_packet(
@@ -19,6 +20,20 @@ pub fn send_icmp_packet(
 
 pub fn recv_icmp_packet(interface: &mut Interface, ipv4_packet: Ipv4Packet) -> Result<()> {
     let icmp_recv_packet = IcmpPacket::deserialize(ipv4_packet.payload.as_slice())?;
+
+    // raw sockets see every message, so replies to their echo requests and errors (ie. time
+    // exceeded, for traceroute) get to them
+    let sender = SocketAddr {
+        addr: ipv4_packet.src_addr.into(),
+        port: 0,
+    };
+    for (_, socket) in SOCKETS.lock().iter_mut() {
+        if let TaggedSocket::Raw(socket) = socket {
+            if socket.protocol() == Ipv4Protocol::ICMP {
+                let _ = socket.recv_enqueue(ipv4_packet.payload.clone(), sender);
+            }
+        }
+    }
     // icmp_recv_packet.check_encoding()?;
 
     let icmp_send_packet = match icmp_recv_packet.message {
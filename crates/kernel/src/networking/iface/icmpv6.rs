@@ -0,0 +1,53 @@
+use crate::networking::iface::{ipv6, ndp, Interface};
+use crate::networking::repr::{
+    Icmpv6Message, Icmpv6Packet, Ipv6Address, Ipv6NextHeader, Ipv6Packet,
+};
+use crate::networking::{Error, Result};
+
+pub fn send_icmpv6_packet(
+    interface: &mut Interface,
+    src_addr: Ipv6Address,
+    dst_addr: Ipv6Address,
+    message: Icmpv6Message,
+) -> Result<()> {
+    let icmp_packet = Icmpv6Packet::new(message);
+
+    ipv6::send_ipv6_packet_from(
+        interface,
+        icmp_packet.serialize(src_addr, dst_addr),
+        Ipv6NextHeader::ICMPV6,
+        src_addr,
+        dst_addr,
+    )
+}
+
+pub fn recv_icmpv6_packet(interface: &mut Interface, ipv6_packet: Ipv6Packet) -> Result<()> {
+    let icmp_recv_packet = Icmpv6Packet::deserialize(
+        ipv6_packet.payload.as_slice(),
+        ipv6_packet.src_addr,
+        ipv6_packet.dst_addr,
+    )?;
+
+    match icmp_recv_packet.message {
+        Icmpv6Message::EchoRequest { id, seq, data } => {
+            // requests to a multicast group are answered from one of our own addresses
+            let src_addr = if ipv6_packet.dst_addr.is_multicast() {
+                ipv6::source_addr(interface, ipv6_packet.src_addr)
+            } else {
+                ipv6_packet.dst_addr
+            };
+            send_icmpv6_packet(
+                interface,
+                src_addr,
+                ipv6_packet.src_addr,
+                Icmpv6Message::EchoReply { id, seq, data },
+            )
+        }
+        Icmpv6Message::RouterAdvertisement { .. }
+        | Icmpv6Message::NeighborSolicitation { .. }
+        | Icmpv6Message::NeighborAdvertisement { .. } => {
+            ndp::recv_ndp_message(interface, &ipv6_packet, icmp_recv_packet.message)
+        }
+        _ => Err(Error::Ignored),
+    }
+}
@@ -1,11 +1,33 @@
-use crate::networking::iface::{arp, ethernet, icmp, tcp, udp, Interface};
-use crate::networking::repr::{EthernetFrame, EthernetType, Ipv4Address, Ipv4Packet, Ipv4Protocol};
+use crate::networking::iface::route::ROUTES;
+use crate::networking::iface::{arp, ethernet, get_interface_mut, icmp, tcp, udp, Interface};
+use crate::networking::repr::{
+    EthernetFrame, EthernetType, Ipv4Address, Ipv4Packet, Ipv4Protocol, Medium,
+};
 use crate::networking::{Error, Result};
 
-use crate::device::usb::device::net::{get_dhcpd_mut, get_interface_mut};
+use crate::device::system_timer;
 use crate::event::thread;
 
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, Ordering};
+
+// identifies the fragments of each datagram we send
+static NEXT_ID: AtomicU16 = AtomicU16::new(1);
+
+fn new_ipv4_packet(
+    interface: &Interface,
+    payload: Vec<u8>,
+    protocol: Ipv4Protocol,
+    dst_addr: Ipv4Address,
+    ttl: Option<u8>,
+) -> Ipv4Packet {
+    let mut ipv4_packet = Ipv4Packet::new(*interface.ipv4_addr(), dst_addr, protocol, payload);
+    ipv4_packet.id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    if let Some(ttl) = ttl {
+        ipv4_packet.ttl = ttl;
+    }
+    ipv4_packet
+}
 
 pub fn send_ipv4_packet(
     interface: &mut Interface,
@@ -13,25 +35,54 @@ pub fn send_ipv4_packet(
     protocol: Ipv4Protocol,
     dst_addr: Ipv4Address,
 ) -> Result<()> {
+    send_ipv4_packet_with_ttl(interface, payload, protocol, dst_addr, None)
+}
+
+// with a time to live other than the default, for raw sockets (ie. traceroute)
+pub fn send_ipv4_packet_with_ttl(
+    interface: &mut Interface,
+    payload: Vec<u8>,
+    protocol: Ipv4Protocol,
+    dst_addr: Ipv4Address,
+    ttl: Option<u8>,
+) -> Result<()> {
+    if payload.len() > Ipv4Packet::MAX_PAYLOAD_LEN {
+        return Err(Error::InvalidLength);
+    }
+
+    // no link layer addresses to resolve, the device takes the ip packets as they are
+    if interface.dev.medium() == Medium::Ip {
+        let ipv4_packet = new_ipv4_packet(interface, payload, protocol, dst_addr, ttl);
+        for fragment in ipv4_packet.fragment(interface.dev.mtu())? {
+            interface.transmit(fragment.serialize())?;
+        }
+        return Ok(());
+    }
+
     let next_hop = ipv4_addr_route(interface, dst_addr);
     match arp::eth_addr_for_ip(interface, next_hop) {
         Ok(dst_mac) => {
             println!("ip resolved: sending ip packet");
 
-            let ipv4_packet = Ipv4Packet::new(*interface.ipv4_addr, dst_addr, protocol, payload);
+            let ipv4_packet = new_ipv4_packet(interface, payload, protocol, dst_addr, ttl);
 
-            ethernet::send_ethernet_frame(
-                interface,
-                ipv4_packet.serialize(),
-                dst_mac,
-                EthernetType::IPV4,
-            )
+            // anything bigger than the device takes goes out in pieces
+            for fragment in ipv4_packet.fragment(interface.dev.mtu())? {
+                ethernet::send_ethernet_frame(
+                    interface,
+                    fragment.serialize(),
+                    dst_mac,
+                    EthernetType::IPV4,
+                )?;
+            }
+            Ok(())
         }
         Err(e) => {
             println!("failed to resolve ip, queuing another send, waiting for ARP");
+            let index = interface.index;
             thread::thread(move || {
-                let interface = get_interface_mut();
-                let _ = send_ipv4_packet(interface, payload, protocol, dst_addr);
+                let interface = get_interface_mut(index);
+                let _ = send_ipv4_packet_with_ttl(interface, payload, protocol, dst_addr, ttl);
             });
             Err(e)
         }
@@ -45,15 +96,7 @@ pub fn recv_ip_packet(interface: &mut Interface, eth_frame: EthernetFrame) -> Re
         return Err(Error::Checksum);
     }
 
-    let dhcpd = get_dhcpd_mut();
-    if dhcpd.is_transacting() {
-        return Err(Error::Ignored);
-    }
-
-    if ipv4_packet.dst_addr != *interface.ipv4_addr
-        && !interface.ipv4_addr.is_member(ipv4_packet.dst_addr)
-        && !interface.ipv4_addr.is_broadcast(ipv4_packet.dst_addr)
-    {
+    if !is_for_interface(interface, ipv4_packet.dst_addr) {
         return Err(Error::Ignored);
     }
 
@@ -63,21 +106,74 @@ pub fn recv_ip_packet(interface: &mut Interface, eth_frame: EthernetFrame) -> Re
         arp_cache.set_eth_addr_for_ip(ipv4_packet.src_addr, eth_frame.src);
     }
 
+    deliver_ipv4_packet(interface, ipv4_packet)
+}
+
+// recv a bare ip packet, from a device without a link layer
+pub fn recv_ipv4_packet(interface: &mut Interface, buffer: &[u8]) -> Result<()> {
+    println!("[!] received IP packet");
+    let ipv4_packet = Ipv4Packet::deserialize(buffer)?;
+    if !ipv4_packet.is_valid_checksum() {
+        return Err(Error::Checksum);
+    }
+
+    if !is_for_interface(interface, ipv4_packet.dst_addr) {
+        return Err(Error::Ignored);
+    }
+
+    deliver_ipv4_packet(interface, ipv4_packet)
+}
+
+fn is_for_interface(interface: &Interface, address: Ipv4Address) -> bool {
+    let ipv4_addr = interface.ipv4_addr();
+    address == *ipv4_addr
+        || ipv4_addr.is_member(address)
+        || ipv4_addr.is_broadcast(address)
+        || address.is_limited_broadcast()
+}
+
+fn deliver_ipv4_packet(interface: &mut Interface, ipv4_packet: Ipv4Packet) -> Result<()> {
+    // hold on to fragments until the whole datagram is here
+    let ipv4_packet = if ipv4_packet.is_fragment() {
+        let mut fragments = interface.fragments.lock();
+        match fragments.insert(ipv4_packet, system_timer::get_time()) {
+            Some(datagram) => datagram,
+            None => return Ok(()),
+        }
+    } else {
+        ipv4_packet
+    };
+
+    let src_addr = ipv4_packet.src_addr.into();
+    let dst_addr = ipv4_packet.dst_addr.into();
     match ipv4_packet.protocol {
-        Ipv4Protocol::TCP => tcp::recv_tcp_packet(interface, ipv4_packet),
-        Ipv4Protocol::UDP => udp::recv_udp_packet(interface, ipv4_packet),
+        Ipv4Protocol::TCP => {
+            tcp::recv_tcp_packet(interface, src_addr, dst_addr, &ipv4_packet.payload)
+        }
+        Ipv4Protocol::UDP => {
+            udp::recv_udp_packet(interface, src_addr, dst_addr, &ipv4_packet.payload)
+        }
         Ipv4Protocol::ICMP => icmp::recv_icmp_packet(interface, ipv4_packet),
         _ => Err(Error::Ignored),
     }
 }
 
-// get next hop for a packet destined to a specified address.
+// get next hop for a packet destined to a specified address, leaving through an interface.
 pub fn ipv4_addr_route(interface: &mut Interface, address: Ipv4Address) -> Ipv4Address {
-    if interface.ipv4_addr.is_member(address) || interface.ipv4_addr.is_broadcast(address) {
+    if address.is_limited_broadcast() || interface.ipv4_addr().is_broadcast(address) {
         println!("{} will be routed through link", address);
-        address
-    } else {
-        println!("{} will be routed through default gateway", address);
-        interface.default_gateway
+        return address;
+    }
+
+    match ROUTES.lock().lookup_on(address, interface.index) {
+        Some(route) => {
+            println!("{} will be routed through {}", address, route);
+            route.next_hop(address)
+        }
+        None => {
+            // nothing says otherwise, so assume it's on the link
+            println!("{} has no route, sending through link", address);
+            address
+        }
     }
 }
@@ -0,0 +1,203 @@
+use crate::networking::iface::{ethernet, get_interface_mut, icmpv6, interfaces, ndp, tcp, udp};
+use crate::networking::iface::{Interface, LOOPBACK};
+use crate::networking::repr::{
+    EthernetFrame, EthernetType, Ipv6Address, Ipv6NextHeader, Ipv6Packet, Medium,
+};
+use crate::networking::{Error, Result};
+
+use crate::event::thread;
+
+use alloc::vec::Vec;
+
+// send from whichever of the interface's addresses suits the destination best
+pub fn send_ipv6_packet(
+    interface: &mut Interface,
+    payload: Vec<u8>,
+    next_header: u8,
+    dst_addr: Ipv6Address,
+) -> Result<()> {
+    let src_addr = source_addr(interface, dst_addr);
+    send_ipv6_packet_from(interface, payload, next_header, src_addr, dst_addr)
+}
+
+// send from a given address, which neighbor discovery needs (ie. :: before we have one)
+pub fn send_ipv6_packet_from(
+    interface: &mut Interface,
+    payload: Vec<u8>,
+    next_header: u8,
+    src_addr: Ipv6Address,
+    dst_addr: Ipv6Address,
+) -> Result<()> {
+    // only the sender may fragment ipv6 packets, and we don't yet
+    if payload.len() + Ipv6Packet::HEADER_LEN > interface.dev.mtu() {
+        return Err(Error::InvalidLength);
+    }
+
+    let mut ipv6_packet = Ipv6Packet::new(src_addr, dst_addr, next_header, payload);
+    // neighbor discovery messages are only accepted with this hop limit, so they can't have come
+    // from off the link
+    if next_header == Ipv6NextHeader::ICMPV6 {
+        ipv6_packet.hop_limit = 255;
+    }
+
+    // no link layer addresses to resolve, the device takes the ip packets as they are
+    if interface.dev.medium() == Medium::Ip {
+        return interface.transmit(ipv6_packet.serialize());
+    }
+
+    let next_hop = ipv6_addr_route(interface, dst_addr);
+    match ndp::eth_addr_for_ip(interface, next_hop) {
+        Ok(dst_mac) => ethernet::send_ethernet_frame(
+            interface,
+            ipv6_packet.serialize(),
+            dst_mac,
+            EthernetType::IPV6,
+        ),
+        Err(e) => {
+            println!("failed to resolve ipv6 address, queuing another send, waiting for NDP");
+            let index = interface.index;
+            thread::thread(move || {
+                let interface = get_interface_mut(index);
+                let _ = send_ipv6_packet_from(
+                    interface,
+                    ipv6_packet.payload,
+                    next_header,
+                    src_addr,
+                    dst_addr,
+                );
+            });
+            Err(e)
+        }
+    }
+}
+
+pub fn recv_ipv6_frame(interface: &mut Interface, eth_frame: EthernetFrame) -> Result<()> {
+    println!("[!] received IPv6 packet");
+    let ipv6_packet = Ipv6Packet::deserialize(eth_frame.payload.as_slice())?;
+
+    if !is_for_interface(interface, ipv6_packet.dst_addr) {
+        return Err(Error::Ignored);
+    }
+
+    deliver_ipv6_packet(interface, ipv6_packet)
+}
+
+// recv a bare ipv6 packet, from a device without a link layer
+pub fn recv_ipv6_packet(interface: &mut Interface, buffer: &[u8]) -> Result<()> {
+    println!("[!] received IPv6 packet");
+    let ipv6_packet = Ipv6Packet::deserialize(buffer)?;
+
+    if !is_for_interface(interface, ipv6_packet.dst_addr) {
+        return Err(Error::Ignored);
+    }
+
+    deliver_ipv6_packet(interface, ipv6_packet)
+}
+
+// our own addresses, all nodes, and the solicited-node groups neighbor solicitations for our
+// addresses are sent to
+fn is_for_interface(interface: &Interface, address: Ipv6Address) -> bool {
+    address == Ipv6Address::ALL_NODES
+        || interface
+            .addrs
+            .lock()
+            .ipv6_addrs
+            .iter()
+            .any(|cidr| **cidr == address || cidr.solicited_node() == address)
+}
+
+fn deliver_ipv6_packet(interface: &mut Interface, ipv6_packet: Ipv6Packet) -> Result<()> {
+    let src_addr = ipv6_packet.src_addr.into();
+    let dst_addr = ipv6_packet.dst_addr.into();
+
+    // extension headers (ie. hop-by-hop options on mld queries) aren't understood, so those
+    // packets are dropped
+    match ipv6_packet.next_header {
+        Ipv6NextHeader::TCP => {
+            tcp::recv_tcp_packet(interface, src_addr, dst_addr, &ipv6_packet.payload)
+        }
+        Ipv6NextHeader::UDP => {
+            udp::recv_udp_packet(interface, src_addr, dst_addr, &ipv6_packet.payload)
+        }
+        Ipv6NextHeader::ICMPV6 => icmpv6::recv_icmpv6_packet(interface, ipv6_packet),
+        _ => Err(Error::Ignored),
+    }
+}
+
+// the address to send from: one on the destination's prefix if there is one, the link-local
+// address for link-local destinations, and otherwise a global one
+pub fn source_addr(interface: &Interface, dst_addr: Ipv6Address) -> Ipv6Address {
+    let addrs = &interface.addrs.lock().ipv6_addrs;
+
+    if let Some(cidr) = addrs
+        .iter()
+        .find(|cidr| !cidr.is_link_local() && cidr.is_member(dst_addr))
+    {
+        return **cidr;
+    }
+
+    let link_scope = dst_addr.is_link_local() || dst_addr.is_multicast();
+    addrs
+        .iter()
+        .find(|cidr| cidr.is_link_local() == link_scope)
+        .or(addrs.first())
+        .map(|cidr| **cidr)
+        .unwrap_or(Ipv6Address::UNSPECIFIED)
+}
+
+// get next hop for a packet destined to a specified address, leaving through an interface.
+pub fn ipv6_addr_route(interface: &Interface, address: Ipv6Address) -> Ipv6Address {
+    let addrs = interface.addrs.lock();
+    let on_link = address.is_link_local()
+        || address.is_multicast()
+        || addrs.ipv6_addrs.iter().any(|cidr| cidr.is_member(address));
+    let router = addrs.ipv6_router;
+    drop(addrs);
+    if on_link {
+        return address;
+    }
+
+    match router {
+        Some(router) => {
+            println!("{} will be routed through {}", address, router);
+            router
+        }
+        None => {
+            // nothing says otherwise, so assume it's on the link
+            println!("{} has no route, sending through link", address);
+            address
+        }
+    }
+}
+
+// the interface packets to an address should leave through. there's no routing table for ipv6,
+// prefixes and default routers come from router advertisements on each interface
+pub fn route_interface(address: Ipv6Address) -> Option<usize> {
+    if address.is_loopback() {
+        return Some(LOOPBACK);
+    }
+
+    let links = || interfaces().filter(|interface| interface.index != LOOPBACK);
+    let on_link = links().find(|interface| {
+        interface
+            .addrs
+            .lock()
+            .ipv6_addrs
+            .iter()
+            .any(|cidr| !cidr.is_link_local() && cidr.is_member(address))
+    });
+    if let Some(interface) = on_link {
+        return Some(interface.index);
+    }
+
+    // link-local and multicast addresses don't say which link they're on, so use the first one
+    if address.is_link_local() || address.is_multicast() {
+        return links()
+            .find(|interface| !interface.addrs.lock().ipv6_addrs.is_empty())
+            .map(|interface| interface.index);
+    }
+
+    links()
+        .find(|interface| interface.addrs.lock().ipv6_router.is_some())
+        .map(|interface| interface.index)
+}
@@ -0,0 +1,59 @@
+use crate::networking::repr::{Device, LinkState, Medium};
+use crate::networking::utils::frame_queue::FrameQueue;
+use crate::networking::Result;
+use crate::sync::InterruptSpinLock;
+
+use alloc::vec::Vec;
+use core::task::{Context, Poll};
+
+// largest packet an ipv4 header can describe
+const LOOPBACK_MTU: usize = 65535;
+// packets sent to ourselves that haven't been received yet
+const LOOPBACK_QUEUE_LEN: usize = 64;
+
+// a device that receives whatever is sent through it, so the stack can talk to itself. senders on
+// any core push to the queue while the receive task pops from it
+pub struct Loopback {
+    queue: InterruptSpinLock<FrameQueue>,
+}
+
+impl Loopback {
+    pub fn new() -> Self {
+        Loopback {
+            queue: InterruptSpinLock::new(FrameQueue::new(LOOPBACK_QUEUE_LEN)),
+        }
+    }
+}
+
+impl Device for Loopback {
+    // received later by the interface's receive task rather than right away, since the sender may
+    // be in the middle of handling a packet itself (ie. a tcp reply sent while the sockets are
+    // locked)
+    fn transmit(&mut self, frame: Vec<u8>) -> Result<()> {
+        self.queue.lock().push(frame)
+    }
+
+    fn poll_receive(&mut self, cx: &mut Context<'_>) -> Poll<Vec<u8>> {
+        self.queue.lock().poll_pop(cx)
+    }
+
+    // there's no cable to pull
+    fn link_state(&self) -> LinkState {
+        LinkState::Up
+    }
+
+    fn poll_link(&mut self, _cx: &mut Context<'_>, known: LinkState) -> Poll<LinkState> {
+        match known {
+            LinkState::Up => Poll::Pending,
+            LinkState::Down => Poll::Ready(LinkState::Up),
+        }
+    }
+
+    fn mtu(&self) -> usize {
+        LOOPBACK_MTU
+    }
+
+    fn medium(&self) -> Medium {
+        Medium::Ip
+    }
+}
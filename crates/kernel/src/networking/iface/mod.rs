@@ -1,57 +1,268 @@
 /** [`iface`] module
 * based this interface setup off of: https://github.com/ykskb/rust-user-net
-* the Interface will store all of our shared values, including
-*   1. arp cache
-*   2. addresses
+* there's an Interface per device, kept in a global list, and the routing table (see [`route`])
+* picks which one a packet leaves through. sockets are shared by all of them (see the [`socket`]
+* module for more). each Interface gets a task receiving from its device, and one watching its link
+* (see [`add_interface`]). each Interface will store
+*   1. arp cache (and the neighbor cache, for ipv6)
+*   2. addresses, ipv4 and ipv6
 *   3. device (where we actually send and recv our packets)
-*   4. sockets (see the [`socket`] module for more)
 */
 use crate::device::system_timer;
+use crate::event::task::spawn_async;
 use crate::sync::SpinLock;
 
-use crate::networking::repr::{Device, EthernetAddress, Ipv4Address, Ipv4Cidr};
-use crate::networking::socket::TaggedSocket;
-use crate::networking::utils::arp_cache::ArpCache;
+use crate::networking::repr::{
+    Device, EthernetAddress, IpAddress, Ipv4Address, Ipv4Cidr, Ipv4Protocol, Ipv6Address, Ipv6Cidr,
+    LinkState, Medium,
+};
+use crate::networking::utils::arp_cache::{ArpCache, NeighborCache};
+use crate::networking::utils::fragments::FragmentBuffer;
+use crate::networking::{Error, Result};
 
 use alloc::boxed::Box;
-use alloc::collections::btree_map::BTreeMap;
+use alloc::vec::Vec;
+use core::future::poll_fn;
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
 
 pub mod arp;
+pub mod capture;
 pub mod cdcecm;
 pub mod dhcp;
+pub mod dns;
 pub mod ethernet;
 pub mod icmp;
+pub mod icmpv6;
 pub mod ipv4;
+pub mod ipv6;
+pub mod loopback;
+pub mod ndp;
+pub mod rndis;
+pub mod route;
 pub mod socket;
 pub mod tcp;
 pub mod udp;
 
-use cdcecm::CDCECM;
+use loopback::Loopback;
+use route::{Route, ROUTES};
 
-// WARN: for now, we assume that we will only ever set our ethernet_addr, ipv4_addr, and
-// default_gateway once when we first initialize
+// how long the fragments of a datagram are kept waiting for the rest, in microseconds (rfc 791
+// suggests 15 seconds)
+const REASSEMBLY_TIMEOUT: u64 = 15_000_000;
+// memory all the incomplete datagrams on an interface may use
+const REASSEMBLY_MAX_BYTES: usize = 256 * 1024;
+const REASSEMBLY_MAX_DATAGRAMS: usize = 16;
+
+// the ethernet_addr is set once, from the device. the addresses change as the interface learns about
+// its network (see [`Addresses`])
 pub struct Interface {
+    // position in the interface list, which routes refer to
+    pub index: usize,
     pub dev: Box<dyn Device>,
 
     pub arp_cache: SpinLock<ArpCache>,
     pub ethernet_addr: EthernetAddress,
 
-    pub ipv4_addr: Ipv4Cidr,
-    pub default_gateway: Ipv4Address,
+    // fragments of datagrams sent to us, waiting to be reassembled
+    pub fragments: SpinLock<FragmentBuffer>,
 
-    pub sockets: SpinLock<BTreeMap<u16, TaggedSocket>>,
+    pub neighbor_cache: SpinLock<NeighborCache>,
+    pub addrs: SpinLock<Addresses>,
+}
+
+// what the interface learns about its network as it goes. other cores read it to pick source
+// addresses and routes while it changes, so it's behind a lock. the ipv4 address and name servers
+// change as dhcp leases are bound, renewed and lost (see [`dhcp`]), along with the interface's
+// routes, which hold the gateway (see [`route`])
+pub struct Addresses {
+    pub ipv4_addr: Ipv4Cidr,
+    // name servers for this network, from dhcp
+    pub dns_servers: Vec<Ipv4Address>,
+    // the link-local address, and any from slaac (see [`ndp`])
+    pub ipv6_addrs: Vec<Ipv6Cidr>,
+    // addresses still under duplicate address detection, and when it ends for each
+    pub ipv6_tentative: Vec<(Ipv6Address, u64)>,
+    // default router, from router advertisements
+    pub ipv6_router: Option<Ipv6Address>,
 }
 
 impl Interface {
-    pub fn new() -> Self {
+    pub fn new(dev: Box<dyn Device>) -> Self {
+        let ethernet_addr = dev.mac_addr().unwrap_or(EthernetAddress::empty());
         Interface {
-            dev: Box::new(CDCECM::new(1500)),
+            index: 0,
+            dev,
             arp_cache: SpinLock::new(ArpCache::new(60, system_timer::get_time())),
-            ethernet_addr: EthernetAddress::empty(),
-            ipv4_addr: Ipv4Cidr::empty(),
-            default_gateway: Ipv4Address::empty(),
+            ethernet_addr,
+            fragments: SpinLock::new(FragmentBuffer::new(
+                REASSEMBLY_TIMEOUT,
+                REASSEMBLY_MAX_BYTES,
+                REASSEMBLY_MAX_DATAGRAMS,
+            )),
+            neighbor_cache: SpinLock::new(NeighborCache::new(60, system_timer::get_time())),
+            addrs: SpinLock::new(Addresses {
+                ipv4_addr: Ipv4Cidr::empty(),
+                dns_servers: Vec::new(),
+                ipv6_addrs: Vec::new(),
+                ipv6_tentative: Vec::new(),
+                ipv6_router: None,
+            }),
+        }
+    }
+
+    // hands a frame (or a bare ip packet, depending on the medium) to the device. everything sent
+    // goes through here, so it can be captured
+    pub fn transmit(&mut self, frame: Vec<u8>) -> Result<()> {
+        capture::record(self, capture::Direction::Sent, &frame);
+        self.dev.transmit(frame)
+    }
+
+    pub fn ipv4_addr(&self) -> Ipv4Cidr {
+        self.addrs.lock().ipv4_addr
+    }
+
+    // our address to send to `dst_addr` from, of the same version
+    pub fn source_addr(&self, dst_addr: IpAddress) -> IpAddress {
+        match dst_addr {
+            IpAddress::V4(_) => IpAddress::V4(*self.ipv4_addr()),
+            IpAddress::V6(dst_addr) => IpAddress::V6(ipv6::source_addr(self, dst_addr)),
+        }
+    }
+}
+
+// the loopback interface is always the first one
+pub const LOOPBACK: usize = 0;
+
+pub const MAX_INTERFACES: usize = 8;
+
+// interfaces are leaked boxes, so references to them stay valid forever. the slots are fixed so
+// that readers on other cores never see them move: a slot is filled in before the count is bumped
+// past it (with release ordering), so every slot below the count can be read without a lock
+// WARN: interfaces are only added while devices are being initialized, and never removed
+static INTERFACES: [AtomicPtr<Interface>; MAX_INTERFACES] =
+    [const { AtomicPtr::new(null_mut()) }; MAX_INTERFACES];
+static INTERFACE_COUNT: AtomicUsize = AtomicUsize::new(0);
+// serializes adding interfaces
+static ADD_LOCK: SpinLock<()> = SpinLock::new(());
+
+// adds the loopback interface and starts the socket loop, this has to happen before any other
+// interface is added
+pub fn init() {
+    let interface = Interface::new(Box::new(Loopback::new()));
+    let cidr = Ipv4Cidr::new(Ipv4Address::new([127, 0, 0, 1]), 8).unwrap();
+    let mut addrs = interface.addrs.lock();
+    addrs.ipv4_addr = cidr;
+    addrs
+        .ipv6_addrs
+        .push(Ipv6Cidr::new(Ipv6Address::LOOPBACK, 128).unwrap());
+    drop(addrs);
+
+    let index = add_interface(interface);
+    ROUTES.lock().add(Route {
+        cidr,
+        gateway: None,
+        interface: index,
+    });
+
+    // begin socket send loop, this iterates through all existing sockets, and attempts to send as
+    // many packets as possible from each socket
+    socket::socket_send_loop();
+}
+
+// registers an interface and starts receiving from its device, returning its index
+pub fn add_interface(mut interface: Interface) -> usize {
+    let index = {
+        let _guard = ADD_LOCK.lock();
+        let index = INTERFACE_COUNT.load(Ordering::Relaxed);
+        assert!(index < MAX_INTERFACES, "too many interfaces");
+        interface.index = index;
+        INTERFACES[index].store(Box::into_raw(Box::new(interface)), Ordering::Release);
+        INTERFACE_COUNT.store(index + 1, Ordering::Release);
+        index
+    };
+
+    receive_loop(index);
+    link_watch_loop(index);
+
+    index
+}
+
+// hands everything the device receives up the stack, one frame at a time
+fn receive_loop(index: usize) {
+    spawn_async(async move {
+        loop {
+            let frame = poll_fn(|cx| get_interface_mut(index).dev.poll_receive(cx)).await;
+            let interface = get_interface_mut(index);
+            let _ = match interface.dev.medium() {
+                Medium::Ethernet => ethernet::recv_ethernet_frame(interface, &frame),
+                // not captured, it's the same packet the interface captured sending. the version is
+                // in the first 4 bits of either header
+                Medium::Ip => match frame.first().map(|byte| byte >> 4) {
+                    Some(6) => ipv6::recv_ipv6_packet(interface, &frame),
+                    _ => ipv4::recv_ipv4_packet(interface, &frame),
+                },
+            };
+        }
+    });
+}
+
+// logs the link going up and down. whoever was on the other end may have changed while it was
+// down, so the neighbors learned before are forgotten
+fn link_watch_loop(index: usize) {
+    spawn_async(async move {
+        let mut known = get_interface_mut(index).dev.link_state();
+        loop {
+            known = poll_fn(|cx| get_interface_mut(index).dev.poll_link(cx, known)).await;
+            println!("interface {} link is {:?}", index, known);
+
+            let interface = get_interface_mut(index);
+            if known == LinkState::Up {
+                interface.arp_cache.lock().clear();
+                interface.neighbor_cache.lock().clear();
+            }
+        }
+    });
+}
+
+pub fn get_interface_mut(index: usize) -> &'static mut Interface {
+    assert!(
+        index < INTERFACE_COUNT.load(Ordering::Acquire),
+        "interface not initialized"
+    );
+    unsafe { &mut *INTERFACES[index].load(Ordering::Acquire) }
+}
+
+pub fn interfaces() -> impl Iterator<Item = &'static Interface> {
+    let count = INTERFACE_COUNT.load(Ordering::Acquire);
+    INTERFACES[..count]
+        .iter()
+        .map(|slot| unsafe { &*slot.load(Ordering::Acquire) })
+}
+
+// the interface packets to an address should leave through, according to the routing table for
+// ipv4, and the prefixes and routers each interface has learned for ipv6
+pub fn route_interface(address: IpAddress) -> Result<&'static mut Interface> {
+    let index = match address {
+        IpAddress::V4(addr) => ROUTES.lock().lookup(addr).map(|route| route.interface),
+        IpAddress::V6(addr) => ipv6::route_interface(addr),
+    };
+    let index = index.ok_or(Error::NoRoute(address))?;
+    Ok(get_interface_mut(index))
+}
 
-            sockets: SpinLock::new(BTreeMap::new()),
+// sends a tcp or udp payload over whichever ip version the destination is
+pub fn send_ip_packet(
+    interface: &mut Interface,
+    payload: Vec<u8>,
+    protocol: Ipv4Protocol,
+    dst_addr: IpAddress,
+) -> Result<()> {
+    match dst_addr {
+        IpAddress::V4(dst_addr) => ipv4::send_ipv4_packet(interface, payload, protocol, dst_addr),
+        // the next header values are the same protocol numbers
+        IpAddress::V6(dst_addr) => {
+            ipv6::send_ipv6_packet(interface, payload, protocol as u8, dst_addr)
         }
     }
 }
@@ -0,0 +1,233 @@
+// neighbor discovery (rfc 4861), which does arp's job for ipv6, and stateless address
+// autoconfiguration (rfc 4862), which picks our addresses from the prefixes routers advertise
+use crate::device::system_timer;
+use crate::networking::iface::{icmpv6, ipv6, Interface};
+use crate::networking::repr::{
+    EthernetAddress, Icmpv6Message, Ipv6Address, Ipv6Cidr, Ipv6Packet, Medium, NdpNeighborFlags,
+    NdpPrefixFlags, NdpPrefixInfo,
+};
+use crate::networking::{Error, Result};
+
+// slaac only makes addresses from /64 prefixes, the other half is the interface identifier
+const SLAAC_PREFIX_LEN: u8 = 64;
+// how long an answer to duplicate address detection can take, the default RetransTimer (rfc 4861
+// section 10) times one solicitation, in microseconds
+const DAD_TIMEOUT: u64 = 1_000_000;
+
+// configures the link-local address and asks for router advertisements, once the interface has
+// its mac address
+pub fn start(interface: &mut Interface) -> Result<()> {
+    let link_local = Ipv6Address::link_local_from_mac(interface.ethernet_addr);
+    add_address(interface, Ipv6Cidr::new(link_local, SLAAC_PREFIX_LEN)?)?;
+
+    let source_lladdr = Some(interface.ethernet_addr);
+    icmpv6::send_icmpv6_packet(
+        interface,
+        link_local,
+        Ipv6Address::ALL_ROUTERS,
+        Icmpv6Message::RouterSolicitation { source_lladdr },
+    )
+}
+
+// starts using an address, and checks that nobody else already has it. duplicate address
+// detection is optimistic (rfc 4429): the address is used right away and dropped if anyone
+// answers for it while it's still tentative
+pub fn add_address(interface: &mut Interface, cidr: Ipv6Cidr) -> Result<()> {
+    println!("[!] adding ipv6 address {}", cidr);
+    let mut addrs = interface.addrs.lock();
+    addrs.ipv6_addrs.push(cidr);
+
+    if interface.dev.medium() == Medium::Ip {
+        return Ok(());
+    }
+
+    let now = system_timer::get_time();
+    addrs.ipv6_tentative.retain(|&(_, until)| until > now);
+    addrs.ipv6_tentative.push((*cidr, now + DAD_TIMEOUT));
+    drop(addrs);
+
+    icmpv6::send_icmpv6_packet(
+        interface,
+        Ipv6Address::UNSPECIFIED,
+        cidr.solicited_node(),
+        Icmpv6Message::NeighborSolicitation {
+            target: *cidr,
+            source_lladdr: None,
+        },
+    )
+}
+
+fn has_address(interface: &Interface, address: Ipv6Address) -> bool {
+    let addrs = interface.addrs.lock();
+    addrs.ipv6_addrs.iter().any(|cidr| **cidr == address)
+}
+
+// whether duplicate address detection for the address is still going
+fn is_tentative(interface: &Interface, address: Ipv6Address) -> bool {
+    let now = system_timer::get_time();
+    let addrs = interface.addrs.lock();
+    addrs
+        .ipv6_tentative
+        .iter()
+        .any(|&(addr, until)| addr == address && until > now)
+}
+
+pub fn recv_ndp_message(
+    interface: &mut Interface,
+    ipv6_packet: &Ipv6Packet,
+    message: Icmpv6Message,
+) -> Result<()> {
+    // routers decrement the hop limit, so anything less came from off the link
+    if ipv6_packet.hop_limit != 255 {
+        return Err(Error::Malformed);
+    }
+
+    let src_addr = ipv6_packet.src_addr;
+    match message {
+        Icmpv6Message::NeighborSolicitation {
+            target,
+            source_lladdr,
+        } => {
+            if !has_address(interface, target) {
+                return Err(Error::Ignored);
+            }
+
+            // someone else checking whether the address is free, but we already hold it
+            if src_addr.is_unspecified() {
+                return Err(Error::Ignored);
+            }
+
+            if let Some(lladdr) = source_lladdr {
+                let mut neighbor_cache = interface.neighbor_cache.lock();
+                neighbor_cache.set_eth_addr_for_ip(src_addr, lladdr);
+            }
+
+            let target_lladdr = Some(interface.ethernet_addr);
+            icmpv6::send_icmpv6_packet(
+                interface,
+                target,
+                src_addr,
+                Icmpv6Message::NeighborAdvertisement {
+                    flags: NdpNeighborFlags::SOLICITED | NdpNeighborFlags::OVERRIDE,
+                    target,
+                    target_lladdr,
+                },
+            )
+        }
+        Icmpv6Message::NeighborAdvertisement {
+            target,
+            target_lladdr,
+            ..
+        } => {
+            if has_address(interface, target) {
+                if target_lladdr == Some(interface.ethernet_addr) {
+                    return Ok(());
+                }
+                // once detection is over the address is ours, and someone else is misconfigured
+                if !is_tentative(interface, target) {
+                    println!("[!] ipv6 address {} is also used by another host", target);
+                    return Ok(());
+                }
+                println!("[!] ipv6 address {} is already in use, dropping it", target);
+                let mut addrs = interface.addrs.lock();
+                addrs.ipv6_addrs.retain(|cidr| **cidr != target);
+                addrs.ipv6_tentative.retain(|&(addr, _)| addr != target);
+                return Ok(());
+            }
+
+            if let Some(lladdr) = target_lladdr {
+                let mut neighbor_cache = interface.neighbor_cache.lock();
+                neighbor_cache.set_eth_addr_for_ip(target, lladdr);
+            }
+            Ok(())
+        }
+        Icmpv6Message::RouterAdvertisement {
+            router_lifetime,
+            source_lladdr,
+            prefixes,
+            ..
+        } => {
+            if !src_addr.is_link_local() {
+                return Err(Error::Malformed);
+            }
+
+            if let Some(lladdr) = source_lladdr {
+                let mut neighbor_cache = interface.neighbor_cache.lock();
+                neighbor_cache.set_eth_addr_for_ip(src_addr, lladdr);
+            }
+
+            // a lifetime of 0 means the router is going away, or was never a default router
+            // TODO: expire the router, and slaac addresses, when their lifetimes run out
+            let mut addrs = interface.addrs.lock();
+            if router_lifetime > 0 {
+                addrs.ipv6_router = Some(src_addr);
+            } else if addrs.ipv6_router == Some(src_addr) {
+                addrs.ipv6_router = None;
+            }
+            drop(addrs);
+
+            for prefix in prefixes.iter() {
+                autoconfigure(interface, prefix)?;
+            }
+            Ok(())
+        }
+        _ => Err(Error::Ignored),
+    }
+}
+
+// makes an address from an advertised prefix (rfc 4862 section 5.5.3)
+fn autoconfigure(interface: &mut Interface, prefix: &NdpPrefixInfo) -> Result<()> {
+    if prefix.flags & NdpPrefixFlags::AUTONOMOUS == 0
+        || prefix.prefix.is_link_local()
+        || prefix.prefix_len != SLAAC_PREFIX_LEN
+        || prefix.preferred_lifetime > prefix.valid_lifetime
+        || prefix.valid_lifetime == 0
+    {
+        return Ok(());
+    }
+
+    let address = Ipv6Address::from_prefix_and_mac(prefix.prefix, interface.ethernet_addr);
+    if has_address(interface, address) {
+        return Ok(());
+    }
+
+    add_address(interface, Ipv6Cidr::new(address, prefix.prefix_len)?)
+}
+
+// matches ipv6 -> mac
+//      no mapping: neighbor solicitation + error
+//      mapping exists: the advertisement was processed by recv_ndp_message -> update cache
+pub fn eth_addr_for_ip(
+    interface: &mut Interface,
+    ipv6_addr: Ipv6Address,
+) -> Result<EthernetAddress> {
+    if ipv6_addr.is_multicast() {
+        return Ok(ipv6_addr.multicast_mac());
+    }
+
+    let mut neighbor_cache = interface.neighbor_cache.lock();
+    let eth_addr = neighbor_cache.eth_addr_for_ip(ipv6_addr);
+    drop(neighbor_cache);
+
+    match eth_addr {
+        Some(eth_addr) => Ok(eth_addr),
+        None => {
+            println!(
+                "address not found, sending neighbor solicitation for {}",
+                ipv6_addr
+            );
+            let src_addr = ipv6::source_addr(interface, ipv6_addr);
+            let source_lladdr = Some(interface.ethernet_addr);
+            icmpv6::send_icmpv6_packet(
+                interface,
+                src_addr,
+                ipv6_addr.solicited_node(),
+                Icmpv6Message::NeighborSolicitation {
+                    target: ipv6_addr,
+                    source_lladdr,
+                },
+            )?;
+            Err(Error::MacResolution(ipv6_addr.into()))
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use crate::networking::repr::{Device, EthernetAddress, LinkState};
+use crate::networking::utils::frame_queue::DeviceQueues;
+use crate::networking::Result;
+
+use crate::device::usb::device::rndis::{rndis_unwrap_packet, rndis_wrap_packet};
+
+use alloc::vec::Vec;
+use core::task::{Context, Poll};
+
+// a usb rndis device. every frame crosses the bus inside an rndis packet message, which is put on
+// here on the way out and taken off on the way in, so the interface only sees ethernet frames
+pub struct Rndis {
+    queues: &'static DeviceQueues,
+    mac_addr: EthernetAddress,
+    max_transmission_unit: usize,
+}
+
+impl Rndis {
+    pub fn new(queues: &'static DeviceQueues, mac_addr: EthernetAddress, mtu: usize) -> Self {
+        Rndis {
+            queues,
+            mac_addr,
+            max_transmission_unit: mtu,
+        }
+    }
+}
+
+impl Device for Rndis {
+    fn transmit(&mut self, frame: Vec<u8>) -> Result<()> {
+        self.queues.tx.lock().push(rndis_wrap_packet(&frame))
+    }
+
+    fn poll_receive(&mut self, cx: &mut Context<'_>) -> Poll<Vec<u8>> {
+        let mut rx = self.queues.rx.lock();
+        loop {
+            let message = match rx.poll_pop(cx) {
+                Poll::Ready(message) => message,
+                Poll::Pending => return Poll::Pending,
+            };
+            // anything other than a packet message (or a short one) is dropped
+            match rndis_unwrap_packet(&message) {
+                Some(frame) => return Poll::Ready(frame.to_vec()),
+                None => println!("dropping a malformed rndis packet message"),
+            }
+        }
+    }
+
+    fn link_state(&self) -> LinkState {
+        self.queues.link_state()
+    }
+
+    fn poll_link(&mut self, cx: &mut Context<'_>, known: LinkState) -> Poll<LinkState> {
+        self.queues.poll_link(cx, known)
+    }
+
+    fn mac_addr(&self) -> Option<EthernetAddress> {
+        Some(self.mac_addr)
+    }
+
+    fn mtu(&self) -> usize {
+        self.max_transmission_unit
+    }
+}
@@ -0,0 +1,79 @@
+use crate::networking::repr::{Ipv4Address, Ipv4Cidr};
+use crate::sync::SpinLock;
+
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+
+pub static ROUTES: SpinLock<RoutingTable> = SpinLock::new(RoutingTable::new());
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Route {
+    // the destinations this route covers, 0.0.0.0/0 being the default route
+    pub cidr: Ipv4Cidr,
+    // where to send packets, or none if the destinations are directly on the link
+    pub gateway: Option<Ipv4Address>,
+    // index of the interface the packets leave through
+    pub interface: usize,
+}
+
+impl Route {
+    // the address a packet to `address` is actually sent to on the link
+    pub fn next_hop(&self, address: Ipv4Address) -> Ipv4Address {
+        self.gateway.unwrap_or(address)
+    }
+}
+
+impl Display for Route {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self.gateway {
+            Some(gateway) => write!(f, "{} via {} dev {}", self.cidr, gateway, self.interface),
+            None => write!(f, "{} dev {}", self.cidr, self.interface),
+        }
+    }
+}
+
+pub struct RoutingTable {
+    routes: Vec<Route>,
+}
+
+impl RoutingTable {
+    pub const fn new() -> Self {
+        RoutingTable { routes: Vec::new() }
+    }
+
+    pub fn add(&mut self, route: Route) {
+        self.routes.push(route);
+    }
+
+    // drops every route through an interface, for when its addresses change
+    pub fn remove_interface(&mut self, interface: usize) {
+        self.routes.retain(|route| route.interface != interface);
+    }
+
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+
+    // longest prefix match, ties go to the route added first
+    pub fn lookup(&self, address: Ipv4Address) -> Option<Route> {
+        self.best(address, |_| true)
+    }
+
+    // like lookup, but only considering routes through one interface
+    pub fn lookup_on(&self, address: Ipv4Address, interface: usize) -> Option<Route> {
+        self.best(address, |route| route.interface == interface)
+    }
+
+    fn best(&self, address: Ipv4Address, filter: impl Fn(&Route) -> bool) -> Option<Route> {
+        let mut best: Option<Route> = None;
+        for route in self.routes.iter().filter(|route| filter(route)) {
+            if !route.cidr.is_member(address) {
+                continue;
+            }
+            if best.is_none_or(|b| route.cidr.subnet_len() > b.cidr.subnet_len()) {
+                best = Some(*route);
+            }
+        }
+        best
+    }
+}
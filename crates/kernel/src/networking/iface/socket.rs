@@ -1,30 +1,27 @@
-use crate::device::usb::device::net::get_interface_mut;
-use crate::event::thread;
-use crate::sync;
+use crate::event::task::spawn_async;
+use crate::sync::time::{interval, MissedTicks};
 
-use crate::networking::socket::TaggedSocket;
+use crate::networking::socket::SOCKETS;
 
-use alloc::vec::Vec;
+// how often sockets get to send, in microseconds. this is also the granularity of tcp's
+// retransmission timers
+const SOCKET_POLL_INTERVAL: u64 = 20_000;
 
 pub fn socket_send_loop() {
-    let interface = get_interface_mut();
-
-    let to_send: Vec<_> = {
-        let mut sockets = interface.sockets.lock();
-        sockets
-            .iter_mut()
-            .map(|(_, socket)| socket as *mut TaggedSocket)
-            .collect()
-    };
+    spawn_async(async {
+        let mut interval =
+            interval(SOCKET_POLL_INTERVAL).with_missed_tick_behavior(MissedTicks::Skip);
+        while interval.tick().await {
+            poll_sockets();
+        }
+    });
+}
 
-    for &socket_ptr in &to_send {
-        let socket: &mut TaggedSocket = unsafe { &mut *socket_ptr };
-        let _ = socket.send(interface);
+// the lock is held throughout, so a socket can't be closed (and freed) while it's sending.
+// sending never waits on the receive path, which takes the same lock: frames to ourselves go
+// through the loopback device's queue
+fn poll_sockets() {
+    for (_, socket) in SOCKETS.lock().iter_mut() {
+        let _ = socket.send();
     }
-
-    // WARN: this is not good
-    thread::thread(move || {
-        sync::spin_sleep(500_000);
-        socket_send_loop();
-    });
 }
@@ -1,39 +1,16 @@
 use crate::networking::iface::*;
 use crate::networking::repr::*;
-use crate::networking::socket::SocketAddr;
+use crate::networking::socket::{SocketAddr, SOCKETS};
 use crate::networking::Result;
 
-use alloc::vec::Vec;
-
-pub fn send_tcp_packet(
-    interface: &mut Interface,
-    src_port: u16,
-    dst_port: u16,
-    seq_number: u32,
-    ack_number: u32,
-    flags: u8,
-    window_size: u16,
-    dst_addr: Ipv4Address,
-    payload: Vec<u8>,
-) -> Result<()> {
+pub fn send_tcp_segment(interface: &mut Interface, tcp_packet: TcpPacket) -> Result<()> {
     println!(
         "\t[!] sending tcp {} {} {} {}",
-        src_port, dst_port, *interface.ipv4_addr, dst_addr
-    );
-
-    let tcp_packet = TcpPacket::new(
-        src_port,
-        dst_port,
-        seq_number,
-        ack_number,
-        flags,
-        window_size,
-        payload,
-        *interface.ipv4_addr,
-        dst_addr,
+        tcp_packet.src_port, tcp_packet.dst_port, tcp_packet.src_ip, tcp_packet.dst_ip
     );
 
-    ipv4::send_ipv4_packet(
+    let dst_addr = tcp_packet.dst_ip;
+    send_ip_packet(
         interface,
         tcp_packet.serialize(),
         Ipv4Protocol::TCP,
@@ -41,30 +18,30 @@ pub fn send_tcp_packet(
     )
 }
 
-pub fn recv_tcp_packet(interface: &mut Interface, ipv4_packet: Ipv4Packet) -> Result<()> {
+// a tcp segment from either ip version, by the addresses of the packet it came in
+pub fn recv_tcp_packet(
+    interface: &mut Interface,
+    src_addr: IpAddress,
+    dst_addr: IpAddress,
+    payload: &[u8],
+) -> Result<()> {
     println!("\t received tcp packet");
-    let tcp_packet = TcpPacket::deserialize(ipv4_packet.payload.as_slice())?;
+    let tcp_packet = TcpPacket::deserialize(payload)?;
 
     let local_socket_addr = SocketAddr {
-        addr: ipv4_packet.dst_addr,
+        addr: dst_addr,
         port: tcp_packet.dst_port,
     };
 
     let sender_socket_addr = SocketAddr {
-        addr: ipv4_packet.src_addr,
+        addr: src_addr,
         port: tcp_packet.src_port,
     };
 
-    let mut sockets = interface.sockets.lock();
+    let mut sockets = SOCKETS.lock();
     for (_, socket) in sockets.iter_mut() {
         if socket.binding_equals(local_socket_addr) {
-            let _ = socket.recv_enqueue(
-                tcp_packet.seq_number,
-                tcp_packet.ack_number,
-                tcp_packet.flags,
-                tcp_packet.payload.clone(),
-                sender_socket_addr,
-            );
+            let _ = socket.recv_segment(interface, &tcp_packet, sender_socket_addr);
         }
     }
 
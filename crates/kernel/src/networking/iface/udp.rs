@@ -1,25 +1,26 @@
 use crate::networking::iface::*;
 use crate::networking::repr::*;
-use crate::networking::socket::SocketAddr;
+use crate::networking::socket::{SocketAddr, SOCKETS};
 use crate::networking::Result;
 
 use alloc::vec::Vec;
 
 pub fn send_udp_packet(
     interface: &mut Interface,
-    dst_addr: Ipv4Address,
+    dst_addr: IpAddress,
     payload: Vec<u8>,
     src_port: u16,
     dst_port: u16,
 ) -> Result<()> {
+    let src_addr = interface.source_addr(dst_addr);
     println!(
         "\t[!] sending udp {} {} {} {}",
-        src_port, dst_port, *interface.ipv4_addr, dst_addr
+        src_port, dst_port, src_addr, dst_addr
     );
 
-    let udp_packet = UdpPacket::new(src_port, dst_port, payload, *interface.ipv4_addr, dst_addr);
+    let udp_packet = UdpPacket::new(src_port, dst_port, payload, src_addr, dst_addr);
 
-    ipv4::send_ipv4_packet(
+    send_ip_packet(
         interface,
         udp_packet.serialize(),
         Ipv4Protocol::UDP,
@@ -27,24 +28,30 @@ pub fn send_udp_packet(
     )
 }
 
-pub fn recv_udp_packet(interface: &mut Interface, ipv4_packet: Ipv4Packet) -> Result<()> {
+// a udp datagram from either ip version, by the addresses of the packet it came in
+pub fn recv_udp_packet(
+    _interface: &mut Interface,
+    src_addr: IpAddress,
+    dst_addr: IpAddress,
+    payload: &[u8],
+) -> Result<()> {
     println!("\t received udp packet");
-    let udp_packet = UdpPacket::deserialize(ipv4_packet.payload.as_slice())?;
+    let udp_packet = UdpPacket::deserialize(payload)?;
 
     let local_socket_addr = SocketAddr {
-        addr: ipv4_packet.dst_addr,
+        addr: dst_addr,
         port: udp_packet.dst_port,
     };
 
     let sender_socket_addr = SocketAddr {
-        addr: ipv4_packet.src_addr,
+        addr: src_addr,
         port: udp_packet.src_port,
     };
 
-    let mut sockets = interface.sockets.lock();
+    let mut sockets = SOCKETS.lock();
     for (_, socket) in sockets.iter_mut() {
         if socket.binding_equals(local_socket_addr) {
-            let _ = socket.recv_enqueue(0, 0, 0, udp_packet.payload.clone(), sender_socket_addr);
+            let _ = socket.recv_enqueue(udp_packet.payload.clone(), sender_socket_addr);
         }
     }
 
@@ -15,7 +15,7 @@ pub mod utils;
 
 use core::result::Result as CoreResult;
 
-use crate::networking::repr::Ipv4Address;
+use crate::networking::repr::IpAddress;
 use crate::networking::socket::SocketAddr;
 
 // TODO: make more detailed
@@ -29,8 +29,12 @@ pub enum Error {
     // not supported/implemented
     Unsupported,
     InvalidLength,
-    // mac address cannot be resolved to ipv4 address
-    MacResolution(Ipv4Address),
+    // ip address cannot be resolved to a mac address (by arp or neighbor discovery)
+    MacResolution(IpAddress),
+    // no route to the address in the routing table
+    NoRoute(IpAddress),
+    // host name doesn't exist, or has no addresses
+    UnknownHost,
     // socket reuse
     BindingInUse(SocketAddr),
     InvalidSocket(u16),
@@ -1,9 +1,49 @@
+use crate::networking::repr::EthernetAddress;
+use crate::networking::Result;
+
+use alloc::vec::Vec;
+use core::task::{Context, Poll};
+
+// what a device's frames carry, which decides whether the interface needs link layer addressing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Medium {
+    // ethernet frames, addressed with arp
+    Ethernet,
+    // bare ip packets (ie. loopback)
+    Ip,
+}
+
+// whether the device has a carrier, as far as its driver knows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    Up,
+    Down,
+}
+
+// a network device as the interface sees it: a queue of frames going out and a queue of frames
+// coming in, with buffers owned by whoever has the frame at the moment. whatever framing the
+// hardware needs on top (ie. rndis headers) is the device's business, the interface only ever
+// sees frames of its medium
 pub trait Device {
-    // fn send(&mut self, buffer: &[u8]) -> Result<()>;
-    fn send(&mut self, buffer: &mut [u8], buffer_len: u32);
+    // queues a frame to be sent, Exhausted (and the frame is dropped) if the device is backed up
+    fn transmit(&mut self, frame: Vec<u8>) -> Result<()>;
+
+    // the next frame received, once there is one
+    fn poll_receive(&mut self, cx: &mut Context<'_>) -> Poll<Vec<u8>>;
+
+    fn link_state(&self) -> LinkState;
 
-    // fn recv(&mut self, buffer: &mut [u8], buffer_len: u32) -> Result<usize>;
-    fn recv(&mut self, buffer: &mut [u8], buffer_len: u32);
+    // ready once the link state is something other than `known`
+    fn poll_link(&mut self, cx: &mut Context<'_>, known: LinkState) -> Poll<LinkState>;
+
+    // the device's own ethernet address, if its medium has one
+    fn mac_addr(&self) -> Option<EthernetAddress> {
+        None
+    }
 
     fn mtu(&self) -> usize;
+
+    fn medium(&self) -> Medium {
+        Medium::Ethernet
+    }
 }
@@ -305,6 +305,26 @@ impl Packet {
         None
     }
 
+    // t1, when to start renewing the lease (seconds)
+    pub fn get_renewal_time(&self) -> Option<u32> {
+        if let Some(data) = self.get_option(58) {
+            if data.len() == 4 {
+                return Some(NetworkEndian::read_u32(data));
+            }
+        }
+        None
+    }
+
+    // t2, when to give up on the server that gave the lease and ask any (seconds)
+    pub fn get_rebinding_time(&self) -> Option<u32> {
+        if let Some(data) = self.get_option(59) {
+            if data.len() == 4 {
+                return Some(NetworkEndian::read_u32(data));
+            }
+        }
+        None
+    }
+
     pub fn get_dns_servers(&self) -> Vec<Ipv4Address> {
         let mut servers = Vec::new();
         if let Some(data) = self.get_option(6) {
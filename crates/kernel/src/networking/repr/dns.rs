@@ -0,0 +1,291 @@
+use byteorder::{ByteOrder, NetworkEndian};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::Ipv4Address;
+use crate::networking::{Error, Result};
+
+// https://datatracker.ietf.org/doc/html/rfc1035#section-4
+
+#[allow(non_snake_case)]
+pub mod RecordTypes {
+    pub const A: u16 = 1;
+    pub const CNAME: u16 = 5;
+}
+
+const CLASS_IN: u16 = 1;
+
+// header flags
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_TRUNCATED: u16 = 0x0200;
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+const RCODE_MASK: u16 = 0x000F;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResponseCode {
+    NoError,
+    FormatError,
+    ServerFailure,
+    NameError, // the name doesn't exist
+    NotImplemented,
+    Refused,
+    Other(u8),
+}
+
+impl From<u16> for ResponseCode {
+    fn from(code: u16) -> Self {
+        match code {
+            0 => ResponseCode::NoError,
+            1 => ResponseCode::FormatError,
+            2 => ResponseCode::ServerFailure,
+            3 => ResponseCode::NameError,
+            4 => ResponseCode::NotImplemented,
+            5 => ResponseCode::Refused,
+            code => ResponseCode::Other(code as u8),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Question {
+    pub name: String,
+    pub qtype: u16,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecordData {
+    A(Ipv4Address),
+    Cname(String),
+    // anything we don't use
+    Other(u16),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    pub name: String,
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Packet {
+    pub id: u16,
+    pub flags: u16,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Record>,
+}
+
+impl Packet {
+    pub const HEADER_LEN: usize = 12;
+    // names are at most 255 bytes, and each label at most 63
+    const MAX_NAME_LEN: usize = 255;
+    const MAX_LABEL_LEN: usize = 63;
+
+    // a recursive query for one name
+    pub fn query(id: u16, name: &str, qtype: u16) -> Self {
+        Packet {
+            id,
+            flags: FLAG_RECURSION_DESIRED,
+            questions: Vec::from([Question {
+                name: String::from(name),
+                qtype,
+            }]),
+            answers: Vec::new(),
+        }
+    }
+
+    pub fn is_response(&self) -> bool {
+        self.flags & FLAG_RESPONSE != 0
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.flags & FLAG_TRUNCATED != 0
+    }
+
+    pub fn response_code(&self) -> ResponseCode {
+        ResponseCode::from(self.flags & RCODE_MASK)
+    }
+
+    // only the questions are written, which is all a query has
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(Self::HEADER_LEN + 32);
+        buf.resize(Self::HEADER_LEN, 0);
+        NetworkEndian::write_u16(&mut buf[0..2], self.id);
+        NetworkEndian::write_u16(&mut buf[2..4], self.flags);
+        NetworkEndian::write_u16(&mut buf[4..6], self.questions.len() as u16);
+
+        for question in &self.questions {
+            write_name(&mut buf, &question.name)?;
+            buf.extend_from_slice(&question.qtype.to_be_bytes());
+            buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+        }
+        Ok(buf)
+    }
+
+    // authority and additional records are skipped
+    pub fn deserialize(buf: &[u8]) -> Result<Self> {
+        if buf.len() < Self::HEADER_LEN {
+            return Err(Error::Malformed);
+        }
+
+        let id = NetworkEndian::read_u16(&buf[0..2]);
+        let flags = NetworkEndian::read_u16(&buf[2..4]);
+        let qdcount = NetworkEndian::read_u16(&buf[4..6]);
+        let ancount = NetworkEndian::read_u16(&buf[6..8]);
+
+        let mut pos = Self::HEADER_LEN;
+        let mut questions = Vec::new();
+        for _ in 0..qdcount {
+            let name = read_name(buf, &mut pos)?;
+            let fixed = buf.get(pos..pos + 4).ok_or(Error::Malformed)?;
+            let qtype = NetworkEndian::read_u16(&fixed[0..2]);
+            pos += 4;
+            questions.push(Question { name, qtype });
+        }
+
+        let mut answers = Vec::new();
+        for _ in 0..ancount {
+            let name = read_name(buf, &mut pos)?;
+            let fixed = buf.get(pos..pos + 10).ok_or(Error::Malformed)?;
+            let rtype = NetworkEndian::read_u16(&fixed[0..2]);
+            let class = NetworkEndian::read_u16(&fixed[2..4]);
+            let ttl = NetworkEndian::read_u32(&fixed[4..8]);
+            let rdlength = NetworkEndian::read_u16(&fixed[8..10]) as usize;
+            pos += 10;
+
+            let rdata = buf.get(pos..pos + rdlength).ok_or(Error::Malformed)?;
+            let data = match (rtype, class) {
+                (RecordTypes::A, CLASS_IN) => RecordData::A(Ipv4Address::from_bytes(rdata)?),
+                (RecordTypes::CNAME, CLASS_IN) => {
+                    // the name may point outside of rdata, so it's read from the whole message
+                    let mut name_pos = pos;
+                    RecordData::Cname(read_name(buf, &mut name_pos)?)
+                }
+                _ => RecordData::Other(rtype),
+            };
+            pos += rdlength;
+            answers.push(Record { name, ttl, data });
+        }
+
+        Ok(Packet {
+            id,
+            flags,
+            questions,
+            answers,
+        })
+    }
+}
+
+// a name as a list of length prefixed labels, ending with the empty root label
+fn write_name(buf: &mut Vec<u8>, name: &str) -> Result<()> {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    if name.len() + 2 > Packet::MAX_NAME_LEN {
+        return Err(Error::InvalidLength);
+    }
+
+    if !name.is_empty() {
+        for label in name.split('.') {
+            if label.is_empty() || label.len() > Packet::MAX_LABEL_LEN {
+                return Err(Error::Malformed);
+            }
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+    }
+    buf.push(0);
+    Ok(())
+}
+
+// reads a name at `pos`, following compression pointers, and moves `pos` past it
+fn read_name(buf: &[u8], pos: &mut usize) -> Result<String> {
+    let mut name = String::new();
+    let mut cursor = *pos;
+    // where the name ends in the message, which is after the first pointer if there is one
+    let mut end = None;
+    // every pointer has to go backwards, so they can't loop
+    let mut limit = cursor;
+
+    loop {
+        let len = *buf.get(cursor).ok_or(Error::Malformed)? as usize;
+        match len & 0xC0 {
+            0x00 if len == 0 => {
+                cursor += 1;
+                break;
+            }
+            0x00 => {
+                let label = buf
+                    .get(cursor + 1..cursor + 1 + len)
+                    .ok_or(Error::Malformed)?;
+                if !name.is_empty() {
+                    name.push('.');
+                }
+                name.extend(label.iter().map(|&c| c as char));
+                if name.len() > Packet::MAX_NAME_LEN {
+                    return Err(Error::Malformed);
+                }
+                cursor += 1 + len;
+            }
+            0xC0 => {
+                let low = *buf.get(cursor + 1).ok_or(Error::Malformed)? as usize;
+                let target = (len & 0x3F) << 8 | low;
+                if target >= limit {
+                    return Err(Error::Malformed);
+                }
+                end.get_or_insert(cursor + 2);
+                limit = target;
+                cursor = target;
+            }
+            _ => return Err(Error::Malformed),
+        }
+    }
+
+    *pos = end.unwrap_or(cursor);
+    Ok(name)
+}
+
+// a response with one question and one answer
+const TEST_HEADER: [u8; 12] = [0x12, 0x34, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0];
+
+test_case!(dns_compressed_answer);
+fn dns_compressed_answer() -> core::result::Result<(), crate::test::BoxError> {
+    let mut buf = Vec::from(TEST_HEADER);
+    buf.extend_from_slice(b"\x07example\x03com\x00\x00\x01\x00\x01");
+    // the answer's name points back at the question's
+    buf.extend_from_slice(&[
+        0xC0, 12, 0, 1, 0, 1, 0, 0, 0x0e, 0x10, 0, 4, 93, 184, 216, 34,
+    ]);
+
+    let packet = Packet::deserialize(&buf).map_err(|_| "deserialize failed")?;
+    kassert_eq!(packet.id, 0x1234)?;
+    kassert!(packet.is_response())?;
+    kassert_eq!(packet.questions[0].name.as_str(), "example.com")?;
+    kassert_eq!(
+        packet.answers,
+        [Record {
+            name: String::from("example.com"),
+            ttl: 3600,
+            data: RecordData::A(Ipv4Address::new([93, 184, 216, 34])),
+        }]
+    )?;
+    Ok(())
+}
+
+test_case!(dns_pointer_loops);
+fn dns_pointer_loops() -> core::result::Result<(), crate::test::BoxError> {
+    // a pointer to itself
+    let mut buf = Vec::from(TEST_HEADER);
+    buf.extend_from_slice(&[0xC0, 12, 0, 1, 0, 1]);
+    kassert!(Packet::deserialize(&buf).is_err())?;
+
+    // a pointer forwards, to one pointing back
+    let mut buf = Vec::from(TEST_HEADER);
+    buf.extend_from_slice(&[0xC0, 14, 0xC0, 12, 0, 1, 0, 1]);
+    kassert!(Packet::deserialize(&buf).is_err())?;
+
+    // a label running past the end
+    let mut buf = Vec::from(TEST_HEADER);
+    buf.extend_from_slice(&[5, b'a', b'b']);
+    kassert!(Packet::deserialize(&buf).is_err())?;
+    Ok(())
+}
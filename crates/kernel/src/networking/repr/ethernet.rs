@@ -74,6 +74,7 @@ impl fmt::Display for Address {
 pub mod EtherType {
     pub const IPV4: u16 = 0x800;
     pub const ARP: u16 = 0x806;
+    pub const IPV6: u16 = 0x86DD;
 }
 
 pub struct Frame {
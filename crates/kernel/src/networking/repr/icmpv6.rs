@@ -0,0 +1,344 @@
+use byteorder::{ByteOrder, NetworkEndian};
+
+use alloc::vec::Vec;
+
+use super::{EthernetAddress, IpAddress, Ipv6Address, Ipv6NextHeader};
+use crate::networking::utils::checksum::internet_checksum;
+use crate::networking::{Error, Result};
+
+/*
++-----------------------------------+
+| Type (1 byte)    | Code (1 byte)   |
++-----------------------------------+
+| Checksum (2 bytes)                |
++-----------------------------------+
+| Message Body                      |
++-----------------------------------+
+*/
+
+// https://datatracker.ietf.org/doc/html/rfc4443 (icmpv6)
+// https://datatracker.ietf.org/doc/html/rfc4861 (neighbor discovery)
+#[allow(non_snake_case)]
+mod Types {
+    pub const ECHO_REQUEST: u8 = 128;
+    pub const ECHO_REPLY: u8 = 129;
+    pub const ROUTER_SOLICITATION: u8 = 133;
+    pub const ROUTER_ADVERTISEMENT: u8 = 134;
+    pub const NEIGHBOR_SOLICITATION: u8 = 135;
+    pub const NEIGHBOR_ADVERTISEMENT: u8 = 136;
+}
+
+// neighbor discovery option types
+const OPTION_SOURCE_LLADDR: u8 = 1;
+const OPTION_TARGET_LLADDR: u8 = 2;
+const OPTION_PREFIX_INFO: u8 = 3;
+const OPTION_MTU: u8 = 5;
+
+#[allow(non_snake_case)]
+pub mod NeighborFlags {
+    pub const ROUTER: u8 = 0b10000000;
+    pub const SOLICITED: u8 = 0b01000000;
+    pub const OVERRIDE: u8 = 0b00100000;
+}
+
+#[allow(non_snake_case)]
+pub mod PrefixFlags {
+    pub const ON_LINK: u8 = 0b10000000;
+    pub const AUTONOMOUS: u8 = 0b01000000;
+}
+
+// a prefix from a router advertisement, which slaac makes addresses out of
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PrefixInfo {
+    pub prefix_len: u8,
+    pub flags: u8,
+    // in seconds, 0xFFFFFFFF is forever
+    pub valid_lifetime: u32,
+    pub preferred_lifetime: u32,
+    pub prefix: Ipv6Address,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    EchoRequest {
+        id: u16,
+        seq: u16,
+        data: Vec<u8>,
+    },
+    EchoReply {
+        id: u16,
+        seq: u16,
+        data: Vec<u8>,
+    },
+    RouterSolicitation {
+        source_lladdr: Option<EthernetAddress>,
+    },
+    RouterAdvertisement {
+        hop_limit: u8,
+        flags: u8,
+        // in seconds, 0 means this isn't a default router
+        router_lifetime: u16,
+        reachable_time: u32,
+        retrans_timer: u32,
+        source_lladdr: Option<EthernetAddress>,
+        mtu: Option<u32>,
+        prefixes: Vec<PrefixInfo>,
+    },
+    NeighborSolicitation {
+        target: Ipv6Address,
+        source_lladdr: Option<EthernetAddress>,
+    },
+    NeighborAdvertisement {
+        flags: u8,
+        target: Ipv6Address,
+        target_lladdr: Option<EthernetAddress>,
+    },
+    // anything we don't handle (ie. errors), by type and code
+    Other {
+        icmp_type: u8,
+        code: u8,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Packet {
+    pub message: Message,
+}
+
+impl Packet {
+    pub const HEADER_LEN: usize = 4;
+
+    pub fn new(message: Message) -> Self {
+        Packet { message }
+    }
+
+    // the checksum covers an ipv6 pseudo header, so the addresses the packet is sent between are
+    // needed to check it
+    pub fn deserialize(buf: &[u8], src_addr: Ipv6Address, dst_addr: Ipv6Address) -> Result<Self> {
+        if buf.len() < 8 {
+            return Err(Error::Malformed);
+        }
+
+        if checksum(buf, src_addr, dst_addr) != 0 {
+            return Err(Error::Checksum);
+        }
+
+        let icmp_type = buf[0];
+        let code = buf[1];
+        let body = &buf[4..];
+
+        let message = match icmp_type {
+            Types::ECHO_REQUEST | Types::ECHO_REPLY => {
+                let id = NetworkEndian::read_u16(&body[0..2]);
+                let seq = NetworkEndian::read_u16(&body[2..4]);
+                let data = body[4..].to_vec();
+                if icmp_type == Types::ECHO_REQUEST {
+                    Message::EchoRequest { id, seq, data }
+                } else {
+                    Message::EchoReply { id, seq, data }
+                }
+            }
+            Types::ROUTER_SOLICITATION => {
+                let options = Options::parse(&body[4..])?;
+                Message::RouterSolicitation {
+                    source_lladdr: options.source_lladdr,
+                }
+            }
+            Types::ROUTER_ADVERTISEMENT => {
+                if body.len() < 12 {
+                    return Err(Error::Malformed);
+                }
+                let options = Options::parse(&body[12..])?;
+                Message::RouterAdvertisement {
+                    hop_limit: body[0],
+                    flags: body[1],
+                    router_lifetime: NetworkEndian::read_u16(&body[2..4]),
+                    reachable_time: NetworkEndian::read_u32(&body[4..8]),
+                    retrans_timer: NetworkEndian::read_u32(&body[8..12]),
+                    source_lladdr: options.source_lladdr,
+                    mtu: options.mtu,
+                    prefixes: options.prefixes,
+                }
+            }
+            Types::NEIGHBOR_SOLICITATION => {
+                if body.len() < 20 {
+                    return Err(Error::Malformed);
+                }
+                let options = Options::parse(&body[20..])?;
+                Message::NeighborSolicitation {
+                    target: Ipv6Address::from_bytes(&body[4..20])?,
+                    source_lladdr: options.source_lladdr,
+                }
+            }
+            Types::NEIGHBOR_ADVERTISEMENT => {
+                if body.len() < 20 {
+                    return Err(Error::Malformed);
+                }
+                let options = Options::parse(&body[20..])?;
+                Message::NeighborAdvertisement {
+                    flags: body[0],
+                    target: Ipv6Address::from_bytes(&body[4..20])?,
+                    target_lladdr: options.target_lladdr,
+                }
+            }
+            _ => Message::Other { icmp_type, code },
+        };
+
+        Ok(Packet { message })
+    }
+
+    pub fn serialize(&self, src_addr: Ipv6Address, dst_addr: Ipv6Address) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(64);
+        buf.extend_from_slice(&[0, 0, 0, 0]);
+
+        match &self.message {
+            Message::EchoRequest { id, seq, data } | Message::EchoReply { id, seq, data } => {
+                buf[0] = match self.message {
+                    Message::EchoRequest { .. } => Types::ECHO_REQUEST,
+                    _ => Types::ECHO_REPLY,
+                };
+                buf.extend_from_slice(&id.to_be_bytes());
+                buf.extend_from_slice(&seq.to_be_bytes());
+                buf.extend_from_slice(data);
+            }
+            Message::RouterSolicitation { source_lladdr } => {
+                buf[0] = Types::ROUTER_SOLICITATION;
+                buf.extend_from_slice(&[0; 4]); // reserved
+                write_lladdr(&mut buf, OPTION_SOURCE_LLADDR, *source_lladdr);
+            }
+            Message::RouterAdvertisement {
+                hop_limit,
+                flags,
+                router_lifetime,
+                reachable_time,
+                retrans_timer,
+                source_lladdr,
+                mtu,
+                prefixes,
+            } => {
+                buf[0] = Types::ROUTER_ADVERTISEMENT;
+                buf.push(*hop_limit);
+                buf.push(*flags);
+                buf.extend_from_slice(&router_lifetime.to_be_bytes());
+                buf.extend_from_slice(&reachable_time.to_be_bytes());
+                buf.extend_from_slice(&retrans_timer.to_be_bytes());
+                write_lladdr(&mut buf, OPTION_SOURCE_LLADDR, *source_lladdr);
+                if let Some(mtu) = mtu {
+                    buf.extend_from_slice(&[OPTION_MTU, 1, 0, 0]);
+                    buf.extend_from_slice(&mtu.to_be_bytes());
+                }
+                for prefix in prefixes {
+                    buf.extend_from_slice(&[
+                        OPTION_PREFIX_INFO,
+                        4,
+                        prefix.prefix_len,
+                        prefix.flags,
+                    ]);
+                    buf.extend_from_slice(&prefix.valid_lifetime.to_be_bytes());
+                    buf.extend_from_slice(&prefix.preferred_lifetime.to_be_bytes());
+                    buf.extend_from_slice(&[0; 4]); // reserved
+                    buf.extend_from_slice(prefix.prefix.as_bytes());
+                }
+            }
+            Message::NeighborSolicitation {
+                target,
+                source_lladdr,
+            } => {
+                buf[0] = Types::NEIGHBOR_SOLICITATION;
+                buf.extend_from_slice(&[0; 4]); // reserved
+                buf.extend_from_slice(target.as_bytes());
+                write_lladdr(&mut buf, OPTION_SOURCE_LLADDR, *source_lladdr);
+            }
+            Message::NeighborAdvertisement {
+                flags,
+                target,
+                target_lladdr,
+            } => {
+                buf[0] = Types::NEIGHBOR_ADVERTISEMENT;
+                buf.extend_from_slice(&[*flags, 0, 0, 0]);
+                buf.extend_from_slice(target.as_bytes());
+                write_lladdr(&mut buf, OPTION_TARGET_LLADDR, *target_lladdr);
+            }
+            Message::Other { icmp_type, code } => {
+                buf[0] = *icmp_type;
+                buf[1] = *code;
+                buf.extend_from_slice(&[0; 4]);
+            }
+        }
+
+        let checksum = checksum(&buf, src_addr, dst_addr);
+        NetworkEndian::write_u16(&mut buf[2..4], checksum);
+
+        buf
+    }
+}
+
+fn checksum(buf: &[u8], src_addr: Ipv6Address, dst_addr: Ipv6Address) -> u16 {
+    let mut data = IpAddress::pseudo_header(
+        src_addr.into(),
+        dst_addr.into(),
+        Ipv6NextHeader::ICMPV6,
+        buf.len(),
+    );
+    data.extend_from_slice(buf);
+    internet_checksum(&data)
+}
+
+fn write_lladdr(buf: &mut Vec<u8>, kind: u8, lladdr: Option<EthernetAddress>) {
+    if let Some(lladdr) = lladdr {
+        // length is in units of 8 bytes
+        buf.extend_from_slice(&[kind, 1]);
+        buf.extend_from_slice(lladdr.as_bytes());
+    }
+}
+
+// the neighbor discovery options we understand, anything else is skipped
+#[derive(Default)]
+struct Options {
+    source_lladdr: Option<EthernetAddress>,
+    target_lladdr: Option<EthernetAddress>,
+    mtu: Option<u32>,
+    prefixes: Vec<PrefixInfo>,
+}
+
+impl Options {
+    fn parse(mut buf: &[u8]) -> Result<Self> {
+        let mut options = Options::default();
+
+        while buf.len() >= 2 {
+            let kind = buf[0];
+            let len = buf[1] as usize * 8;
+            // a zero length option would never end (rfc 4861 section 4.6)
+            if len == 0 || len > buf.len() {
+                return Err(Error::Malformed);
+            }
+            let option = &buf[..len];
+
+            match kind {
+                OPTION_SOURCE_LLADDR if len >= 8 => {
+                    options.source_lladdr = Some(EthernetAddress::from_bytes(&option[2..8])?);
+                }
+                OPTION_TARGET_LLADDR if len >= 8 => {
+                    options.target_lladdr = Some(EthernetAddress::from_bytes(&option[2..8])?);
+                }
+                OPTION_MTU if len >= 8 => {
+                    options.mtu = Some(NetworkEndian::read_u32(&option[4..8]));
+                }
+                OPTION_PREFIX_INFO if len >= 32 => {
+                    options.prefixes.push(PrefixInfo {
+                        prefix_len: option[2],
+                        flags: option[3],
+                        valid_lifetime: NetworkEndian::read_u32(&option[4..8]),
+                        preferred_lifetime: NetworkEndian::read_u32(&option[8..12]),
+                        prefix: Ipv6Address::from_bytes(&option[16..32])?,
+                    });
+                }
+                _ => {}
+            }
+
+            buf = &buf[len..];
+        }
+
+        Ok(options)
+    }
+}
@@ -0,0 +1,78 @@
+use core::fmt::{Display, Formatter, Result as FmtResult};
+
+use alloc::vec::Vec;
+
+use super::{Ipv4Address, Ipv6Address};
+
+// an address of either ip version, so sockets and everything above the ip layer can be dual-stack
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Address {
+    V4(Ipv4Address),
+    V6(Ipv6Address),
+}
+
+impl Address {
+    // 0.0.0.0 or ::, which sockets bind to for "any address"
+    pub fn is_unspecified(&self) -> bool {
+        match self {
+            Address::V4(addr) => *addr == Ipv4Address::empty(),
+            Address::V6(addr) => addr.is_unspecified(),
+        }
+    }
+
+    // the pseudo header that tcp, udp and icmpv6 checksums cover
+    pub fn pseudo_header(
+        src_addr: Address,
+        dst_addr: Address,
+        protocol: u8,
+        len: usize,
+    ) -> Vec<u8> {
+        let mut pseudo_header = Vec::with_capacity(40);
+        match (src_addr, dst_addr) {
+            (Address::V6(src_addr), Address::V6(dst_addr)) => {
+                // rfc 8200 section 8.1
+                pseudo_header.extend_from_slice(src_addr.as_bytes());
+                pseudo_header.extend_from_slice(dst_addr.as_bytes());
+                pseudo_header.extend_from_slice(&(len as u32).to_be_bytes());
+                pseudo_header.extend_from_slice(&[0, 0, 0, protocol]);
+            }
+            _ => {
+                pseudo_header.extend_from_slice(src_addr.v4_bytes());
+                pseudo_header.extend_from_slice(dst_addr.v4_bytes());
+                pseudo_header.push(0);
+                pseudo_header.push(protocol);
+                pseudo_header.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+        }
+        pseudo_header
+    }
+
+    // mixing versions in one packet can't happen, a v6 address here is a bug
+    fn v4_bytes(&self) -> &[u8] {
+        match self {
+            Address::V4(addr) => addr.as_bytes(),
+            Address::V6(_) => &[0; 4],
+        }
+    }
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Address::V4(addr) => write!(f, "{}", addr),
+            Address::V6(addr) => write!(f, "{}", addr),
+        }
+    }
+}
+
+impl From<Ipv4Address> for Address {
+    fn from(addr: Ipv4Address) -> Self {
+        Address::V4(addr)
+    }
+}
+
+impl From<Ipv6Address> for Address {
+    fn from(addr: Ipv6Address) -> Self {
+        Address::V6(addr)
+    }
+}
@@ -49,6 +49,11 @@ impl Address {
         NetworkEndian::read_u32(&self.0[..])
     }
 
+    // 255.255.255.255, the broadcast on whatever link a packet is sent out of
+    pub fn is_limited_broadcast(&self) -> bool {
+        self.0 == [255; 4]
+    }
+
     // check classes, see cidr for more
     pub fn is_unicast(&self) -> bool {
         !(self.is_multicast() || self.is_reserved())
@@ -160,8 +165,17 @@ impl AddressCidr {
         })
     }
 
+    pub fn subnet_len(&self) -> u32 {
+        self.subnet_len
+    }
+
+    // shifting a u32 by 32 overflows, so a /32 needs checking
+    fn mask(&self) -> u32 {
+        !0xFFFFFFFFu32.checked_shr(self.subnet_len).unwrap_or(0)
+    }
+
     pub fn is_member(&self, address: Address) -> bool {
-        let mask = !(0xFFFFFFFF >> self.subnet_len);
+        let mask = self.mask();
         (address.as_u32() & mask) == (self.address.as_u32() & mask)
     }
     pub fn is_broadcast(&self, address: Address) -> bool {
@@ -169,7 +183,7 @@ impl AddressCidr {
         address == self.broadcast()
     }
     pub fn broadcast(&self) -> Address {
-        let mask = !(0xFFFFFFFF >> self.subnet_len);
+        let mask = self.mask();
         let addr = (self.address.as_u32() & mask) | (!mask);
         Address::from_u32(addr)
     }
@@ -197,7 +211,6 @@ pub mod Protocols {
     pub const UDP: u8 = 17;
 }
 
-#[allow(dead_code)]
 pub mod flags {
     pub const DONT_FRAGMENT: u8 = 0b00000010;
     pub const NOT_LAST: u8 = 0b00000001;
@@ -231,6 +244,9 @@ pub struct Packet {
 impl Packet {
     pub const MIN_HEADER_LEN: usize = 20; // 5 * 4 = 20 bytes, minimum
 
+    // largest payload total_len can describe, with the minimum header
+    pub const MAX_PAYLOAD_LEN: usize = u16::MAX as usize - Self::MIN_HEADER_LEN;
+
     /// Simple constructor for typical user usage
     pub fn new(src_addr: Address, dst_addr: Address, protocol: Protocol, payload: Vec<u8>) -> Self {
         Self {
@@ -342,6 +358,65 @@ impl Packet {
         buf
     }
 
+    // whether this is only part of a datagram
+    pub fn is_fragment(&self) -> bool {
+        self.flags & flags::NOT_LAST != 0 || self.frag_offset != 0
+    }
+
+    // where the payload goes in the whole datagram, in bytes
+    pub fn fragment_start(&self) -> usize {
+        self.frag_offset as usize * 8
+    }
+
+    // splits the packet into fragments that each fit in `mtu` bytes (header included). packets
+    // that already fit are returned as they are
+    pub fn fragment(self, mtu: usize) -> Result<Vec<Packet>> {
+        let header_len = Self::MIN_HEADER_LEN;
+        if header_len + self.payload.len() <= mtu {
+            return Ok(vec![self]);
+        }
+        if self.flags & flags::DONT_FRAGMENT != 0 {
+            return Err(Error::InvalidLength);
+        }
+
+        // offsets are counted in 8 byte units, so every fragment but the last has to be a
+        // multiple of 8 long
+        let chunk_len = (mtu.saturating_sub(header_len)) & !7;
+        if chunk_len == 0 {
+            return Err(Error::InvalidLength);
+        }
+
+        let chunks = self.payload.chunks(chunk_len);
+        let count = chunks.len();
+        let fragments = chunks
+            .enumerate()
+            .map(|(i, chunk)| {
+                let last = i + 1 == count;
+                Packet {
+                    version: self.version,
+                    ihl: header_len as u8,
+                    dscp: self.dscp,
+                    total_len: (header_len + chunk.len()) as u16,
+                    id: self.id,
+                    // a fragment of a fragment still has more after it, unless it was the last
+                    flags: if last {
+                        self.flags
+                    } else {
+                        self.flags | flags::NOT_LAST
+                    },
+                    frag_offset: self.frag_offset + (i * chunk_len / 8) as u16,
+                    ttl: self.ttl,
+                    protocol: self.protocol,
+                    checksum: 0,
+                    src_addr: self.src_addr,
+                    dst_addr: self.dst_addr,
+                    payload: chunk.to_vec(),
+                }
+            })
+            .collect();
+        Ok(fragments)
+    }
+
     pub fn is_valid_checksum(&self) -> bool {
         let header_len = (self.ihl as usize) * 4;
         let mut buf = vec![0u8; header_len];
@@ -0,0 +1,340 @@
+use byteorder::{ByteOrder, NetworkEndian};
+
+use core::fmt::{Display, Formatter, Result as FmtResult};
+use core::ops::Deref;
+use core::result::Result as StdResult;
+use core::str::FromStr;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::EthernetAddress;
+use crate::networking::{Error, Result};
+
+// https://datatracker.ietf.org/doc/html/rfc8200
+// https://datatracker.ietf.org/doc/html/rfc4291 (addressing)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Address([u8; 16]);
+
+impl Address {
+    pub const UNSPECIFIED: Address = Address([0; 16]);
+    pub const LOOPBACK: Address = Address([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    // ff02::1
+    pub const ALL_NODES: Address = Address([0xff, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    // ff02::2
+    pub const ALL_ROUTERS: Address = Address([0xff, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+
+    pub fn new(addr: [u8; 16]) -> Address {
+        Address(addr)
+    }
+
+    pub fn from_bytes(addr: &[u8]) -> Result<Address> {
+        if addr.len() != 16 {
+            return Err(Error::Malformed);
+        }
+
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(addr);
+        Ok(Address(bytes))
+    }
+
+    pub fn from_segments(segments: [u16; 8]) -> Address {
+        let mut bytes = [0; 16];
+        for (i, segment) in segments.iter().enumerate() {
+            NetworkEndian::write_u16(&mut bytes[i * 2..i * 2 + 2], *segment);
+        }
+        Address(bytes)
+    }
+
+    pub fn segments(&self) -> [u16; 8] {
+        let mut segments = [0; 8];
+        for (i, segment) in segments.iter_mut().enumerate() {
+            *segment = NetworkEndian::read_u16(&self.0[i * 2..i * 2 + 2]);
+        }
+        segments
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    // an address made from a /64 prefix and an interface identifier derived from the mac (modified
+    // eui-64, rfc 4291 appendix a), which is how slaac picks addresses
+    pub fn from_prefix_and_mac(prefix: Address, mac: EthernetAddress) -> Address {
+        let mac = mac.as_bytes();
+        let mut bytes = prefix.0;
+        bytes[8] = mac[0] ^ 0b00000010;
+        bytes[9] = mac[1];
+        bytes[10] = mac[2];
+        bytes[11] = 0xff;
+        bytes[12] = 0xfe;
+        bytes[13] = mac[3];
+        bytes[14] = mac[4];
+        bytes[15] = mac[5];
+        Address(bytes)
+    }
+
+    // fe80::/64 plus the interface identifier
+    pub fn link_local_from_mac(mac: EthernetAddress) -> Address {
+        let prefix = Address([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        Self::from_prefix_and_mac(prefix, mac)
+    }
+
+    // ff02::1:ffXX:XXXX, where neighbor solicitations for this address are sent (rfc 4291 2.7.1)
+    pub fn solicited_node(&self) -> Address {
+        let mut bytes = [0xff, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0xff, 0, 0, 0];
+        bytes[13..].copy_from_slice(&self.0[13..]);
+        Address(bytes)
+    }
+
+    // multicast addresses map onto 33:33 followed by the low 32 bits (rfc 2464 section 7)
+    pub fn multicast_mac(&self) -> EthernetAddress {
+        let mut bytes = [0x33, 0x33, 0, 0, 0, 0];
+        bytes[2..].copy_from_slice(&self.0[12..]);
+        EthernetAddress::from_bytes(&bytes).unwrap()
+    }
+
+    // check classes
+    pub fn is_unspecified(&self) -> bool {
+        self.0 == [0; 16]
+    }
+    pub fn is_loopback(&self) -> bool {
+        *self == Self::LOOPBACK
+    }
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] == 0xff
+    }
+    // fe80::/10
+    pub fn is_link_local(&self) -> bool {
+        self.0[0] == 0xfe && (self.0[1] & 0b11000000) == 0b10000000
+    }
+    pub fn is_unicast(&self) -> bool {
+        !(self.is_multicast() || self.is_unspecified())
+    }
+}
+
+// written the short way from rfc 5952: lowercase hex, no leading zeros, and the longest run of
+// zero groups replaced with "::"
+impl Display for Address {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let segments = self.segments();
+
+        // find the longest run of zeros, at least two groups long
+        let (mut best_start, mut best_len) = (0, 0);
+        let mut i = 0;
+        while i < 8 {
+            if segments[i] != 0 {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < 8 && segments[i] == 0 {
+                i += 1;
+            }
+            if i - start > best_len {
+                best_start = start;
+                best_len = i - start;
+            }
+        }
+
+        if best_len < 2 {
+            best_len = 0;
+        }
+
+        let mut i = 0;
+        while i < 8 {
+            if best_len > 0 && i == best_start {
+                write!(f, "::")?;
+                i += best_len;
+                continue;
+            }
+            if i > 0 && !(best_len > 0 && i == best_start + best_len) {
+                write!(f, ":")?;
+            }
+            write!(f, "{:x}", segments[i])?;
+            i += 1;
+        }
+        Ok(())
+    }
+}
+
+// NOTE: str must be in the usual hex form, ie. "fe80::1" (no embedded ipv4 or zone ids)
+impl FromStr for Address {
+    type Err = ();
+
+    fn from_str(addr: &str) -> StdResult<Address, Self::Err> {
+        fn parse_groups(part: &str) -> StdResult<Vec<u16>, ()> {
+            if part.is_empty() {
+                return Ok(Vec::new());
+            }
+            part.split(':')
+                .map(|group| {
+                    if group.is_empty() || group.len() > 4 {
+                        return Err(());
+                    }
+                    u16::from_str_radix(group, 16).map_err(|_| ())
+                })
+                .collect()
+        }
+
+        let mut segments = [0u16; 8];
+        match addr.split_once("::") {
+            Some((head, tail)) => {
+                let head = parse_groups(head)?;
+                let tail = parse_groups(tail)?;
+                // "::" has to stand for at least one group
+                if head.len() + tail.len() > 7 {
+                    return Err(());
+                }
+                segments[..head.len()].copy_from_slice(&head);
+                segments[8 - tail.len()..].copy_from_slice(&tail);
+            }
+            None => {
+                let groups = parse_groups(addr)?;
+                if groups.len() != 8 {
+                    return Err(());
+                }
+                segments.copy_from_slice(&groups);
+            }
+        }
+
+        Ok(Address::from_segments(segments))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AddressCidr {
+    address: Address,
+    prefix_len: u8,
+}
+
+impl AddressCidr {
+    pub fn new(address: Address, prefix_len: u8) -> Result<AddressCidr> {
+        if prefix_len > 128 {
+            return Err(Error::Malformed);
+        }
+
+        Ok(AddressCidr {
+            address,
+            prefix_len,
+        })
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    pub fn is_member(&self, address: Address) -> bool {
+        let mask = u128::MAX
+            .checked_shl(128 - self.prefix_len as u32)
+            .unwrap_or(0);
+        let lhs = u128::from_be_bytes(address.0);
+        let rhs = u128::from_be_bytes(self.address.0);
+        (lhs & mask) == (rhs & mask)
+    }
+}
+
+impl Display for AddressCidr {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}/{}", self.address, self.prefix_len)
+    }
+}
+
+// we only want the address when we dereference this
+impl Deref for AddressCidr {
+    type Target = Address;
+
+    fn deref(&self) -> &Address {
+        &self.address
+    }
+}
+
+#[allow(non_snake_case)]
+pub mod NextHeader {
+    pub const HOP_BY_HOP: u8 = 0;
+    pub const TCP: u8 = 6;
+    pub const UDP: u8 = 17;
+    pub const ICMPV6: u8 = 58;
+    pub const NO_NEXT_HEADER: u8 = 59;
+}
+
+/*
++---------+---------------+--------------------------------------+
+| Version | Traffic Class |              Flow Label              |
++---------+---------------+--------+-------------+---------------+
+|        Payload Length            | Next Header |   Hop Limit   |
++----------------------------------+-------------+---------------+
+|                      Source Address (16B)                      |
++----------------------------------------------------------------+
+|                   Destination Address (16B)                    |
++----------------------------------------------------------------+
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Packet {
+    pub traffic_class: u8,
+    pub flow_label: u32, // lower 20 bits used
+    pub next_header: u8,
+    pub hop_limit: u8,
+    pub src_addr: Address,
+    pub dst_addr: Address,
+    pub payload: Vec<u8>,
+}
+
+impl Packet {
+    pub const HEADER_LEN: usize = 40;
+
+    pub fn new(src_addr: Address, dst_addr: Address, next_header: u8, payload: Vec<u8>) -> Self {
+        Packet {
+            traffic_class: 0,
+            flow_label: 0,
+            next_header,
+            hop_limit: 64,
+            src_addr,
+            dst_addr,
+            payload,
+        }
+    }
+
+    pub fn deserialize(buf: &[u8]) -> Result<Self> {
+        if buf.len() < Self::HEADER_LEN {
+            return Err(Error::Malformed);
+        }
+
+        let first = NetworkEndian::read_u32(&buf[0..4]);
+        if first >> 28 != 6 {
+            return Err(Error::Malformed);
+        }
+
+        let payload_len = NetworkEndian::read_u16(&buf[4..6]) as usize;
+        // jumbograms aren't supported, and ethernet padding may follow the payload
+        let payload = buf
+            .get(Self::HEADER_LEN..Self::HEADER_LEN + payload_len)
+            .ok_or(Error::Malformed)?
+            .to_vec();
+
+        Ok(Packet {
+            traffic_class: (first >> 20) as u8,
+            flow_label: first & 0xFFFFF,
+            next_header: buf[6],
+            hop_limit: buf[7],
+            src_addr: Address::from_bytes(&buf[8..24])?,
+            dst_addr: Address::from_bytes(&buf[24..40])?,
+            payload,
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = vec![0u8; Self::HEADER_LEN + self.payload.len()];
+
+        let first = 6 << 28 | (self.traffic_class as u32) << 20 | (self.flow_label & 0xFFFFF);
+        NetworkEndian::write_u32(&mut buf[0..4], first);
+        NetworkEndian::write_u16(&mut buf[4..6], self.payload.len() as u16);
+        buf[6] = self.next_header;
+        buf[7] = self.hop_limit;
+        buf[8..24].copy_from_slice(self.src_addr.as_bytes());
+        buf[24..40].copy_from_slice(self.dst_addr.as_bytes());
+        buf[Self::HEADER_LEN..].copy_from_slice(&self.payload);
+
+        buf
+    }
+}
@@ -20,9 +20,13 @@
 mod arp;
 pub mod dev;
 mod dhcp;
+mod dns;
 mod ethernet;
 mod icmp;
+mod icmpv6;
+mod ip;
 mod ipv4;
+mod ipv6;
 mod tcp;
 mod udp;
 
@@ -33,18 +37,36 @@ pub use self::ethernet::{
 pub use self::arp::{Hardware as ArpHardware, Operation as ArpOperation, Packet as ArpPacket};
 
 pub use self::ipv4::{
-    Address as Ipv4Address, AddressCidr as Ipv4Cidr, Packet as Ipv4Packet, Protocol as Ipv4Protocol,
+    flags as Ipv4Flags, Address as Ipv4Address, AddressCidr as Ipv4Cidr, Packet as Ipv4Packet,
+    Protocol as Ipv4Protocol,
 };
 
+pub use self::ipv6::{
+    Address as Ipv6Address, AddressCidr as Ipv6Cidr, NextHeader as Ipv6NextHeader,
+    Packet as Ipv6Packet,
+};
+
+pub use self::ip::Address as IpAddress;
+
 pub use self::icmp::{
     DestinationUnreachable as IcmpDstUnreachable, Message as IcmpMessage, Packet as IcmpPacket,
     TimeExceeded as IcmpTimeExceeded,
 };
 
+pub use self::icmpv6::{
+    Message as Icmpv6Message, NeighborFlags as NdpNeighborFlags, Packet as Icmpv6Packet,
+    PrefixFlags as NdpPrefixFlags, PrefixInfo as NdpPrefixInfo,
+};
+
 pub use self::udp::Packet as UdpPacket;
 
 pub use self::dhcp::{DhcpOption, DhcpParam, MessageType as DhcpMessageType, Packet as DhcpPacket};
 
+pub use self::dns::{
+    Packet as DnsPacket, Question as DnsQuestion, Record as DnsRecord, RecordData as DnsRecordData,
+    RecordTypes as DnsRecordTypes, ResponseCode as DnsResponseCode,
+};
+
 pub use self::tcp::{Flags as TcpFlags, Packet as TcpPacket};
 
-pub use dev::Device;
+pub use dev::{Device, LinkState, Medium};
@@ -3,7 +3,7 @@ use byteorder::{ByteOrder, NetworkEndian};
 use alloc::vec;
 use alloc::vec::Vec;
 
-use super::Ipv4Address;
+use super::{IpAddress, Ipv4Address, Ipv4Protocol};
 use crate::networking::{Error, Result};
 
 #[allow(non_snake_case)]
@@ -27,11 +27,18 @@ pub struct Packet {
     pub window_size: u16,
     pub checksum: u16,
     pub urgent_ptr: u16,
-    pub src_ip: Ipv4Address,
-    pub dst_ip: Ipv4Address,
+    pub src_ip: IpAddress,
+    pub dst_ip: IpAddress,
+    // maximum segment size option, only sent on SYN segments
+    pub mss: Option<u16>,
     pub payload: Vec<u8>,
 }
 
+// option kinds
+const OPTION_END: u8 = 0;
+const OPTION_NOP: u8 = 1;
+const OPTION_MSS: u8 = 2;
+
 impl Packet {
     pub const HEADER_LEN: usize = 20;
 
@@ -43,8 +50,8 @@ impl Packet {
         flags: u8,
         window_size: u16,
         payload: Vec<u8>,
-        src_ip: Ipv4Address,
-        dst_ip: Ipv4Address,
+        src_ip: IpAddress,
+        dst_ip: IpAddress,
     ) -> Self {
         Packet {
             src_port,
@@ -58,10 +65,53 @@ impl Packet {
             urgent_ptr: 0,
             src_ip,
             dst_ip,
+            mss: None,
             payload,
         }
     }
 
+    pub fn with_mss(mut self, mss: u16) -> Self {
+        self.mss = Some(mss);
+        self.data_offset = 6; // 4 more bytes for the option
+        self
+    }
+
+    // returns the payload length plus one for each of SYN and FIN, which also take up a sequence
+    // number
+    pub fn seq_len(&self) -> u32 {
+        let mut len = self.payload.len() as u32;
+        if self.flags & Flags::TCP_SYN != 0 {
+            len += 1;
+        }
+        if self.flags & Flags::TCP_FIN != 0 {
+            len += 1;
+        }
+        len
+    }
+
+    // walks the options between the fixed header and the payload, only MSS is understood for now
+    fn parse_options(options: &[u8]) -> Result<Option<u16>> {
+        let mut mss = None;
+        let mut i = 0;
+        while i < options.len() {
+            match options[i] {
+                OPTION_END => break,
+                OPTION_NOP => i += 1,
+                kind => {
+                    let len = *options.get(i + 1).ok_or(Error::Malformed)? as usize;
+                    if len < 2 || i + len > options.len() {
+                        return Err(Error::Malformed);
+                    }
+                    if kind == OPTION_MSS && len == 4 {
+                        mss = Some(NetworkEndian::read_u16(&options[i + 2..i + 4]));
+                    }
+                    i += len;
+                }
+            }
+        }
+        Ok(mss)
+    }
+
     pub fn deserialize(buf: &[u8]) -> Result<Self> {
         if buf.len() < Self::HEADER_LEN {
             return Err(Error::Malformed);
@@ -86,6 +136,7 @@ impl Packet {
             return Err(Error::Malformed);
         }
 
+        let mss = Self::parse_options(&buf[Self::HEADER_LEN..header_len])?;
         let payload = buf[header_len..].to_vec();
 
         Ok(Packet {
@@ -98,14 +149,15 @@ impl Packet {
             window_size,
             checksum,
             urgent_ptr,
-            src_ip: Ipv4Address::new([0, 0, 0, 0]),
-            dst_ip: Ipv4Address::new([0, 0, 0, 0]),
+            src_ip: IpAddress::V4(Ipv4Address::empty()),
+            dst_ip: IpAddress::V4(Ipv4Address::empty()),
+            mss,
             payload,
         })
     }
 
     pub fn serialize(&self) -> Vec<u8> {
-        let header_len = Self::HEADER_LEN;
+        let header_len = (self.data_offset as usize) * 4;
         let mut buf = vec![0u8; header_len + self.payload.len()];
 
         NetworkEndian::write_u16(&mut buf[0..2], self.src_port);
@@ -117,6 +169,11 @@ impl Packet {
         NetworkEndian::write_u16(&mut buf[14..16], self.window_size);
         NetworkEndian::write_u16(&mut buf[16..18], 0); // temporary checksum
         NetworkEndian::write_u16(&mut buf[18..20], self.urgent_ptr);
+        if let Some(mss) = self.mss {
+            buf[20] = OPTION_MSS;
+            buf[21] = 4;
+            NetworkEndian::write_u16(&mut buf[22..24], mss);
+        }
 
         buf[header_len..].copy_from_slice(&self.payload);
 
@@ -126,13 +183,9 @@ impl Packet {
         buf
     }
 
-    fn compute_checksum_raw(src_ip: Ipv4Address, dst_ip: Ipv4Address, tcp_segment: &[u8]) -> u16 {
-        let mut pseudo_header = Vec::with_capacity(12);
-        pseudo_header.extend_from_slice(&src_ip.as_bytes());
-        pseudo_header.extend_from_slice(&dst_ip.as_bytes());
-        pseudo_header.push(0);
-        pseudo_header.push(6); // TCP protocol number
-        pseudo_header.extend_from_slice(&(tcp_segment.len() as u16).to_be_bytes());
+    fn compute_checksum_raw(src_ip: IpAddress, dst_ip: IpAddress, tcp_segment: &[u8]) -> u16 {
+        let pseudo_header =
+            IpAddress::pseudo_header(src_ip, dst_ip, Ipv4Protocol::TCP as u8, tcp_segment.len());
 
         let mut data = pseudo_header;
         data.extend_from_slice(tcp_segment);
@@ -3,15 +3,15 @@ use byteorder::{ByteOrder, NetworkEndian};
 use alloc::vec;
 use alloc::vec::Vec;
 
-use super::Ipv4Address;
+use super::{IpAddress, Ipv4Address, Ipv4Protocol};
 use crate::networking::{Error, Result};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Packet {
     pub src_port: u16,
     pub dst_port: u16,
-    pub src_ip: Ipv4Address,
-    pub dst_ip: Ipv4Address,
+    pub src_ip: IpAddress,
+    pub dst_ip: IpAddress,
     pub length: u16,
     pub checksum: u16,
     pub payload: Vec<u8>,
@@ -24,8 +24,8 @@ impl Packet {
         src_port: u16,
         dst_port: u16,
         payload: Vec<u8>,
-        src_ip: Ipv4Address,
-        dst_ip: Ipv4Address,
+        src_ip: IpAddress,
+        dst_ip: IpAddress,
     ) -> Self {
         let length = Self::HEADER_LEN as u16 + payload.len() as u16;
         Packet {
@@ -59,8 +59,8 @@ impl Packet {
         Ok(Packet {
             src_port,
             dst_port,
-            src_ip: Ipv4Address::new([0, 0, 0, 0]),
-            dst_ip: Ipv4Address::new([0, 0, 0, 0]),
+            src_ip: IpAddress::V4(Ipv4Address::empty()),
+            dst_ip: IpAddress::V4(Ipv4Address::empty()),
             length,
             checksum,
             payload,
@@ -86,13 +86,9 @@ impl Packet {
         buf
     }
 
-    fn compute_checksum_raw(src_ip: Ipv4Address, dst_ip: Ipv4Address, udp_segment: &[u8]) -> u16 {
-        let mut pseudo_header = Vec::with_capacity(12);
-        pseudo_header.extend_from_slice(&src_ip.as_bytes());
-        pseudo_header.extend_from_slice(&dst_ip.as_bytes());
-        pseudo_header.push(0);
-        pseudo_header.push(17); // UDP protocol number
-        pseudo_header.extend_from_slice(&(udp_segment.len() as u16).to_be_bytes());
+    fn compute_checksum_raw(src_ip: IpAddress, dst_ip: IpAddress, udp_segment: &[u8]) -> u16 {
+        let pseudo_header =
+            IpAddress::pseudo_header(src_ip, dst_ip, Ipv4Protocol::UDP as u8, udp_segment.len());
 
         let mut data = pseudo_header;
         data.extend_from_slice(udp_segment);
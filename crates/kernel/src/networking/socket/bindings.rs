@@ -1,32 +1,49 @@
 use core::fmt::{Display, Formatter, Result as FmtResult};
+use core::future::{poll_fn, Future};
 use core::hash::Hash;
+use core::pin::pin;
 use core::sync::atomic::{AtomicU16, Ordering};
+use core::task::{Context, Poll};
 
+use alloc::collections::btree_map::BTreeMap;
 use alloc::vec::Vec;
 
-use crate::networking::repr::Ipv4Address;
+use crate::networking::repr::{IpAddress, Ipv4Address};
+use crate::networking::socket::raw::send_raw_packet;
+use crate::networking::socket::TaggedSocket;
 use crate::networking::{Error, Result};
 
-use crate::device::usb::device::net::get_interface_mut;
+use crate::sync::{time, SpinLock};
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct SocketAddr {
-    pub addr: Ipv4Address,
+    pub addr: IpAddress,
     pub port: u16,
 }
 
 impl SocketAddr {
     pub fn default() -> Self {
         SocketAddr {
-            addr: Ipv4Address::new([0, 0, 0, 0]),
+            addr: IpAddress::V4(Ipv4Address::empty()),
             port: 0,
         }
     }
 }
 
+impl SocketAddr {
+    // whether a socket bound here takes packets sent to `saddr`. sockets are bound to 0.0.0.0 (or
+    // ::), which stands for every interface's address of either version
+    pub fn accepts(&self, saddr: SocketAddr) -> bool {
+        self.port == saddr.port && (self.addr.is_unspecified() || self.addr == saddr.addr)
+    }
+}
+
 impl Display for SocketAddr {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
-        write!(f, "{}:{}", self.addr, self.port)
+        match self.addr {
+            IpAddress::V4(addr) => write!(f, "{}:{}", addr, self.port),
+            IpAddress::V6(addr) => write!(f, "[{}]:{}", addr, self.port),
+        }
     }
 }
 
@@ -34,15 +51,24 @@ impl Display for SocketAddr {
 pub static NEXT_EPHEMERAL: AtomicU16 = AtomicU16::new(32768);
 pub static NEXT_SOCKETFD: AtomicU16 = AtomicU16::new(1);
 
+// every socket, by socketfd. shared by all interfaces
+pub static SOCKETS: SpinLock<BTreeMap<u16, TaggedSocket>> = SpinLock::new(BTreeMap::new());
+
 pub fn send_to(socketfd: u16, payload: Vec<u8>, saddr: SocketAddr) -> Result<()> {
-    let interface = get_interface_mut();
-    let mut sockets = interface.sockets.lock();
+    let mut sockets = SOCKETS.lock();
 
     // 1. check if socket fd is valid if not return error
     let tagged_socket = sockets
         .get_mut(&socketfd)
         .ok_or(Error::InvalidSocket(socketfd))?;
 
+    // raw sockets don't queue, the message is sent now (without holding the lock)
+    if let TaggedSocket::Raw(socket) = tagged_socket {
+        let (protocol, ttl) = (socket.protocol(), socket.ttl());
+        drop(sockets);
+        return send_raw_packet(protocol, ttl, payload, saddr);
+    }
+
     // 2. if socket not bound, bind to ephemeral port (32768–60999)
     if !tagged_socket.is_bound() {
         let ephem_port = NEXT_EPHEMERAL.fetch_add(1, Ordering::SeqCst);
@@ -54,10 +80,9 @@ pub fn send_to(socketfd: u16, payload: Vec<u8>, saddr: SocketAddr) -> Result<()>
     tagged_socket.send_enqueue(payload, saddr)
 }
 
-// TODO: this needs to be blocking
+// takes a packet off the socket, Exhausted if there isn't one yet (see recv_from_async)
 pub fn recv_from(socketfd: u16) -> Result<(Vec<u8>, SocketAddr)> {
-    let interface = get_interface_mut();
-    let mut sockets = interface.sockets.lock();
+    let mut sockets = SOCKETS.lock();
 
     // 1. check if a socketfd is valid if not return error
     let tagged_socket = sockets
@@ -69,13 +94,66 @@ pub fn recv_from(socketfd: u16) -> Result<(Vec<u8>, SocketAddr)> {
         return Err(Error::InvalidSocket(socketfd));
     }
 
-    // 3. blocking recv from socket recv queue
-    tagged_socket.recv() // this needs to be blocking
+    // 3. recv from socket recv queue
+    tagged_socket.recv()
+}
+
+// like send_to, but waits for room in the send queue instead of failing with Exhausted
+pub async fn send_to_async(socketfd: u16, payload: Vec<u8>, saddr: SocketAddr) -> Result<()> {
+    poll_fn(|cx| {
+        let mut sockets = SOCKETS.lock();
+        let tagged_socket = sockets
+            .get_mut(&socketfd)
+            .ok_or(Error::InvalidSocket(socketfd))?;
+
+        if let TaggedSocket::Raw(socket) = tagged_socket {
+            let (protocol, ttl) = (socket.protocol(), socket.ttl());
+            drop(sockets);
+            return Poll::Ready(send_raw_packet(protocol, ttl, payload.clone(), saddr));
+        }
+
+        if !tagged_socket.is_bound() {
+            let ephem_port = NEXT_EPHEMERAL.fetch_add(1, Ordering::SeqCst);
+            tagged_socket.bind(ephem_port);
+        }
+
+        tagged_socket.poll_send(&payload, saddr, cx)
+    })
+    .await
+}
+
+// the poll behind recv_from_async, for futures that wait on other things at the same time
+pub fn poll_recv_from(socketfd: u16, cx: &mut Context<'_>) -> Poll<Result<(Vec<u8>, SocketAddr)>> {
+    let mut sockets = SOCKETS.lock();
+    let Some(tagged_socket) = sockets.get_mut(&socketfd) else {
+        return Poll::Ready(Err(Error::InvalidSocket(socketfd)));
+    };
+
+    if !tagged_socket.is_bound() {
+        return Poll::Ready(Err(Error::InvalidSocket(socketfd)));
+    }
+
+    tagged_socket.poll_recv(cx)
+}
+
+// waits for a packet (udp) or data (tcp). tcp gives NotConnected once the peer has closed its
+// side and everything was read
+pub async fn recv_from_async(socketfd: u16) -> Result<(Vec<u8>, SocketAddr)> {
+    poll_fn(|cx| poll_recv_from(socketfd, cx)).await
+}
+
+// recv_from_async, that gives up with Timeout after `μs` microseconds
+pub async fn recv_from_timeout(socketfd: u16, μs: u64) -> Result<(Vec<u8>, SocketAddr)> {
+    let mut timeout = pin!(time::sleep(μs));
+    poll_fn(|cx| match poll_recv_from(socketfd, cx) {
+        Poll::Ready(result) => Poll::Ready(result),
+        Poll::Pending => timeout.as_mut().poll(cx).map(|_| Err(Error::Timeout)),
+    })
+    .await
 }
 
 pub fn connect(socketfd: u16, saddr: SocketAddr) -> Result<()> {
-    let interface = get_interface_mut();
-    let mut sockets = interface.sockets.lock();
+    let mut sockets = SOCKETS.lock();
 
     // 1. check if a socketfd is valid if not return error
     let tagged_socket = sockets
@@ -85,10 +163,24 @@ pub fn connect(socketfd: u16, saddr: SocketAddr) -> Result<()> {
     tagged_socket.connect(saddr)
 }
 
+// connects, and waits for the handshake to finish
+pub async fn connect_async(socketfd: u16, saddr: SocketAddr) -> Result<()> {
+    connect(socketfd, saddr)?;
+
+    poll_fn(|cx| {
+        let mut sockets = SOCKETS.lock();
+        let tagged_socket = sockets
+            .get_mut(&socketfd)
+            .ok_or(Error::InvalidSocket(socketfd))?;
+
+        tagged_socket.poll_connect(cx)
+    })
+    .await
+}
+
 pub fn listen(socketfd: u16, num_requests: usize) -> Result<()> {
-    let interface = get_interface_mut();
     // 1.check if binded, if not error
-    let mut sockets = interface.sockets.lock();
+    let mut sockets = SOCKETS.lock();
 
     let tagged_socket = sockets
         .get_mut(&socketfd)
@@ -103,9 +195,8 @@ pub fn listen(socketfd: u16, num_requests: usize) -> Result<()> {
 }
 
 pub fn accept(socketfd: u16) -> Result<SocketAddr> {
-    let interface = get_interface_mut();
     // 1. if listener not started, error
-    let mut sockets = interface.sockets.lock();
+    let mut sockets = SOCKETS.lock();
 
     let tagged_socket = sockets
         .get_mut(&socketfd)
@@ -115,14 +206,26 @@ pub fn accept(socketfd: u16) -> Result<SocketAddr> {
     tagged_socket.accept()
 }
 
+// waits for a connection to come in
+pub async fn accept_async(socketfd: u16) -> Result<SocketAddr> {
+    poll_fn(|cx| {
+        let mut sockets = SOCKETS.lock();
+        let tagged_socket = sockets
+            .get_mut(&socketfd)
+            .ok_or(Error::InvalidSocket(socketfd))?;
+
+        tagged_socket.poll_accept(cx)
+    })
+    .await
+}
+
 pub fn bind(socketfd: u16, port: u16) -> Result<()> {
-    let interface = get_interface_mut();
     // 1. check if binding is already in use by another socket
     let bind_addr = SocketAddr {
-        addr: *interface.ipv4_addr,
+        addr: IpAddress::V4(Ipv4Address::empty()),
         port,
     };
-    let mut sockets = interface.sockets.lock();
+    let mut sockets = SOCKETS.lock();
     for (_, socket) in sockets.iter_mut() {
         if socket.binding_equals(bind_addr) {
             return Err(Error::BindingInUse(bind_addr));
@@ -139,3 +242,34 @@ pub fn bind(socketfd: u16, port: u16) -> Result<()> {
 
     Ok(())
 }
+
+// sends a socket's packets out of one interface, regardless of the routing table (ie. broadcasts
+// before the interface has an address)
+pub fn bind_interface(socketfd: u16, interface: usize) -> Result<()> {
+    let mut sockets = SOCKETS.lock();
+
+    let tagged_socket = sockets
+        .get_mut(&socketfd)
+        .ok_or(Error::InvalidSocket(socketfd))?;
+
+    tagged_socket.bind_interface(interface)
+}
+
+// time to live of the packets a (raw) socket sends, None goes back to the default
+pub fn set_ttl(socketfd: u16, ttl: Option<u8>) -> Result<()> {
+    let mut sockets = SOCKETS.lock();
+
+    let tagged_socket = sockets
+        .get_mut(&socketfd)
+        .ok_or(Error::InvalidSocket(socketfd))?;
+
+    tagged_socket.set_ttl(ttl)
+}
+
+pub fn close(socketfd: u16) -> Result<()> {
+    let mut sockets = SOCKETS.lock();
+    sockets
+        .remove(&socketfd)
+        .map(|_| ())
+        .ok_or(Error::InvalidSocket(socketfd))
+}
@@ -5,10 +5,13 @@ pub mod tcp;
 pub mod udp;
 // pub mod unix;
 
-pub use self::bindings::{bind, connect, recv_from, send_to, SocketAddr};
+pub use self::bindings::{
+    accept_async, bind, bind_interface, close, connect, connect_async, poll_recv_from, recv_from,
+    recv_from_async, recv_from_timeout, send_to, send_to_async, set_ttl, SocketAddr, SOCKETS,
+};
 
 pub use self::tagged::TaggedSocket;
 
-pub use self::raw::{RawSocket, RawType};
+pub use self::raw::RawSocket;
 pub use self::tcp::TcpSocket;
 pub use self::udp::UdpSocket;
@@ -1,70 +1,107 @@
-use crate::networking::utils::{ring::Ring, slice::Slice};
-use crate::networking::Result;
+use crate::networking::iface::{ipv4, route_interface};
+use crate::networking::repr::{IpAddress, Ipv4Protocol};
+use crate::networking::socket::bindings::{NEXT_SOCKETFD, SOCKETS};
+use crate::networking::socket::tagged::TaggedSocket;
+use crate::networking::socket::udp::new_ring_packet_buffer;
+use crate::networking::socket::SocketAddr;
+use crate::networking::utils::checksum::internet_checksum;
+use crate::networking::utils::ring::Ring;
+use crate::networking::utils::waker::WakerRegistration;
+use crate::networking::{Error, Result};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum RawType {
-    Ethernet,
-    Ipv4,
-}
+use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
+use core::task::Waker;
+
+pub static RAW_BUFFER_LEN: usize = 64;
 
-// Socket for sending and receiving raw ethernet or IP packets.
-#[derive(Debug)]
+// Socket for sending and receiving whole messages of one ip protocol, headers and all (but not
+// the ip header). only used for icmp over ipv4 for now, which is what ping and traceroute need
 pub struct RawSocket {
-    raw_type: RawType,
-    send_buffer: Ring<Slice<u8>>,
-    recv_buffer: Ring<Slice<u8>>,
+    protocol: Ipv4Protocol,
+    // time to live of the packets sent, the default if None
+    ttl: Option<u8>,
+    recv_buffer: Ring<(Vec<u8>, SocketAddr)>,
+    rx_waker: WakerRegistration,
 }
 
 impl RawSocket {
-    pub fn new(
-        raw_type: RawType,
-        send_buffer: Ring<Slice<u8>>,
-        recv_buffer: Ring<Slice<u8>>,
-    ) -> RawSocket {
-        RawSocket {
-            raw_type,
-            send_buffer,
-            recv_buffer,
-        }
-    }
+    pub fn new(protocol: Ipv4Protocol) -> u16 {
+        let socket = RawSocket {
+            protocol,
+            ttl: None,
+            recv_buffer: new_ring_packet_buffer(RAW_BUFFER_LEN),
+            rx_waker: WakerRegistration::new(),
+        };
 
-    pub fn send(&mut self, buffer_len: usize) -> Result<&mut [u8]> {
-        self.send_buffer.enqueue_maybe(|buffer| {
-            buffer.try_resize(buffer_len, 0)?;
+        let socketfd = NEXT_SOCKETFD.fetch_add(1, Ordering::SeqCst);
+        let mut sockets = SOCKETS.lock();
+        sockets.insert(socketfd, TaggedSocket::Raw(socket));
 
-            for i in 0..buffer_len {
-                buffer[i] = 0;
-            }
+        socketfd
+    }
 
-            return Ok(&mut buffer[..buffer_len]);
-        })
+    pub fn protocol(&self) -> Ipv4Protocol {
+        self.protocol
     }
 
-    // Dequeues a received packet from the socket.
-    pub fn recv(&mut self) -> Result<&[u8]> {
-        self.recv_buffer.dequeue_with(|buffer| &buffer[..])
+    pub fn ttl(&self) -> Option<u8> {
+        self.ttl
     }
 
-    // Dequeues a packet enqueued for sending via a function f.
-    //
-    // The packet is only dequeued if f does not return an error.
-    pub fn send_dequeue<F, R>(&mut self, f: F) -> Result<R>
-    where
-        F: FnOnce(&[u8]) -> Result<R>,
-    {
-        self.send_buffer.dequeue_maybe(|buffer| f(&buffer[..]))
+    pub fn set_ttl(&mut self, ttl: Option<u8>) {
+        self.ttl = ttl;
     }
 
-    // Enqueues a packet for receiving.
-    pub fn recv_enqueue(&mut self, packet: &[u8]) -> Result<()> {
-        self.recv_buffer.enqueue_maybe(|buffer| {
-            buffer.try_resize(packet.len(), 0)?;
-            buffer.copy_from_slice(packet);
+    // Dequeues a received message along with the address it came from.
+    pub fn recv(&mut self) -> Result<(Vec<u8>, SocketAddr)> {
+        self.recv_buffer
+            .dequeue_with(|entry: &mut (Vec<u8>, SocketAddr)| {
+                let (buffer, addr) = entry;
+                (buffer.clone(), *addr)
+            })
+    }
+
+    // Enqueues a message for receiving, every raw socket of the protocol gets its own copy.
+    pub fn recv_enqueue(&mut self, payload: Vec<u8>, sender: SocketAddr) -> Result<()> {
+        self.recv_buffer.enqueue_maybe(|(buffer, addr)| {
+            *buffer = payload;
+            *addr = sender;
             Ok(())
-        })
+        })?;
+        self.rx_waker.wake();
+        Ok(())
     }
 
-    pub fn raw_type(&self) -> RawType {
-        self.raw_type
+    pub fn register_recv_waker(&mut self, waker: &Waker) {
+        self.rx_waker.register(waker);
     }
+
+    // Returns the number of messages enqueued for receiving.
+    pub fn num_recv_enqueued(&self) -> usize {
+        self.recv_buffer.len()
+    }
+}
+
+// sends one message from a raw socket. there's no send queue, the message goes out as soon as it's
+// given, so ping times the network rather than the socket loop. the checksum is filled in here
+pub fn send_raw_packet(
+    protocol: Ipv4Protocol,
+    ttl: Option<u8>,
+    mut payload: Vec<u8>,
+    dest: SocketAddr,
+) -> Result<()> {
+    let IpAddress::V4(dst_addr) = dest.addr else {
+        return Err(Error::Unsupported);
+    };
+    if payload.len() < 4 {
+        return Err(Error::InvalidLength);
+    }
+
+    payload[2..4].copy_from_slice(&[0, 0]);
+    let checksum = internet_checksum(&payload);
+    payload[2..4].copy_from_slice(&checksum.to_be_bytes());
+
+    let interface = route_interface(dest.addr)?;
+    ipv4::send_ipv4_packet_with_ttl(interface, payload, protocol, dst_addr, ttl)
 }
@@ -1,74 +1,100 @@
 use crate::networking::iface::Interface;
-use crate::networking::socket::{SocketAddr, TcpSocket, UdpSocket};
+use crate::networking::repr::TcpPacket;
+use crate::networking::socket::tcp::TcpState;
+use crate::networking::socket::{RawSocket, SocketAddr, TcpSocket, UdpSocket};
 use crate::networking::{Error, Result};
 
-use crate::device::usb::device::net::get_interface_mut;
-
+use alloc::boxed::Box;
 use alloc::vec::Vec;
+use core::task::{Context, Poll, Waker};
 
 pub enum TaggedSocket {
-    // Raw(RawSocket),
+    Raw(RawSocket),
     Udp(UdpSocket),
-    Tcp(TcpSocket),
+    Tcp(Box<TcpSocket>),
 }
 
 impl TaggedSocket {
     pub fn is_bound(&mut self) -> bool {
         match self {
-            // TaggedSocket::Raw(socket) => socket.accepts(pair),
+            // raw sockets have no ports, they get every message of their protocol
+            TaggedSocket::Raw(_socket) => true,
             TaggedSocket::Udp(socket) => socket.is_bound(),
             TaggedSocket::Tcp(socket) => socket.is_bound(),
         }
     }
 
     pub fn bind(&mut self, port: u16) {
-        let interface = get_interface_mut();
         match self {
-            // TaggedSocket::Raw(socket) => socket.accepts(pair),
-            TaggedSocket::Udp(socket) => socket.bind(interface, port),
-            TaggedSocket::Tcp(socket) => socket.bind(interface, port),
+            TaggedSocket::Raw(_socket) => {}
+            TaggedSocket::Udp(socket) => socket.bind(port),
+            TaggedSocket::Tcp(socket) => socket.bind(port),
+        }
+    }
+
+    // TODO: tcp could do this too, but a connection only ever goes one way anyway
+    pub fn bind_interface(&mut self, interface: usize) -> Result<()> {
+        match self {
+            TaggedSocket::Udp(socket) => {
+                socket.bind_interface(interface);
+                Ok(())
+            }
+            TaggedSocket::Raw(_) | TaggedSocket::Tcp(_) => Err(Error::Unsupported),
+        }
+    }
+
+    // time to live of the packets sent, None for the default
+    pub fn set_ttl(&mut self, ttl: Option<u8>) -> Result<()> {
+        match self {
+            TaggedSocket::Raw(socket) => {
+                socket.set_ttl(ttl);
+                Ok(())
+            }
+            _ => Err(Error::Unsupported),
         }
     }
 
-    pub fn send(&mut self, interface: &mut Interface) -> Result<()> {
+    pub fn send(&mut self) -> Result<()> {
         match self {
-            // TaggedSocket::Raw(socket) => socket.send(),
-            TaggedSocket::Udp(socket) => socket.send(interface),
-            TaggedSocket::Tcp(socket) => socket.send(interface),
+            // raw sockets send right away, see send_raw_packet
+            TaggedSocket::Raw(_socket) => Ok(()),
+            TaggedSocket::Udp(socket) => socket.send(),
+            TaggedSocket::Tcp(socket) => socket.send(),
         }
     }
 
     pub fn send_enqueue(&mut self, payload: Vec<u8>, saddr: SocketAddr) -> Result<()> {
         match self {
-            // TaggedSocket::Raw(socket) => socket.queue_send(payload, saddr),
+            TaggedSocket::Raw(_socket) => Err(Error::Unsupported),
             TaggedSocket::Udp(socket) => socket.send_enqueue(payload, saddr),
             TaggedSocket::Tcp(socket) => socket.send_enqueue(payload, saddr),
         }
     }
 
-    // TODO: this is so ugl lol
-    pub fn recv_enqueue(
+    pub fn recv_enqueue(&mut self, payload: Vec<u8>, saddr: SocketAddr) -> Result<()> {
+        match self {
+            TaggedSocket::Raw(socket) => socket.recv_enqueue(payload, saddr),
+            TaggedSocket::Udp(socket) => socket.recv_enqueue(payload, saddr),
+            // tcp needs the whole header, see recv_segment
+            TaggedSocket::Tcp(_socket) => Err(Error::Ignored),
+        }
+    }
+
+    pub fn recv_segment(
         &mut self,
-        seq_num: u32,
-        ack_num: u32,
-        flags: u8,
-        payload: Vec<u8>,
+        interface: &mut Interface,
+        segment: &TcpPacket,
         saddr: SocketAddr,
     ) -> Result<()> {
-        let interface = get_interface_mut();
-
         match self {
-            // TaggedSocket::Raw(socket) => socket.queue_recv(payload, saddr),
-            TaggedSocket::Udp(socket) => socket.recv_enqueue(payload, saddr),
-            TaggedSocket::Tcp(socket) => {
-                socket.recv_enqueue(interface, seq_num, ack_num, flags, payload, saddr)
-            }
+            TaggedSocket::Tcp(socket) => socket.recv_segment(interface, segment, saddr),
+            _ => Err(Error::Ignored),
         }
     }
 
     pub fn recv(&mut self) -> Result<(Vec<u8>, SocketAddr)> {
         match self {
-            // TaggedSocket::Raw(socket) => socket.recv(),
+            TaggedSocket::Raw(socket) => socket.recv(),
             TaggedSocket::Udp(socket) => socket.recv(),
             TaggedSocket::Tcp(socket) => socket.recv(),
         }
@@ -76,38 +102,103 @@ impl TaggedSocket {
 
     pub fn binding_equals(&mut self, saddr: SocketAddr) -> bool {
         match self {
-            // TaggedSocket::Raw(socket) => socket.recv(),
+            TaggedSocket::Raw(_socket) => false,
             TaggedSocket::Udp(socket) => socket.binding_equals(saddr),
             TaggedSocket::Tcp(socket) => socket.binding_equals(saddr),
         }
     }
 
-    // TODO: should block
+    // doesn't wait for the handshake, see poll_connect
     // TODO: udp just throws error for now, but can be used like berkley posix to instead set the
     // default destination as well in the future
     pub fn connect(&mut self, saddr: SocketAddr) -> Result<()> {
-        let interface = get_interface_mut();
         match self {
-            // TaggedSocket::Raw(socket) => socket.recv(),
-            TaggedSocket::Udp(_socket) => Err(Error::Ignored),
-            TaggedSocket::Tcp(socket) => socket.connect(interface, saddr),
+            TaggedSocket::Raw(_) | TaggedSocket::Udp(_) => Err(Error::Ignored),
+            TaggedSocket::Tcp(socket) => socket.connect(saddr),
         }
     }
 
     pub fn listen(&mut self, num_req: usize) -> Result<()> {
-        let interface = get_interface_mut();
         match self {
-            // TaggedSocket::Raw(socket) => socket.recv(),
-            TaggedSocket::Udp(_socket) => Err(Error::Ignored),
-            TaggedSocket::Tcp(socket) => socket.listen(interface, num_req),
+            TaggedSocket::Raw(_) | TaggedSocket::Udp(_) => Err(Error::Ignored),
+            TaggedSocket::Tcp(socket) => socket.listen(num_req),
         }
     }
 
     pub fn accept(&mut self) -> Result<SocketAddr> {
         match self {
-            // TaggedSocket::Raw(socket) => socket.recv(),
-            TaggedSocket::Udp(_socket) => Err(Error::Ignored),
+            TaggedSocket::Raw(_) | TaggedSocket::Udp(_) => Err(Error::Ignored),
             TaggedSocket::Tcp(socket) => socket.accept(),
         }
     }
+
+    fn register_recv_waker(&mut self, waker: &Waker) {
+        match self {
+            TaggedSocket::Raw(socket) => socket.register_recv_waker(waker),
+            TaggedSocket::Udp(socket) => socket.register_recv_waker(waker),
+            TaggedSocket::Tcp(socket) => socket.register_recv_waker(waker),
+        }
+    }
+
+    fn register_send_waker(&mut self, waker: &Waker) {
+        match self {
+            // never has to wait to send
+            TaggedSocket::Raw(_socket) => {}
+            TaggedSocket::Udp(socket) => socket.register_send_waker(waker),
+            TaggedSocket::Tcp(socket) => socket.register_send_waker(waker),
+        }
+    }
+
+    // the poll_ versions of recv, send_enqueue and accept wait where those would say Exhausted:
+    // the task is woken when it's worth trying again
+
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Result<(Vec<u8>, SocketAddr)>> {
+        match self.recv() {
+            Err(Error::Exhausted) => {
+                self.register_recv_waker(cx.waker());
+                Poll::Pending
+            }
+            result => Poll::Ready(result),
+        }
+    }
+
+    pub fn poll_send(
+        &mut self,
+        payload: &[u8],
+        saddr: SocketAddr,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<()>> {
+        match self.send_enqueue(payload.to_vec(), saddr) {
+            Err(Error::Exhausted) => {
+                self.register_send_waker(cx.waker());
+                Poll::Pending
+            }
+            result => Poll::Ready(result),
+        }
+    }
+
+    pub fn poll_accept(&mut self, cx: &mut Context<'_>) -> Poll<Result<SocketAddr>> {
+        match self.accept() {
+            Err(Error::Exhausted) => {
+                self.register_recv_waker(cx.waker());
+                Poll::Pending
+            }
+            result => Poll::Ready(result),
+        }
+    }
+
+    // ready once the handshake started by connect is over, with NotConnected if it failed
+    pub fn poll_connect(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        match self {
+            TaggedSocket::Raw(_) | TaggedSocket::Udp(_) => Poll::Ready(Err(Error::Ignored)),
+            TaggedSocket::Tcp(socket) => match socket.get_state() {
+                TcpState::SynSent | TcpState::SynReceived => {
+                    socket.register_send_waker(cx.waker());
+                    Poll::Pending
+                }
+                TcpState::Closed => Poll::Ready(Err(Error::NotConnected)),
+                _ => Poll::Ready(Ok(())),
+            },
+        }
+    }
 }
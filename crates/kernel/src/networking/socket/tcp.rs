@@ -1,21 +1,22 @@
-use crate::device::usb::device::net::get_interface_mut;
-use crate::networking::iface::{tcp, Interface};
-use crate::networking::socket::bindings::{NEXT_EPHEMERAL, NEXT_SOCKETFD};
+use crate::networking::iface::{route_interface, tcp, Interface};
+use crate::networking::repr::{IpAddress, Ipv4Address, TcpPacket};
+use crate::networking::socket::bindings::{NEXT_EPHEMERAL, NEXT_SOCKETFD, SOCKETS};
 use crate::networking::socket::tagged::TaggedSocket;
 use crate::networking::socket::SocketAddr;
-use crate::networking::utils::ring::Ring;
+use crate::networking::utils::assembler::Assembler;
+use crate::networking::utils::rtt::RttEstimator;
+use crate::networking::utils::stream::StreamBuffer;
+use crate::networking::utils::waker::WakerRegistration;
 use crate::networking::{Error, Result};
-use alloc::vec;
+use crate::sync;
+use alloc::boxed::Box;
 use alloc::vec::Vec;
 use core::sync::atomic::Ordering;
+use core::task::Waker;
 
-fn new_ring_packet_buffer(capacity: usize) -> Ring<(Vec<u8>, SocketAddr)> {
-    let default_entry = (Vec::new(), SocketAddr::default());
-    let buffer = vec![default_entry; capacity];
-    Ring::from(buffer)
-}
-
-pub static TCP_BUFFER_LEN: usize = 128;
+// size of the send and receive streams in bytes. our receive window is advertised from the free
+// space in the receive stream, so without window scaling it can't be any larger than this
+pub static TCP_BUFFER_LEN: usize = 65535;
 
 // flags
 pub const TCP_FLAG_FIN: u8 = 0x01;
@@ -25,9 +26,46 @@ pub const TCP_FLAG_PSH: u8 = 0x08;
 pub const TCP_FLAG_ACK: u8 = 0x10;
 
 const INITIAL_SEQ_NUMBER: u32 = 1000; // TODO: maybe do a random initialization
-const DEFAULT_WINDOW_SIZE: u16 = 8192;
 
-#[derive(PartialEq, Eq)]
+// segment size to assume when the peer doesn't send the option (rfc 1122)
+const DEFAULT_MSS: u16 = 536;
+// ip and tcp headers without options, subtracted from the mtu for our own mss
+const IPV4_HEADERS_LEN: usize = 40;
+const IPV6_HEADERS_LEN: usize = 60;
+// how many segments can be held while waiting for the ones before them
+const MAX_OUT_OF_ORDER: usize = 64;
+// the connection is dropped after this many retransmission timeouts in a row
+const MAX_RETRANSMITS: u32 = 8;
+// duplicate acks that trigger a fast retransmit
+const DUP_ACK_THRESHOLD: u32 = 3;
+// time spent in TIME_WAIT, in microseconds. this should be twice the maximum segment lifetime,
+// but minutes is a long time to hold on to a port
+const TIME_WAIT_LEN: u64 = 4_000_000;
+
+fn now() -> u64 {
+    sync::get_time() as u64
+}
+
+// comparisons in sequence space, which wraps around
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+fn seq_le(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) <= 0
+}
+
+// initial congestion window (rfc 5681)
+fn initial_cwnd(mss: u16) -> u32 {
+    let mss = mss as u32;
+    match mss {
+        2191.. => 2 * mss,
+        1096..=2190 => 3 * mss,
+        _ => 4 * mss,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TcpState {
     Closed,      // initial state, no connection
     SynSent,     // connect initiated, waiting for SYN-ACK
@@ -48,67 +86,126 @@ pub struct TcpSocket {
     pending_conn: Vec<SocketAddr>,
     max_pending: usize,
     connected: bool,
-    send_buffer: Ring<(Vec<u8>, SocketAddr)>,
-    recv_buffer: Ring<(Vec<u8>, SocketAddr)>,
+    // bytes from snd_una onwards: first the ones in flight, then the ones not sent yet
+    send_buffer: StreamBuffer,
+    // bytes received in order, waiting to be read
+    recv_buffer: StreamBuffer,
+    // segments received past a gap
+    assembler: Assembler,
 
     state: TcpState,
     remote_addr: Option<SocketAddr>,
-    seq_number: u32,
-    ack_number: u32,
-    window_size: u16,
+
+    // send sequence space, see rfc 793 section 3.2
+    iss: u32,
+    snd_una: u32,
+    snd_nxt: u32,
+    snd_wnd: u32,
+    // close was called, a FIN goes out after the last byte of the send buffer
+    fin_queued: bool,
+
+    // receive sequence space
+    rcv_nxt: u32,
+    // sequence number of the peer's FIN, if it arrived before all of the data in front of it
+    remote_fin: Option<u32>,
+    // the window we last advertised was too small to be useful, so tell the peer once reads make
+    // room again
+    window_update: bool,
+
+    // largest segment we send
+    mss: u16,
+    rtt: RttEstimator,
+    // when the oldest unacknowledged segment will be retransmitted (or the window probed)
+    retransmit_at: Option<u64>,
+    retransmits: u32,
+    // one segment at a time is timed, as the ack number that covers it and when it was sent
+    rtt_probe: Option<(u32, u64)>,
+
+    // congestion control, reno with fast retransmit and recovery (rfc 5681)
+    cwnd: u32,
+    ssthresh: u32,
+    dup_acks: u32,
+    // snd_nxt when fast recovery started, it lasts until that's acknowledged
+    recover: Option<u32>,
+
+    time_wait_until: Option<u64>,
+
+    // tasks waiting to receive or accept, and to send or connect
+    rx_waker: WakerRegistration,
+    tx_waker: WakerRegistration,
 }
 
 impl TcpSocket {
     pub fn new() -> u16 {
-        let interface = get_interface_mut();
         let socket = TcpSocket {
-            binding: SocketAddr {
-                addr: *interface.ipv4_addr,
-                port: 0,
-            },
+            binding: SocketAddr::default(),
             is_bound: false,
             is_listener: false,
             pending_conn: Vec::new(),
             max_pending: 0,
             connected: false,
-            send_buffer: new_ring_packet_buffer(TCP_BUFFER_LEN),
-            recv_buffer: new_ring_packet_buffer(TCP_BUFFER_LEN),
+            send_buffer: StreamBuffer::new(TCP_BUFFER_LEN),
+            recv_buffer: StreamBuffer::new(TCP_BUFFER_LEN),
+            assembler: Assembler::new(MAX_OUT_OF_ORDER),
 
             state: TcpState::Closed,
             remote_addr: None,
-            seq_number: INITIAL_SEQ_NUMBER,
-            ack_number: 0,
-            window_size: DEFAULT_WINDOW_SIZE,
+
+            iss: INITIAL_SEQ_NUMBER,
+            snd_una: INITIAL_SEQ_NUMBER,
+            snd_nxt: INITIAL_SEQ_NUMBER,
+            snd_wnd: 0,
+            fin_queued: false,
+
+            rcv_nxt: 0,
+            remote_fin: None,
+            window_update: false,
+
+            mss: DEFAULT_MSS,
+            rtt: RttEstimator::new(),
+            retransmit_at: None,
+            retransmits: 0,
+            rtt_probe: None,
+
+            cwnd: initial_cwnd(DEFAULT_MSS),
+            ssthresh: u32::MAX,
+            dup_acks: 0,
+            recover: None,
+
+            time_wait_until: None,
+
+            rx_waker: WakerRegistration::new(),
+            tx_waker: WakerRegistration::new(),
         };
 
         let socketfd = NEXT_SOCKETFD.fetch_add(1, Ordering::SeqCst);
-        let mut sockets = interface.sockets.lock();
-        sockets.insert(socketfd, TaggedSocket::Tcp(socket));
+        let mut sockets = SOCKETS.lock();
+        sockets.insert(socketfd, TaggedSocket::Tcp(Box::new(socket)));
         socketfd
     }
 
     pub fn binding_equals(&self, saddr: SocketAddr) -> bool {
-        self.binding == saddr
+        self.binding.accepts(saddr)
     }
 
     pub fn is_bound(&self) -> bool {
         self.is_bound
     }
 
-    pub fn bind(&mut self, interface: &mut Interface, port: u16) {
+    pub fn bind(&mut self, port: u16) {
         self.is_bound = true;
         let bind_addr = SocketAddr {
-            addr: *interface.ipv4_addr,
+            addr: IpAddress::V4(Ipv4Address::empty()),
             port,
         };
         self.binding = bind_addr;
     }
 
-    pub fn listen(&mut self, interface: &mut Interface, num_max_requests: usize) -> Result<()> {
+    pub fn listen(&mut self, num_max_requests: usize) -> Result<()> {
         if !self.is_bound {
             // bind to ephemeral if not bound
             let ephemeral_port = NEXT_EPHEMERAL.fetch_add(1, Ordering::SeqCst);
-            self.bind(interface, ephemeral_port as u16);
+            self.bind(ephemeral_port as u16);
         }
 
         self.is_listener = true;
@@ -132,36 +229,22 @@ impl TcpSocket {
         }
     }
 
-    pub fn connect(&mut self, interface: &mut Interface, saddr: SocketAddr) -> Result<()> {
-        // make sure we're not already connected
-        // match self.state {
-        //     TcpState::Closed => {},
-        //     _ => return Err(Error::AlreadyConnected),
-        // }
+    pub fn connect(&mut self, saddr: SocketAddr) -> Result<()> {
+        let interface = route_interface(saddr.addr)?;
 
         // if not already bound, bind to an ephemeral port
         if !self.is_bound {
             let ephemeral_port = NEXT_EPHEMERAL.fetch_add(1, Ordering::SeqCst);
-            self.bind(interface, ephemeral_port as u16);
+            self.bind(ephemeral_port as u16);
         }
         self.is_bound = true;
 
+        self.reset();
         self.remote_addr = Some(saddr);
-
-        let flags = TCP_FLAG_SYN;
-        tcp::send_tcp_packet(
-            interface,
-            self.binding.port,
-            saddr.port,
-            self.seq_number,
-            0, // no ACK yet
-            flags,
-            self.window_size,
-            saddr.addr,
-            Vec::new(), // no payload
-        )?;
-
         self.state = TcpState::SynSent;
+        self.snd_nxt = self.iss.wrapping_add(1); // SYN consumes one sequence number
+        self.send_segment(interface, self.iss, TCP_FLAG_SYN, Vec::new())?;
+        self.arm_retransmit(now());
 
         println!("[!] sent syn");
 
@@ -169,327 +252,622 @@ impl TcpSocket {
     }
 
     pub fn send_enqueue(&mut self, payload: Vec<u8>, dest: SocketAddr) -> Result<()> {
-        if self.state != TcpState::Established {
+        if !matches!(self.state, TcpState::Established | TcpState::CloseWait) || self.fin_queued {
             return Err(Error::NotConnected);
         }
 
         // verify the destination matches the connected remote address
-        if let Some(remote) = self.remote_addr {
-            if remote != dest {
-                return Err(Error::NotConnected);
-            }
-        } else {
+        if self.remote_addr != Some(dest) {
             return Err(Error::NotConnected);
         }
 
-        self.send_buffer.enqueue_maybe(|(buffer, addr)| {
-            *buffer = payload;
-            *addr = dest;
-            Ok(())
-        })
+        // all or nothing, so callers can retry the same payload later. more than the whole buffer
+        // would never fit
+        if payload.len() > TCP_BUFFER_LEN {
+            return Err(Error::InvalidLength);
+        }
+        if payload.len() > self.send_buffer.free() {
+            return Err(Error::Exhausted);
+        }
+        self.send_buffer.write(&payload);
+        Ok(())
     }
 
-    pub fn send(&mut self, interface: &mut Interface) -> Result<()> {
-        if self.state != TcpState::Established {
-            return Err(Error::NotConnected);
-        }
+    // drives the connection: fires expired timers, then sends as much queued data as the peer's
+    // window and the congestion window allow. called periodically by the socket loop
+    pub fn send(&mut self) -> Result<()> {
+        let Some(remote) = self.remote_addr else {
+            return Ok(());
+        };
+        let interface = route_interface(remote.addr)?;
+        let now = now();
 
-        match self.state {
-            TcpState::Established => {
-                // Process outgoing data
-                loop {
-                    match self.send_buffer.dequeue_with(|entry| {
-                        let (payload, addr) = entry;
-                        (payload.clone(), *addr)
-                    }) {
-                        Ok((payload, dest)) => {
-                            // Send with appropriate TCP flags
-                            tcp::send_tcp_packet(
-                                interface,
-                                self.binding.port,
-                                dest.port,
-                                self.seq_number,
-                                self.ack_number,
-                                TCP_FLAG_ACK | TCP_FLAG_PSH, // PSH to push data to application layer
-                                self.window_size,
-                                dest.addr,
-                                payload.clone(),
-                            )?;
-
-                            // Update sequence number
-                            self.seq_number += payload.len() as u32;
-                        }
-                        Err(Error::Exhausted) => break,
-                        Err(e) => return Err(e),
-                    }
-                }
-                Ok(())
+        if self.state == TcpState::TimeWait {
+            if self.time_wait_until.is_some_and(|until| now >= until) {
+                self.reset();
             }
-            _ => Err(Error::NotConnected),
+            return Ok(());
         }
+
+        if self.retransmit_at.is_some_and(|at| now >= at) {
+            self.retransmit_timeout(interface, now)?;
+        }
+
+        if self.window_update && self.recv_buffer.free() >= self.mss as usize {
+            self.window_update = false;
+            self.send_ack(interface)?;
+        }
+
+        self.transmit(interface, now)
     }
 
+    // returns everything received so far, in order. Exhausted if more may still come, NotConnected
+    // once the peer has finished sending (or the connection is gone) and everything was read
     pub fn recv(&mut self) -> Result<(Vec<u8>, SocketAddr)> {
-        self.recv_buffer
-            .dequeue_with(|entry: &mut (Vec<u8>, SocketAddr)| {
-                let (buffer, addr) = entry;
-                (buffer.clone(), addr.clone())
-            })
+        if self.recv_buffer.is_empty() {
+            return match self.state {
+                TcpState::SynSent
+                | TcpState::SynReceived
+                | TcpState::Established
+                | TcpState::FinWait1
+                | TcpState::FinWait2 => Err(Error::Exhausted),
+                TcpState::Closed if self.is_listener => Err(Error::Exhausted),
+                _ => Err(Error::NotConnected),
+            };
+        }
+        let remote = self.remote_addr.unwrap_or(SocketAddr::default());
+        Ok((self.recv_buffer.read(TCP_BUFFER_LEN), remote))
     }
 
-    // Enqueues a packet for receiving and handles TCP state machine
-    pub fn recv_enqueue(
+    // handles a segment sent to this socket: the state machine, acknowledgements and data
+    pub fn recv_segment(
         &mut self,
         interface: &mut Interface,
-        seq_number: u32,
-        ack_number: u32,
-        flags: u8,
-        payload: Vec<u8>,
+        segment: &TcpPacket,
         sender: SocketAddr,
     ) -> Result<()> {
-        // Handle connection establishment if in SYN_SENT state
-        if self.state == TcpState::SynSent {
-            // Check if this is a valid remote endpoint response
-            if let Some(remote) = self.remote_addr {
-                if remote == sender {
-                    // This is a response to our SYN
-                    if (flags & (TCP_FLAG_SYN | TCP_FLAG_ACK)) == (TCP_FLAG_SYN | TCP_FLAG_ACK) {
-                        // Received SYN-ACK, update ACK number
-                        self.ack_number = seq_number + 1;
-
-                        // Send ACK to complete three-way handshake
-                        tcp::send_tcp_packet(
-                            interface,
-                            self.binding.port,
-                            sender.port,
-                            self.seq_number + 1, // SYN consumes one sequence number
-                            self.ack_number,
-                            TCP_FLAG_ACK,
-                            self.window_size,
-                            sender.addr,
-                            Vec::new(),
-                        )?;
-
-                        // Update state and sequence number
-                        self.state = TcpState::Established;
-                        self.connected = true;
-                        self.seq_number += 1; // SYN consumes one sequence number
-
-                        return Ok(());
-                    }
+        let flags = segment.flags;
+
+        // a listening socket takes on the first connection that comes in
+        if self.is_listener && self.state == TcpState::Closed {
+            if flags & TCP_FLAG_SYN != 0 && flags & (TCP_FLAG_ACK | TCP_FLAG_RST) == 0 {
+                self.reset();
+                self.remote_addr = Some(sender);
+                self.rcv_nxt = segment.seq_number.wrapping_add(1);
+                self.snd_wnd = segment.window_size as u32;
+                self.negotiate_mss(interface, segment.mss);
+                self.state = TcpState::SynReceived;
+                self.snd_nxt = self.iss.wrapping_add(1);
+                self.send_segment(interface, self.iss, TCP_FLAG_SYN | TCP_FLAG_ACK, Vec::new())?;
+                self.arm_retransmit(now());
+            }
+            return Ok(());
+        }
+
+        if self.remote_addr != Some(sender) {
+            return Err(Error::Ignored);
+        }
+
+        if flags & TCP_FLAG_RST != 0 {
+            // a reset is only believed if it's in the window, or acks our SYN
+            let acceptable = match self.state {
+                TcpState::SynSent => segment.ack_number == self.snd_nxt,
+                _ => self.in_window(segment.seq_number),
+            };
+            if acceptable {
+                println!("[!] tcp connection reset by {}", sender);
+                self.reset();
+            }
+            return Ok(());
+        }
+
+        match self.state {
+            TcpState::Closed => return Err(Error::NotConnected),
+            TcpState::SynSent => {
+                let syn_ack = TCP_FLAG_SYN | TCP_FLAG_ACK;
+                if flags & syn_ack == syn_ack && segment.ack_number == self.snd_nxt {
+                    self.rcv_nxt = segment.seq_number.wrapping_add(1);
+                    self.negotiate_mss(interface, segment.mss);
+                    self.established(segment, now());
+                    self.send_ack(interface)?;
                 }
+                return Ok(());
             }
-        } else if self.state == TcpState::Closed {
-            // Handle incoming SYN for passive open (if we're listening)
-            if flags & TCP_FLAG_SYN != 0 && flags & TCP_FLAG_ACK == 0 {
-                // This would be for a server socket - not handling passive open in this example
-                // But this is where you would handle it
+            TcpState::SynReceived => {
+                if flags & TCP_FLAG_SYN != 0 {
+                    // our SYN-ACK was lost, the retransmit timer will send it again
+                    return Ok(());
+                }
+                if flags & TCP_FLAG_ACK == 0 || segment.ack_number != self.snd_nxt {
+                    return Ok(());
+                }
+                self.established(segment, now());
+                if self.pending_conn.len() < self.max_pending.max(1) {
+                    self.pending_conn.push(sender);
+                    self.rx_waker.wake();
+                }
+                // the ACK may carry data too
             }
+            _ => {}
         }
 
-        // Process state transitions based on TCP flags
-        self.process_tcp_state_transitions(interface, flags, seq_number, ack_number, sender)?;
+        self.process_ack(interface, segment)?;
+        self.process_data(interface, segment)
+    }
 
-        // Now that we've handled any state transitions, enqueue actual data for user
-        // Only enqueue if we're in established state and there's actual data
-        if self.state == TcpState::Established && (payload.len() > 0) {
-            // Only enqueue if there's actual data
-            self.recv_buffer.enqueue_maybe(|(buffer, addr)| {
-                *buffer = payload.clone();
-                *addr = sender;
+    // Returns the number of bytes waiting to be sent or acknowledged.
+    pub fn num_send_enqueued(&self) -> usize {
+        self.send_buffer.len()
+    }
+
+    // Returns the number of bytes waiting to be read.
+    pub fn num_recv_enqueued(&self) -> usize {
+        self.recv_buffer.len()
+    }
+
+    // Close the connection gracefully, the FIN goes out once everything before it has been sent
+    pub fn close(&mut self) -> Result<()> {
+        // nothing to close without a connection
+        let remote = self.remote_addr.ok_or(Error::Malformed)?;
+        let interface = route_interface(remote.addr)?;
+
+        match self.state {
+            TcpState::Established => {
+                self.fin_queued = true;
+                self.state = TcpState::FinWait1;
+                self.transmit(interface, now())
+            }
+            TcpState::CloseWait => {
+                self.fin_queued = true;
+                self.state = TcpState::LastAck;
+                self.transmit(interface, now())
+            }
+            TcpState::SynSent | TcpState::SynReceived => {
+                self.reset();
                 Ok(())
-            })?;
-
-            // Update ACK number and send ACK for the data
-            self.ack_number = seq_number + payload.len() as u32;
-
-            // Send ACK for received data
-            if let Some(remote) = self.remote_addr {
-                tcp::send_tcp_packet(
-                    interface,
-                    self.binding.port,
-                    remote.port,
-                    self.seq_number,
-                    self.ack_number,
-                    TCP_FLAG_ACK,
-                    self.window_size,
-                    remote.addr,
-                    Vec::new(),
-                )?;
             }
+            _ => Err(Error::Malformed),
         }
+    }
 
-        Ok(())
+    pub fn get_state(&self) -> &TcpState {
+        &self.state
+    }
+
+    // wakes the task when there's data to read, a connection to accept, or the peer is done
+    pub fn register_recv_waker(&mut self, waker: &Waker) {
+        self.rx_waker.register(waker);
+    }
+
+    // wakes the task when there's room to send more, or the connection is up (or failed)
+    pub fn register_send_waker(&mut self, waker: &Waker) {
+        self.tx_waker.register(waker);
+    }
+
+    // forgets the current connection, if any
+    fn reset(&mut self) {
+        self.state = TcpState::Closed;
+        self.connected = false;
+        self.remote_addr = None;
+        self.send_buffer.clear();
+        self.recv_buffer.clear();
+        self.assembler.clear();
+
+        self.snd_una = self.iss;
+        self.snd_nxt = self.iss;
+        self.snd_wnd = 0;
+        self.fin_queued = false;
+        self.rcv_nxt = 0;
+        self.remote_fin = None;
+        self.window_update = false;
+
+        self.mss = DEFAULT_MSS;
+        self.rtt = RttEstimator::new();
+        self.retransmit_at = None;
+        self.retransmits = 0;
+        self.rtt_probe = None;
+
+        self.cwnd = initial_cwnd(DEFAULT_MSS);
+        self.ssthresh = u32::MAX;
+        self.dup_acks = 0;
+        self.recover = None;
+        self.time_wait_until = None;
+
+        // anyone waiting on the old connection will find it closed
+        self.rx_waker.wake();
+        self.tx_waker.wake();
+    }
+
+    // our SYN was acknowledged
+    fn established(&mut self, segment: &TcpPacket, now: u64) {
+        self.state = TcpState::Established;
+        self.connected = true;
+        self.snd_una = segment.ack_number;
+        self.snd_wnd = segment.window_size as u32;
+        self.retransmit_at = None;
+        if self.retransmits == 0 {
+            // the handshake makes a first rtt sample, unless the SYN was retransmitted
+            if let Some(sent) = self.rtt_probe.take().map(|(_, sent)| sent) {
+                self.rtt.sample(now.saturating_sub(sent));
+            }
+        }
+        self.rtt_probe = None;
+        self.retransmits = 0;
+        self.tx_waker.wake();
+    }
+
+    fn negotiate_mss(&mut self, interface: &mut Interface, peer_mss: Option<u16>) {
+        let ours = self.local_mss(interface);
+        self.mss = peer_mss.unwrap_or(DEFAULT_MSS).min(ours).max(1);
+        self.cwnd = initial_cwnd(self.mss);
+    }
+
+    fn local_mss(&self, interface: &mut Interface) -> u16 {
+        let headers_len = match self.remote_addr {
+            Some(SocketAddr {
+                addr: IpAddress::V6(_),
+                ..
+            }) => IPV6_HEADERS_LEN,
+            _ => IPV4_HEADERS_LEN,
+        };
+        interface
+            .dev
+            .mtu()
+            .saturating_sub(headers_len)
+            .min(u16::MAX as usize) as u16
     }
 
-    // Helper function to process TCP state transitions based on packet flags
-    fn process_tcp_state_transitions(
+    // the receive window we advertise
+    fn window(&self) -> u16 {
+        self.recv_buffer.free().min(u16::MAX as usize) as u16
+    }
+
+    fn in_window(&self, seq: u32) -> bool {
+        let offset = seq.wrapping_sub(self.rcv_nxt);
+        offset <= (self.window() as u32).max(1)
+    }
+
+    fn fin_seq(&self) -> u32 {
+        self.snd_una.wrapping_add(self.send_buffer.len() as u32)
+    }
+
+    fn send_segment(
         &mut self,
         interface: &mut Interface,
+        seq: u32,
         flags: u8,
-        seq_number: u32,
-        _ack_number: u32,
-        _sender: SocketAddr,
+        payload: Vec<u8>,
     ) -> Result<()> {
-        match self.state {
-            TcpState::Established => {
-                // Handle FIN from remote
-                if flags & TCP_FLAG_FIN != 0 {
-                    self.ack_number = seq_number + 1; // FIN consumes a sequence number
-
-                    // Send ACK for FIN
-                    if let Some(remote) = self.remote_addr {
-                        tcp::send_tcp_packet(
-                            interface,
-                            self.binding.port,
-                            remote.port,
-                            self.seq_number,
-                            self.ack_number,
-                            TCP_FLAG_ACK,
-                            self.window_size,
-                            remote.addr,
-                            Vec::new(),
-                        )?;
-                    }
+        let remote = self.remote_addr.ok_or(Error::NotConnected)?;
+        let ack = if flags & TCP_FLAG_ACK != 0 {
+            self.rcv_nxt
+        } else {
+            0
+        };
+        let window = self.window();
+        if (window as usize) < self.mss as usize {
+            self.window_update = true;
+        }
+        let mut segment = TcpPacket::new(
+            self.binding.port,
+            remote.port,
+            seq,
+            ack,
+            flags,
+            window,
+            payload,
+            interface.source_addr(remote.addr),
+            remote.addr,
+        );
+        if flags & TCP_FLAG_SYN != 0 {
+            segment = segment.with_mss(self.local_mss(interface));
+            if self.rtt_probe.is_none() {
+                self.rtt_probe = Some((seq.wrapping_add(1), now()));
+            }
+        }
+        tcp::send_tcp_segment(interface, segment)
+    }
 
-                    self.state = TcpState::CloseWait;
-                }
+    fn send_ack(&mut self, interface: &mut Interface) -> Result<()> {
+        self.send_segment(interface, self.snd_nxt, TCP_FLAG_ACK, Vec::new())
+    }
+
+    // starts the retransmission timer, unless it's already running
+    fn arm_retransmit(&mut self, now: u64) {
+        if self.retransmit_at.is_none() {
+            self.retransmit_at = Some(now + self.rtt.rto());
+        }
+    }
 
-                // Handle RST from remote
-                if flags & TCP_FLAG_RST != 0 {
-                    self.state = TcpState::Closed;
-                    self.connected = false;
-                    self.remote_addr = None;
+    fn restart_retransmit(&mut self, now: u64) {
+        self.retransmit_at = None;
+        self.arm_retransmit(now);
+    }
+
+    // sends new segments, within both the peer's window and the congestion window
+    fn transmit(&mut self, interface: &mut Interface, now: u64) -> Result<()> {
+        if !matches!(
+            self.state,
+            TcpState::Established
+                | TcpState::CloseWait
+                | TcpState::FinWait1
+                | TcpState::Closing
+                | TcpState::LastAck
+        ) {
+            return Ok(());
+        }
+
+        loop {
+            let in_flight = self.snd_nxt.wrapping_sub(self.snd_una);
+            // offset of snd_nxt into the send buffer, which doesn't hold the FIN
+            let sent = (in_flight as usize).min(self.send_buffer.len());
+            let unsent = self.send_buffer.len() - sent;
+            let window = self.snd_wnd.min(self.cwnd);
+            let usable = window.saturating_sub(in_flight) as usize;
+            let len = unsent.min(usable).min(self.mss as usize);
+            if len == 0 {
+                if unsent > 0 && in_flight == 0 {
+                    // the peer's window is closed, probe it when the timer runs out
+                    self.arm_retransmit(now);
                 }
+                break;
+            }
+
+            let payload = self.send_buffer.peek(sent, len);
+            let seq = self.snd_nxt;
+            self.send_segment(interface, seq, TCP_FLAG_ACK | TCP_FLAG_PSH, payload)?;
+            self.snd_nxt = seq.wrapping_add(len as u32);
+            if self.rtt_probe.is_none() && self.retransmits == 0 {
+                self.rtt_probe = Some((self.snd_nxt, now));
             }
+            self.arm_retransmit(now);
+        }
+
+        if self.fin_queued && self.snd_nxt == self.fin_seq() {
+            let seq = self.snd_nxt;
+            self.send_segment(interface, seq, TCP_FLAG_FIN | TCP_FLAG_ACK, Vec::new())?;
+            self.snd_nxt = seq.wrapping_add(1); // FIN consumes a sequence number
+            self.arm_retransmit(now);
+        }
+        Ok(())
+    }
 
-            TcpState::FinWait1 => {
-                if flags & TCP_FLAG_ACK != 0 {
-                    // Our FIN was acknowledged
-                    if flags & TCP_FLAG_FIN != 0 {
-                        // Simultaneous FIN-ACK
-                        self.ack_number = seq_number + 1;
-
-                        // Send ACK for their FIN
-                        if let Some(remote) = self.remote_addr {
-                            tcp::send_tcp_packet(
-                                interface,
-                                self.binding.port,
-                                remote.port,
-                                self.seq_number,
-                                self.ack_number,
-                                TCP_FLAG_ACK,
-                                self.window_size,
-                                remote.addr,
-                                Vec::new(),
-                            )?;
-                        }
-
-                        self.state = TcpState::TimeWait;
-                    } else {
-                        // Just ACK for our FIN
-                        self.state = TcpState::FinWait2;
+    fn retransmit_timeout(&mut self, interface: &mut Interface, now: u64) -> Result<()> {
+        self.retransmit_at = None;
+        self.retransmits += 1;
+        if self.retransmits > MAX_RETRANSMITS {
+            println!("[!] tcp connection timed out");
+            self.reset();
+            return Err(Error::Timeout);
+        }
+        self.rtt.back_off();
+        // karn's algorithm: retransmitted segments can't be timed
+        self.rtt_probe = None;
+
+        match self.state {
+            TcpState::SynSent => {
+                self.send_segment(interface, self.iss, TCP_FLAG_SYN, Vec::new())?;
+            }
+            TcpState::SynReceived => {
+                let flags = TCP_FLAG_SYN | TCP_FLAG_ACK;
+                self.send_segment(interface, self.iss, flags, Vec::new())?;
+            }
+            _ => {
+                let in_flight = self.snd_nxt.wrapping_sub(self.snd_una);
+                if in_flight == 0 {
+                    // zero window probe: one byte past the window, which the peer will ack
+                    // with its current window
+                    if !self.send_buffer.is_empty() {
+                        let payload = self.send_buffer.peek(0, 1);
+                        self.send_segment(interface, self.snd_una, TCP_FLAG_ACK, payload)?;
+                        self.snd_nxt = self.snd_una.wrapping_add(1);
                     }
+                } else {
+                    // everything in flight is presumed lost: back to slow start, and send it
+                    // all again from the oldest unacknowledged byte
+                    let mss = self.mss as u32;
+                    self.ssthresh = (in_flight / 2).max(2 * mss);
+                    self.cwnd = mss;
+                    self.dup_acks = 0;
+                    self.recover = None;
+                    self.snd_nxt = self.snd_una;
                 }
             }
+        }
+        self.arm_retransmit(now);
+        Ok(())
+    }
 
-            TcpState::FinWait2 => {
-                if flags & TCP_FLAG_FIN != 0 {
-                    self.ack_number = seq_number + 1;
-
-                    // Send ACK for their FIN
-                    if let Some(remote) = self.remote_addr {
-                        tcp::send_tcp_packet(
-                            interface,
-                            self.binding.port,
-                            remote.port,
-                            self.seq_number,
-                            self.ack_number,
-                            TCP_FLAG_ACK,
-                            self.window_size,
-                            remote.addr,
-                            Vec::new(),
-                        )?;
-                    }
+    // resends the oldest unacknowledged segment, for fast retransmit
+    fn retransmit_first(&mut self, interface: &mut Interface) -> Result<()> {
+        let len = self.send_buffer.len().min(self.mss as usize);
+        if len > 0 {
+            let payload = self.send_buffer.peek(0, len);
+            let flags = TCP_FLAG_ACK | TCP_FLAG_PSH;
+            self.send_segment(interface, self.snd_una, flags, payload)?;
+        } else if self.fin_queued {
+            self.send_segment(
+                interface,
+                self.snd_una,
+                TCP_FLAG_FIN | TCP_FLAG_ACK,
+                Vec::new(),
+            )?;
+        }
+        // the timing would be ambiguous
+        self.rtt_probe = None;
+        Ok(())
+    }
 
-                    self.state = TcpState::TimeWait;
-                    // In a real implementation, start the TIME_WAIT timer here
+    fn process_ack(&mut self, interface: &mut Interface, segment: &TcpPacket) -> Result<()> {
+        if segment.flags & TCP_FLAG_ACK == 0 {
+            return Ok(());
+        }
+        let ack = segment.ack_number;
+        let now = now();
+        let mss = self.mss as u32;
+
+        if seq_lt(self.snd_nxt, ack) {
+            // acks something we haven't sent
+            return self.send_ack(interface);
+        }
+
+        if seq_lt(self.snd_una, ack) {
+            let acked = ack.wrapping_sub(self.snd_una);
+            let fin_acked = self.fin_queued && ack == self.fin_seq().wrapping_add(1);
+            self.send_buffer.discard(acked as usize);
+            self.tx_waker.wake();
+            self.snd_una = ack;
+            self.snd_wnd = segment.window_size as u32;
+            self.retransmits = 0;
+
+            if let Some((probe, sent)) = self.rtt_probe {
+                if seq_le(probe, ack) {
+                    self.rtt.sample(now.saturating_sub(sent));
+                    self.rtt_probe = None;
                 }
             }
 
-            TcpState::LastAck => {
-                if flags & TCP_FLAG_ACK != 0 {
-                    // Final ACK received, connection fully closed
-                    self.state = TcpState::Closed;
-                    self.connected = false;
-                    self.remote_addr = None;
+            match self.recover {
+                Some(recover) if seq_lt(ack, recover) => {
+                    // partial ack during fast recovery: the next hole was lost too (rfc 6582)
+                    self.cwnd = self.cwnd.saturating_sub(acked).max(mss) + mss;
+                    self.retransmit_first(interface)?;
+                }
+                Some(_) => {
+                    // everything from before the loss has arrived
+                    self.cwnd = self.ssthresh;
+                    self.recover = None;
+                }
+                None if self.cwnd < self.ssthresh => {
+                    // slow start
+                    self.cwnd = self.cwnd.saturating_add(acked.min(mss));
+                }
+                None => {
+                    // congestion avoidance, about one segment per round trip
+                    self.cwnd = self.cwnd.saturating_add((mss * mss / self.cwnd).max(1));
                 }
             }
+            self.dup_acks = 0;
 
-            // Handle other states as needed
-            _ => {}
-        }
+            if self.snd_una == self.snd_nxt {
+                self.retransmit_at = None;
+            } else {
+                self.restart_retransmit(now);
+            }
 
+            if fin_acked {
+                self.fin_queued = false;
+                match self.state {
+                    TcpState::FinWait1 => self.state = TcpState::FinWait2,
+                    TcpState::Closing => self.enter_time_wait(now),
+                    TcpState::LastAck => self.reset(),
+                    _ => {}
+                }
+            }
+        } else if ack == self.snd_una {
+            let in_flight = self.snd_nxt.wrapping_sub(self.snd_una);
+            let window_changed = segment.window_size as u32 != self.snd_wnd;
+            self.snd_wnd = segment.window_size as u32;
+            if segment.payload.is_empty() && !window_changed && in_flight > 0 {
+                self.dup_acks += 1;
+                if self.dup_acks == DUP_ACK_THRESHOLD && self.recover.is_none() {
+                    // fast retransmit, then fast recovery
+                    self.ssthresh = (in_flight / 2).max(2 * mss);
+                    self.cwnd = self.ssthresh + DUP_ACK_THRESHOLD * mss;
+                    self.recover = Some(self.snd_nxt);
+                    self.retransmit_first(interface)?;
+                } else if self.dup_acks > DUP_ACK_THRESHOLD {
+                    // each duplicate means another segment has left the network
+                    self.cwnd = self.cwnd.saturating_add(mss);
+                }
+            }
+        }
         Ok(())
     }
 
-    // Returns the number of packets enqueued for sending.
-    pub fn num_send_enqueued(&self) -> usize {
-        self.send_buffer.len()
-    }
+    fn process_data(&mut self, interface: &mut Interface, segment: &TcpPacket) -> Result<()> {
+        let mut fin = segment.flags & TCP_FLAG_FIN != 0;
+        if segment.payload.is_empty() && !fin {
+            return Ok(());
+        }
 
-    // Returns the number of packets enqueued for receiving.
-    pub fn num_recv_enqueued(&self) -> usize {
-        self.recv_buffer.len()
-    }
+        if !matches!(
+            self.state,
+            TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2
+        ) {
+            // the peer already finished sending, this is a retransmission of something we have
+            return self.send_ack(interface);
+        }
 
-    // Close the connection gracefully
-    pub fn close(&mut self, interface: &mut Interface) -> Result<()> {
-        match self.state {
-            TcpState::Established => {
-                // Send FIN packet
-                if let Some(remote) = self.remote_addr {
-                    tcp::send_tcp_packet(
-                        interface,
-                        self.binding.port,
-                        remote.port,
-                        self.seq_number,
-                        self.ack_number,
-                        TCP_FLAG_FIN | TCP_FLAG_ACK,
-                        self.window_size,
-                        remote.addr,
-                        Vec::new(),
-                    )?;
-
-                    self.seq_number += 1; // FIN consumes a sequence number
-                    self.state = TcpState::FinWait1;
-                }
-                Ok(())
+        let mut seq = segment.seq_number;
+        if segment.flags & TCP_FLAG_SYN != 0 {
+            seq = seq.wrapping_add(1);
+        }
+        let mut payload = &segment.payload[..];
+
+        // drop the part we already have
+        let behind = self.rcv_nxt.wrapping_sub(seq) as i32;
+        if behind > 0 {
+            let behind = behind as usize;
+            if behind > payload.len() {
+                // nothing new, not even the FIN
+                return self.send_ack(interface);
             }
-            TcpState::CloseWait => {
-                if let Some(remote) = self.remote_addr {
-                    tcp::send_tcp_packet(
-                        interface,
-                        self.binding.port,
-                        remote.port,
-                        self.seq_number,
-                        self.ack_number,
-                        TCP_FLAG_FIN | TCP_FLAG_ACK,
-                        self.window_size,
-                        remote.addr,
-                        Vec::new(),
-                    )?;
-
-                    self.seq_number += 1;
-                    self.state = TcpState::LastAck;
+            payload = &payload[behind..];
+            seq = self.rcv_nxt;
+        }
+
+        // and the part that doesn't fit in the window
+        let offset = seq.wrapping_sub(self.rcv_nxt) as usize;
+        let window = self.recv_buffer.free();
+        if offset > window {
+            return self.send_ack(interface);
+        }
+        if payload.len() > window - offset {
+            payload = &payload[..window - offset];
+            fin = false;
+        }
+
+        if fin {
+            self.remote_fin = Some(seq.wrapping_add(payload.len() as u32));
+        }
+        if offset == 0 {
+            let written = self.recv_buffer.write(payload);
+            self.rcv_nxt = self.rcv_nxt.wrapping_add(written as u32);
+            // fill in from whatever arrived early
+            while let Some(data) = self.assembler.pop(self.rcv_nxt) {
+                let written = self.recv_buffer.write(&data);
+                self.rcv_nxt = self.rcv_nxt.wrapping_add(written as u32);
+                if written < data.len() {
+                    break;
                 }
-                Ok(())
             }
-            _ => Err(Error::Malformed),
+        } else {
+            self.assembler.insert(seq, payload.to_vec());
         }
+
+        if self.remote_fin == Some(self.rcv_nxt) {
+            self.remote_fin = None;
+            self.rcv_nxt = self.rcv_nxt.wrapping_add(1); // FIN consumes a sequence number
+            match self.state {
+                TcpState::Established => self.state = TcpState::CloseWait,
+                TcpState::FinWait1 => self.state = TcpState::Closing,
+                TcpState::FinWait2 => self.enter_time_wait(now()),
+                _ => {}
+            }
+        }
+        if !self.recv_buffer.is_empty() || self.state != TcpState::Established {
+            self.rx_waker.wake();
+        }
+
+        // ack everything right away, duplicate acks for out of order data let the peer fast
+        // retransmit
+        self.send_ack(interface)
     }
 
-    pub fn get_state(&self) -> &TcpState {
-        &self.state
+    fn enter_time_wait(&mut self, now: u64) {
+        self.state = TcpState::TimeWait;
+        self.retransmit_at = None;
+        self.time_wait_until = Some(now + TIME_WAIT_LEN);
     }
 }
@@ -1,17 +1,18 @@
-use crate::device::usb::device::net::get_interface_mut;
-use crate::networking::iface::udp;
-use crate::networking::iface::Interface;
-use crate::networking::socket::bindings::NEXT_SOCKETFD;
+use crate::networking::iface::{get_interface_mut, route_interface, udp};
+use crate::networking::repr::{IpAddress, Ipv4Address};
+use crate::networking::socket::bindings::{NEXT_SOCKETFD, SOCKETS};
 use crate::networking::socket::tagged::TaggedSocket;
 use crate::networking::socket::SocketAddr;
 use crate::networking::utils::ring::Ring;
+use crate::networking::utils::waker::WakerRegistration;
 use crate::networking::{Error, Result};
 
 use alloc::vec;
 use alloc::vec::Vec;
 use core::sync::atomic::Ordering;
+use core::task::Waker;
 
-fn new_ring_packet_buffer(capacity: usize) -> Ring<(Vec<u8>, SocketAddr)> {
+pub fn new_ring_packet_buffer(capacity: usize) -> Ring<(Vec<u8>, SocketAddr)> {
     let default_entry = (Vec::new(), SocketAddr::default()); // or some placeholder address
     let buffer = vec![default_entry; capacity];
     Ring::from(buffer)
@@ -23,48 +24,56 @@ pub static UDP_BUFFER_LEN: usize = 128;
 pub struct UdpSocket {
     binding: SocketAddr,
     is_bound: bool,
+    // interface to always send from, instead of the routed one
+    interface: Option<usize>,
     send_buffer: Ring<(Vec<u8>, SocketAddr)>,
     recv_buffer: Ring<(Vec<u8>, SocketAddr)>,
+    // tasks waiting for a packet, or for room to queue one
+    rx_waker: WakerRegistration,
+    tx_waker: WakerRegistration,
 }
 
 impl UdpSocket {
     pub fn new() -> u16 {
-        let interface = get_interface_mut();
         let socket = UdpSocket {
-            binding: SocketAddr {
-                addr: *interface.ipv4_addr,
-                port: 0,
-            },
+            binding: SocketAddr::default(),
             is_bound: false,
+            interface: None,
             send_buffer: new_ring_packet_buffer(UDP_BUFFER_LEN),
             recv_buffer: new_ring_packet_buffer(UDP_BUFFER_LEN),
+            rx_waker: WakerRegistration::new(),
+            tx_waker: WakerRegistration::new(),
         };
 
         let socketfd = NEXT_SOCKETFD.fetch_add(1, Ordering::SeqCst);
-        let mut sockets = interface.sockets.lock();
+        let mut sockets = SOCKETS.lock();
         sockets.insert(socketfd, TaggedSocket::Udp(socket));
 
         socketfd
     }
 
     pub fn binding_equals(&self, saddr: SocketAddr) -> bool {
-        self.binding == saddr
+        self.binding.accepts(saddr)
     }
 
     pub fn is_bound(&self) -> bool {
         self.is_bound
     }
 
-    pub fn bind(&mut self, interface: &mut Interface, port: u16) {
+    pub fn bind(&mut self, port: u16) {
         self.is_bound = true;
         let bind_addr = SocketAddr {
-            addr: *interface.ipv4_addr,
+            addr: IpAddress::V4(Ipv4Address::empty()),
             port,
         };
 
         self.binding = bind_addr;
     }
 
+    pub fn bind_interface(&mut self, interface: usize) {
+        self.interface = Some(interface);
+    }
+
     pub fn send_enqueue(&mut self, payload: Vec<u8>, dest: SocketAddr) -> Result<()> {
         self.send_buffer.enqueue_maybe(|(buffer, addr)| {
             *buffer = payload;
@@ -73,13 +82,20 @@ impl UdpSocket {
         })
     }
 
-    pub fn send(&mut self, interface: &mut Interface) -> Result<()> {
+    pub fn send(&mut self) -> Result<()> {
         loop {
             match self.send_buffer.dequeue_with(|entry| {
                 let (payload, addr) = entry;
                 (payload.clone(), *addr)
             }) {
                 Ok((payload, dest)) => {
+                    let interface = match self.interface {
+                        Some(index) => get_interface_mut(index),
+                        None => match route_interface(dest.addr) {
+                            Ok(interface) => interface,
+                            Err(_) => continue,
+                        },
+                    };
                     let _ = udp::send_udp_packet(
                         interface,
                         dest.addr,
@@ -93,12 +109,13 @@ impl UdpSocket {
             }
         }
 
+        // the queue is empty again
+        self.tx_waker.wake();
         Ok(())
     }
 
     // Dequeues a received packet along with it's source address from the
-    // socket.
-    // TODO: make blocking
+    // socket. Exhausted when there isn't one, see register_recv_waker
     pub fn recv(&mut self) -> Result<(Vec<u8>, SocketAddr)> {
         self.recv_buffer
             .dequeue_with(|entry: &mut (Vec<u8>, SocketAddr)| {
@@ -113,7 +130,19 @@ impl UdpSocket {
             *buffer = payload;
             *addr = sender;
             Ok(())
-        })
+        })?;
+        self.rx_waker.wake();
+        Ok(())
+    }
+
+    // wakes the task once a packet has been received
+    pub fn register_recv_waker(&mut self, waker: &Waker) {
+        self.rx_waker.register(waker);
+    }
+
+    // wakes the task once queued packets have been sent, so there's room for more
+    pub fn register_send_waker(&mut self, waker: &Waker) {
+        self.tx_waker.register(waker);
     }
 
     // Returns the number of packets enqueued for sending.
@@ -1,7 +1,7 @@
 use crate::device::system_timer;
 use alloc::collections::BTreeMap;
 
-use crate::networking::repr::{EthernetAddress, Ipv4Address};
+use crate::networking::repr::{EthernetAddress, Ipv4Address, Ipv6Address};
 // use crate::networking::utils::arp_cache::u64;
 use core::time::Duration;
 
@@ -10,14 +10,17 @@ struct Entry {
     in_cache_since: u64,
 }
 
-// expiring set of IPv4 -> ethernet address mappings
-pub struct ArpCache {
-    entries: BTreeMap<Ipv4Address, Entry>,
+// expiring set of IP -> ethernet address mappings, IPv4 by default
+pub struct ArpCache<A = Ipv4Address> {
+    entries: BTreeMap<A, Entry>,
     expiration: Duration,
     in_cache_since_min: u64,
 }
 
-impl ArpCache {
+// the same mappings for IPv6, filled in by neighbor discovery instead of arp
+pub type NeighborCache = ArpCache<Ipv6Address>;
+
+impl<A: Ord> ArpCache<A> {
     pub fn new(expiration_in_secs: u64, now: u64) -> Self {
         ArpCache {
             entries: BTreeMap::new(),
@@ -26,12 +29,12 @@ impl ArpCache {
         }
     }
 
-    pub fn eth_addr_for_ip(&mut self, ipv4_addr: Ipv4Address) -> Option<EthernetAddress> {
+    pub fn eth_addr_for_ip(&mut self, ip_addr: A) -> Option<EthernetAddress> {
         self.expire_eth_addr();
-        self.entries.get(&ipv4_addr).map(|entry| entry.eth_addr)
+        self.entries.get(&ip_addr).map(|entry| entry.eth_addr)
     }
 
-    pub fn set_eth_addr_for_ip(&mut self, ipv4_addr: Ipv4Address, eth_addr: EthernetAddress) {
+    pub fn set_eth_addr_for_ip(&mut self, ip_addr: A, eth_addr: EthernetAddress) {
         self.expire_eth_addr();
 
         let now = system_timer::get_time(); // Use system_time::get_time() to get current time
@@ -40,7 +43,7 @@ impl ArpCache {
         }
 
         self.entries.insert(
-            ipv4_addr,
+            ip_addr,
             Entry {
                 eth_addr,
                 in_cache_since: now,
@@ -48,6 +51,12 @@ impl ArpCache {
         );
     }
 
+    // forgets every mapping, ie. when the link comes back and the neighbors may have changed
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.in_cache_since_min = system_timer::get_time();
+    }
+
     fn expire_eth_addr(&mut self) {
         let now = system_timer::get_time(); // Use system_time::get_time() to get current time
 
@@ -0,0 +1,128 @@
+use alloc::vec::Vec;
+
+// holds tcp segments that arrived ahead of the next expected sequence number, until the gap
+// before them is filled in
+#[derive(Debug)]
+pub struct Assembler {
+    // (sequence number of the first byte, data), in no particular order
+    segments: Vec<(u32, Vec<u8>)>,
+    max_segments: usize,
+}
+
+// how far a is past b, in sequence space
+fn seq_offset(a: u32, b: u32) -> i32 {
+    a.wrapping_sub(b) as i32
+}
+
+impl Assembler {
+    pub fn new(max_segments: usize) -> Self {
+        Assembler {
+            segments: Vec::new(),
+            max_segments,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.segments.clear();
+    }
+
+    // stores a segment for later, dropping it if it's a duplicate or there's no room
+    pub fn insert(&mut self, seq: u32, data: Vec<u8>) {
+        if data.is_empty() {
+            return;
+        }
+        let end = seq.wrapping_add(data.len() as u32);
+        let covered = self.segments.iter().any(|(s, d)| {
+            seq_offset(seq, *s) >= 0 && seq_offset(end, s.wrapping_add(d.len() as u32)) <= 0
+        });
+        if covered || self.segments.len() >= self.max_segments {
+            return;
+        }
+        self.segments.push((seq, data));
+    }
+
+    // takes out the data continuing the stream from next, if any has arrived, dropping
+    // everything that's now behind it
+    pub fn pop(&mut self, next: u32) -> Option<Vec<u8>> {
+        let mut found = None;
+        let mut i = 0;
+        while i < self.segments.len() {
+            let (seq, ref data) = self.segments[i];
+            let end = seq.wrapping_add(data.len() as u32);
+            if seq_offset(end, next) <= 0 {
+                // entirely old data
+                self.segments.swap_remove(i);
+                continue;
+            }
+            if found.is_none() && seq_offset(seq, next) <= 0 {
+                let (_, data) = self.segments.swap_remove(i);
+                let skip = seq_offset(next, seq) as usize;
+                found = Some(data[skip..].to_vec());
+                continue;
+            }
+            i += 1;
+        }
+        found
+    }
+}
+
+test_case!(assembler_out_of_order);
+fn assembler_out_of_order() -> Result<(), crate::test::BoxError> {
+    use alloc::vec;
+
+    let mut asm = Assembler::new(4);
+    asm.insert(105, vec![5, 6, 7, 8, 9]);
+    kassert_eq!(asm.pop(100), None)?;
+    asm.insert(100, vec![0, 1, 2, 3, 4]);
+    kassert_eq!(asm.pop(100), Some(vec![0, 1, 2, 3, 4]))?;
+    kassert_eq!(asm.pop(105), Some(vec![5, 6, 7, 8, 9]))?;
+    kassert_eq!(asm.pop(110), None)?;
+    kassert!(asm.is_empty())?;
+
+    // no more than max_segments are held
+    let mut asm = Assembler::new(2);
+    asm.insert(10, vec![1]);
+    asm.insert(20, vec![2]);
+    asm.insert(30, vec![3]);
+    kassert_eq!(asm.pop(30), None)?;
+    Ok(())
+}
+
+test_case!(assembler_overlap);
+fn assembler_overlap() -> Result<(), crate::test::BoxError> {
+    use alloc::vec;
+
+    let mut asm = Assembler::new(4);
+    asm.insert(100, vec![0, 1, 2, 3, 4, 5, 6, 7]);
+    // entirely inside the first segment, so dropped
+    asm.insert(102, vec![2, 3, 4]);
+    // old data, dropped on the next pop
+    asm.insert(90, vec![0; 5]);
+    kassert_eq!(asm.segments.len(), 2)?;
+
+    // the part that's already been received is cut off
+    kassert_eq!(asm.pop(103), Some(vec![3, 4, 5, 6, 7]))?;
+    kassert!(asm.is_empty())?;
+    Ok(())
+}
+
+test_case!(assembler_wraparound);
+fn assembler_wraparound() -> Result<(), crate::test::BoxError> {
+    use alloc::vec;
+
+    let mut asm = Assembler::new(4);
+    asm.insert(u32::MAX - 1, vec![0, 1, 2, 3]);
+    asm.insert(2, vec![4, 5]);
+    kassert_eq!(asm.pop(u32::MAX), Some(vec![1, 2, 3]))?;
+    kassert_eq!(asm.pop(2), Some(vec![4, 5]))?;
+
+    // behind next, even though it's numerically larger
+    asm.insert(u32::MAX - 4, vec![0; 4]);
+    kassert_eq!(asm.pop(0), None)?;
+    kassert!(asm.is_empty())?;
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::networking::repr::Ipv4Address;
+
+struct Entry {
+    addrs: Vec<Ipv4Address>,
+    expires_at: u64,
+}
+
+// names resolved to addresses, each kept for as long as the ttl in its answer
+pub struct DnsCache {
+    entries: BTreeMap<String, Entry>,
+    max_entries: usize,
+}
+
+impl DnsCache {
+    pub const fn new(max_entries: usize) -> Self {
+        DnsCache {
+            entries: BTreeMap::new(),
+            max_entries,
+        }
+    }
+
+    pub fn lookup(&mut self, name: &str, now: u64) -> Option<Vec<Ipv4Address>> {
+        match self.entries.get(name) {
+            Some(entry) if entry.expires_at > now => Some(entry.addrs.clone()),
+            Some(_) => {
+                self.entries.remove(name);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&mut self, name: String, addrs: Vec<Ipv4Address>, ttl_in_secs: u32, now: u64) {
+        if ttl_in_secs == 0 {
+            return;
+        }
+
+        if self.entries.len() >= self.max_entries && !self.entries.contains_key(&name) {
+            self.entries.retain(|_, entry| entry.expires_at > now);
+            // still full, make room by dropping whatever expires first
+            if self.entries.len() >= self.max_entries {
+                let soonest = self
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.expires_at)
+                    .map(|(name, _)| name.clone());
+                if let Some(soonest) = soonest {
+                    self.entries.remove(&soonest);
+                }
+            }
+        }
+
+        let expires_at = now + ttl_in_secs as u64 * 1_000_000;
+        self.entries.insert(name, Entry { addrs, expires_at });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+test_case!(dns_cache_expiry);
+fn dns_cache_expiry() -> Result<(), crate::test::BoxError> {
+    use alloc::vec;
+
+    let addr = Ipv4Address::new([10, 0, 0, 1]);
+    let mut cache = DnsCache::new(2);
+    cache.insert(String::from("a"), vec![addr], 10, 0);
+    kassert_eq!(cache.lookup("a", 9_999_999), Some(vec![addr]))?;
+    kassert_eq!(cache.lookup("a", 10_000_000), None)?;
+    // the expired entry was dropped, not just hidden
+    kassert_eq!(cache.lookup("a", 0), None)?;
+
+    // a ttl of 0 means the answer can't be cached
+    cache.insert(String::from("b"), vec![addr], 0, 0);
+    kassert_eq!(cache.lookup("b", 0), None)?;
+    Ok(())
+}
+
+test_case!(dns_cache_eviction);
+fn dns_cache_eviction() -> Result<(), crate::test::BoxError> {
+    use alloc::vec;
+
+    let addr = Ipv4Address::new([10, 0, 0, 1]);
+    let mut cache = DnsCache::new(2);
+    cache.insert(String::from("a"), vec![addr], 10, 0);
+    cache.insert(String::from("b"), vec![addr], 5, 0);
+    // full, so whatever expires first makes room
+    cache.insert(String::from("c"), vec![addr], 20, 0);
+    kassert!(cache.lookup("a", 0).is_some())?;
+    kassert!(cache.lookup("b", 0).is_none())?;
+    kassert!(cache.lookup("c", 0).is_some())?;
+
+    // expired entries go before live ones
+    cache.insert(String::from("d"), vec![addr], 20, 15_000_000);
+    kassert!(cache.lookup("a", 15_000_000).is_none())?;
+    kassert!(cache.lookup("c", 15_000_000).is_some())?;
+    kassert!(cache.lookup("d", 15_000_000).is_some())?;
+    Ok(())
+}
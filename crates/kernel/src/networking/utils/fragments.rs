@@ -0,0 +1,271 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::networking::repr::{Ipv4Address, Ipv4Flags, Ipv4Packet};
+
+// fragments belong to the same datagram when all of these match (rfc 791)
+type Key = (Ipv4Address, Ipv4Address, u8, u16);
+
+struct Datagram {
+    // the first fragment, whose header the whole datagram gets
+    first: Option<Ipv4Packet>,
+    data: Vec<u8>,
+    // byte ranges of data that have arrived, sorted and merged
+    received: Vec<(usize, usize)>,
+    // known once the last fragment arrives
+    total_len: Option<usize>,
+    expires_at: u64,
+}
+
+impl Datagram {
+    fn is_complete(&self) -> bool {
+        match self.total_len {
+            Some(total_len) => self.received == [(0, total_len)],
+            None => false,
+        }
+    }
+
+    fn add_range(&mut self, start: usize, end: usize) {
+        let (mut start, mut end) = (start, end);
+        // absorb every range this one touches
+        self.received.retain(|&(s, e)| {
+            if e < start || s > end {
+                return true;
+            }
+            start = start.min(s);
+            end = end.max(e);
+            false
+        });
+        let at = self.received.partition_point(|&(s, _)| s < start);
+        self.received.insert(at, (start, end));
+    }
+}
+
+// fragments of ipv4 datagrams waiting for the rest of them. datagrams that don't complete in time
+// are dropped, as are the oldest ones when too much memory is in use
+pub struct FragmentBuffer {
+    datagrams: BTreeMap<Key, Datagram>,
+    timeout: u64,
+    max_bytes: usize,
+    max_datagrams: usize,
+    used_bytes: usize,
+}
+
+impl FragmentBuffer {
+    pub fn new(timeout: u64, max_bytes: usize, max_datagrams: usize) -> Self {
+        FragmentBuffer {
+            datagrams: BTreeMap::new(),
+            timeout,
+            max_bytes,
+            max_datagrams,
+            used_bytes: 0,
+        }
+    }
+
+    // adds a fragment, returning the whole datagram once every part of it has arrived
+    pub fn insert(&mut self, fragment: Ipv4Packet, now: u64) -> Option<Ipv4Packet> {
+        self.expire(now);
+
+        let start = fragment.fragment_start();
+        let end = start + fragment.payload.len();
+        let last = fragment.flags & Ipv4Flags::NOT_LAST == 0;
+        if end > Ipv4Packet::MAX_PAYLOAD_LEN || (!last && fragment.payload.len() % 8 != 0) {
+            return None;
+        }
+
+        let key = (
+            fragment.src_addr,
+            fragment.dst_addr,
+            fragment.protocol as u8,
+            fragment.id,
+        );
+
+        // make room, oldest first, so a flood of fragments can't use up the heap
+        let growth = match self.datagrams.get(&key) {
+            Some(datagram) => end.saturating_sub(datagram.data.len()),
+            None => end,
+        };
+        while self.used_bytes + growth > self.max_bytes
+            || (!self.datagrams.contains_key(&key) && self.datagrams.len() >= self.max_datagrams)
+        {
+            let oldest = self
+                .datagrams
+                .iter()
+                .filter(|(k, _)| **k != key)
+                .min_by_key(|(_, datagram)| datagram.expires_at)
+                .map(|(k, _)| *k);
+            match oldest {
+                Some(oldest) => self.remove(&oldest),
+                None => {
+                    // the datagram is too big to ever fit
+                    self.remove(&key);
+                    return None;
+                }
+            };
+        }
+
+        let expires_at = now + self.timeout;
+        let datagram = self.datagrams.entry(key).or_insert_with(|| Datagram {
+            first: None,
+            data: Vec::new(),
+            received: Vec::new(),
+            total_len: None,
+            expires_at,
+        });
+
+        if last {
+            // every fragment has to agree on where the datagram ends
+            if datagram.total_len.is_some_and(|total_len| total_len != end)
+                || datagram.received.last().is_some_and(|&(_, e)| e > end)
+            {
+                self.remove(&key);
+                return None;
+            }
+            datagram.total_len = Some(end);
+        } else if datagram.total_len.is_some_and(|total_len| end > total_len) {
+            self.remove(&key);
+            return None;
+        }
+
+        if datagram.data.len() < end {
+            self.used_bytes += end - datagram.data.len();
+            datagram.data.resize(end, 0);
+        }
+        datagram.data[start..end].copy_from_slice(&fragment.payload);
+        datagram.add_range(start, end);
+        if start == 0 {
+            datagram.first = Some(Ipv4Packet {
+                payload: Vec::new(),
+                ..fragment
+            });
+        }
+
+        if !datagram.is_complete() {
+            return None;
+        }
+
+        let datagram = self.remove(&key)?;
+        let first = datagram.first?;
+        let mut packet = Ipv4Packet::new(
+            first.src_addr,
+            first.dst_addr,
+            first.protocol,
+            datagram.data,
+        );
+        packet.id = first.id;
+        packet.dscp = first.dscp;
+        packet.ttl = first.ttl;
+        Some(packet)
+    }
+
+    fn remove(&mut self, key: &Key) -> Option<Datagram> {
+        let datagram = self.datagrams.remove(key)?;
+        self.used_bytes -= datagram.data.len();
+        Some(datagram)
+    }
+
+    fn expire(&mut self, now: u64) {
+        let expired: Vec<Key> = self
+            .datagrams
+            .iter()
+            .filter(|(_, datagram)| datagram.expires_at <= now)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in expired {
+            println!("[!] dropping incomplete ipv4 datagram {}", key.3);
+            self.remove(&key);
+        }
+    }
+}
+
+// a fragment of a udp datagram from 10.0.0.1 to 10.0.0.2
+fn test_fragment(id: u16, start: usize, last: bool, payload: &[u8]) -> Ipv4Packet {
+    use crate::networking::repr::Ipv4Protocol;
+
+    let mut packet = Ipv4Packet::new(
+        Ipv4Address::new([10, 0, 0, 1]),
+        Ipv4Address::new([10, 0, 0, 2]),
+        Ipv4Protocol::UDP,
+        payload.to_vec(),
+    );
+    packet.id = id;
+    packet.flags = if last { 0 } else { Ipv4Flags::NOT_LAST };
+    packet.frag_offset = (start / 8) as u16;
+    packet
+}
+
+test_case!(fragments_overlap);
+fn fragments_overlap() -> Result<(), crate::test::BoxError> {
+    let data: Vec<u8> = (0..24).collect();
+    let mut buffer = FragmentBuffer::new(1_000_000, 65536, 4);
+
+    // the middle is sent twice over, once inside the first fragment
+    kassert!(buffer
+        .insert(test_fragment(1, 0, false, &data[..16]), 0)
+        .is_none())?;
+    kassert!(buffer
+        .insert(test_fragment(1, 8, false, &data[8..16]), 0)
+        .is_none())?;
+    kassert_eq!(buffer.used_bytes, 16)?;
+    let packet = buffer.insert(test_fragment(1, 16, true, &data[16..]), 0);
+    kassert_eq!(packet.map(|packet| packet.payload), Some(data.clone()))?;
+    kassert_eq!(buffer.used_bytes, 0)?;
+
+    // out of order, and only complete once every byte has arrived, not just the first and last
+    kassert!(buffer
+        .insert(test_fragment(2, 16, true, &data[16..]), 0)
+        .is_none())?;
+    kassert!(buffer
+        .insert(test_fragment(2, 0, false, &data[..8]), 0)
+        .is_none())?;
+    let packet = buffer.insert(test_fragment(2, 0, false, &data[..16]), 0);
+    kassert_eq!(packet.map(|packet| packet.payload), Some(data.clone()))?;
+
+    // fragments that disagree on where the datagram ends drop it
+    kassert!(buffer
+        .insert(test_fragment(3, 8, true, &data[8..16]), 0)
+        .is_none())?;
+    kassert!(buffer
+        .insert(test_fragment(3, 16, true, &data[16..]), 0)
+        .is_none())?;
+    kassert!(buffer.datagrams.is_empty())?;
+
+    // as do ones past the end
+    kassert!(buffer
+        .insert(test_fragment(4, 8, true, &data[8..16]), 0)
+        .is_none())?;
+    kassert!(buffer
+        .insert(test_fragment(4, 16, false, &data[16..]), 0)
+        .is_none())?;
+    kassert!(buffer.datagrams.is_empty())?;
+    kassert_eq!(buffer.used_bytes, 0)?;
+    Ok(())
+}
+
+test_case!(fragments_timeout);
+fn fragments_timeout() -> Result<(), crate::test::BoxError> {
+    let data: Vec<u8> = (0..16).collect();
+    let mut buffer = FragmentBuffer::new(1_000_000, 65536, 4);
+
+    kassert!(buffer
+        .insert(test_fragment(1, 0, false, &data[..8]), 0)
+        .is_none())?;
+    // the first half expired, so this starts over
+    kassert!(buffer
+        .insert(test_fragment(1, 8, true, &data[8..]), 1_000_000)
+        .is_none())?;
+    kassert_eq!(buffer.used_bytes, 16)?;
+    let packet = buffer.insert(test_fragment(1, 0, false, &data[..8]), 1_500_000);
+    kassert_eq!(packet.map(|packet| packet.payload), Some(data.clone()))?;
+
+    // a lone fragment is gone after the timeout, even if nothing else arrives for it
+    kassert!(buffer
+        .insert(test_fragment(2, 0, false, &data[..8]), 2_000_000)
+        .is_none())?;
+    kassert!(buffer
+        .insert(test_fragment(3, 0, false, &data[..8]), 3_000_000)
+        .is_none())?;
+    kassert_eq!(buffer.datagrams.len(), 1)?;
+    kassert_eq!(buffer.used_bytes, 8)?;
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use crate::networking::repr::LinkState;
+use crate::networking::utils::waker::WakerRegistration;
+use crate::networking::{Error, Result};
+use crate::sync::InterruptSpinLock;
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::task::{Context, Poll};
+
+// frames going one way between a driver and an interface. whoever pushes gives up the buffer, and
+// whoever pops owns it. the popping side waits on it with poll_pop
+pub struct FrameQueue {
+    frames: VecDeque<Vec<u8>>,
+    capacity: usize,
+    waker: WakerRegistration,
+    // frames turned away because the queue was full
+    dropped: usize,
+}
+
+impl FrameQueue {
+    pub const fn new(capacity: usize) -> Self {
+        FrameQueue {
+            frames: VecDeque::new(),
+            capacity,
+            waker: WakerRegistration::new(),
+            dropped: 0,
+        }
+    }
+
+    // Exhausted (and the frame is dropped) when full, like a nic with no free descriptors
+    pub fn push(&mut self, frame: Vec<u8>) -> Result<()> {
+        if self.frames.len() >= self.capacity {
+            self.dropped += 1;
+            return Err(Error::Exhausted);
+        }
+        self.frames.push_back(frame);
+        self.waker.wake();
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        self.frames.pop_front()
+    }
+
+    pub fn poll_pop(&mut self, cx: &mut Context<'_>) -> Poll<Vec<u8>> {
+        match self.frames.pop_front() {
+            Some(frame) => Poll::Ready(frame),
+            None => {
+                self.waker.register(cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+}
+
+// what a driver and its Device share: the frames each way and the link state. the driver's side
+// runs in usb callbacks, so everything here is locked with interrupts off, and only ever pushes,
+// pops or wakes while locked
+pub struct DeviceQueues {
+    pub rx: InterruptSpinLock<FrameQueue>,
+    pub tx: InterruptSpinLock<FrameQueue>,
+    link: InterruptSpinLock<(LinkState, WakerRegistration)>,
+}
+
+impl DeviceQueues {
+    pub const fn new(capacity: usize, link: LinkState) -> Self {
+        DeviceQueues {
+            rx: InterruptSpinLock::new(FrameQueue::new(capacity)),
+            tx: InterruptSpinLock::new(FrameQueue::new(capacity)),
+            link: InterruptSpinLock::new((link, WakerRegistration::new())),
+        }
+    }
+
+    pub fn link_state(&self) -> LinkState {
+        self.link.lock().0
+    }
+
+    pub fn set_link_state(&self, state: LinkState) {
+        let mut link = self.link.lock();
+        if link.0 != state {
+            link.0 = state;
+            link.1.wake();
+        }
+    }
+
+    pub fn poll_link(&self, cx: &mut Context<'_>, known: LinkState) -> Poll<LinkState> {
+        let mut link = self.link.lock();
+        if link.0 != known {
+            return Poll::Ready(link.0);
+        }
+        link.1.register(cx.waker());
+        Poll::Pending
+    }
+}
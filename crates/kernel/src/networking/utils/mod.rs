@@ -1,7 +1,15 @@
 pub mod checksum;
 
 pub mod arp_cache;
+pub mod dns_cache;
+pub mod fragments;
+pub mod frame_queue;
+pub mod pcap;
 
+pub mod assembler;
 pub mod ring;
+pub mod rtt;
 pub mod slice;
+pub mod stream;
+pub mod waker;
 // pub mod range;
@@ -0,0 +1,61 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+// https://www.ietf.org/archive/id/draft-ietf-opsawg-pcap-03.html
+// everything is written little endian, readers tell from the magic number
+
+const MAGIC: u32 = 0xA1B2C3D4; // microsecond timestamps
+const VERSION_MAJOR: u16 = 2;
+const VERSION_MINOR: u16 = 4;
+
+#[allow(non_snake_case)]
+pub mod LinkType {
+    pub const ETHERNET: u32 = 1;
+    // bare ipv4 or ipv6 packets, told apart by their version field
+    pub const RAW: u32 = 101;
+}
+
+pub const GLOBAL_HEADER_LEN: usize = 24;
+pub const RECORD_HEADER_LEN: usize = 16;
+
+pub fn write_global_header(buf: &mut Vec<u8>, link_type: u32, snap_len: u32) {
+    buf.extend_from_slice(&MAGIC.to_le_bytes());
+    buf.extend_from_slice(&VERSION_MAJOR.to_le_bytes());
+    buf.extend_from_slice(&VERSION_MINOR.to_le_bytes());
+    buf.extend_from_slice(&0u32.to_le_bytes()); // reserved (was the timezone)
+    buf.extend_from_slice(&0u32.to_le_bytes()); // reserved (was the timestamp accuracy)
+    buf.extend_from_slice(&snap_len.to_le_bytes());
+    buf.extend_from_slice(&link_type.to_le_bytes());
+}
+
+// `data` may have been cut short of `orig_len` when captured
+pub fn write_record(buf: &mut Vec<u8>, timestamp_us: u64, data: &[u8], orig_len: usize) {
+    let secs = (timestamp_us / 1_000_000) as u32;
+    let micros = (timestamp_us % 1_000_000) as u32;
+    buf.extend_from_slice(&secs.to_le_bytes());
+    buf.extend_from_slice(&micros.to_le_bytes());
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&(orig_len as u32).to_le_bytes());
+    buf.extend_from_slice(data);
+}
+
+// one packet as a hex dump that wireshark's "import from hex dump" (or text2pcap) reads
+// back in, for getting captures out over a serial console
+pub fn hex_dump(timestamp_us: u64, data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len() * 3 + data.len() / 16 * 8 + 32);
+    let _ = writeln!(
+        out,
+        "{}.{:06}",
+        timestamp_us / 1_000_000,
+        timestamp_us % 1_000_000
+    );
+    for (i, line) in data.chunks(16).enumerate() {
+        let _ = write!(out, "{:06x}", i * 16);
+        for byte in line {
+            let _ = write!(out, " {:02x}", byte);
+        }
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,75 @@
+// retransmission timeout estimation, from rfc 6298. all times are in microseconds
+
+const INITIAL_RTO: u64 = 1_000_000;
+const MIN_RTO: u64 = 200_000;
+const MAX_RTO: u64 = 60_000_000;
+// clock granularity, the g in the rfc
+const GRANULARITY: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RttEstimator {
+    // smoothed round trip time and its variation, none until the first sample
+    srtt: Option<u64>,
+    rttvar: u64,
+    rto: u64,
+}
+
+impl RttEstimator {
+    pub fn new() -> Self {
+        RttEstimator {
+            srtt: None,
+            rttvar: 0,
+            rto: INITIAL_RTO,
+        }
+    }
+
+    pub fn rto(&self) -> u64 {
+        self.rto
+    }
+
+    pub fn sample(&mut self, rtt: u64) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                // rttvar = 3/4 rttvar + 1/4 |srtt - rtt|, srtt = 7/8 srtt + 1/8 rtt
+                self.rttvar = (3 * self.rttvar + srtt.abs_diff(rtt)) / 4;
+                self.srtt = Some((7 * srtt + rtt) / 8);
+            }
+        }
+        let srtt = self.srtt.unwrap_or(rtt);
+        self.rto = (srtt + GRANULARITY.max(4 * self.rttvar)).clamp(MIN_RTO, MAX_RTO);
+    }
+
+    // doubles the timeout after it expired
+    pub fn back_off(&mut self) {
+        self.rto = (self.rto * 2).min(MAX_RTO);
+    }
+}
+
+test_case!(rtt_backoff);
+fn rtt_backoff() -> Result<(), crate::test::BoxError> {
+    let mut rtt = RttEstimator::new();
+    kassert_eq!(rtt.rto(), INITIAL_RTO)?;
+
+    // srtt = 100ms, rttvar = 50ms
+    rtt.sample(100_000);
+    kassert_eq!(rtt.rto(), 300_000)?;
+    rtt.back_off();
+    kassert_eq!(rtt.rto(), 600_000)?;
+    for _ in 0..10 {
+        rtt.back_off();
+    }
+    kassert_eq!(rtt.rto(), MAX_RTO)?;
+
+    // a new sample replaces the backed off timeout; rttvar = 37.5ms
+    rtt.sample(100_000);
+    kassert_eq!(rtt.rto(), 250_000)?;
+
+    let mut rtt = RttEstimator::new();
+    rtt.sample(1_000);
+    kassert_eq!(rtt.rto(), MIN_RTO)?;
+    Ok(())
+}
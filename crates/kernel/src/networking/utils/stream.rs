@@ -0,0 +1,85 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+// a bounded byte stream, used for both directions of a tcp connection. on the sending side the
+// front of the buffer is the oldest unacknowledged byte, on the receiving side it's the next byte
+// the application will read
+#[derive(Debug)]
+pub struct StreamBuffer {
+    data: VecDeque<u8>,
+    capacity: usize,
+}
+
+impl StreamBuffer {
+    pub fn new(capacity: usize) -> Self {
+        StreamBuffer {
+            data: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn free(&self) -> usize {
+        self.capacity - self.data.len()
+    }
+
+    // appends as much of buf as fits, and returns how much that was
+    pub fn write(&mut self, buf: &[u8]) -> usize {
+        let len = buf.len().min(self.free());
+        self.data.extend(&buf[..len]);
+        len
+    }
+
+    // removes and returns up to max bytes from the front
+    pub fn read(&mut self, max: usize) -> Vec<u8> {
+        let len = max.min(self.data.len());
+        self.data.drain(..len).collect()
+    }
+
+    // copies up to len bytes starting offset bytes from the front, without removing them
+    pub fn peek(&self, offset: usize, len: usize) -> Vec<u8> {
+        let start = offset.min(self.data.len());
+        let end = offset.saturating_add(len).min(self.data.len());
+        self.data.range(start..end).copied().collect()
+    }
+
+    // drops up to len bytes from the front, ie. once they've been acknowledged
+    pub fn discard(&mut self, len: usize) {
+        let len = len.min(self.data.len());
+        self.data.drain(..len);
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+}
+
+test_case!(stream_buffer);
+fn stream_buffer() -> Result<(), crate::test::BoxError> {
+    let mut buf = StreamBuffer::new(4);
+    kassert_eq!(buf.write(b"abcdef"), 4)?;
+    kassert_eq!(buf.free(), 0)?;
+    kassert_eq!(buf.peek(1, 2), b"bc")?;
+    kassert_eq!(buf.peek(3, 10), b"d")?;
+    kassert!(buf.peek(10, 1).is_empty())?;
+
+    buf.discard(1);
+    kassert_eq!(buf.write(b"xy"), 1)?;
+    kassert_eq!(buf.read(10), b"bcdx")?;
+    kassert!(buf.is_empty())?;
+
+    buf.discard(10);
+    kassert_eq!(buf.free(), 4)?;
+    Ok(())
+}
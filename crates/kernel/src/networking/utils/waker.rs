@@ -0,0 +1,40 @@
+use core::task::Waker;
+
+// the one task waiting on something a socket will do, like receive data. a socket only has one
+// reader and one writer, so a second task registering pushes the first one out, and that one is
+// woken so it can register again instead of being forgotten
+#[derive(Debug, Default)]
+pub struct WakerRegistration {
+    waker: Option<Waker>,
+}
+
+impl WakerRegistration {
+    pub const fn new() -> Self {
+        WakerRegistration { waker: None }
+    }
+
+    pub fn register(&mut self, waker: &Waker) {
+        match &self.waker {
+            Some(current) if current.will_wake(waker) => {}
+            _ => {
+                if let Some(old) = self.waker.replace(waker.clone()) {
+                    old.wake();
+                }
+            }
+        }
+    }
+
+    // wakes whoever registered, they have to register again to be woken another time
+    pub fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+// a socket that is closed can't wake anyone later, so they find out now
+impl Drop for WakerRegistration {
+    fn drop(&mut self) {
+        self.wake();
+    }
+}
@@ -1,10 +1,15 @@
-use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 
 use crate::sync::once_cell::BlockingOnceCell;
 use crate::sync::SpinLock;
 
+pub mod coredump;
 pub mod fd;
+pub mod job;
 pub mod mem;
 
 pub type ProcessRef = Arc<Process>;
@@ -17,25 +22,107 @@ pub struct ExitStatus {
     pub status: u32,
 }
 
+pub type Pid = u32;
+
 pub struct Process {
+    pub pid: Pid,
     pub mem: SpinLock<mem::UserAddrSpace>,
     pub root: Option<fd::ArcFd>,
     pub file_descriptors: SpinLock<FileDescriptorList>,
     pub exit_code: Arc<BlockingOnceCell<ExitStatus>>,
+    pub job: Arc<job::JobControl>,
+    pgid: AtomicU32,
+    ignored_signals: AtomicU64,
+    /// Largest core file to write if the process crashes, with 0
+    /// meaning none at all (see [`coredump`]).
+    core_limit: AtomicUsize,
+    killed: AtomicBool,
+}
+
+static NEXT_PID: AtomicU32 = AtomicU32::new(1);
+
+/// All processes that have been given a thread, for operations that
+/// need to act on every process (such as memory reclaim).
+static PROCESS_TABLE: SpinLock<BTreeMap<Pid, Weak<Process>>> = SpinLock::new(BTreeMap::new());
+
+fn alloc_pid() -> Pid {
+    NEXT_PID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Add a process to the global process table; repeated calls for the
+/// same process are harmless.
+pub fn register(process: &ProcessRef) {
+    let mut table = PROCESS_TABLE.lock();
+    table.retain(|_, proc| proc.strong_count() > 0);
+    table.insert(process.pid, Arc::downgrade(process));
+}
+
+/// Get a reference to every live process.
+pub fn all_processes() -> Vec<ProcessRef> {
+    let table = PROCESS_TABLE.lock();
+    table.values().filter_map(Weak::upgrade).collect()
 }
 
 impl Process {
     pub fn new() -> Self {
         let mem = mem::UserAddrSpace::new();
 
+        let pid = alloc_pid();
         Process {
+            pid,
             mem: SpinLock::new(mem),
             root: None,
             file_descriptors: SpinLock::new(FileDescriptorList { desc: Vec::new() }),
             exit_code: Arc::new(BlockingOnceCell::new()),
+            job: Arc::new(job::JobControl::new()),
+            pgid: AtomicU32::new(pid),
+            ignored_signals: AtomicU64::new(0),
+            core_limit: AtomicUsize::new(0),
+            killed: AtomicBool::new(false),
         }
     }
 
+    /// Terminate the process with the given exit status.
+    ///
+    /// This only marks the process as killed; its threads exit the
+    /// next time they are scheduled or fault, and its memory is freed
+    /// once the last of them is gone.
+    pub fn kill(&self, status: u32) {
+        self.set_exit_status(status);
+        self.killed.store(true, Ordering::SeqCst);
+        // Parked threads have to run to be freed
+        self.job.resume();
+    }
+
+    /// Record the exit status of the process, unless it already has one.
+    pub fn set_exit_status(&self, status: u32) {
+        if self.exit_code.try_set(ExitStatus { status }).is_ok() {
+            self.job.notify_exit();
+        }
+    }
+
+    /// The process group, which terminals send signals to.
+    pub fn pgid(&self) -> Pid {
+        self.pgid.load(Ordering::Relaxed)
+    }
+
+    pub fn set_pgid(&self, pgid: Pid) {
+        self.pgid.store(pgid, Ordering::Relaxed);
+    }
+
+    pub fn core_limit(&self) -> usize {
+        self.core_limit.load(Ordering::Relaxed)
+    }
+
+    /// Replace the core file size limit, returning the old one.
+    pub fn set_core_limit(&self, limit: usize) -> usize {
+        self.core_limit.swap(limit, Ordering::Relaxed)
+    }
+
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::Relaxed)
+    }
+
     pub fn get_ttbr0(&self) -> usize {
         self.mem.lock().get_ttbr0()
     }
@@ -56,11 +143,19 @@ impl Process {
             }
         }
 
+        // Children join their parent's process group and keep its core
+        // file limit, but start out without ignoring any signals
         let new_process = Process {
+            pid: alloc_pid(),
             mem: SpinLock::new(new_mem),
             root: self.root.clone(),
             file_descriptors: SpinLock::new(new_fds),
             exit_code: Arc::new(BlockingOnceCell::new()),
+            job: Arc::new(job::JobControl::new()),
+            pgid: AtomicU32::new(self.pgid()),
+            ignored_signals: AtomicU64::new(0),
+            core_limit: AtomicUsize::new(self.core_limit()),
+            killed: AtomicBool::new(false),
         };
 
         new_process
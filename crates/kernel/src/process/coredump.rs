@@ -0,0 +1,309 @@
+//! ELF core dumps of user processes that hit an unrecoverable fault.
+//!
+//! There is no writable filesystem yet, so the core file is streamed
+//! over the UART as base64, one line at a time with a `core: ` prefix,
+//! between `| core dump` marker lines.  It can be recovered from a
+//! serial log with:
+//!
+//! ```text
+//! grep -a '^core: ' serial.log | cut -c7- | base64 -d > core
+//! ```
+//!
+//! and then inspected with the `elf` crate's readelf-like binary, or
+//! with `gdb <program> core`.
+//!
+//! The file contains a single `NT_PRSTATUS` note with the registers of
+//! the faulting thread, and a `PT_LOAD` segment for every mapped range.
+//! Only memory owned by the process is included: ranges shared with a
+//! file (such as the framebuffer) are dumped without contents, and
+//! trailing pages that were never populated are left out of the file.
+//!
+//! Streaming a core file takes a long time at UART speeds, and happens
+//! in exception context, so dumps are opt-in: a process only gets one
+//! if it set a core file limit (`ulib::sys::set_core_limit`), and the
+//! file is cut short at that limit (or [`MAX_CORE_SIZE`]).  Segments
+//! past the cut keep their size but lose their contents, as they would
+//! with a core rlimit on Linux.  The console sets a limit, so it and
+//! every program started from it get core files.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use elf::note::{self, AArch64PrStatus};
+
+use super::mem::UserAddrSpace;
+use super::Process;
+use crate::arch::memory::vmm::{DIRECT_MAP_BASE, PAGE_SIZE};
+use crate::event::async_handler::HandlerContext;
+use crate::event::context::Context;
+
+pub const SIGILL: i32 = 4;
+pub const SIGTRAP: i32 = 5;
+pub const SIGBUS: i32 = 7;
+pub const SIGFPE: i32 = 8;
+pub const SIGSEGV: i32 = 11;
+
+const ET_CORE: u16 = 4;
+const EM_AARCH64: u16 = 183;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_RWX: u32 = 0x7;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+
+/// Input bytes per line of output; encodes to 76 characters.
+const LINE_BYTES: usize = 57;
+
+/// Largest core file ever written, whatever the process's limit.  At
+/// 115200 baud this is still several minutes of output.
+pub const MAX_CORE_SIZE: usize = 4 << 20;
+
+/// Get the signal that Linux would deliver for an exception class.
+pub fn signal_for_exception(class: u64) -> i32 {
+    match class {
+        0x20 | 0x24 => SIGSEGV,
+        0x22 | 0x26 => SIGBUS,
+        0x28 | 0x2c => SIGFPE,
+        0x30..=0x35 | 0x38 | 0x3c => SIGTRAP,
+        _ => SIGILL,
+    }
+}
+
+struct Segment {
+    vaddr: usize,
+    memsz: usize,
+    filesz: usize,
+}
+
+/// The shape of the core file, worked out once under the lock.
+struct Layout {
+    segments: Vec<Segment>,
+    data_offset: usize,
+    truncated: bool,
+}
+
+/// Work out the segments and the pages to write, cutting the contents
+/// short so that the whole file fits in `limit` bytes.
+fn layout(mem: &UserAddrSpace, limit: usize) -> Layout {
+    let phnum = 1 + mem.vmes().count();
+    let note_offset = EHDR_SIZE + phnum * PHDR_SIZE;
+    let note_size = note::note_size(note::CORE_NOTE_NAME, size_of::<AArch64PrStatus>());
+    let data_offset = (note_offset + note_size).next_multiple_of(PAGE_SIZE);
+
+    let mut budget = limit.saturating_sub(data_offset) / PAGE_SIZE * PAGE_SIZE;
+    let mut truncated = false;
+    let mut segments = Vec::new();
+    for vme in mem.vmes() {
+        let mut filesz = if vme.kind.owns_frames() {
+            (vme.start..vme.start + vme.size)
+                .step_by(PAGE_SIZE)
+                .rev()
+                .find(|&page| mem.translate_page(page).is_some())
+                .map_or(0, |page| page + PAGE_SIZE - vme.start)
+        } else {
+            0
+        };
+        if filesz > budget {
+            filesz = budget;
+            truncated = true;
+        }
+        budget -= filesz;
+
+        segments.push(Segment {
+            vaddr: vme.start,
+            memsz: vme.size,
+            filesz,
+        });
+    }
+
+    Layout {
+        segments,
+        data_offset,
+        truncated,
+    }
+}
+
+/// Write a core file for `proc` to the UART, with `regs` as the state
+/// of the faulting thread, if the process asked for core files.
+pub fn dump_core(proc: &Process, regs: &Context, signal: i32) {
+    let limit = proc.core_limit().min(MAX_CORE_SIZE);
+    if limit == 0 {
+        return;
+    }
+
+    // Writing the file takes far longer than working out its layout, so
+    // the lock is only held while copying each page out, and other
+    // threads of the process can keep running.  A page they unmap in the
+    // meantime is written as zeros; its frame may already be reused.
+    let layout = layout(&proc.mem.lock(), limit);
+
+    let phnum = 1 + layout.segments.len();
+    let note_offset = EHDR_SIZE + phnum * PHDR_SIZE;
+    let note_size = note::note_size(note::CORE_NOTE_NAME, size_of::<AArch64PrStatus>());
+    let data_offset = layout.data_offset;
+    let file_size = data_offset + layout.segments.iter().map(|s| s.filesz).sum::<usize>();
+
+    println!(
+        "| core dump begin: pid {}, signal {signal}, {file_size} bytes",
+        proc.pid
+    );
+    if layout.truncated {
+        println!("| core dump truncated to the limit of {limit} bytes");
+    }
+    let mut out = Base64Writer::new();
+
+    let mut header = [0u8; EHDR_SIZE];
+    header[..8].copy_from_slice(b"\x7fELF\x02\x01\x01\x00");
+    header[16..18].copy_from_slice(&ET_CORE.to_le_bytes());
+    header[18..20].copy_from_slice(&EM_AARCH64.to_le_bytes());
+    header[20..24].copy_from_slice(&1u32.to_le_bytes());
+    header[32..40].copy_from_slice(&(EHDR_SIZE as u64).to_le_bytes());
+    header[52..54].copy_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+    header[54..56].copy_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+    header[56..58].copy_from_slice(&(phnum as u16).to_le_bytes());
+    header[58..60].copy_from_slice(&64u16.to_le_bytes());
+    out.write(&header);
+
+    out.write(&program_header(PT_NOTE, 0, note_offset, 0, note_size, 0, 4));
+    let mut offset = data_offset;
+    for seg in &layout.segments {
+        out.write(&program_header(
+            PT_LOAD, PF_RWX, offset, seg.vaddr, seg.filesz, seg.memsz, PAGE_SIZE,
+        ));
+        offset += seg.filesz;
+    }
+
+    let mut pr_reg = [0u64; 34];
+    for (reg, val) in pr_reg.iter_mut().zip(regs.regs) {
+        *reg = val as u64;
+    }
+    pr_reg[31] = regs.sp_el0 as u64;
+    pr_reg[32] = regs.elr as u64;
+    pr_reg[33] = regs.spsr as u64;
+    let status = AArch64PrStatus::new(proc.pid as i32, signal, pr_reg);
+
+    let mut note_header = [0u8; 20];
+    let len = note::write_note_header(
+        &mut note_header,
+        note::CORE_NOTE_NAME,
+        note::NT_PRSTATUS,
+        size_of::<AArch64PrStatus>(),
+    );
+    out.write(&note_header[..len]);
+    out.write(status.as_bytes());
+    out.write_zeros(data_offset - out.total);
+
+    let mut buf = vec![0u8; PAGE_SIZE];
+    for seg in &layout.segments {
+        for page in (seg.vaddr..seg.vaddr + seg.filesz).step_by(PAGE_SIZE) {
+            let mem = proc.mem.lock();
+            match mem.translate_page(page) {
+                Some(paddr) => buf.copy_from_slice(unsafe {
+                    core::slice::from_raw_parts((DIRECT_MAP_BASE + paddr) as *const u8, PAGE_SIZE)
+                }),
+                None => buf.fill(0),
+            }
+            drop(mem);
+            out.write(&buf);
+        }
+    }
+
+    out.flush();
+    println!("| core dump end: pid {}", proc.pid);
+}
+
+/// Write a core file for the process of the thread running `context`.
+pub fn dump_current(context: &mut HandlerContext<'_>, signal: i32) {
+    let Some(proc) = context.cur_process().cloned() else {
+        return;
+    };
+    dump_core(&proc, &context.regs(), signal);
+}
+
+fn program_header(
+    p_type: u32,
+    flags: u32,
+    offset: usize,
+    vaddr: usize,
+    filesz: usize,
+    memsz: usize,
+    align: usize,
+) -> [u8; PHDR_SIZE] {
+    let mut phdr = [0u8; PHDR_SIZE];
+    phdr[0..4].copy_from_slice(&p_type.to_le_bytes());
+    phdr[4..8].copy_from_slice(&flags.to_le_bytes());
+    phdr[8..16].copy_from_slice(&(offset as u64).to_le_bytes());
+    phdr[16..24].copy_from_slice(&(vaddr as u64).to_le_bytes());
+    phdr[32..40].copy_from_slice(&(filesz as u64).to_le_bytes());
+    phdr[40..48].copy_from_slice(&(memsz as u64).to_le_bytes());
+    phdr[48..56].copy_from_slice(&(align as u64).to_le_bytes());
+    phdr
+}
+
+struct Base64Writer {
+    line: [u8; LINE_BYTES],
+    len: usize,
+    total: usize,
+}
+
+impl Base64Writer {
+    fn new() -> Self {
+        Self {
+            line: [0; LINE_BYTES],
+            len: 0,
+            total: 0,
+        }
+    }
+
+    fn write(&mut self, mut data: &[u8]) {
+        self.total += data.len();
+        while !data.is_empty() {
+            let n = data.len().min(LINE_BYTES - self.len);
+            self.line[self.len..self.len + n].copy_from_slice(&data[..n]);
+            self.len += n;
+            data = &data[n..];
+            if self.len == LINE_BYTES {
+                self.flush();
+            }
+        }
+    }
+
+    fn write_zeros(&mut self, count: usize) {
+        for _ in 0..count / LINE_BYTES {
+            self.write(&[0; LINE_BYTES]);
+        }
+        self.write(&[0; LINE_BYTES][..count % LINE_BYTES]);
+    }
+
+    fn flush(&mut self) {
+        if self.len == 0 {
+            return;
+        }
+        const ALPHABET: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+        let mut encoded = [0u8; LINE_BYTES / 3 * 4];
+        let mut out_len = 0;
+        for chunk in self.line[..self.len].chunks(3) {
+            let b = [
+                chunk[0],
+                *chunk.get(1).unwrap_or(&0),
+                *chunk.get(2).unwrap_or(&0),
+            ];
+            let bits = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+            for i in 0..4 {
+                encoded[out_len + i] = if i <= chunk.len() {
+                    ALPHABET[(bits >> (18 - 6 * i)) as usize & 0x3f]
+                } else {
+                    b'='
+                };
+            }
+            out_len += 4;
+        }
+        self.len = 0;
+
+        let encoded = core::str::from_utf8(&encoded[..out_len]).unwrap();
+        println!("core: {encoded}");
+    }
+}
@@ -60,11 +60,27 @@ pub trait FileDescriptor: Any {
     fn size<'a>(&'a self) -> SmallFuture<'a, FileDescResult>;
     fn mmap_page(&self, offset: u64) -> SmallFuture<Option<FileDescResult>>;
 
+    /// Whether each page returned by [`mmap_page`](Self::mmap_page) is a
+    /// freshly allocated copy of the file contents.  If so, the mapping
+    /// owns the frame, and may free it (and later read it back from the
+    /// file) as long as it has not been written to.
+    fn mmap_is_private_copy(&self) -> bool {
+        false
+    }
+
     fn open<'a>(&'a self, name: &'a [u8]) -> SmallFuture<'a, Result<ArcFd, ()>> {
         let _ = name;
         boxed_future(async move { Err(()).into() })
     }
 
+    /// Device-specific control requests; `arg` is usually a pointer to
+    /// a user buffer, and the user's address space is active during the
+    /// call.
+    fn ioctl(&self, request: u64, arg: usize) -> FileDescResult {
+        let _ = (request, arg);
+        FileDescResult::err(1)
+    }
+
     // TODO: unneeded after rust 1.86 by trait upcasting
     fn as_any(&self) -> &dyn Any;
 }
@@ -0,0 +1,173 @@
+//! Job control: process groups, and stopping, continuing and
+//! terminating processes on behalf of shells and terminals.
+//!
+//! There are no signal handlers; a signal either stops the process,
+//! continues it, or kills it with an exit status of `128 + signal`, as
+//! a shell would report it.  Processes may ignore the signals that
+//! would otherwise interrupt or stop them.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::event::thread::Thread;
+use crate::event::{Event, SCHEDULER};
+use crate::sync::once_cell::BlockingOnceCell;
+use crate::sync::{Condvar, SpinLock};
+
+use super::{ExitStatus, Pid, Process};
+
+pub const SIGINT: u32 = 2;
+pub const SIGQUIT: u32 = 3;
+pub const SIGKILL: u32 = 9;
+pub const SIGTERM: u32 = 15;
+pub const SIGCONT: u32 = 18;
+pub const SIGSTOP: u32 = 19;
+pub const SIGTSTP: u32 = 20;
+
+/// The signals that can't be ignored.
+const UNIGNORABLE: u64 = (1 << SIGKILL) | (1 << SIGSTOP) | (1 << SIGCONT);
+
+pub enum JobEvent {
+    Exited(u32),
+    Stopped,
+}
+
+/// Whether a process is stopped, shared between the process and the
+/// wait fd its parent uses to follow it.
+pub struct JobControl {
+    stopped: AtomicBool,
+    state: SpinLock<JobState>,
+    changed: Condvar,
+}
+
+struct JobState {
+    /// Whether the current stop has been reported by [`JobControl::wait`].
+    reported: bool,
+    /// Threads of the process that were about to run while it was stopped.
+    #[allow(clippy::vec_box)]
+    parked: Vec<Box<Thread>>,
+}
+
+impl JobControl {
+    pub fn new() -> Self {
+        JobControl {
+            stopped: AtomicBool::new(false),
+            state: SpinLock::new(JobState {
+                reported: false,
+                parked: Vec::new(),
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Stop the process; its threads are parked the next time they
+    /// would return to user mode.
+    pub fn stop(&self) {
+        let mut state = self.state.lock();
+        if !self.stopped.swap(true, Ordering::SeqCst) {
+            state.reported = false;
+        }
+        drop(state);
+        self.changed.notify_all();
+    }
+
+    /// Continue a stopped process, rescheduling its parked threads.
+    pub fn resume(&self) {
+        let mut state = self.state.lock();
+        self.stopped.store(false, Ordering::SeqCst);
+        let parked = core::mem::take(&mut state.parked);
+        drop(state);
+        for thread in parked {
+            SCHEDULER.add_task(Event::schedule_thread(thread));
+        }
+        self.changed.notify_all();
+    }
+
+    /// Keep a thread of the process from running until it's continued.
+    /// The thread is handed back if the process isn't stopped anymore.
+    pub fn park(&self, thread: Box<Thread>) -> Result<(), Box<Thread>> {
+        let mut state = self.state.lock();
+        if !self.is_stopped() {
+            return Err(thread);
+        }
+        state.parked.push(thread);
+        Ok(())
+    }
+
+    /// Wake anyone waiting in [`wait`](Self::wait), after the process exits.
+    pub fn notify_exit(&self) {
+        // Taking the lock orders this after a waiter's check of the exit
+        // status, so the wakeup can't be missed
+        drop(self.state.lock());
+        self.changed.notify_all();
+    }
+
+    /// Wait until the process exits, or stops without that having been
+    /// reported yet.
+    pub async fn wait(&self, exit_code: &BlockingOnceCell<ExitStatus>) -> JobEvent {
+        let mut state = self.state.lock();
+        loop {
+            if let Some(status) = exit_code.try_get() {
+                return JobEvent::Exited(status.status);
+            }
+            if self.is_stopped() && !state.reported {
+                state.reported = true;
+                return JobEvent::Stopped;
+            }
+            state = self.changed.wait(state).await;
+        }
+    }
+}
+
+impl Process {
+    /// Deliver a signal to the process.
+    pub fn signal(&self, signal: u32) {
+        if signal >= 64 || signal == 0 {
+            return;
+        }
+        let ignored = self.ignored_signals.load(Ordering::Relaxed) & !UNIGNORABLE;
+        if ignored & (1 << signal) != 0 || self.exit_code.try_get().is_some() {
+            return;
+        }
+        match signal {
+            SIGCONT => self.job.resume(),
+            SIGSTOP | SIGTSTP => {
+                // Stopping init would leave nothing to continue it
+                if self.pid != 1 {
+                    self.job.stop();
+                }
+            }
+            _ => self.kill(128 + signal),
+        }
+    }
+
+    /// Replace the set of ignored signals, returning the old one.
+    pub fn set_ignored_signals(&self, mask: u64) -> u64 {
+        self.ignored_signals
+            .swap(mask & !UNIGNORABLE, Ordering::Relaxed)
+    }
+}
+
+/// Deliver a signal to every live process in a group, returning whether
+/// there were any.
+pub fn signal_group(pgid: Pid, signal: u32) -> bool {
+    let mut found = false;
+    for process in super::all_processes() {
+        if process.pgid() == pgid && process.exit_code.try_get().is_none() {
+            process.signal(signal);
+            found = true;
+        }
+    }
+    found
+}
+
+/// Find a live process by its pid.
+pub fn find_process(pid: Pid) -> Option<super::ProcessRef> {
+    super::all_processes().into_iter().find(|p| p.pid == pid)
+}
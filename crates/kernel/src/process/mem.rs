@@ -1,33 +1,53 @@
 use alloc::boxed::Box;
 use alloc::collections::btree_map::BTreeMap;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
+use crate::arch::memory::asid;
 use crate::arch::memory::machine::{LeafDescriptor, TranslationDescriptor};
-use crate::arch::memory::palloc::{Size4KiB, PAGE_ALLOCATOR};
+use crate::arch::memory::palloc::{PhysicalPage, Size2MiB, Size4KiB, PAGE_ALLOCATOR};
 use crate::arch::memory::table::PageTablePtr;
 use crate::arch::memory::vmm::{
-    alloc_top_page_table, get_translation_descriptor, set_translation_descriptor, MappingError,
-    PAGE_SIZE, USER_PG_SZ,
+    alloc_page_table, alloc_top_page_table, get_translation_descriptor, set_translation_descriptor,
+    MappingError, DIRECT_MAP_BASE, HUGE_PG_SZ, PAGE_SIZE, USER_PG_SZ,
 };
 use crate::event::async_handler::{run_async_handler, HandlerContext};
 use crate::event::context::Context;
 use crate::event::exceptions::DataAbortISS;
+use crate::memory::reclaim::{OomAction, OOM_EXIT_STATUS, OOM_WAIT};
 use crate::syscall::proc::exit_user_thread;
 
 use crate::syscall::fb_hack::MemFd;
 
+use super::coredump;
 use super::fd::ArcFd;
 
+/// The end of the user half of the address space.
+pub const USER_SPACE_END: usize = 1 << 48;
+
+/// Whether the `len` bytes at `addr` are all in the user half of the
+/// address space (they may still be unmapped).
+pub fn is_user_range(addr: usize, len: usize) -> bool {
+    addr.checked_add(len)
+        .is_some_and(|end| end <= USER_SPACE_END)
+}
+
 #[derive(Debug)]
 pub enum MmapError {
     MemoryRangeCollision,
     NoSuchEntry,
     RequestedSizeUnavailable,
     FileError,
+    OutOfMemory,
+    /// The address or size doesn't describe a valid range.
+    InvalidArgument,
 }
 
 pub struct UserAddrSpace {
     table: PageTablePtr,
     memory_range_map: BTreeMap<usize, MemoryRangeNode>, //key: start addr
+    /// Number of mapped frames that this address space is responsible
+    /// for freeing.
+    owned_pages: AtomicUsize,
 }
 
 #[derive(Clone)]
@@ -35,6 +55,8 @@ pub struct MemoryRangeNode {
     pub start: usize,
     pub size: usize,
     pub kind: MappingKind,
+    /// Populate the range with 2 MiB blocks where they fit.
+    pub huge: bool,
 }
 
 #[derive(Clone)]
@@ -43,6 +65,17 @@ pub enum MappingKind {
     File { fd: ArcFd, offset: usize },
 }
 
+impl MappingKind {
+    /// Whether the frames mapped for this range belong to the mapping
+    /// (rather than being shared with a file).
+    pub fn owns_frames(&self) -> bool {
+        match self {
+            MappingKind::Anon => true,
+            MappingKind::File { fd, .. } => fd.mmap_is_private_copy(),
+        }
+    }
+}
+
 impl UserAddrSpace {
     pub fn new() -> Self {
         let table = alloc_top_page_table();
@@ -50,6 +83,7 @@ impl UserAddrSpace {
         Self {
             table,
             memory_range_map: BTreeMap::new(),
+            owned_pages: AtomicUsize::new(0),
         }
     }
 
@@ -57,8 +91,13 @@ impl UserAddrSpace {
         self.table.paddr()
     }
 
+    /// The number of physical pages that would be freed by tearing
+    /// down this address space.
+    pub fn owned_pages(&self) -> usize {
+        self.owned_pages.load(Ordering::Relaxed)
+    }
+
     pub async fn fork(&self) -> Self {
-        use core::arch::asm;
         use core::mem::MaybeUninit;
         use core::ptr::copy_nonoverlapping;
 
@@ -71,19 +110,15 @@ impl UserAddrSpace {
         let old_page_dir = self.get_ttbr0();
         let new_page_dir = new_mem.get_ttbr0();
 
-        let active_page_dir: usize;
-        unsafe {
-            asm!("mrs {0}, TTBR0_EL1", out(reg) active_page_dir);
-            if active_page_dir != old_page_dir {
-                asm!("msr TTBR0_EL1, {0}", "isb", "dsb sy", "tlbi vmalle1is", "dsb sy", in(reg) old_page_dir);
-            }
-        }
+        let active_page_dir = asid::current_table();
+        unsafe { asid::switch_user_table(old_page_dir) };
 
         for (range_start, node) in &self.memory_range_map {
             let start = new_mem
                 .insert_vme_at(node.start, node.size, node.kind.clone())
                 .unwrap();
             assert!(start == *range_start);
+            new_mem.memory_range_map.get_mut(&start).unwrap().huge = node.huge;
 
             for offset in (0..node.size).step_by(buf_size) {
                 let chunk_size = (node.size - offset).min(buf_size);
@@ -108,17 +143,15 @@ impl UserAddrSpace {
                 let buf_ptr: *mut u8 = buffer.as_mut_ptr().cast();
                 unsafe {
                     copy_nonoverlapping(src_data.byte_add(offset), buf_ptr, chunk_size);
-                    asm!("msr TTBR0_EL1, {0}", "isb", "dsb sy", "tlbi vmalle1is", "dsb sy", in(reg) new_page_dir);
+                    asid::switch_user_table(new_page_dir);
                     copy_nonoverlapping(buf_ptr, dst_data.byte_add(offset), chunk_size);
-                    asm!("msr TTBR0_EL1, {0}", "isb", "dsb sy", "tlbi vmalle1is", "dsb sy", in(reg) old_page_dir);
+                    asid::switch_user_table(old_page_dir);
                 }
             }
         }
 
         if active_page_dir != old_page_dir {
-            unsafe {
-                asm!("msr TTBR0_EL1, {0}", "isb", "dsb sy", "tlbi vmalle1is", "dsb sy", in(reg) active_page_dir);
-            }
+            unsafe { asid::switch_user_table(active_page_dir) };
         }
 
         new_mem
@@ -131,7 +164,11 @@ impl UserAddrSpace {
         kind: MappingKind,
     ) -> Result<usize, MmapError> {
         let start_addr = (start / PAGE_SIZE) * PAGE_SIZE;
-        let size_pages = (size + (start - start_addr)).next_multiple_of(PAGE_SIZE);
+        let size_pages = size
+            .checked_add(start - start_addr)
+            .and_then(|size| size.checked_next_multiple_of(PAGE_SIZE))
+            .filter(|size| start_addr.checked_add(*size).is_some())
+            .ok_or(MmapError::InvalidArgument)?;
 
         if let Some((_, last_before)) = self.memory_range_map.range(0..start_addr).last() {
             if last_before.start + last_before.size > start_addr {
@@ -148,25 +185,37 @@ impl UserAddrSpace {
             start: start_addr,
             size: size_pages,
             kind,
+            huge: false,
         };
         self.memory_range_map.insert(start_addr, node);
         Ok(start_addr)
     }
 
     pub fn find_vme_space(&mut self, size: usize) -> Result<usize, MmapError> {
-        let size = size.next_multiple_of(PAGE_SIZE);
+        self.find_vme_space_aligned(size, PAGE_SIZE)
+    }
+
+    pub fn find_vme_space_aligned(
+        &mut self,
+        size: usize,
+        align: usize,
+    ) -> Result<usize, MmapError> {
+        let size = size
+            .checked_next_multiple_of(PAGE_SIZE)
+            .ok_or(MmapError::InvalidArgument)?;
 
         let mut prev_end = 4096; // Don't map the null page...
         for (_, node) in self.memory_range_map.range(prev_end..) {
-            if node.start - prev_end >= size {
-                return Ok(prev_end);
+            let start = prev_end.next_multiple_of(align);
+            if node.start >= start && node.start - start >= size {
+                return Ok(start);
             }
             prev_end = node.start + node.size;
         }
 
-        let space_end = 1 << 48;
-        if space_end - prev_end >= size {
-            Ok(prev_end)
+        let start = prev_end.next_multiple_of(align);
+        if USER_SPACE_END >= start && USER_SPACE_END - start >= size {
+            Ok(start)
         } else {
             Err(MmapError::RequestedSizeUnavailable)
         }
@@ -186,6 +235,25 @@ impl UserAddrSpace {
         Ok(base_addr)
     }
 
+    /// Create an anonymous mapping that is backed by 2 MiB blocks
+    /// (falling back to individual pages if none are available).
+    pub fn mmap_huge(
+        &mut self,
+        start_addr: Option<usize>,
+        size: usize,
+    ) -> Result<usize, MmapError> {
+        let size = size
+            .checked_next_multiple_of(HUGE_PG_SZ)
+            .ok_or(MmapError::InvalidArgument)?;
+        let start_addr = match start_addr {
+            Some(s) => s,
+            None => self.find_vme_space_aligned(size, HUGE_PG_SZ)?,
+        };
+        let base_addr = self.insert_vme_at(start_addr, size, MappingKind::Anon)?;
+        self.memory_range_map.get_mut(&base_addr).unwrap().huge = true;
+        Ok(base_addr)
+    }
+
     // Addr must be the start of a VME
     pub fn unmap(&mut self, addr: usize) -> Result<(), MmapError> {
         let vme = self
@@ -193,54 +261,206 @@ impl UserAddrSpace {
             .remove(&addr)
             .ok_or(MmapError::NoSuchEntry)?;
 
+        self.unmap_pages(&vme.kind, vme.start, vme.start + vme.size);
+
+        Ok(())
+    }
+
+    /// Unmap every page in the given range, which may cover parts of
+    /// several VMEs; the VMEs are trimmed or split to match.
+    pub fn unmap_range(&mut self, addr: usize, size: usize) -> Result<(), MmapError> {
+        let start = (addr / PAGE_SIZE) * PAGE_SIZE;
+        let end = addr
+            .checked_add(size)
+            .and_then(|end| end.checked_next_multiple_of(PAGE_SIZE))
+            .ok_or(MmapError::InvalidArgument)?;
+
+        let overlapping: alloc::vec::Vec<usize> = self
+            .memory_range_map
+            .range(..end)
+            .filter(|(_, node)| node.start + node.size > start)
+            .map(|(k, _)| *k)
+            .collect();
+        if overlapping.is_empty() {
+            return Err(MmapError::NoSuchEntry);
+        }
+
+        for key in overlapping {
+            let vme = self.memory_range_map.remove(&key).unwrap();
+            let vme_end = vme.start + vme.size;
+            let (a, b) = (start.max(vme.start), end.min(vme_end));
+
+            self.unmap_pages(&vme.kind, a, b);
+
+            if vme.start < a {
+                let mut left = vme.clone();
+                left.size = a - vme.start;
+                self.memory_range_map.insert(left.start, left);
+            }
+            if b < vme_end {
+                let mut right = vme.clone();
+                right.start = b;
+                right.size = vme_end - b;
+                if let MappingKind::File { offset, .. } = &mut right.kind {
+                    *offset += b - vme.start;
+                }
+                self.memory_range_map.insert(right.start, right);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn unmap_pages(&self, kind: &MappingKind, start: usize, end: usize) {
         // TODO: only unmap allocated pages
-        for virt_addr in (vme.start..(vme.start + vme.size)).step_by(USER_PG_SZ) {
+        let mut virt_addr = start;
+        while virt_addr < end {
             let cur = unsafe { get_translation_descriptor(self.table, virt_addr, 3, 0) };
 
             let desc = match cur {
                 Ok(desc) => desc,
                 Err(MappingError::HugePagePresent) => {
-                    todo!("Unmapping ranges with huge pages?")
+                    let block = (virt_addr / HUGE_PG_SZ) * HUGE_PG_SZ;
+                    if block >= start && block + HUGE_PG_SZ <= end {
+                        self.unmap_block(block);
+                        virt_addr = block + HUGE_PG_SZ;
+                    } else {
+                        // Only part of the block is being unmapped
+                        self.split_block(block);
+                    }
+                    continue;
+                }
+                Err(MappingError::LevelEntryUnset(_)) => {
+                    virt_addr += USER_PG_SZ;
+                    continue;
                 }
-                Err(MappingError::LevelEntryUnset(_)) => continue,
                 Err(_) => todo!(),
             };
 
             let leaf = unsafe { desc.leaf };
 
             if leaf.is_valid() {
-                let new_desc = TranslationDescriptor::unset();
-                unsafe {
-                    set_translation_descriptor(self.table, virt_addr, 3, 0, new_desc, false)
-                        .unwrap()
-                }
-                //Need this invalidation here or it still accesses old page
-                unsafe {
-                    core::arch::asm! {
-                        "dsb ISH",
-                        "tlbi vaae1, {0}",
-                        in(reg) (virt_addr >> 12)
-                        //options(readonly, nostack, preserves_flags)
-                    }
-                }
+                self.unmap_page(virt_addr, leaf, kind);
+            }
+            virt_addr += USER_PG_SZ;
+        }
 
-                // TODO: free it
-                match &vme.kind {
-                    MappingKind::Anon => {
-                        // TODO: free
-                    }
-                    MappingKind::File { .. } => {
-                        // TODO: notify file that it's unused?
-                        // (for ref counts, page cache?)
-                    }
+        // TODO: don't flush in each individual set_descriptor
+    }
+
+    fn block_leaf(&self, block: usize) -> LeafDescriptor {
+        let desc = unsafe { get_translation_descriptor(self.table, block, 2, 0) }.unwrap();
+        let leaf = unsafe { desc.leaf };
+        assert!(leaf.is_valid() && !leaf.contains(LeafDescriptor::IS_PAGE_DESCRIPTOR));
+        leaf
+    }
+
+    /// Remove a 2 MiB block mapping, and free its frames.
+    fn unmap_block(&self, block: usize) {
+        let leaf = self.block_leaf(block);
+        let new_desc = TranslationDescriptor::unset();
+        unsafe { set_translation_descriptor(self.table, block, 2, 0, new_desc, false).unwrap() }
+        self.invalidate_page(block);
+
+        // Huge mappings are always anonymous
+        let frame = PhysicalPage::<Size2MiB>::new(leaf.get_pa());
+        PAGE_ALLOCATOR.get().dealloc_huge_frame(frame);
+        self.owned_pages
+            .fetch_sub(HUGE_PG_SZ / PAGE_SIZE, Ordering::Relaxed);
+    }
+
+    /// Replace a 2 MiB block mapping with a table of pages mapping the
+    /// same frames, so that they can be unmapped individually.
+    fn split_block(&self, block: usize) {
+        let leaf = self.block_leaf(block);
+        let mut table = alloc_page_table();
+        for i in 0..HUGE_PG_SZ / PAGE_SIZE {
+            let paddr = leaf.get_pa().0 + i * PAGE_SIZE;
+            let page = leaf
+                .difference(LeafDescriptor::OA)
+                .union(LeafDescriptor::from_bits_retain(paddr as u64))
+                .union(LeafDescriptor::IS_PAGE_DESCRIPTOR);
+            unsafe { table.set_entry(i, page.into()) };
+        }
+
+        // Break before make: the block has to be gone from the TLB before
+        // the table replaces it.
+        let unset = TranslationDescriptor::unset();
+        unsafe { set_translation_descriptor(self.table, block, 2, 0, unset, false).unwrap() };
+        self.invalidate_page(block);
+        let desc = table.to_descriptor();
+        unsafe { set_translation_descriptor(self.table, block, 2, 0, desc.into(), false).unwrap() };
+    }
+
+    fn invalidate_page(&self, vaddr: usize) {
+        asid::invalidate_page(self.table.paddr(), vaddr);
+    }
+
+    /// Remove a single valid leaf mapping, freeing the frame if this
+    /// address space owns it.
+    fn unmap_page(&self, virt_addr: usize, leaf: LeafDescriptor, kind: &MappingKind) {
+        let new_desc = TranslationDescriptor::unset();
+        unsafe { set_translation_descriptor(self.table, virt_addr, 3, 0, new_desc, false).unwrap() }
+        //Need this invalidation here or it still accesses old page
+        self.invalidate_page(virt_addr);
+
+        if kind.owns_frames() {
+            let frame = PhysicalPage::<Size4KiB>::new(leaf.get_pa());
+            PAGE_ALLOCATOR.get().dealloc_frame(frame);
+            self.owned_pages.fetch_sub(1, Ordering::Relaxed);
+        }
+        // TODO: notify file that it's unused for shared mappings?
+        // (for ref counts, page cache?)
+    }
+
+    /// Unmap up to `target` clean pages of private file mappings; they
+    /// are read back from the file on the next access.  Returns the
+    /// number of pages freed.
+    pub fn reclaim_clean_pages(&self, target: usize) -> usize {
+        let mut freed = 0;
+        for vme in self.memory_range_map.values() {
+            if !matches!(vme.kind, MappingKind::File { .. }) || !vme.kind.owns_frames() {
+                continue;
+            }
+            for virt_addr in (vme.start..(vme.start + vme.size)).step_by(USER_PG_SZ) {
+                if freed >= target {
+                    return freed;
+                }
+                let Ok(desc) = (unsafe { get_translation_descriptor(self.table, virt_addr, 3, 0) })
+                else {
+                    continue;
+                };
+                let leaf = unsafe { desc.leaf };
+                if leaf.is_valid() && !leaf.contains(LeafDescriptor::SW_DIRTY) {
+                    self.unmap_page(virt_addr, leaf, &vme.kind);
+                    freed += 1;
                 }
             }
         }
+        freed
+    }
 
-        // TODO: flush?
-        // TODO: don't flush in each individual set_descriptor
+    /// Iterate over all mapped ranges, in order of address.
+    pub fn vmes(&self) -> impl Iterator<Item = &MemoryRangeNode> {
+        self.memory_range_map.values()
+    }
 
-        Ok(())
+    /// Get the physical address of the page mapped at `vaddr`, if it
+    /// has been populated.
+    pub fn translate_page(&self, vaddr: usize) -> Option<usize> {
+        let vaddr = (vaddr / PAGE_SIZE) * PAGE_SIZE;
+        match unsafe { get_translation_descriptor(self.table, vaddr, 3, 0) } {
+            Ok(desc) => {
+                let leaf = unsafe { desc.leaf };
+                leaf.is_valid().then(|| leaf.get_pa().0)
+            }
+            Err(MappingError::HugePagePresent) => {
+                let desc = unsafe { get_translation_descriptor(self.table, vaddr, 2, 0) }.ok()?;
+                let leaf = unsafe { desc.leaf };
+                Some(leaf.get_pa().0 + vaddr % HUGE_PG_SZ)
+            }
+            Err(_) => None,
+        }
     }
 
     pub fn get_vme(&self, addr: usize) -> Option<&MemoryRangeNode> {
@@ -253,30 +473,57 @@ impl UserAddrSpace {
         None
     }
 
+    /// Map the page at `vaddr` if it isn't already present.
+    ///
+    /// Pages of private file mappings that are populated for reading
+    /// are mapped read-only, so that the first write marks them dirty
+    /// (and no longer eligible for reclaim).
     pub async fn populate_page(
         &self,
         vme: &MemoryRangeNode,
         vaddr: usize,
+        write: bool,
     ) -> Result<(), MmapError> {
         assert!(vaddr % PAGE_SIZE == 0);
 
-        if let Ok(desc) = unsafe { get_translation_descriptor(self.table, vaddr, 3, 0) } {
-            if unsafe { desc.leaf }.is_valid() {
+        let cur = unsafe { get_translation_descriptor(self.table, vaddr, 3, 0) };
+        if let Err(MappingError::HugePagePresent) = cur {
+            return Ok(());
+        }
+        if vme.huge && self.populate_block(vme, vaddr) {
+            return Ok(());
+        }
+
+        if let Ok(desc) = cur {
+            let leaf = unsafe { desc.leaf };
+            if leaf.is_valid() {
+                if write && leaf.contains(LeafDescriptor::READ_ONLY) {
+                    let desc = leaf
+                        .difference(LeafDescriptor::READ_ONLY)
+                        .union(LeafDescriptor::SW_DIRTY);
+                    unsafe {
+                        set_translation_descriptor(self.table, vaddr, 3, 0, desc.into(), false)
+                            .unwrap();
+                    }
+                    self.invalidate_page(vaddr);
+                }
                 return Ok(());
             }
         }
 
         let desc = match &vme.kind {
             MappingKind::Anon => {
-                let page = PAGE_ALLOCATOR.get().alloc_frame::<Size4KiB>();
-                let desc = LeafDescriptor::new(page.paddr)
+                let page = PAGE_ALLOCATOR
+                    .get()
+                    .try_alloc_user_frame::<Size4KiB>()
+                    .ok_or(MmapError::OutOfMemory)?;
+                let paddr = page.paddr;
+                let virt = PAGE_ALLOCATOR.get().get_mapped_frame(page);
+                unsafe { core::ptr::write_bytes(virt, 0, 1) };
+
+                LeafDescriptor::new(paddr)
                     .union(LeafDescriptor::UNPRIVILEGED_ACCESS)
                     .difference(LeafDescriptor::UXN)
-                    .set_global();
-
-                // TODO: zero the page...
-
-                desc
             }
             MappingKind::File {
                 fd: arc_fd,
@@ -292,18 +539,30 @@ impl UserAddrSpace {
                     Some(Err(_e)) => {
                         return Err(MmapError::FileError);
                     }
+                    None if arc_fd.mmap_is_private_copy()
+                        && !PAGE_ALLOCATOR.get().has_user_headroom() =>
+                    {
+                        return Err(MmapError::OutOfMemory);
+                    }
                     None => {
                         return Err(MmapError::FileError);
                     }
                 };
                 let desc = LeafDescriptor::new(page as usize)
                     .union(LeafDescriptor::UNPRIVILEGED_ACCESS)
-                    .difference(LeafDescriptor::UXN)
-                    .set_global();
-                desc
+                    .difference(LeafDescriptor::UXN);
+                match (arc_fd.mmap_is_private_copy(), write) {
+                    (false, _) => desc,
+                    (true, false) => desc.union(LeafDescriptor::READ_ONLY),
+                    (true, true) => desc.union(LeafDescriptor::SW_DIRTY),
+                }
             }
         };
 
+        if vme.kind.owns_frames() {
+            self.owned_pages.fetch_add(1, Ordering::Relaxed);
+        }
+
         unsafe {
             set_translation_descriptor(self.table, vaddr, 3, 0, desc.into(), true).unwrap();
         }
@@ -311,6 +570,39 @@ impl UserAddrSpace {
         Ok(())
     }
 
+    /// Try to map the whole 2 MiB block containing `vaddr`; this only
+    /// succeeds if the block lies entirely within the VME, none of it
+    /// is mapped yet, and a free block of physical memory is available.
+    fn populate_block(&self, vme: &MemoryRangeNode, vaddr: usize) -> bool {
+        let block = (vaddr / HUGE_PG_SZ) * HUGE_PG_SZ;
+        if block < vme.start || block + HUGE_PG_SZ > vme.start + vme.size {
+            return false;
+        }
+        match unsafe { get_translation_descriptor(self.table, block, 2, 0) } {
+            Ok(desc) if !unsafe { desc.table }.is_valid() => (),
+            Err(MappingError::LevelEntryUnset(_)) => (),
+            _ => return false,
+        }
+
+        let Some(frame) = PAGE_ALLOCATOR.get().try_alloc_user_frame::<Size2MiB>() else {
+            return false;
+        };
+        let paddr = frame.paddr;
+        unsafe { core::ptr::write_bytes((DIRECT_MAP_BASE + paddr) as *mut u8, 0, HUGE_PG_SZ) };
+
+        let desc = LeafDescriptor::new(paddr)
+            .difference(LeafDescriptor::IS_PAGE_DESCRIPTOR)
+            .union(LeafDescriptor::UNPRIVILEGED_ACCESS)
+            .difference(LeafDescriptor::UXN);
+        unsafe {
+            set_translation_descriptor(self.table, block, 2, 0, desc.into(), true).unwrap();
+        }
+        self.owned_pages
+            .fetch_add(HUGE_PG_SZ / PAGE_SIZE, Ordering::Relaxed);
+        true
+    }
+
+    /// Map every page in the range, writable, for access from the kernel.
     pub async fn populate_range(
         &self,
         vme: &MemoryRangeNode,
@@ -318,7 +610,7 @@ impl UserAddrSpace {
         len: usize,
     ) -> Result<(), MmapError> {
         for off in (start..start + len).step_by(PAGE_SIZE) {
-            self.populate_page(vme, off).await?;
+            self.populate_page(vme, off, true).await?;
         }
         Ok(())
     }
@@ -336,41 +628,63 @@ impl UserAddrSpace {
 impl Drop for UserAddrSpace {
     fn drop(&mut self) {
         self.clear_address_space();
+        asid::release(self.table.paddr());
     }
 }
 
 unsafe impl Send for UserAddrSpace {}
 unsafe impl Sync for UserAddrSpace {}
 
-pub fn page_fault_handler(ctx: &mut Context, far: usize, _iss: DataAbortISS) -> *mut Context {
+pub fn page_fault_handler(ctx: &mut Context, far: usize, iss: DataAbortISS) -> *mut Context {
     run_async_handler(ctx, async move |mut context: HandlerContext<'_>| {
         let proc = context.cur_process().unwrap();
         // TODO: make sure misaligned loads don't loop here?
         let page_addr = (far / PAGE_SIZE) * PAGE_SIZE;
+        let write = iss.contains(DataAbortISS::WRITE);
 
-        let mem = proc.mem.lock();
-        let vme = mem.get_vme(page_addr);
-        match vme {
-            None => {
-                let exit_code = &proc.exit_code;
-                exit_code
-                    .try_set(crate::process::ExitStatus {
-                        status: -1i32 as u32,
-                    })
-                    .ok();
-                drop(mem);
-
-                println!("Invalid user access at addr {far:#10x}");
-                println!("{:#?}", &*context.regs());
-
+        loop {
+            if proc.is_killed() {
                 let thread = context.detach_thread();
-                unsafe { exit_user_thread(thread, -4i32 as u32) }
+                unsafe { exit_user_thread(thread, OOM_EXIT_STATUS) }
             }
-            Some(vme) => {
-                mem.populate_page(vme, page_addr).await.unwrap(); // TODO: errors?
-                drop(mem);
-                context.resume_final()
+
+            let mem = proc.mem.lock();
+            let Some(vme) = mem.get_vme(page_addr) else {
+                break;
+            };
+            match mem.populate_page(vme, page_addr, write).await {
+                Ok(()) => {
+                    drop(mem);
+                    return context.resume_final();
+                }
+                Err(MmapError::OutOfMemory) => {
+                    drop(mem);
+                    match crate::memory::reclaim::handle_out_of_memory(proc) {
+                        OomAction::Retry => {}
+                        OomAction::Wait => crate::sync::time::sleep(OOM_WAIT).await,
+                        OomAction::Killed => {
+                            let thread = context.detach_thread();
+                            unsafe { exit_user_thread(thread, OOM_EXIT_STATUS) }
+                        }
+                    }
+                }
+                Err(e) => {
+                    drop(mem);
+                    println!("| failed to populate user page at {far:#10x}: {e:?}");
+                    coredump::dump_current(&mut context, coredump::SIGBUS);
+                    let thread = context.detach_thread();
+                    unsafe { exit_user_thread(thread, -4i32 as u32) }
+                }
             }
         }
+
+        proc.set_exit_status(-1i32 as u32);
+
+        println!("Invalid user access at addr {far:#10x}");
+        println!("{:#?}", &*context.regs());
+        coredump::dump_current(&mut context, coredump::SIGSEGV);
+
+        let thread = context.detach_thread();
+        unsafe { exit_user_thread(thread, -4i32 as u32) }
     })
 }
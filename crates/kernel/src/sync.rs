@@ -4,6 +4,7 @@ mod condvar;
 pub mod handler_table;
 pub mod init;
 pub mod lock;
+pub mod notify;
 pub mod once_cell;
 pub mod per_core;
 pub mod semaphore;
@@ -20,6 +21,7 @@ pub use interrupts::{disable_interrupts, enable_interrupts, restore_interrupts,
 pub use lock::{InterruptSpinLock, InterruptSpinLockGuard, InterruptSpinLockInner};
 pub use lock::{Lock, LockGuard, LockImpl};
 pub use lock::{SpinLock, SpinLockGuard, SpinLockInner};
+pub use notify::NotifyList;
 pub use per_core::{ConstInit, PerCore};
 pub use time::{get_time, spin_sleep, spin_sleep_until};
 
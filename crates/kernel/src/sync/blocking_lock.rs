@@ -23,6 +23,9 @@ impl BlockingLockInner {
             .wait_while(guard, |locked| core::mem::replace(locked, true))
             .await;
     }
+    pub fn try_lock(&self) -> bool {
+        !core::mem::replace(&mut *self.lock.lock(), true)
+    }
     pub fn unlock(&self) {
         let mut guard = self.lock.lock();
         assert!(*guard);
@@ -36,6 +39,9 @@ impl LockImpl for BlockingLockInner {
     fn lock(&self) {
         self.lock_blocking()
     }
+    fn try_lock(&self) -> bool {
+        self.try_lock()
+    }
     fn unlock(&self) {
         self.unlock()
     }
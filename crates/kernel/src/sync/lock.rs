@@ -8,6 +8,7 @@ pub trait LockImpl {
     const DEFAULT: Self;
     // const fn new() -> Self;
     fn lock(&self);
+    fn try_lock(&self) -> bool;
     fn unlock(&self);
 }
 
@@ -48,6 +49,14 @@ impl<T: ?Sized, L: LockImpl> Lock<T, L> {
             marker: PhantomData,
         }
     }
+
+    /// Acquire the lock if it is not currently held, without spinning.
+    pub fn try_lock(&self) -> Option<LockGuard<'_, T, L>> {
+        self.inner.try_lock().then(|| LockGuard {
+            lock: self,
+            marker: PhantomData,
+        })
+    }
 }
 
 unsafe impl<T: Send + ?Sized, L: Send> Send for Lock<T, L> {}
@@ -112,6 +121,9 @@ impl LockImpl for SpinLockInner {
     fn lock(&self) {
         self.lock()
     }
+    fn try_lock(&self) -> bool {
+        self.try_acquire()
+    }
     fn unlock(&self) {
         self.unlock()
     }
@@ -150,6 +162,16 @@ impl InterruptSpinLockInner {
             self.state.get().write(Some(state));
         }
     }
+    pub fn try_lock(&self) -> bool {
+        let state = unsafe { disable_interrupts() };
+        if self.try_acquire() {
+            unsafe { self.state.get().write(Some(state)) };
+            true
+        } else {
+            unsafe { restore_interrupts(state) };
+            false
+        }
+    }
     pub fn unlock(&self) {
         let state = unsafe { (*self.state.get()).take() };
         self.flag.store(false, Ordering::Release);
@@ -163,6 +185,9 @@ impl LockImpl for InterruptSpinLockInner {
     fn lock(&self) {
         self.lock()
     }
+    fn try_lock(&self) -> bool {
+        self.try_lock()
+    }
     fn unlock(&self) {
         self.unlock()
     }
@@ -0,0 +1,40 @@
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+use super::semaphore::BinarySemaphore;
+use super::SpinLock;
+
+/// A list of flags to raise whenever some event source has new events,
+/// so that a task can wait on several sources at once.
+///
+/// Subscribers are held weakly, and are dropped from the list once
+/// their flag has been freed.
+pub struct NotifyList {
+    waiters: SpinLock<Vec<Weak<BinarySemaphore>>>,
+}
+
+impl NotifyList {
+    pub const fn new() -> Self {
+        NotifyList {
+            waiters: SpinLock::new(Vec::new()),
+        }
+    }
+
+    pub fn subscribe(&self, flag: &Arc<BinarySemaphore>) {
+        let mut waiters = self.waiters.lock();
+        if !waiters.iter().any(|w| w.as_ptr() == Arc::as_ptr(flag)) {
+            waiters.push(Arc::downgrade(flag));
+        }
+    }
+
+    /// Raise the flags of all subscribers.
+    pub fn notify(&self) {
+        self.waiters.lock().retain(|waiter| match waiter.upgrade() {
+            Some(flag) => {
+                flag.up();
+                true
+            }
+            None => false,
+        });
+    }
+}
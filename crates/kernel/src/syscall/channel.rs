@@ -5,7 +5,7 @@ use crate::event::async_handler::{run_async_handler, run_event_handler, HandlerC
 use crate::event::context::Context;
 use crate::process::fd;
 use crate::ringbuffer;
-use crate::sync::SpinLock;
+use crate::sync::{NotifyList, SpinLock};
 
 // TODO: tracking ownership of objects
 
@@ -13,6 +13,9 @@ use crate::sync::SpinLock;
 pub struct Channel {
     pub send: SpinLock<ringbuffer::Sender<16, Message>>,
     pub recv: SpinLock<ringbuffer::Receiver<16, Message>>,
+    /// Notified when a message is sent to this end of the channel.
+    pub waiters: Arc<NotifyList>,
+    peer_waiters: Arc<NotifyList>,
 }
 
 pub struct Message {
@@ -36,13 +39,18 @@ bitflags::bitflags! {
 pub unsafe fn sys_channel(ctx: &mut Context) -> *mut Context {
     let (a_tx, b_rx) = ringbuffer::channel();
     let (b_tx, a_rx) = ringbuffer::channel();
+    let (a_waiters, b_waiters) = (Arc::new(NotifyList::new()), Arc::new(NotifyList::new()));
     let a_chan = Channel {
         send: SpinLock::new(a_tx),
         recv: SpinLock::new(a_rx),
+        waiters: a_waiters.clone(),
+        peer_waiters: b_waiters.clone(),
     };
     let b_chan = Channel {
         send: SpinLock::new(b_tx),
         recv: SpinLock::new(b_rx),
+        waiters: b_waiters,
+        peer_waiters: a_waiters,
     };
 
     run_event_handler(ctx, move |mut context: HandlerContext<'_>| {
@@ -137,6 +145,9 @@ pub unsafe fn sys_send(ctx: &mut Context) -> *mut Context {
             sender.send.lock().send(msg).await;
             res = 0;
         }
+        if res == 0 {
+            sender.peer_waiters.notify();
+        }
 
         context.resume_return(res)
     })
@@ -1,4 +1,4 @@
-use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::btree_map::{BTreeMap, Entry};
 use alloc::sync::Arc;
 
 use crate::arch::memory::palloc::{PhysicalPage, Size4KiB, PAGE_ALLOCATOR};
@@ -10,6 +10,8 @@ use crate::event::context::Context;
 use crate::process::fd;
 use crate::sync::SpinLock;
 
+use super::notify::NotifyFd;
+
 /// syscall sys_alloc_fb(width: usize, height: usize) -> (fd, buffer_size: usize, width: usize, height: usize, pitch: usize)
 pub unsafe fn sys_acquire_fb(ctx: &mut Context) -> *mut Context {
     let width = ctx.regs[0];
@@ -37,6 +39,18 @@ pub unsafe fn sys_acquire_fb(ctx: &mut Context) -> *mut Context {
     })
 }
 
+/// syscall wait_vsync()
+///
+/// Sleep until the start of the next frame, to pace updates to the
+/// framebuffer.
+pub unsafe fn sys_wait_vsync(ctx: &mut Context) -> *mut Context {
+    run_async_handler(ctx, async move |mut context: HandlerContext<'_>| {
+        crate::device::mailbox::wait_for_frame().await;
+        context.regs().regs[0] = 0;
+        context.resume_final()
+    })
+}
+
 struct FramebufferFd(RawFB);
 
 impl fd::FileDescriptor for FramebufferFd {
@@ -182,6 +196,31 @@ pub unsafe fn sys_poll_key_event(ctx: &mut Context) -> *mut Context {
     })
 }
 
+/// syscall notify_input(notify_fd)
+///
+/// Raise the notification whenever a key or mouse event is available.
+pub unsafe fn sys_notify_input(ctx: &mut Context) -> *mut Context {
+    let fd = ctx.regs[0];
+
+    run_async_handler(ctx, async move |context: HandlerContext<'_>| {
+        use crate::device::usb::keyboard::KEY_EVENTS;
+        use crate::device::usb::mouse::MOUSE_EVENTS;
+
+        let proc = context.cur_process().unwrap();
+
+        let file = proc.file_descriptors.lock().get(fd).cloned();
+        let Some(file) = file else {
+            return context.resume_return(-1i64 as usize);
+        };
+        let Some(notify) = file.as_any().downcast_ref::<NotifyFd>() else {
+            return context.resume_return(-1i64 as usize);
+        };
+        KEY_EVENTS.waiters.subscribe(&notify.0);
+        MOUSE_EVENTS.waiters.subscribe(&notify.0);
+        context.resume_return(0)
+    })
+}
+
 pub unsafe fn sys_poll_mouse_event(ctx: &mut Context) -> *mut Context {
     let buf_ptr = ctx.regs[0];
     let buf_len = ctx.regs[1].min(u32::MAX as usize) as u32;
@@ -272,13 +311,16 @@ impl fd::FileDescriptor for MemFd {
 
         let page_addr = {
             let mut pages = self.pages.lock();
-            let frame = pages
-                .entry(offset as usize)
-                .or_insert_with(|| PAGE_ALLOCATOR.get().alloc_frame());
-            frame.paddr
+            match pages.entry(offset as usize) {
+                Entry::Occupied(frame) => Some(frame.get().paddr),
+                Entry::Vacant(slot) => PAGE_ALLOCATOR
+                    .get()
+                    .try_alloc_user_frame()
+                    .map(|frame| slot.insert(frame).paddr),
+            }
         };
 
-        fd::boxed_future(async move { Some(fd::FileDescResult::ok(page_addr as u64)) })
+        fd::boxed_future(async move { page_addr.map(|p| fd::FileDescResult::ok(p as u64)) })
     }
     fn as_any(&self) -> &dyn core::any::Any {
         self
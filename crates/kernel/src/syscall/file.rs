@@ -143,6 +143,25 @@ pub unsafe fn sys_pwrite(ctx: &mut Context) -> *mut Context {
     })
 }
 
+/// syscall ioctl(fd: u32, request: u64, arg: usize) -> i64
+pub unsafe fn sys_ioctl(ctx: &mut Context) -> *mut Context {
+    let fd = ctx.regs[0];
+    let request = ctx.regs[1] as u64;
+    let arg = ctx.regs[2];
+
+    run_async_handler(ctx, async move |context: HandlerContext<'_>| {
+        let proc = context.cur_process().unwrap();
+
+        let file = proc.file_descriptors.lock().get(fd).cloned();
+        let Some(file) = file else {
+            return context.resume_return(-1i64 as usize);
+        };
+
+        let res = context.with_user_vmem(|| file.ioctl(request, arg));
+        context.resume_return(res.0 as usize)
+    })
+}
+
 bitflags::bitflags! {
     struct OpenFlags: u32 {
     }
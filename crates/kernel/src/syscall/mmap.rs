@@ -9,6 +9,7 @@ bitflags::bitflags! {
         const MAP_FIXED = 1 << 0;
         const MAP_ANONYMOUS = 1 << 1; //if not set this indicates file
         const MAP_SHARED = 1 << 2; //if not set indicates private mapping
+        const MAP_HUGE = 1 << 3; // back anonymous mappings with 2 MiB blocks
     }
 }
 
@@ -32,6 +33,11 @@ pub unsafe fn sys_mmap(ctx: &mut Context) -> *mut Context {
         return ctx;
     };
 
+    if flags.contains(MmapFlags::MAP_HUGE) && !flags.contains(MmapFlags::MAP_ANONYMOUS) {
+        ctx.regs[0] = i64::from(-1) as usize;
+        return ctx;
+    }
+
     let fd = ctx.regs[4];
     let offset = ctx.regs[5];
 
@@ -51,13 +57,13 @@ pub unsafe fn sys_mmap(ctx: &mut Context) -> *mut Context {
             MappingKind::File { fd: file, offset }
         };
 
-        let res;
-        if flags.contains(MmapFlags::MAP_FIXED) {
-            res = proc.mem.lock().mmap(Some(request_addr), request_size, kind);
+        // TODO: try to respect hint?
+        let start_addr = flags.contains(MmapFlags::MAP_FIXED).then_some(request_addr);
+        let res = if flags.contains(MmapFlags::MAP_HUGE) {
+            proc.mem.lock().mmap_huge(start_addr, request_size)
         } else {
-            // TODO: try to respect hint?
-            res = proc.mem.lock().mmap(None, request_size, kind);
-        }
+            proc.mem.lock().mmap(start_addr, request_size, kind)
+        };
 
         match res {
             Ok(addr) => {
@@ -71,6 +77,8 @@ pub unsafe fn sys_mmap(ctx: &mut Context) -> *mut Context {
                     MmapError::NoSuchEntry => -3,
                     MmapError::RequestedSizeUnavailable => -4,
                     MmapError::FileError => -5,
+                    MmapError::OutOfMemory => -6,
+                    MmapError::InvalidArgument => -7,
                 };
                 context.regs().regs[0] = code as usize;
                 context.resume_final()
@@ -79,15 +87,20 @@ pub unsafe fn sys_mmap(ctx: &mut Context) -> *mut Context {
     })
 }
 
-// TODO: partial unmap?
-// syscall sys_munmap(addr: *mut ())
+// syscall sys_munmap(addr: *mut (), size: usize)
+// A size of zero unmaps the whole mapping starting at addr.
 pub unsafe fn sys_munmap(ctx: &mut Context) -> *mut Context {
     let addr = ctx.regs[0];
+    let size = ctx.regs[1];
 
     run_async_handler(ctx, async move |mut context: HandlerContext<'_>| {
         let proc = context.cur_process().unwrap();
 
-        let res = proc.mem.lock().unmap(addr);
+        let res = if size == 0 {
+            proc.mem.lock().unmap(addr)
+        } else {
+            proc.mem.lock().unmap_range(addr, size)
+        };
 
         match res {
             Ok(()) => {
@@ -5,8 +5,11 @@ pub mod exec;
 pub mod fb_hack;
 pub mod file;
 pub mod mmap;
+pub mod net;
+pub mod notify;
 pub mod pipe;
 pub mod proc;
+pub mod pty;
 pub mod semaphore;
 pub mod sync;
 pub mod time;
@@ -44,7 +47,31 @@ pub unsafe fn register_syscalls() {
         register_syscall_handler(28, semaphore::sys_sem_down);
 
         register_syscall_handler(30, fb_hack::sys_poll_mouse_event);
+        register_syscall_handler(31, fb_hack::sys_wait_vsync);
 
         register_syscall_handler(34, proc::sys_try_wait);
+
+        register_syscall_handler(35, notify::sys_notify_create);
+        register_syscall_handler(36, notify::sys_notify_signal);
+        register_syscall_handler(37, notify::sys_notify_wait);
+        register_syscall_handler(38, notify::sys_notify_subscribe);
+        register_syscall_handler(39, fb_hack::sys_notify_input);
+
+        register_syscall_handler(40, pty::sys_pty_create);
+        register_syscall_handler(41, file::sys_ioctl);
+        register_syscall_handler(42, proc::sys_getpid);
+        register_syscall_handler(43, proc::sys_setpgid);
+        register_syscall_handler(44, proc::sys_getpgid);
+        register_syscall_handler(45, proc::sys_kill);
+        register_syscall_handler(46, proc::sys_sigignore);
+
+        register_syscall_handler(47, net::sys_resolve);
+        register_syscall_handler(48, net::sys_capture);
+        register_syscall_handler(49, net::sys_raw_socket);
+        register_syscall_handler(50, net::sys_sendto);
+        register_syscall_handler(51, net::sys_recvfrom);
+        register_syscall_handler(52, net::sys_set_ttl);
+
+        register_syscall_handler(53, proc::sys_set_core_limit);
     }
 }
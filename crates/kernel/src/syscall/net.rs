@@ -0,0 +1,322 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::event::async_handler::{run_async_handler, HandlerContext};
+use crate::event::context::Context;
+use crate::networking::iface::{capture, dns, interfaces};
+use crate::networking::repr::{IpAddress, Ipv4Address, Ipv4Protocol};
+use crate::networking::socket::{self, RawSocket, SocketAddr};
+use crate::process::fd::{self, FileDescriptor};
+
+// longest name a query can carry
+const MAX_NAME_LEN: usize = 253;
+// biggest message a raw socket sends, so the ip packet (header and all) fits in 64k
+const MAX_RAW_LEN: usize = 65535 - 20;
+
+/// syscall resolve(name: *const u8, name_len: usize, addrs: *mut [u8; 4], addrs_len: usize) -> i64
+///
+/// Writes as many of the addresses as fit, and returns how many there
+/// are.
+pub unsafe fn sys_resolve(ctx: &mut Context) -> *mut Context {
+    let name_ptr = ctx.regs[0];
+    let name_len = ctx.regs[1];
+    let addrs_ptr = ctx.regs[2];
+    let addrs_len = ctx.regs[3];
+
+    if name_len > MAX_NAME_LEN {
+        ctx.regs[0] = -1i64 as usize;
+        return ctx;
+    }
+
+    // TODO: check user buffers
+    let name = unsafe { core::slice::from_raw_parts(name_ptr as *const u8, name_len) };
+    let Ok(name) = core::str::from_utf8(name).map(String::from) else {
+        ctx.regs[0] = -1i64 as usize;
+        return ctx;
+    };
+
+    run_async_handler(ctx, async move |mut context: HandlerContext<'_>| {
+        let addrs = match dns::resolve(&name).await {
+            Ok(addrs) => addrs,
+            Err(e) => {
+                println!("| resolve {name}: {e:?}");
+                return context.resume_return(-1i64 as usize);
+            }
+        };
+
+        // the query may have finished on another core, with another address space active
+        context.with_user_vmem(|| {
+            let out =
+                unsafe { core::slice::from_raw_parts_mut(addrs_ptr as *mut [u8; 4], addrs_len) };
+            for (out, addr) in out.iter_mut().zip(&addrs) {
+                out.copy_from_slice(addr.as_bytes());
+            }
+        });
+
+        context.regs().regs[0] = addrs.len();
+        context.resume_final()
+    })
+}
+
+// capture flags
+const CAPTURE_CLEAR: usize = 1 << 0; // empty the capture buffer afterwards
+const CAPTURE_UART: usize = 1 << 1; // print it over the uart instead of returning an fd
+const CAPTURE_STOP: usize = 1 << 2;
+const CAPTURE_START: usize = 1 << 3;
+
+/// syscall capture(interface: usize, flags: usize) -> i64
+///
+/// Returns a file with the frames captured on an interface so far, in
+/// pcap format.
+pub unsafe fn sys_capture(ctx: &mut Context) -> *mut Context {
+    let interface = ctx.regs[0];
+    let flags = ctx.regs[1];
+
+    run_async_handler(ctx, async move |context: HandlerContext<'_>| {
+        if !interfaces().any(|iface| iface.index == interface) {
+            return context.resume_return(-1i64 as usize);
+        }
+
+        if flags & CAPTURE_STOP != 0 {
+            capture::set_enabled(false);
+        }
+        if flags & CAPTURE_START != 0 {
+            capture::set_enabled(true);
+        }
+
+        let res = if flags & CAPTURE_UART != 0 {
+            capture::dump_uart(interface);
+            0
+        } else {
+            let proc = context.cur_process().unwrap();
+            let file = CaptureFd(capture::export_pcap(interface));
+            proc.file_descriptors.lock().insert(Arc::new(file))
+        };
+
+        if flags & CAPTURE_CLEAR != 0 {
+            capture::clear();
+        }
+        context.resume_return(res)
+    })
+}
+
+// a snapshot of a capture, so it reads the same however long the reader takes
+pub struct CaptureFd(Vec<u8>);
+
+impl FileDescriptor for CaptureFd {
+    fn is_same_file(&self, other: &dyn FileDescriptor) -> bool {
+        let other = other.as_any().downcast_ref::<Self>();
+        other.map(|o| core::ptr::eq(self, o)).unwrap_or(false)
+    }
+    fn kind(&self) -> fd::FileKind {
+        fd::FileKind::Regular
+    }
+    fn read<'a>(
+        &'a self,
+        offset: u64,
+        buf: &'a mut [u8],
+    ) -> fd::SmallFuture<'a, fd::FileDescResult> {
+        let start = (offset as usize).min(self.0.len());
+        let len = buf.len().min(self.0.len() - start);
+        buf[..len].copy_from_slice(&self.0[start..start + len]);
+        fd::boxed_future(async move { Ok(len as u64).into() })
+    }
+    fn write<'a>(
+        &'a self,
+        _offset: u64,
+        _buf: &'a [u8],
+    ) -> fd::SmallFuture<'a, fd::FileDescResult> {
+        fd::boxed_future(async move { Err(1).into() })
+    }
+    fn size<'a>(&'a self) -> fd::SmallFuture<'a, fd::FileDescResult> {
+        let size = self.0.len() as u64;
+        fd::boxed_future(async move { Ok(size).into() })
+    }
+    fn mmap_page(&self, _offset: u64) -> fd::SmallFuture<Option<fd::FileDescResult>> {
+        fd::boxed_future(async move { None })
+    }
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+}
+
+/// syscall raw_socket(protocol: usize) -> i64
+///
+/// Opens a socket that sends and receives whole messages of an IP
+/// protocol (only ICMP for now).
+pub unsafe fn sys_raw_socket(ctx: &mut Context) -> *mut Context {
+    let protocol = match ctx.regs[0] as u8 {
+        1 => Ipv4Protocol::ICMP,
+        _ => {
+            ctx.regs[0] = -1i64 as usize;
+            return ctx;
+        }
+    };
+
+    run_async_handler(ctx, async move |context: HandlerContext<'_>| {
+        let socketfd = RawSocket::new(protocol);
+        let proc = context.cur_process().unwrap();
+        let fd = proc
+            .file_descriptors
+            .lock()
+            .insert(Arc::new(SocketFd(socketfd)));
+        context.resume_return(fd)
+    })
+}
+
+/// syscall sendto(fd: usize, buf: *const u8, buf_len: usize, addr: *const [u8; 4]) -> i64
+///
+/// Sends one message on a raw socket to `addr`, and returns its length.
+pub unsafe fn sys_sendto(ctx: &mut Context) -> *mut Context {
+    let fd = ctx.regs[0];
+    let buf_ptr = ctx.regs[1];
+    let buf_len = ctx.regs[2];
+    let addr_ptr = ctx.regs[3];
+
+    if buf_len > MAX_RAW_LEN {
+        ctx.regs[0] = -1i64 as usize;
+        return ctx;
+    }
+
+    // TODO: check user buffers
+    let payload = unsafe { core::slice::from_raw_parts(buf_ptr as *const u8, buf_len) }.to_vec();
+    let addr = unsafe { *(addr_ptr as *const [u8; 4]) };
+    let saddr = SocketAddr {
+        addr: IpAddress::V4(Ipv4Address::new(addr)),
+        port: 0,
+    };
+
+    run_async_handler(ctx, async move |context: HandlerContext<'_>| {
+        let Some(socketfd) = socket_for_fd(&context, fd) else {
+            return context.resume_return(-1i64 as usize);
+        };
+
+        match socket::send_to_async(socketfd, payload, saddr).await {
+            Ok(()) => context.resume_return(buf_len),
+            Err(e) => {
+                println!("| sendto {saddr}: {e:?}");
+                context.resume_return(-1i64 as usize)
+            }
+        }
+    })
+}
+
+/// syscall recvfrom(fd: usize, buf: *mut u8, buf_len: usize, addr: *mut [u8; 4], timeout_ms: usize) -> i64
+///
+/// Waits for a message, at most `timeout_ms` (or forever if 0). Writes
+/// as much of it as fits, and the sender to `addr` unless it's null,
+/// and returns its length.
+pub unsafe fn sys_recvfrom(ctx: &mut Context) -> *mut Context {
+    let fd = ctx.regs[0];
+    let buf_ptr = ctx.regs[1];
+    let buf_len = ctx.regs[2];
+    let addr_ptr = ctx.regs[3];
+    let timeout_ms = ctx.regs[4];
+
+    run_async_handler(ctx, async move |mut context: HandlerContext<'_>| {
+        let Some(socketfd) = socket_for_fd(&context, fd) else {
+            return context.resume_return(-1i64 as usize);
+        };
+
+        let received = match timeout_ms {
+            0 => socket::recv_from_async(socketfd).await,
+            ms => socket::recv_from_timeout(socketfd, ms as u64 * 1000).await,
+        };
+        let Ok((data, sender)) = received else {
+            return context.resume_return(-1i64 as usize);
+        };
+
+        // the message may have arrived on another core, with another address space active
+        context.with_user_vmem(|| {
+            let out = unsafe { core::slice::from_raw_parts_mut(buf_ptr as *mut u8, buf_len) };
+            let len = data.len().min(buf_len);
+            out[..len].copy_from_slice(&data[..len]);
+            // the sender is optional
+            match sender.addr {
+                IpAddress::V4(addr) if addr_ptr != 0 => unsafe {
+                    (*(addr_ptr as *mut [u8; 4])).copy_from_slice(addr.as_bytes())
+                },
+                _ => {}
+            }
+        });
+
+        context.regs().regs[0] = data.len();
+        context.resume_final()
+    })
+}
+
+/// syscall set_ttl(fd: usize, ttl: usize) -> i64
+///
+/// Sets the time to live of the packets a socket sends; 0 goes back to
+/// the default.
+pub unsafe fn sys_set_ttl(ctx: &mut Context) -> *mut Context {
+    let fd = ctx.regs[0];
+    let ttl = match ctx.regs[1] {
+        0 => None,
+        ttl @ 1..=255 => Some(ttl as u8),
+        _ => {
+            ctx.regs[0] = -1i64 as usize;
+            return ctx;
+        }
+    };
+
+    run_async_handler(ctx, async move |context: HandlerContext<'_>| {
+        let Some(socketfd) = socket_for_fd(&context, fd) else {
+            return context.resume_return(-1i64 as usize);
+        };
+
+        match socket::set_ttl(socketfd, ttl) {
+            Ok(()) => context.resume_return(0),
+            Err(_) => context.resume_return(-1i64 as usize),
+        }
+    })
+}
+
+fn socket_for_fd(context: &HandlerContext<'_>, fd: usize) -> Option<u16> {
+    let proc = context.cur_process().unwrap();
+    let file = proc.file_descriptors.lock().get(fd).cloned()?;
+    file.as_any().downcast_ref::<SocketFd>().map(|file| file.0)
+}
+
+// a kernel socket, owned by a process. the socket is closed along with the last fd
+pub struct SocketFd(u16);
+
+impl Drop for SocketFd {
+    fn drop(&mut self) {
+        let _ = socket::close(self.0);
+    }
+}
+
+impl FileDescriptor for SocketFd {
+    fn is_same_file(&self, other: &dyn FileDescriptor) -> bool {
+        let other = other.as_any().downcast_ref::<Self>();
+        other.map(|o| self.0 == o.0).unwrap_or(false)
+    }
+    fn kind(&self) -> fd::FileKind {
+        fd::FileKind::Other
+    }
+    fn read<'a>(
+        &'a self,
+        _offset: u64,
+        _buf: &'a mut [u8],
+    ) -> fd::SmallFuture<'a, fd::FileDescResult> {
+        fd::boxed_future(async move { Err(1).into() })
+    }
+    fn write<'a>(
+        &'a self,
+        _offset: u64,
+        _buf: &'a [u8],
+    ) -> fd::SmallFuture<'a, fd::FileDescResult> {
+        fd::boxed_future(async move { Err(1).into() })
+    }
+    fn size<'a>(&'a self) -> fd::SmallFuture<'a, fd::FileDescResult> {
+        fd::boxed_future(async move { Err(1).into() })
+    }
+    fn mmap_page(&self, _offset: u64) -> fd::SmallFuture<Option<fd::FileDescResult>> {
+        fd::boxed_future(async move { None })
+    }
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+}
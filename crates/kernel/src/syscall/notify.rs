@@ -0,0 +1,133 @@
+//! Notification fds: a flag that other processes, channels and input
+//! devices can raise, for a process to wait on several event sources
+//! at once.  Raising an already raised flag has no effect, and waiting
+//! clears it again.
+
+use alloc::sync::Arc;
+
+use crate::event::async_handler::{run_async_handler, HandlerContext};
+use crate::event::context::Context;
+use crate::process::fd::{self, FileDescriptor};
+use crate::sync::semaphore::BinarySemaphore;
+
+use super::channel::Channel;
+
+pub struct NotifyFd(pub Arc<BinarySemaphore>);
+
+/// syscall notify_create() -> fd
+///
+/// Create a new notification, initially not raised.
+pub unsafe fn sys_notify_create(ctx: &mut Context) -> *mut Context {
+    run_async_handler(ctx, async move |context: HandlerContext<'_>| {
+        let proc = context.cur_process().unwrap();
+
+        let descriptor = NotifyFd(Arc::new(BinarySemaphore::new(false)));
+        let fd = proc.file_descriptors.lock().insert(Arc::new(descriptor));
+
+        context.resume_return(fd)
+    })
+}
+
+/// syscall notify_signal(notify_fd)
+///
+/// Raise the notification, waking a thread waiting on it.
+pub unsafe fn sys_notify_signal(ctx: &mut Context) -> *mut Context {
+    let fd = ctx.regs[0];
+
+    run_async_handler(ctx, async move |context: HandlerContext<'_>| {
+        let proc = context.cur_process().unwrap();
+
+        let file = proc.file_descriptors.lock().get(fd).cloned();
+        let Some(file) = file else {
+            return context.resume_return(-1i64 as usize);
+        };
+        let Some(notify) = file.as_any().downcast_ref::<NotifyFd>() else {
+            return context.resume_return(-1i64 as usize);
+        };
+        notify.0.up();
+        context.resume_return(0)
+    })
+}
+
+/// syscall notify_wait(notify_fd)
+///
+/// Wait until the notification is raised, and clear it.
+pub unsafe fn sys_notify_wait(ctx: &mut Context) -> *mut Context {
+    let fd = ctx.regs[0];
+
+    run_async_handler(ctx, async move |context: HandlerContext<'_>| {
+        let proc = context.cur_process().unwrap();
+
+        let file = proc.file_descriptors.lock().get(fd).cloned();
+        let Some(file) = file else {
+            return context.resume_return(-1i64 as usize);
+        };
+        let Some(notify) = file.as_any().downcast_ref::<NotifyFd>() else {
+            return context.resume_return(-1i64 as usize);
+        };
+        notify.0.down().await;
+        context.resume_return(0)
+    })
+}
+
+/// syscall notify_subscribe(notify_fd, source_fd)
+///
+/// Raise the notification whenever a message arrives on the channel
+/// `source_fd`.
+pub unsafe fn sys_notify_subscribe(ctx: &mut Context) -> *mut Context {
+    let fd = ctx.regs[0];
+    let source_fd = ctx.regs[1];
+
+    run_async_handler(ctx, async move |context: HandlerContext<'_>| {
+        let proc = context.cur_process().unwrap();
+
+        let (file, source) = {
+            let fds = proc.file_descriptors.lock();
+            (fds.get(fd).cloned(), fds.get(source_fd).cloned())
+        };
+        let (Some(file), Some(source)) = (file, source) else {
+            return context.resume_return(-1i64 as usize);
+        };
+        let Some(notify) = file.as_any().downcast_ref::<NotifyFd>() else {
+            return context.resume_return(-1i64 as usize);
+        };
+        let Some(channel) = source.as_any().downcast_ref::<Channel>() else {
+            return context.resume_return(-1i64 as usize);
+        };
+        channel.waiters.subscribe(&notify.0);
+        context.resume_return(0)
+    })
+}
+
+impl FileDescriptor for NotifyFd {
+    fn is_same_file(&self, other: &dyn FileDescriptor) -> bool {
+        let other = other.as_any().downcast_ref::<Self>();
+        other.map(|o| Arc::ptr_eq(&self.0, &o.0)).unwrap_or(false)
+    }
+    fn kind(&self) -> fd::FileKind {
+        fd::FileKind::Other
+    }
+    fn read<'a>(
+        &'a self,
+        _offset: u64,
+        _buf: &'a mut [u8],
+    ) -> fd::SmallFuture<'a, fd::FileDescResult> {
+        fd::boxed_future(async move { Err(1).into() })
+    }
+    fn write<'a>(
+        &'a self,
+        _offset: u64,
+        _buf: &'a [u8],
+    ) -> fd::SmallFuture<'a, fd::FileDescResult> {
+        fd::boxed_future(async move { Err(1).into() })
+    }
+    fn size<'a>(&'a self) -> fd::SmallFuture<'a, fd::FileDescResult> {
+        fd::boxed_future(async move { Err(1).into() })
+    }
+    fn mmap_page(&self, _offset: u64) -> fd::SmallFuture<Option<fd::FileDescResult>> {
+        fd::boxed_future(async move { None })
+    }
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+}
@@ -5,7 +5,8 @@ use crate::event::async_handler::{run_async_handler, run_event_handler, HandlerC
 use crate::event::context::{deschedule_thread, Context, DescheduleAction, CORES};
 use crate::event::thread::Thread;
 use crate::process::fd::{self, FileDescriptor};
-use crate::process::ExitStatus;
+use crate::process::job::{self, JobControl, JobEvent};
+use crate::process::{ExitStatus, Pid};
 use crate::sync::once_cell::BlockingOnceCell;
 use crate::{event, shutdown};
 
@@ -18,10 +19,7 @@ pub unsafe fn sys_shutdown(ctx: &mut Context) -> *mut Context {
         } else {
             println!("Not shutting down...");
             let thread = context.detach_thread();
-            let exit_code = &thread.process.as_ref().unwrap().exit_code;
-            exit_code
-                .try_set(crate::process::ExitStatus { status: 0 as u32 })
-                .ok();
+            thread.process.as_ref().unwrap().set_exit_status(0);
             unsafe { deschedule_thread(DescheduleAction::FreeThread, Some(thread)) }
         }
     })
@@ -52,12 +50,11 @@ pub unsafe fn sys_exit(ctx: &mut Context) -> *mut Context {
 
         // TODO: split exit into process exit and thread exit?
         // TODO: ensure processes can't exit without setting this
-        let exit_code = &thread.process.as_ref().unwrap().exit_code;
-        exit_code
-            .try_set(crate::process::ExitStatus {
-                status: status as u32,
-            })
-            .ok();
+        thread
+            .process
+            .as_ref()
+            .unwrap()
+            .set_exit_status(status as u32);
 
         unsafe { deschedule_thread(DescheduleAction::FreeThread, Some(thread)) }
     })
@@ -81,7 +78,11 @@ pub unsafe fn sys_spawn(ctx: &mut Context) -> *mut Context {
             wait_fd = i32::MAX as usize;
         } else {
             process = Arc::new(old_process.fork().await);
-            let descriptor = WaitFd(process.exit_code.clone());
+            let descriptor = WaitFd {
+                pid: process.pid,
+                exit_code: process.exit_code.clone(),
+                job: process.job.clone(),
+            };
             let fd = old_process
                 .file_descriptors
                 .lock()
@@ -101,9 +102,26 @@ pub unsafe fn sys_spawn(ctx: &mut Context) -> *mut Context {
     })
 }
 
-/// syscall wait(fd: u32) -> i64
+bitflags::bitflags! {
+    struct WaitFlags: u32 {
+        /// Also return when the process is stopped.
+        const STOPPED = 1;
+    }
+}
+
+/// The value returned by [`sys_wait`] for a stopped process, which
+/// can't be confused with an exit status.
+const WAIT_STOPPED: usize = 1 << 32;
+
+/// syscall wait(fd: u32, flags: WaitFlags) -> i64
 pub unsafe fn sys_wait(ctx: &mut Context) -> *mut Context {
     let fd = ctx.regs[0];
+    let flags = ctx.regs[1];
+
+    let Some(flags) = u32::try_from(flags).ok().and_then(WaitFlags::from_bits) else {
+        ctx.regs[0] = -1i64 as usize;
+        return ctx;
+    };
 
     run_async_handler(ctx, async move |context: HandlerContext<'_>| {
         let proc = context.cur_process().unwrap();
@@ -116,9 +134,14 @@ pub unsafe fn sys_wait(ctx: &mut Context) -> *mut Context {
             return context.resume_return(-1i64 as usize);
         };
 
-        let status = file.0.get().await;
-
-        context.resume_return(status.status as usize)
+        if !flags.contains(WaitFlags::STOPPED) {
+            let status = file.exit_code.get().await;
+            return context.resume_return(status.status as usize);
+        }
+        match file.job.wait(&file.exit_code).await {
+            JobEvent::Exited(status) => context.resume_return(status as usize),
+            JobEvent::Stopped => context.resume_return(WAIT_STOPPED),
+        }
     })
 }
 
@@ -137,7 +160,7 @@ pub unsafe fn sys_try_wait(ctx: &mut Context) -> *mut Context {
             return context.resume_return(-1i64 as usize);
         };
 
-        if let Some(status) = file.0.try_get() {
+        if let Some(status) = file.exit_code.try_get() {
             context.resume_return(status.status as usize)
         } else {
             context.resume_return(i64::MIN as usize)
@@ -145,12 +168,145 @@ pub unsafe fn sys_try_wait(ctx: &mut Context) -> *mut Context {
     })
 }
 
-struct WaitFd(Arc<BlockingOnceCell<ExitStatus>>);
+/// syscall getpid(fd: u32) -> i64
+///
+/// Returns the pid of the process behind a wait fd, or of the current
+/// process if `fd` is `u32::MAX`.
+pub unsafe fn sys_getpid(ctx: &mut Context) -> *mut Context {
+    let fd = ctx.regs[0];
+
+    run_async_handler(ctx, async move |context: HandlerContext<'_>| {
+        let proc = context.cur_process().unwrap();
+        if fd == u32::MAX as usize {
+            let pid = proc.pid;
+            return context.resume_return(pid as usize);
+        }
+
+        let file = proc.file_descriptors.lock().get(fd).cloned();
+        let Some(file) = file else {
+            return context.resume_return(-1i64 as usize);
+        };
+        let Some(file) = file.as_any().downcast_ref::<WaitFd>() else {
+            return context.resume_return(-1i64 as usize);
+        };
+        context.resume_return(file.pid as usize)
+    })
+}
+
+/// syscall setpgid(pid: u32, pgid: u32) -> i64
+///
+/// A `pid` of 0 means the current process, and a `pgid` of 0 means a
+/// new group named after the process.
+pub unsafe fn sys_setpgid(ctx: &mut Context) -> *mut Context {
+    let pid = ctx.regs[0];
+    let pgid = ctx.regs[1];
+
+    run_async_handler(ctx, async move |context: HandlerContext<'_>| {
+        let (Ok(pid), Ok(pgid)) = (Pid::try_from(pid), Pid::try_from(pgid)) else {
+            return context.resume_return(-1i64 as usize);
+        };
+        let proc = context.cur_process().unwrap();
+        let target = match pid {
+            0 => proc.clone(),
+            pid => match job::find_process(pid) {
+                Some(p) => p,
+                None => return context.resume_return(-1i64 as usize),
+            },
+        };
+        target.set_pgid(if pgid == 0 { target.pid } else { pgid });
+        context.resume_return(0)
+    })
+}
+
+/// syscall getpgid(pid: u32) -> i64
+pub unsafe fn sys_getpgid(ctx: &mut Context) -> *mut Context {
+    let pid = ctx.regs[0];
+
+    run_async_handler(ctx, async move |context: HandlerContext<'_>| {
+        let proc = context.cur_process().unwrap();
+        let pgid = match pid {
+            0 => Some(proc.pgid()),
+            pid => Pid::try_from(pid)
+                .ok()
+                .and_then(job::find_process)
+                .map(|p| p.pgid()),
+        };
+        match pgid {
+            Some(pgid) => context.resume_return(pgid as usize),
+            None => context.resume_return(-1i64 as usize),
+        }
+    })
+}
+
+/// syscall kill(pid: i64, signal: u32) -> i64
+///
+/// A negative `pid` signals every process in the group `-pid`, and 0
+/// the group of the current process.
+pub unsafe fn sys_kill(ctx: &mut Context) -> *mut Context {
+    let pid = ctx.regs[0] as isize;
+    let signal = ctx.regs[1];
+
+    run_async_handler(ctx, async move |context: HandlerContext<'_>| {
+        let Ok(signal) = u32::try_from(signal) else {
+            return context.resume_return(-1i64 as usize);
+        };
+        let proc = context.cur_process().unwrap();
+        let found = match pid {
+            0 => job::signal_group(proc.pgid(), signal),
+            ..0 => Pid::try_from(pid.unsigned_abs()).is_ok_and(|g| job::signal_group(g, signal)),
+            1.. => match Pid::try_from(pid).ok().and_then(job::find_process) {
+                Some(target) => {
+                    target.signal(signal);
+                    true
+                }
+                None => false,
+            },
+        };
+        context.resume_return(if found { 0 } else { -1i64 as usize })
+    })
+}
+
+/// syscall sigignore(mask: u64) -> u64
+///
+/// Sets which signals the current process ignores, as a bitmask of
+/// signal numbers, and returns the previous mask.
+pub unsafe fn sys_sigignore(ctx: &mut Context) -> *mut Context {
+    let mask = ctx.regs[0] as u64;
+
+    run_async_handler(ctx, async move |context: HandlerContext<'_>| {
+        let proc = context.cur_process().unwrap();
+        let old = proc.set_ignored_signals(mask);
+        context.resume_return(old as usize)
+    })
+}
+
+/// syscall set_core_limit(limit: usize) -> usize
+///
+/// Sets the largest core file the current process writes if it crashes,
+/// in bytes, and returns the previous limit.  The limit starts at 0 (no
+/// core files) and is inherited by children.
+pub unsafe fn sys_set_core_limit(ctx: &mut Context) -> *mut Context {
+    let limit = ctx.regs[0];
+
+    run_async_handler(ctx, async move |context: HandlerContext<'_>| {
+        let proc = context.cur_process().unwrap();
+        let old = proc.set_core_limit(limit);
+        context.resume_return(old)
+    })
+}
+
+struct WaitFd {
+    pid: Pid,
+    exit_code: Arc<BlockingOnceCell<ExitStatus>>,
+    job: Arc<JobControl>,
+}
 
 impl FileDescriptor for WaitFd {
     fn is_same_file(&self, other: &dyn FileDescriptor) -> bool {
         let other = other.as_any().downcast_ref::<Self>();
-        other.map(|o| Arc::ptr_eq(&self.0, &o.0)).unwrap_or(false)
+        other
+            .map(|o| Arc::ptr_eq(&self.exit_code, &o.exit_code))
+            .unwrap_or(false)
     }
     fn kind(&self) -> fd::FileKind {
         fd::FileKind::Other
@@ -0,0 +1,587 @@
+//! Pseudo-terminals: a master end for a terminal emulator, and a slave
+//! end for the programs running in it, joined by a line discipline that
+//! handles line editing, echo, newline translation and job control
+//! characters, configured with termios-style ioctls.
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::event::async_handler::{run_event_handler, HandlerContext};
+use crate::event::context::Context;
+use crate::process::fd::{self, FileDescriptor};
+use crate::process::job::{self, SIGINT, SIGQUIT, SIGTSTP};
+use crate::process::mem::is_user_range;
+use crate::process::Pid;
+use crate::sync::{Condvar, SpinLock, SpinLockGuard};
+
+pub const TCGETS: u64 = 0x5401;
+pub const TCSETS: u64 = 0x5402;
+pub const TIOCGPGRP: u64 = 0x540f;
+pub const TIOCSPGRP: u64 = 0x5410;
+pub const TIOCGWINSZ: u64 = 0x5413;
+pub const TIOCSWINSZ: u64 = 0x5414;
+
+bitflags::bitflags! {
+    #[derive(Copy, Clone)]
+    pub struct InputFlags: u32 {
+        /// Translate carriage returns into newlines.
+        const ICRNL = 0x100;
+    }
+
+    #[derive(Copy, Clone)]
+    pub struct OutputFlags: u32 {
+        const OPOST = 0x1;
+        /// Translate newlines into a carriage return and newline.
+        const ONLCR = 0x4;
+    }
+
+    #[derive(Copy, Clone)]
+    pub struct LocalFlags: u32 {
+        /// Send signals for the interrupt, quit and suspend characters.
+        const ISIG = 0x1;
+        /// Line-by-line input, with editing.
+        const ICANON = 0x2;
+        const ECHO = 0x8;
+        /// Echo erase characters by erasing the previous character.
+        const ECHOE = 0x10;
+    }
+}
+
+pub const VINTR: usize = 0;
+pub const VQUIT: usize = 1;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const VMIN: usize = 6;
+pub const VSUSP: usize = 10;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    pub lflag: u32,
+    pub cc: [u8; 20],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct WinSize {
+    pub rows: u16,
+    pub cols: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Termios {
+    const DEFAULT: Termios = {
+        let mut cc = [0; 20];
+        cc[VINTR] = 0x03;
+        cc[VQUIT] = 0x1c;
+        cc[VERASE] = 0x7f;
+        cc[VKILL] = 0x15;
+        cc[VEOF] = 0x04;
+        cc[VMIN] = 1;
+        cc[VSUSP] = 0x1a;
+        Termios {
+            iflag: InputFlags::ICRNL.bits(),
+            oflag: OutputFlags::OPOST.bits() | OutputFlags::ONLCR.bits(),
+            lflag: LocalFlags::ISIG.bits()
+                | LocalFlags::ICANON.bits()
+                | LocalFlags::ECHO.bits()
+                | LocalFlags::ECHOE.bits(),
+            cc,
+        }
+    };
+
+    fn iflag(&self) -> InputFlags {
+        InputFlags::from_bits_truncate(self.iflag)
+    }
+    fn oflag(&self) -> OutputFlags {
+        OutputFlags::from_bits_truncate(self.oflag)
+    }
+    fn lflag(&self) -> LocalFlags {
+        LocalFlags::from_bits_truncate(self.lflag)
+    }
+}
+
+/// Output written by programs but not yet read by the terminal
+/// emulator; writers wait past this.
+const OUTPUT_LIMIT: usize = 16384;
+const LINE_LIMIT: usize = 4096;
+
+struct PtyState {
+    termios: Termios,
+    winsize: WinSize,
+    foreground: Pid,
+    /// Input the slave can read; in canonical mode, only whole lines.
+    input: VecDeque<u8>,
+    /// End-of-file characters typed on an empty line, each of which
+    /// makes one read return nothing.
+    eof: usize,
+    /// The line being edited, in canonical mode.
+    line: Vec<u8>,
+    output: VecDeque<u8>,
+    hung_up: bool,
+}
+
+pub struct Pty {
+    state: SpinLock<PtyState>,
+    input_ready: Condvar,
+    output_space: Condvar,
+}
+
+impl PtyState {
+    /// Queue output from the slave, or echoed input.
+    fn output(&mut self, data: &[u8]) {
+        let oflag = self.termios.oflag();
+        let onlcr = oflag.contains(OutputFlags::OPOST | OutputFlags::ONLCR);
+        for &b in data {
+            if b == b'\n' && onlcr {
+                self.output.push_back(b'\r');
+            }
+            self.output.push_back(b);
+        }
+    }
+
+    fn echo(&mut self, b: u8) {
+        match b {
+            b'\n' | b'\t' | 0x20.. => self.output(&[b]),
+            _ => self.output(&[b'^', b + 0x40]),
+        }
+    }
+
+    /// Remove the last character of the line being edited.
+    fn erase(&mut self) {
+        // Multi-byte characters are erased together
+        while let Some(b) = self.line.pop() {
+            if b & 0xc0 != 0x80 {
+                break;
+            }
+        }
+        let lflag = self.termios.lflag();
+        if lflag.contains(LocalFlags::ECHO | LocalFlags::ECHOE) {
+            self.output(b"\x08 \x08");
+        }
+    }
+
+    /// Handle a character typed at the terminal, returning the signal
+    /// to send to the foreground process group, if any.
+    fn receive(&mut self, mut b: u8) -> Option<u32> {
+        let iflag = self.termios.iflag();
+        let lflag = self.termios.lflag();
+        let cc = self.termios.cc;
+
+        if b == b'\r' && iflag.contains(InputFlags::ICRNL) {
+            b = b'\n';
+        }
+
+        if lflag.contains(LocalFlags::ISIG) {
+            let signal = match b {
+                _ if b == cc[VINTR] => Some(SIGINT),
+                _ if b == cc[VQUIT] => Some(SIGQUIT),
+                _ if b == cc[VSUSP] => Some(SIGTSTP),
+                _ => None,
+            };
+            if let Some(signal) = signal {
+                self.line.clear();
+                self.input.clear();
+                if lflag.contains(LocalFlags::ECHO) {
+                    self.echo(b);
+                }
+                return Some(signal);
+            }
+        }
+
+        if !lflag.contains(LocalFlags::ICANON) {
+            self.input.push_back(b);
+            if lflag.contains(LocalFlags::ECHO) {
+                self.echo(b);
+            }
+            return None;
+        }
+
+        match b {
+            _ if b == cc[VERASE] || b == 0x08 => self.erase(),
+            _ if b == cc[VKILL] => {
+                while !self.line.is_empty() {
+                    self.erase();
+                }
+            }
+            _ if b == cc[VEOF] => {
+                if self.line.is_empty() {
+                    self.eof += 1;
+                }
+                self.input.extend(self.line.drain(..));
+            }
+            b'\n' => {
+                self.line.push(b);
+                self.input.extend(self.line.drain(..));
+                if lflag.contains(LocalFlags::ECHO) {
+                    self.echo(b);
+                }
+            }
+            _ => {
+                if self.line.len() < LINE_LIMIT {
+                    self.line.push(b);
+                    if lflag.contains(LocalFlags::ECHO) {
+                        self.echo(b);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Pty {
+    fn new() -> Self {
+        Pty {
+            state: SpinLock::new(PtyState {
+                termios: Termios::DEFAULT,
+                winsize: WinSize {
+                    rows: 24,
+                    cols: 80,
+                    width: 0,
+                    height: 0,
+                },
+                foreground: 0,
+                input: VecDeque::new(),
+                eof: 0,
+                line: Vec::new(),
+                output: VecDeque::new(),
+                hung_up: false,
+            }),
+            input_ready: Condvar::new(),
+            output_space: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> SpinLockGuard<'_, PtyState> {
+        self.state.lock()
+    }
+
+    fn ioctl(&self, request: u64, arg: usize) -> fd::FileDescResult {
+        let size = match request {
+            TCGETS | TCSETS => size_of::<Termios>(),
+            TIOCGPGRP | TIOCSPGRP => size_of::<u32>(),
+            TIOCGWINSZ | TIOCSWINSZ => size_of::<WinSize>(),
+            _ => return fd::FileDescResult::err(1),
+        };
+        if !is_user_range(arg, size) {
+            return fd::FileDescResult::err(1);
+        }
+
+        // User memory is only touched outside the lock, as it may fault
+        match request {
+            TCGETS => {
+                let termios = self.lock().termios;
+                unsafe { (arg as *mut Termios).write_unaligned(termios) };
+            }
+            TCSETS => {
+                let termios = unsafe { (arg as *const Termios).read_unaligned() };
+                let mut state = self.lock();
+                state.termios = termios;
+                // Leaving canonical mode makes the partial line readable
+                if !termios.lflag().contains(LocalFlags::ICANON) {
+                    let line = core::mem::take(&mut state.line);
+                    state.input.extend(line);
+                    drop(state);
+                    self.input_ready.notify_all();
+                }
+            }
+            TIOCGPGRP => {
+                let foreground = self.lock().foreground;
+                unsafe { (arg as *mut u32).write_unaligned(foreground) };
+            }
+            TIOCSPGRP => {
+                let foreground = unsafe { (arg as *const u32).read_unaligned() };
+                self.lock().foreground = foreground;
+            }
+            TIOCGWINSZ => {
+                let winsize = self.lock().winsize;
+                unsafe { (arg as *mut WinSize).write_unaligned(winsize) };
+            }
+            TIOCSWINSZ => {
+                let winsize = unsafe { (arg as *const WinSize).read_unaligned() };
+                self.lock().winsize = winsize;
+            }
+            _ => unreachable!(),
+        }
+        fd::FileDescResult::ok(0)
+    }
+}
+
+/// syscall pty_create() -> i64 | (u64, u64)
+///
+/// Returns the master and slave ends of a new pseudo-terminal.
+pub unsafe fn sys_pty_create(ctx: &mut Context) -> *mut Context {
+    run_event_handler(ctx, move |mut context: HandlerContext<'_>| {
+        let proc = context.cur_process().unwrap();
+
+        let pty = Arc::new(Pty::new());
+        let master = Arc::new(PtyMasterFd(pty.clone()));
+        let slave = Arc::new(PtySlaveFd(pty));
+
+        let mut guard = proc.file_descriptors.lock();
+        let master_fdi = guard.insert(master);
+        let slave_fdi = guard.insert(slave);
+        drop(guard);
+
+        let mut regs = context.regs();
+        regs.regs[0] = master_fdi;
+        regs.regs[1] = slave_fdi;
+        context.resume_final()
+    })
+}
+
+pub struct PtyMasterFd(Arc<Pty>);
+
+pub struct PtySlaveFd(Arc<Pty>);
+
+impl Drop for PtyMasterFd {
+    fn drop(&mut self) {
+        self.0.lock().hung_up = true;
+        self.0.input_ready.notify_all();
+        self.0.output_space.notify_all();
+    }
+}
+
+impl FileDescriptor for PtyMasterFd {
+    fn is_same_file(&self, other: &dyn FileDescriptor) -> bool {
+        let Some(other) = other.as_any().downcast_ref::<Self>() else {
+            return false;
+        };
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+    fn kind(&self) -> fd::FileKind {
+        fd::FileKind::Other
+    }
+    /// Read output from the programs on the terminal, without blocking.
+    fn read<'a>(
+        &'a self,
+        _offset: u64,
+        buf: &'a mut [u8],
+    ) -> fd::SmallFuture<'a, fd::FileDescResult> {
+        fd::boxed_future(async move {
+            let mut state = self.0.lock();
+            let len = buf.len().min(state.output.len());
+            for (dst, src) in buf.iter_mut().zip(state.output.drain(..len)) {
+                *dst = src;
+            }
+            drop(state);
+            self.0.output_space.notify_all();
+            fd::FileDescResult::ok(len as u64)
+        })
+    }
+    /// Type input at the terminal.
+    fn write<'a>(&'a self, _offset: u64, buf: &'a [u8]) -> fd::SmallFuture<'a, fd::FileDescResult> {
+        fd::boxed_future(async move {
+            for &b in buf {
+                let mut state = self.0.lock();
+                let signal = state.receive(b);
+                let foreground = state.foreground;
+                drop(state);
+                if let Some(signal) = signal {
+                    if foreground != 0 {
+                        job::signal_group(foreground, signal);
+                    }
+                }
+            }
+            self.0.input_ready.notify_all();
+            fd::FileDescResult::ok(buf.len() as u64)
+        })
+    }
+    fn size<'a>(&'a self) -> fd::SmallFuture<'a, fd::FileDescResult> {
+        fd::boxed_future(async move { Ok(0u64).into() })
+    }
+    fn mmap_page(&self, _offset: u64) -> fd::SmallFuture<Option<fd::FileDescResult>> {
+        fd::boxed_future(async move { None })
+    }
+    fn ioctl(&self, request: u64, arg: usize) -> fd::FileDescResult {
+        self.0.ioctl(request, arg)
+    }
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+}
+
+impl FileDescriptor for PtySlaveFd {
+    fn is_same_file(&self, other: &dyn FileDescriptor) -> bool {
+        let Some(other) = other.as_any().downcast_ref::<Self>() else {
+            return false;
+        };
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+    fn kind(&self) -> fd::FileKind {
+        fd::FileKind::Other
+    }
+    /// Read typed input, waiting for a whole line in canonical mode.
+    fn read<'a>(
+        &'a self,
+        _offset: u64,
+        buf: &'a mut [u8],
+    ) -> fd::SmallFuture<'a, fd::FileDescResult> {
+        fd::boxed_future(async move {
+            let pty = &self.0;
+            let mut state = pty.lock();
+            let nonblocking =
+                !state.termios.lflag().contains(LocalFlags::ICANON) && state.termios.cc[VMIN] == 0;
+            if !nonblocking {
+                state = pty
+                    .input_ready
+                    .wait_while(state, |s| s.input.is_empty() && s.eof == 0 && !s.hung_up)
+                    .await;
+            }
+
+            if state.input.is_empty() {
+                state.eof = state.eof.saturating_sub(1);
+                return fd::FileDescResult::ok(0);
+            }
+
+            // Reads stop at the end of a line in canonical mode
+            let mut len = buf.len().min(state.input.len());
+            if state.termios.lflag().contains(LocalFlags::ICANON) {
+                if let Some(end) = state.input.iter().take(len).position(|&b| b == b'\n') {
+                    len = end + 1;
+                }
+            }
+            for (dst, src) in buf.iter_mut().zip(state.input.drain(..len)) {
+                *dst = src;
+            }
+            fd::FileDescResult::ok(len as u64)
+        })
+    }
+    /// Write output to the terminal.
+    fn write<'a>(&'a self, _offset: u64, buf: &'a [u8]) -> fd::SmallFuture<'a, fd::FileDescResult> {
+        fd::boxed_future(async move {
+            let pty = &self.0;
+            let state = pty.lock();
+            let mut state = pty
+                .output_space
+                .wait_while(state, |s| s.output.len() >= OUTPUT_LIMIT && !s.hung_up)
+                .await;
+            if state.hung_up {
+                return fd::FileDescResult::err(1);
+            }
+            state.output(buf);
+            fd::FileDescResult::ok(buf.len() as u64)
+        })
+    }
+    fn size<'a>(&'a self) -> fd::SmallFuture<'a, fd::FileDescResult> {
+        fd::boxed_future(async move { Ok(0u64).into() })
+    }
+    fn mmap_page(&self, _offset: u64) -> fd::SmallFuture<Option<fd::FileDescResult>> {
+        fd::boxed_future(async move { None })
+    }
+    fn ioctl(&self, request: u64, arg: usize) -> fd::FileDescResult {
+        self.0.ioctl(request, arg)
+    }
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+}
+
+/// Type `input` at the terminal, returning the last signal it raised.
+fn type_input(state: &mut PtyState, input: &[u8]) -> Option<u32> {
+    input
+        .iter()
+        .fold(None, |signal, &b| state.receive(b).or(signal))
+}
+
+fn contents(queue: &VecDeque<u8>) -> Vec<u8> {
+    queue.iter().copied().collect()
+}
+
+test_case!(pty_canonical_lines);
+fn pty_canonical_lines() -> Result<(), crate::test::BoxError> {
+    let pty = Pty::new();
+    let mut state = pty.lock();
+
+    type_input(&mut state, b"ab");
+    kassert!(state.input.is_empty(), "partial line readable")?;
+    // the carriage return from the terminal ends the line
+    type_input(&mut state, b"\r");
+    kassert_eq!(contents(&state.input), b"ab\n")?;
+    kassert_eq!(contents(&state.output), b"ab\r\n")?;
+
+    // without ICANON, each character is readable at once
+    state.termios.lflag &= !LocalFlags::ICANON.bits();
+    type_input(&mut state, b"c\x7f");
+    kassert_eq!(contents(&state.input), b"ab\nc\x7f")?;
+    Ok(())
+}
+
+test_case!(pty_erase_kill);
+fn pty_erase_kill() -> Result<(), crate::test::BoxError> {
+    let pty = Pty::new();
+    let mut state = pty.lock();
+
+    type_input(&mut state, b"abc\x7f");
+    kassert_eq!(state.line.as_slice(), b"ab")?;
+    kassert_eq!(contents(&state.output), b"abc\x08 \x08")?;
+
+    // a multi-byte character is erased as one
+    type_input(&mut state, "é\x7f".as_bytes());
+    kassert_eq!(state.line.as_slice(), b"ab")?;
+
+    type_input(&mut state, b"\x15x\n");
+    kassert_eq!(contents(&state.input), b"x\n")?;
+    Ok(())
+}
+
+test_case!(pty_eof);
+fn pty_eof() -> Result<(), crate::test::BoxError> {
+    let pty = Pty::new();
+    let mut state = pty.lock();
+
+    // on an empty line, one read returns nothing
+    type_input(&mut state, b"\x04");
+    kassert_eq!(state.eof, 1)?;
+    kassert!(state.input.is_empty())?;
+
+    // otherwise, the line is readable without a newline
+    type_input(&mut state, b"ab\x04");
+    kassert_eq!(state.eof, 1)?;
+    kassert_eq!(contents(&state.input), b"ab")?;
+    Ok(())
+}
+
+test_case!(pty_isig);
+fn pty_isig() -> Result<(), crate::test::BoxError> {
+    let pty = Pty::new();
+    let mut state = pty.lock();
+
+    kassert_eq!(type_input(&mut state, b"ab\x03"), Some(SIGINT))?;
+    kassert!(state.line.is_empty(), "line kept after a signal")?;
+    kassert_eq!(type_input(&mut state, b"\x1c"), Some(SIGQUIT))?;
+    kassert_eq!(type_input(&mut state, b"\x1a"), Some(SIGTSTP))?;
+
+    state.termios.lflag &= !LocalFlags::ISIG.bits();
+    kassert_eq!(type_input(&mut state, b"\x03"), None)?;
+    kassert_eq!(state.line.as_slice(), b"\x03")?;
+    Ok(())
+}
+
+test_case!(async pty_isig_foreground);
+async fn pty_isig_foreground() -> Result<(), crate::test::BoxError> {
+    use crate::process::{self, Process};
+
+    let foreground = Arc::new(Process::new());
+    let background = Arc::new(Process::new());
+    process::register(&foreground);
+    process::register(&background);
+
+    let master = PtyMasterFd(Arc::new(Pty::new()));
+    master.0.lock().foreground = foreground.pgid();
+    master.write(0, b"\x03").await;
+
+    let status = foreground.exit_code.try_get().map(|s| s.status);
+    kassert_eq!(status, Some(128 + SIGINT))?;
+    kassert!(
+        background.exit_code.try_get().is_none(),
+        "signalled the background"
+    )?;
+    Ok(())
+}
@@ -11,33 +11,33 @@ extern crate ulib;
 use core::ops::ControlFlow;
 
 use display_client::proto;
+use gfx::canvas::{Canvas, Rect};
 use glam::IVec2;
 
-fn draw_pixel(fb: &mut [u32], stride: usize, pos: IVec2, color: u32) {
-    let height = fb.len() as i32 / stride as i32;
-    let pos = pos.clamp(IVec2::ZERO, IVec2::new(stride as i32, height as i32));
-    fb[(pos.y as usize * stride + pos.x as usize).clamp(0, fb.len() - 1)] = color;
-}
+const PALETTE_HEIGHT: usize = 16;
 
-fn get_pixel(fb: &mut [u32], stride: usize, pos: IVec2) -> u32 {
-    let height = fb.len() as i32 / stride as i32;
-    let pos = pos.clamp(IVec2::ZERO, IVec2::new(stride as i32, height as i32));
-    fb[(pos.y as usize * stride + pos.x as usize).clamp(0, fb.len() - 1)]
+/// The window as a canvas, clipped to the part that can be painted on.
+fn canvas(buf: &mut proto::BufferHandle) -> Canvas<'_> {
+    let (width, height) = (
+        buf.video_meta.width as usize,
+        buf.video_meta.height as usize,
+    );
+    let row_stride = buf.video_meta.row_stride as usize / 4;
+    let mut canvas = Canvas::new(buf.video_mem(), width, height, row_stride);
+    let painted = height.saturating_sub(2 * PALETTE_HEIGHT);
+    canvas.set_clip(Rect::new(0, 0, width as u32, painted as u32));
+    canvas
 }
 
-fn draw_circle(fb: &mut [u32], stride: usize, pos: IVec2, color: u32, r: u32) {
-    let r = r as i32;
-    for x in -r..=r {
-        for y in -r..=r {
-            if x * x + y * y <= r {
-                draw_pixel(fb, stride, pos + IVec2::new(x, y), color);
-            }
-        }
-    }
+fn point(pos: IVec2) -> (f32, f32) {
+    (pos.x as f32 + 0.5, pos.y as f32 + 0.5)
 }
 
 #[no_mangle]
 fn main(argc: usize, argv: *const *const u8) {
+    // a crash leaves a core file in the serial log
+    ulib::sys::set_core_limit(1 << 20);
+
     let argv_array = unsafe { core::slice::from_raw_parts(argv, argc) };
     let _args = argv_array
         .iter()
@@ -49,6 +49,16 @@ fn main(argc: usize, argv: *const *const u8) {
     let mut buf = display_client::connect(512, 384);
     buf.set_title("paint".as_bytes());
 
+    let compressed_font = include_bytes_align!(u32, "../../console/ctrld-fixed-10r.pcf.lz4");
+    let size = lz4::frame::read_frame(compressed_font)
+        .unwrap()
+        .0
+        .content_size()
+        .unwrap();
+    let mut font_data = alloc::vec![0; size as usize];
+    let font_data = lz4::decode_into(compressed_font, &mut font_data).unwrap();
+    let font = gfx::format::pcf::load_pcf(font_data).unwrap();
+
     let (width, height) = (
         buf.video_meta.width as usize,
         buf.video_meta.height as usize,
@@ -59,6 +69,7 @@ fn main(argc: usize, argv: *const *const u8) {
     let mut pos = IVec2::new(0, 0);
     let mut radius = 3;
     let mut color = 0xFF000000;
+    let mut ctrl = false;
 
     buf.video_mem().fill(0xFFFFFFFF);
 
@@ -69,19 +80,21 @@ fn main(argc: usize, argv: *const *const u8) {
         0xFF99D9EA, 0xFF4D6DF3, 0xFF709AD1, 0xFF2F3699, 0xFF546D8E, 0xFF6F3198, 0xFFB5A5D5,
     ];
 
-    let fb = buf.video_mem();
-    let palette_height = 16;
     let palette_elems = 14;
-    for r in height - palette_height..height {
-        for c in 0..width {
-            let idx = (c * palette_elems) / width;
-            fb[r * row_stride + c] = color_palette[idx * 2];
-        }
-    }
-    for r in height - 2 * palette_height..height - palette_height {
-        for c in 0..width {
-            let idx = (c * palette_elems) / width;
-            fb[r * row_stride + c] = color_palette[idx * 2 + 1];
+    let mut palette = canvas(&mut buf);
+    palette.reset_clip();
+    for (idx, colors) in color_palette.chunks(2).enumerate() {
+        let left = idx * width / palette_elems;
+        let right = (idx + 1) * width / palette_elems;
+        for (row, &color) in colors.iter().enumerate() {
+            let y = height - (row + 1) * PALETTE_HEIGHT;
+            let rect = Rect::new(
+                left as i32,
+                y as i32,
+                (right - left) as u32,
+                PALETTE_HEIGHT as u32,
+            );
+            palette.fill_rect(rect, color);
         }
     }
 
@@ -91,18 +104,67 @@ fn main(argc: usize, argv: *const *const u8) {
                 proto::EventKind::INPUT => {
                     use proto::EventData;
                     let data = proto::InputEvent::parse(&ev).expect("TODO");
-                    if let ControlFlow::Break(_) = handle_input(
-                        data,
-                        &mut buf,
-                        row_stride,
-                        &mut down,
-                        &mut pos,
-                        &mut color,
-                        &mut radius,
-                    ) {
+                    if data.kind == proto::InputEvent::KIND_KEY {
+                        let pressed = data.data1 == 1;
+                        match proto::ScanCode(data.data2) {
+                            proto::ScanCode::LEFT_CTRL | proto::ScanCode::RIGHT_CTRL => {
+                                ctrl = pressed;
+                            }
+                            // Copy the current color, as text
+                            proto::ScanCode::C if ctrl => {
+                                if pressed {
+                                    let text = alloc::format!("#{:06x}", color & 0xFFFFFF);
+                                    display_client::set_clipboard(
+                                        &mut buf,
+                                        "text/plain;charset=utf-8",
+                                        text.as_bytes(),
+                                    );
+                                }
+                                continue;
+                            }
+                            // Paste text at the mouse cursor
+                            proto::ScanCode::V if ctrl => {
+                                if pressed {
+                                    display_client::request_clipboard(&mut buf, "text/*");
+                                }
+                                continue;
+                            }
+                            _ => (),
+                        }
+                    }
+                    if let ControlFlow::Break(_) =
+                        handle_input(data, &mut buf, &mut down, &mut pos, &mut color, &mut radius)
+                    {
                         break 'outer;
                     }
                 }
+                proto::EventKind::CLIPBOARD_DATA => {
+                    use proto::EventData;
+                    let Some(proto::ClipboardDataEvent(event)) =
+                        proto::ClipboardDataEvent::parse(&ev)
+                    else {
+                        continue;
+                    };
+                    let data = display_client::receive_clipboard(&buf, &event);
+                    let Some(text) = data.and_then(|d| alloc::string::String::from_utf8(d).ok())
+                    else {
+                        continue;
+                    };
+                    let (x, y) = (pos.x.max(0) as usize, pos.y.max(0) as usize);
+                    // Keep the palette intact
+                    let canvas_end = (height - 2 * PALETTE_HEIGHT) * row_stride;
+                    if x < width && y * row_stride < canvas_end {
+                        font.draw_string(
+                            &text,
+                            &mut buf.video_mem()[..canvas_end],
+                            y * row_stride + x,
+                            Some(width - x),
+                            row_stride,
+                            1,
+                            color,
+                        );
+                    }
+                }
                 proto::EventKind::REQUEST_CLOSE => {
                     break 'outer;
                 }
@@ -110,15 +172,7 @@ fn main(argc: usize, argv: *const *const u8) {
             }
         }
 
-        buf.client_to_server_queue()
-            .try_send(proto::Event {
-                kind: proto::EventKind::PRESENT,
-                data: [0; 7],
-            })
-            .ok();
-
-        // signal(video)? for sync
-        ulib::sys::sem_down(buf.get_sem_fd(buf.present_sem)).unwrap();
+        display_client::present(&mut buf, None);
     }
 
     buf.client_to_server_queue()
@@ -127,12 +181,12 @@ fn main(argc: usize, argv: *const *const u8) {
             data: [0; 7],
         })
         .ok();
+    display_client::notify_server(&buf);
 }
 
 fn handle_input(
     data: proto::InputEvent,
     buf: &mut proto::BufferHandle,
-    row_stride: usize,
     down: &mut bool,
     pos: &mut IVec2,
     color: &mut u32,
@@ -154,21 +208,19 @@ fn handle_input(
         match mode {
             proto::InputEvent::MODE_MOUSE_MOVE => {
                 if *down {
-                    let distance = (new_pos - *pos).abs().max_element();
-                    // TODO: sqrt
-                    for i in (0..=128).step_by((128 / distance.max(1) as usize).max(1)) {
-                        let p = (*pos * (128 - i) + new_pos * i) / 128;
-                        draw_circle(buf.video_mem(), row_stride, p, *color, *radius);
-                    }
+                    let stroke = [point(*pos), point(new_pos)];
+                    canvas(buf).draw_polyline(&stroke, 2.0 * *radius as f32, *color);
                 }
             }
             proto::InputEvent::MODE_MOUSE_DOWN => {
                 if button == 1 {
                     *down = true;
-                    draw_circle(buf.video_mem(), row_stride, new_pos, *color, *radius);
+                    canvas(buf).fill_circle(point(new_pos), *radius as f32, *color);
                 }
                 if button == 3 {
-                    *color = get_pixel(buf.video_mem(), row_stride, new_pos);
+                    if let Some(pixel) = canvas(buf).pixel(new_pos.x, new_pos.y) {
+                        *color = pixel;
+                    }
                 }
             }
             proto::InputEvent::MODE_MOUSE_UP => {
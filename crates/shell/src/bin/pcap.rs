@@ -0,0 +1,74 @@
+#![no_std]
+#![cfg_attr(not(test), no_main)]
+
+extern crate alloc;
+#[macro_use]
+extern crate ulib;
+
+use ulib::sys::{CAPTURE_CLEAR, CAPTURE_START, CAPTURE_STOP, CAPTURE_UART};
+
+// the usb ethernet interface, after loopback
+const DEFAULT_INTERFACE: usize = 1;
+
+fn usage() -> ! {
+    println!("usage: pcap [-c] [-u] [-p | -r] [interface]");
+    println!("  writes the frames captured on an interface to stdout, in pcap format");
+    println!("  -c  clear the capture afterwards");
+    println!("  -u  print hex dumps over the uart instead");
+    println!("  -p  pause capturing");
+    println!("  -r  resume capturing");
+    ulib::sys::exit(1);
+}
+
+#[no_mangle]
+extern "C" fn main(argc: usize, argv: *const *const u8) -> ! {
+    let argv_array = unsafe { core::slice::from_raw_parts(argv, argc) };
+
+    let mut flags = 0;
+    let mut interface = DEFAULT_INTERFACE;
+    for arg in argv_array[1..].iter().copied() {
+        let arg = unsafe { core::ffi::CStr::from_ptr(arg) };
+        let Ok(arg) = arg.to_str() else {
+            usage();
+        };
+
+        match arg {
+            "-c" => flags |= CAPTURE_CLEAR,
+            "-u" => flags |= CAPTURE_UART,
+            "-p" => flags |= CAPTURE_STOP,
+            "-r" => flags |= CAPTURE_START,
+            _ => match arg.parse() {
+                Ok(index) => interface = index,
+                Err(_) => usage(),
+            },
+        }
+    }
+
+    let fd = match ulib::sys::capture(interface, flags) {
+        Ok(Some(fd)) => fd,
+        Ok(None) => ulib::sys::exit(0),
+        Err(_) => {
+            println!("pcap: no interface {}", interface);
+            ulib::sys::exit(1);
+        }
+    };
+
+    let mut buf = [0u8; 512];
+    let mut offset = 0;
+    loop {
+        match ulib::sys::pread(fd, &mut buf, offset) {
+            Ok(0) => break,
+            Ok(len) => {
+                let _ = ulib::sys::pwrite_all(1, &buf[..len], offset);
+                offset += len as u64;
+            }
+            Err(e) => {
+                println!("pcap: error reading capture: {e}");
+                ulib::sys::exit(1);
+            }
+        }
+    }
+    ulib::sys::close(fd).unwrap();
+
+    ulib::sys::exit(0);
+}
@@ -0,0 +1,182 @@
+#![no_std]
+#![cfg_attr(not(test), no_main)]
+
+extern crate alloc;
+#[macro_use]
+extern crate ulib;
+
+use alloc::vec::Vec;
+use ulib::sys::IPPROTO_ICMP;
+
+const ECHO_REPLY: u8 = 0;
+const DESTINATION_UNREACHABLE: u8 = 3;
+const ECHO_REQUEST: u8 = 8;
+const TIME_EXCEEDED: u8 = 11;
+
+// bytes of data after the icmp header, which makes 64 byte messages like everyone else's ping
+const DATA_LEN: usize = 56;
+const INTERVAL_MS: usize = 1000;
+const TIMEOUT_MS: usize = 1000;
+
+fn usage() -> ! {
+    println!("usage: ping [-c count] [-t ttl] host");
+    println!("  sends icmp echo requests to a host, and times the replies");
+    println!("  -c  how many requests to send (4)");
+    println!("  -t  time to live of the requests");
+    ulib::sys::exit(1);
+}
+
+fn now_ms() -> usize {
+    unsafe { ulib::sys::sys_get_time_ms() }
+}
+
+// the checksum is left to the kernel
+fn echo_request(id: u16, seq: u16) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(8 + DATA_LEN);
+    msg.extend_from_slice(&[ECHO_REQUEST, 0, 0, 0]);
+    msg.extend_from_slice(&id.to_be_bytes());
+    msg.extend_from_slice(&seq.to_be_bytes());
+    msg.extend((0..DATA_LEN).map(|i| i as u8));
+    msg
+}
+
+// the id and sequence number of an echo message, or of the echo request an error is about (which
+// quotes its ip header and the first 8 bytes after it)
+fn echo_id_seq(msg: &[u8]) -> Option<(u16, u16)> {
+    let echo = match *msg.first()? {
+        ECHO_REPLY => msg,
+        DESTINATION_UNREACHABLE | TIME_EXCEEDED => {
+            let ip_header_len = (*msg.get(8)? as usize & 0xf) * 4;
+            let quoted = msg.get(8 + ip_header_len..)?;
+            if quoted.first() != Some(&ECHO_REQUEST) {
+                return None;
+            }
+            quoted
+        }
+        _ => return None,
+    };
+    let id = u16::from_be_bytes([*echo.get(4)?, *echo.get(5)?]);
+    let seq = u16::from_be_bytes([*echo.get(6)?, *echo.get(7)?]);
+    Some((id, seq))
+}
+
+fn parse_arg<T: core::str::FromStr>(arg: Option<&str>) -> T {
+    match arg.map(str::parse) {
+        Some(Ok(value)) => value,
+        _ => usage(),
+    }
+}
+
+#[no_mangle]
+extern "C" fn main(argc: usize, argv: *const *const u8) -> ! {
+    let argv_array = unsafe { core::slice::from_raw_parts(argv, argc) };
+    let mut args = argv_array[1..].iter().map(|&arg| {
+        let arg = unsafe { core::ffi::CStr::from_ptr(arg) };
+        arg.to_str().unwrap_or_else(|_| usage())
+    });
+
+    let mut count: u16 = 4;
+    let mut ttl: Option<u8> = None;
+    let mut host = None;
+    while let Some(arg) = args.next() {
+        match arg {
+            "-c" => count = parse_arg(args.next()),
+            "-t" => ttl = Some(parse_arg(args.next())),
+            _ if host.is_none() => host = Some(arg),
+            _ => usage(),
+        }
+    }
+    let Some(host) = host else {
+        usage();
+    };
+
+    let Ok(addr) = ulib::sys::resolve_one(host) else {
+        println!("ping: unknown host {}", host);
+        ulib::sys::exit(1);
+    };
+    let [a, b, c, d] = addr;
+
+    let Ok(fd) = ulib::sys::raw_socket(IPPROTO_ICMP) else {
+        println!("ping: couldn't open a socket");
+        ulib::sys::exit(1);
+    };
+    if ttl.is_some() {
+        ulib::sys::set_ttl(fd, ttl).unwrap();
+    }
+
+    println!(
+        "PING {} ({}.{}.{}.{}): {} data bytes",
+        host, a, b, c, d, DATA_LEN
+    );
+
+    let id = ulib::sys::getpid() as u16;
+    let mut received = 0;
+    let mut buf = [0u8; 1500];
+    for seq in 0..count {
+        let sent_at = now_ms();
+        if ulib::sys::sendto(fd, &echo_request(id, seq), addr).is_err() {
+            println!("ping: couldn't send to {}.{}.{}.{}", a, b, c, d);
+        }
+
+        // wait for this request's reply, skipping anyone else's icmp
+        loop {
+            let waited = now_ms() - sent_at;
+            if waited >= TIMEOUT_MS {
+                println!("request timeout for icmp_seq {}", seq);
+                break;
+            }
+            let Ok((len, [w, x, y, z])) = ulib::sys::recvfrom(fd, &mut buf, TIMEOUT_MS - waited)
+            else {
+                continue;
+            };
+            let msg = &buf[..len.min(buf.len())];
+            if echo_id_seq(msg) != Some((id, seq)) {
+                continue;
+            }
+
+            match msg[0] {
+                ECHO_REPLY => {
+                    received += 1;
+                    println!(
+                        "{} bytes from {}.{}.{}.{}: icmp_seq={} time={} ms",
+                        len,
+                        w,
+                        x,
+                        y,
+                        z,
+                        seq,
+                        now_ms() - sent_at
+                    );
+                }
+                TIME_EXCEEDED => println!(
+                    "from {}.{}.{}.{}: icmp_seq={} time to live exceeded",
+                    w, x, y, z, seq
+                ),
+                _ => println!(
+                    "from {}.{}.{}.{}: icmp_seq={} destination unreachable",
+                    w, x, y, z, seq
+                ),
+            }
+            break;
+        }
+
+        let elapsed = now_ms() - sent_at;
+        if seq + 1 < count && elapsed < INTERVAL_MS {
+            unsafe { ulib::sys::sys_sleep_ms(INTERVAL_MS - elapsed) };
+        }
+    }
+
+    let loss = if count == 0 {
+        0
+    } else {
+        (count as usize - received) * 100 / count as usize
+    };
+    println!("--- {} ping statistics ---", host);
+    println!(
+        "{} packets transmitted, {} received, {}% packet loss",
+        count, received, loss
+    );
+
+    ulib::sys::close(fd).unwrap();
+    ulib::sys::exit(if received > 0 { 0 } else { 1 });
+}
@@ -32,18 +32,22 @@ impl LineReader {
     }
 }
 
-fn readline(reader: &mut LineReader) -> Result<&[u8], usize> {
+/// Read a line from stdin, or `None` at the end of input.
+///
+/// On a terminal, lines are edited and echoed by the terminal itself.
+fn readline(reader: &mut LineReader, tty: bool) -> Result<Option<&[u8]>, usize> {
     reader.shift();
     loop {
         while reader.processed < reader.cursor {
             let i = reader.processed;
             reader.processed += 1;
             match reader.buf[i] {
-                b'\r' => {
+                b'\r' | b'\n' => {
                     let base = reader.cur_base;
                     reader.cur_base = i + 1;
-                    return Ok(&reader.buf[base..i]);
+                    return Ok(Some(&reader.buf[base..i]));
                 }
+                _ if tty => (),
                 b'\x7f' => {
                     if reader.processed >= 2 {
                         reader.processed -= 2;
@@ -60,10 +64,45 @@ fn readline(reader: &mut LineReader) -> Result<&[u8], usize> {
         }
 
         let read = try_read_stdin(&mut reader.buf[reader.cursor..])?;
+        if read == 0 && tty {
+            return Ok(None);
+        }
         reader.cursor += read;
     }
 }
 
+/// Job control state, when running on a terminal.
+struct Jobs {
+    tty: bool,
+    shell_pgid: u32,
+    /// The last job stopped from the terminal, as its wait fd and group.
+    stopped: Option<(FileDesc, u32)>,
+}
+
+impl Jobs {
+    /// Wait for a job in the foreground, then take the terminal back.
+    fn wait_foreground(&mut self, child: FileDesc, pgid: u32) {
+        if self.tty {
+            ulib::sys::tcsetpgrp(STDIN_FD, pgid).ok();
+        }
+        match ulib::sys::wait_job(child) {
+            Ok(ulib::sys::WaitStatus::Exited(status)) => {
+                if status != 0 {
+                    println!("child exited with code {}", status);
+                }
+            }
+            Ok(ulib::sys::WaitStatus::Stopped) => {
+                println!("\n[stopped] (use fg to continue)");
+                self.stopped = Some((child, pgid));
+            }
+            Err(err) => println!("wait failed: {err}"),
+        }
+        if self.tty {
+            ulib::sys::tcsetpgrp(STDIN_FD, self.shell_pgid).ok();
+        }
+    }
+}
+
 struct Redirection<'a> {
     redirect_type: i32,
     file: &'a str,
@@ -102,11 +141,24 @@ fn main(argc: usize, argv: *const *const u8) {
         .map(|arg| core::str::from_utf8(arg).unwrap())
         .collect::<Vec<_>>();
 
+    let tty = ulib::sys::tcgetattr(STDIN_FD).is_ok();
+    if tty {
+        // Interrupting or suspending is meant for the job in the
+        // foreground, not the shell
+        use ulib::sys::{SIGINT, SIGQUIT, SIGTSTP};
+        ulib::sys::sigignore((1 << SIGINT) | (1 << SIGQUIT) | (1 << SIGTSTP));
+    }
+    let mut jobs = Jobs {
+        tty,
+        shell_pgid: ulib::sys::getpgid(0).unwrap_or(0),
+        stopped: None,
+    };
+
     if !args.is_empty() {
         // run a program first
         // This is a hack
         let line = args[1..].join(" ");
-        eval_line(&line, 3);
+        eval_line(&line, 3, &mut jobs);
     }
 
     println!("Starting shell (🐚)");
@@ -146,14 +198,17 @@ fn main(argc: usize, argv: *const *const u8) {
 
     loop {
         print!("$ ");
-        let line = match readline(&mut reader) {
-            Ok(line) => line,
+        let line = match readline(&mut reader, tty) {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
             Err(err) => {
                 println!("Error: {err}");
                 break;
             }
         };
-        println!();
+        if !tty {
+            println!();
+        }
 
         let line = unsafe { core::str::from_utf8_unchecked(&line) };
         if line.trim().is_empty() {
@@ -162,8 +217,16 @@ fn main(argc: usize, argv: *const *const u8) {
 
         if line == "exit" {
             break;
+        } else if line.trim() == "fg" {
+            match jobs.stopped.take() {
+                Some((child, pgid)) => {
+                    ulib::sys::kill(-(pgid as isize), ulib::sys::SIGCONT).ok();
+                    jobs.wait_foreground(child, pgid);
+                }
+                None => println!("fg: no stopped job"),
+            }
         } else {
-            eval_line(line, root_fd);
+            eval_line(line, root_fd, &mut jobs);
         }
     }
 
@@ -186,7 +249,7 @@ fn is_special_char(c: u8) -> bool {
     is_pipe(c) || is_redirection(c) || is_background(c)
 }
 
-fn eval_line(line: &str, root_fd: u32) {
+fn eval_line(line: &str, root_fd: u32, jobs: &mut Jobs) {
     let mut run_background = false;
     let split: Vec<&str> = line.split_ascii_whitespace().collect();
     //TODO: Introduce regex for split and grammar for actual parsing
@@ -244,6 +307,9 @@ fn eval_line(line: &str, root_fd: u32) {
     }
 
     let mut next_pipe = None;
+    // Every command of the line runs in one process group, named after
+    // the first
+    let mut pgid = None;
     for i in 0..queue.len() {
         let next = queue.get(i).unwrap();
         let has_next = i < queue.len() - 1;
@@ -337,16 +403,18 @@ fn eval_line(line: &str, root_fd: u32) {
                 stdout: cur_stdout,
                 stderr: None,
                 args: &argstrs,
+                pgid: Some(pgid.unwrap_or(0)),
             })
             .unwrap();
+            let pgid = *pgid.get_or_insert_with(|| ulib::sys::wait_fd_pid(child).unwrap_or(0));
 
             next_pipe = future_next_pipe;
 
             if !run_background {
                 //TODO: Hacky solution -> need tracking
-                let status = ulib::sys::wait(child).unwrap();
-                if status != 0 {
-                    println!("child exited with code {}", status);
+                jobs.wait_foreground(child, pgid);
+                if jobs.stopped.is_some_and(|(fd, _)| fd == child) {
+                    break;
                 }
             }
         } else {
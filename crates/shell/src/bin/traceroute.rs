@@ -0,0 +1,173 @@
+#![no_std]
+#![cfg_attr(not(test), no_main)]
+
+extern crate alloc;
+#[macro_use]
+extern crate ulib;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use ulib::sys::IPPROTO_ICMP;
+
+const ECHO_REPLY: u8 = 0;
+const DESTINATION_UNREACHABLE: u8 = 3;
+const ECHO_REQUEST: u8 = 8;
+const TIME_EXCEEDED: u8 = 11;
+
+const DEFAULT_MAX_HOPS: u8 = 30;
+const PROBES_PER_HOP: u16 = 3;
+const TIMEOUT_MS: usize = 2000;
+
+fn usage() -> ! {
+    println!("usage: traceroute [-m max_hops] host");
+    println!("  prints the routers on the way to a host, by sending icmp echo requests with a");
+    println!("  growing time to live and seeing who says it ran out");
+    println!("  -m  largest time to live tried (30)");
+    ulib::sys::exit(1);
+}
+
+fn now_ms() -> usize {
+    unsafe { ulib::sys::sys_get_time_ms() }
+}
+
+// the checksum is left to the kernel
+fn echo_request(id: u16, seq: u16) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(8);
+    msg.extend_from_slice(&[ECHO_REQUEST, 0, 0, 0]);
+    msg.extend_from_slice(&id.to_be_bytes());
+    msg.extend_from_slice(&seq.to_be_bytes());
+    msg
+}
+
+// the id and sequence number of an echo message, or of the echo request an error is about (which
+// quotes its ip header and the first 8 bytes after it)
+fn echo_id_seq(msg: &[u8]) -> Option<(u16, u16)> {
+    let echo = match *msg.first()? {
+        ECHO_REPLY => msg,
+        DESTINATION_UNREACHABLE | TIME_EXCEEDED => {
+            let ip_header_len = (*msg.get(8)? as usize & 0xf) * 4;
+            let quoted = msg.get(8 + ip_header_len..)?;
+            if quoted.first() != Some(&ECHO_REQUEST) {
+                return None;
+            }
+            quoted
+        }
+        _ => return None,
+    };
+    let id = u16::from_be_bytes([*echo.get(4)?, *echo.get(5)?]);
+    let seq = u16::from_be_bytes([*echo.get(6)?, *echo.get(7)?]);
+    Some((id, seq))
+}
+
+// sends one probe and waits for whatever comes back about it: the icmp type, who sent it, and the
+// round trip time
+fn probe(
+    fd: ulib::sys::FileDesc,
+    addr: [u8; 4],
+    id: u16,
+    seq: u16,
+) -> Option<(u8, [u8; 4], usize)> {
+    let mut buf = [0u8; 1500];
+    let sent_at = now_ms();
+    ulib::sys::sendto(fd, &echo_request(id, seq), addr).ok()?;
+
+    loop {
+        let waited = now_ms() - sent_at;
+        if waited >= TIMEOUT_MS {
+            return None;
+        }
+        let Ok((len, from)) = ulib::sys::recvfrom(fd, &mut buf, TIMEOUT_MS - waited) else {
+            continue;
+        };
+        let msg = &buf[..len.min(buf.len())];
+        if echo_id_seq(msg) == Some((id, seq)) {
+            return Some((msg[0], from, now_ms() - sent_at));
+        }
+    }
+}
+
+#[no_mangle]
+extern "C" fn main(argc: usize, argv: *const *const u8) -> ! {
+    let argv_array = unsafe { core::slice::from_raw_parts(argv, argc) };
+    let mut args = argv_array[1..].iter().map(|&arg| {
+        let arg = unsafe { core::ffi::CStr::from_ptr(arg) };
+        arg.to_str().unwrap_or_else(|_| usage())
+    });
+
+    let mut max_hops = DEFAULT_MAX_HOPS;
+    let mut host = None;
+    while let Some(arg) = args.next() {
+        match arg {
+            "-m" => match args.next().map(str::parse) {
+                Some(Ok(hops)) if hops > 0 => max_hops = hops,
+                _ => usage(),
+            },
+            _ if host.is_none() => host = Some(arg),
+            _ => usage(),
+        }
+    }
+    let Some(host) = host else {
+        usage();
+    };
+
+    let Ok(addr) = ulib::sys::resolve_one(host) else {
+        println!("traceroute: unknown host {}", host);
+        ulib::sys::exit(1);
+    };
+    let [a, b, c, d] = addr;
+
+    let Ok(fd) = ulib::sys::raw_socket(IPPROTO_ICMP) else {
+        println!("traceroute: couldn't open a socket");
+        ulib::sys::exit(1);
+    };
+
+    println!(
+        "traceroute to {} ({}.{}.{}.{}), {} hops max",
+        host, a, b, c, d, max_hops
+    );
+
+    let id = ulib::sys::getpid() as u16;
+    let mut seq: u16 = 0;
+    for ttl in 1..=max_hops {
+        ulib::sys::set_ttl(fd, Some(ttl)).unwrap();
+
+        let mut line = String::new();
+        let _ = write!(line, "{:2} ", ttl);
+        let mut last_from = None;
+        let mut done = false;
+        for _ in 0..PROBES_PER_HOP {
+            seq = seq.wrapping_add(1);
+            match probe(fd, addr, id, seq) {
+                Some((icmp_type, from, time)) => {
+                    // only name the router again if a probe came back from a different one
+                    if last_from != Some(from) {
+                        let [w, x, y, z] = from;
+                        let _ = write!(line, " {}.{}.{}.{}", w, x, y, z);
+                        last_from = Some(from);
+                    }
+                    let _ = write!(line, "  {} ms", time);
+                    match icmp_type {
+                        ECHO_REPLY => done = true,
+                        DESTINATION_UNREACHABLE => {
+                            let _ = write!(line, " !U");
+                            done = true;
+                        }
+                        _ => {}
+                    }
+                }
+                None => {
+                    let _ = write!(line, "  *");
+                }
+            }
+        }
+        println!("{}", line);
+
+        if done {
+            break;
+        }
+    }
+
+    ulib::sys::close(fd).unwrap();
+    ulib::sys::exit(0);
+}
@@ -11,6 +11,7 @@ use alloc::borrow::ToOwned;
 use alloc::string::String;
 use alloc::vec::Vec;
 use display_client::proto;
+use gfx::format::image::{self, ImageError};
 
 use ulib::sys::FileDesc;
 
@@ -35,15 +36,25 @@ fn read_all(fd: FileDesc) -> alloc::vec::Vec<u8> {
     out
 }
 
-fn load_image(file: u32) -> (usize, usize, Vec<u32>) {
+fn load_image(file: u32) -> Result<(usize, usize, Vec<u32>), ImageError> {
     let img = read_all(file);
-    let (header, data) = gfx::format::qoi::read_qoi_header(&img).unwrap();
-    let width = header.width as usize;
-    let height = header.height as usize;
-    let mut output = alloc::vec![0u32; width * height];
-    gfx::format::qoi::decode_qoi(&header, data, &mut output, width);
+    let (info, output) = image::decode_image_to_vec(&img)?;
+    Ok((info.width, info.height, output))
+}
+
+/// Load the first image, or exit if it can't be decoded.
+fn load_first_image(file: u32, name: &str) -> (usize, usize, Vec<u32>) {
+    load_image(file).unwrap_or_else(|e| {
+        println!("Error decoding {}: {:?}", name, e);
+        ulib::sys::exit(1);
+    })
+}
 
-    (width, height, output)
+/// Whether a file looks like an image we can show, from its name.
+fn is_image(name: &str) -> bool {
+    const EXTENSIONS: [&str; 3] = [".qoi", ".png", ".bmp"];
+    let name = name.to_ascii_lowercase();
+    EXTENSIONS.iter().any(|ext| name.ends_with(ext))
 }
 
 fn list_dir(dir: u32) -> Vec<String> {
@@ -127,16 +138,16 @@ fn main(argc: usize, argv: *const *const u8) {
 
     let mut last_idx = idx;
 
-    if file.ends_with("qoi") {
+    if is_image(file) {
         files.push(file.to_owned());
         let Ok(file) = ulib::sys::openat(3, file.as_bytes(), 0, 0) else {
             println!("Error opening file {}", file);
             ulib::sys::exit(1);
         };
-        (img_width, img_height, img_data) = load_image(file);
+        (img_width, img_height, img_data) = load_first_image(file, &files[0]);
     } else if let Ok(dir) = ulib::sys::openat(3, alloc::format!("{file}/").as_bytes(), 0, 0) {
         files = list_dir(dir);
-        files.retain(|f| f.ends_with("qoi"));
+        files.retain(|f| is_image(f));
         files.sort();
         println!("Loaded files: {:?}", files);
         dir_fd = dir;
@@ -145,7 +156,7 @@ fn main(argc: usize, argv: *const *const u8) {
             println!("Error opening file {}", file);
             ulib::sys::exit(1);
         };
-        (img_width, img_height, img_data) = load_image(file);
+        (img_width, img_height, img_data) = load_first_image(file, &files[0]);
     } else {
         println!("Unknown file format, exiting.");
         ulib::sys::exit(1);
@@ -204,11 +215,18 @@ fn main(argc: usize, argv: *const *const u8) {
         if idx != last_idx {
             let start = unsafe { ulib::sys::sys_get_time_ms() };
             if let Ok(file) = ulib::sys::openat(dir_fd, files[idx].as_bytes(), 0, 0) {
-                drop(img_data);
-                (img_width, img_height, img_data) = load_image(file);
-                let end = unsafe { ulib::sys::sys_get_time_ms() };
-                println!("Loading image took {}ms", end - start);
-                last_idx = idx;
+                match load_image(file) {
+                    Ok(image) => {
+                        (img_width, img_height, img_data) = image;
+                        let end = unsafe { ulib::sys::sys_get_time_ms() };
+                        println!("Loading image took {}ms", end - start);
+                        last_idx = idx;
+                    }
+                    Err(e) => {
+                        println!("Error decoding {}: {:?}", files[idx], e);
+                        idx = last_idx;
+                    }
+                }
             } else {
                 println!("Error opening file {}", file);
                 idx = last_idx;
@@ -223,15 +241,7 @@ fn main(argc: usize, argv: *const *const u8) {
             fb, width, height, row_stride, x, y, &img_data, img_width, img_height, img_width,
         );
 
-        buf.client_to_server_queue()
-            .try_send(proto::Event {
-                kind: proto::EventKind::PRESENT,
-                data: [0; 7],
-            })
-            .ok();
-
-        // signal(video)? for sync
-        ulib::sys::sem_down(buf.get_sem_fd(buf.present_sem)).unwrap();
+        display_client::present(&mut buf, None);
     }
 
     buf.client_to_server_queue()
@@ -240,4 +250,5 @@ fn main(argc: usize, argv: *const *const u8) {
             data: [0; 7],
         })
         .ok();
+    display_client::notify_server(&buf);
 }
@@ -75,10 +75,10 @@ syscall!(16 => pub fn sys_execve_fd(
     envp: *const ArgStr,
 ) -> isize);
 
-syscall!(17 => pub fn sys_wait(fd: usize) -> isize);
+syscall!(17 => pub fn sys_wait(fd: usize, flags: usize) -> isize);
 
 syscall!(18 => pub fn sys_mmap(addr: usize, size: usize, prot_flags: usize, flags: usize, fd: usize, offset: usize) -> isize);
-syscall!(19 => pub fn sys_munmap(addr: usize) -> isize);
+syscall!(19 => pub fn sys_munmap(addr: usize, size: usize) -> isize);
 
 syscall!(21 => pub fn sys_get_time_ms() -> usize);
 syscall!(22 => pub fn sys_sleep_ms(time: usize));
@@ -124,9 +124,33 @@ syscall!(27 => pub fn sys_sem_up(fd: usize) -> isize);
 syscall!(28 => pub fn sys_sem_down(fd: usize) -> isize);
 
 syscall!(30 => pub fn sys_poll_mouse_event(buf: *mut u8, buf_len: usize) -> isize);
+syscall!(31 => pub fn sys_wait_vsync() -> isize);
 
 syscall!(34 => pub fn sys_try_wait(fd: usize) -> isize);
 
+syscall!(35 => pub fn sys_notify_create() -> isize);
+syscall!(36 => pub fn sys_notify_signal(fd: usize) -> isize);
+syscall!(37 => pub fn sys_notify_wait(fd: usize) -> isize);
+syscall!(38 => pub fn sys_notify_subscribe(fd: usize, source_fd: usize) -> isize);
+syscall!(39 => pub fn sys_notify_input(fd: usize) -> isize);
+
+syscall!(40 => pub fn sys_pty_create() -> PipeValues);
+syscall!(41 => pub fn sys_ioctl(fd: usize, request: usize, arg: usize) -> isize);
+syscall!(42 => pub fn sys_getpid(fd: usize) -> isize);
+syscall!(43 => pub fn sys_setpgid(pid: usize, pgid: usize) -> isize);
+syscall!(44 => pub fn sys_getpgid(pid: usize) -> isize);
+syscall!(45 => pub fn sys_kill(pid: isize, signal: usize) -> isize);
+syscall!(46 => pub fn sys_sigignore(mask: u64) -> u64);
+
+syscall!(47 => pub fn sys_resolve(name: *const u8, name_len: usize, addrs: *mut [u8; 4], addrs_len: usize) -> isize);
+syscall!(48 => pub fn sys_capture(interface: usize, flags: usize) -> isize);
+syscall!(49 => pub fn sys_raw_socket(protocol: usize) -> isize);
+syscall!(50 => pub fn sys_sendto(fd: usize, buf: *const u8, buf_len: usize, addr: *const [u8; 4]) -> isize);
+syscall!(51 => pub fn sys_recvfrom(fd: usize, buf: *mut u8, buf_len: usize, addr: *mut [u8; 4], timeout_ms: usize) -> isize);
+syscall!(52 => pub fn sys_set_ttl(fd: usize, ttl: usize) -> isize);
+
+syscall!(53 => pub fn sys_set_core_limit(limit: usize) -> usize);
+
 core::arch::global_asm!(
     ".global {name}; {name}:",
     "mov x0, lr", //Read link register value into x0
@@ -300,10 +324,27 @@ pub unsafe fn execve_fd(
 }
 
 pub fn wait(fd: FileDesc) -> Result<usize, usize> {
-    let res = unsafe { sys_wait(fd as usize) };
+    let res = unsafe { sys_wait(fd as usize, 0) };
     int_to_error(res)
 }
 
+pub enum WaitStatus {
+    Exited(usize),
+    Stopped,
+}
+
+const WAIT_STOPPED: usize = 1;
+
+/// Wait for a process to exit, or to be stopped by job control.
+pub fn wait_job(fd: FileDesc) -> Result<WaitStatus, usize> {
+    let res = unsafe { sys_wait(fd as usize, WAIT_STOPPED) };
+    match int_to_error(res)? {
+        // Stopped processes are reported past the range of exit statuses
+        status if status >> 32 != 0 => Ok(WaitStatus::Stopped),
+        status => Ok(WaitStatus::Exited(status)),
+    }
+}
+
 pub fn try_wait(fd: FileDesc) -> Option<Result<usize, usize>> {
     let res = unsafe { sys_try_wait(fd as usize) };
     if res == isize::MIN {
@@ -318,6 +359,7 @@ pub const MAP_FILE: u32 = 0; //linux mmap ignores this but can have it for reada
 pub const MAP_FIXED: u32 = 1 << 0;
 pub const MAP_ANONYMOUS: u32 = 1 << 1;
 pub const MAP_SHARED: u32 = 1 << 2;
+pub const MAP_HUGE: u32 = 1 << 3;
 
 pub unsafe fn mmap(
     addr: usize,
@@ -341,7 +383,13 @@ pub unsafe fn mmap(
 }
 
 pub unsafe fn munmap(addr: *mut ()) -> Result<usize, usize> {
-    let res = unsafe { sys_munmap(addr.addr()) };
+    let res = unsafe { sys_munmap(addr.addr(), 0) };
+    int_to_error(res)
+}
+
+/// Unmap part of a mapping (or parts of several), splitting them as needed.
+pub unsafe fn munmap_range(addr: *mut (), size: usize) -> Result<usize, usize> {
+    let res = unsafe { sys_munmap(addr.addr(), size.max(1)) };
     int_to_error(res)
 }
 
@@ -358,12 +406,263 @@ pub fn sem_down(fd: FileDesc) -> Result<(), usize> {
     int_to_error(res).map(|_| ())
 }
 
+pub fn notify_create() -> Result<FileDesc, usize> {
+    let res = unsafe { sys_notify_create() };
+    int_to_error(res).map(|f| f as FileDesc)
+}
+pub fn notify_signal(fd: FileDesc) -> Result<(), usize> {
+    let res = unsafe { sys_notify_signal(fd as usize) };
+    int_to_error(res).map(|_| ())
+}
+/// Wait until the notification is raised, and clear it.
+pub fn notify_wait(fd: FileDesc) -> Result<(), usize> {
+    let res = unsafe { sys_notify_wait(fd as usize) };
+    int_to_error(res).map(|_| ())
+}
+/// Raise the notification whenever a message arrives on a channel.
+pub fn notify_subscribe(fd: FileDesc, channel: FileDesc) -> Result<(), usize> {
+    let res = unsafe { sys_notify_subscribe(fd as usize, channel as usize) };
+    int_to_error(res).map(|_| ())
+}
+/// Raise the notification whenever a key or mouse event is available.
+pub fn notify_input(fd: FileDesc) -> Result<(), usize> {
+    let res = unsafe { sys_notify_input(fd as usize) };
+    int_to_error(res).map(|_| ())
+}
+
+pub fn pty_create() -> Result<(FileDesc, FileDesc), usize> {
+    let res = unsafe { sys_pty_create() };
+    let [master, slave] = res.0;
+    if master < 0 {
+        Err(master.unsigned_abs())
+    } else {
+        Ok((
+            master.unsigned_abs() as FileDesc,
+            slave.unsigned_abs() as FileDesc,
+        ))
+    }
+}
+
+pub const TCGETS: usize = 0x5401;
+pub const TCSETS: usize = 0x5402;
+pub const TIOCGPGRP: usize = 0x540f;
+pub const TIOCSPGRP: usize = 0x5410;
+pub const TIOCGWINSZ: usize = 0x5413;
+pub const TIOCSWINSZ: usize = 0x5414;
+
+// Termios flags
+pub const ICRNL: u32 = 0x100;
+pub const OPOST: u32 = 0x1;
+pub const ONLCR: u32 = 0x4;
+pub const ISIG: u32 = 0x1;
+pub const ICANON: u32 = 0x2;
+pub const ECHO: u32 = 0x8;
+pub const ECHOE: u32 = 0x10;
+
+// Indices of termios control characters
+pub const VINTR: usize = 0;
+pub const VQUIT: usize = 1;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const VMIN: usize = 6;
+pub const VSUSP: usize = 10;
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    pub lflag: u32,
+    pub cc: [u8; 20],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct WinSize {
+    pub rows: u16,
+    pub cols: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+pub fn ioctl(fd: FileDesc, request: usize, arg: usize) -> Result<usize, usize> {
+    let res = unsafe { sys_ioctl(fd as usize, request, arg) };
+    int_to_error(res)
+}
+
+pub fn tcgetattr(fd: FileDesc) -> Result<Termios, usize> {
+    let mut termios = Termios::default();
+    ioctl(fd, TCGETS, (&raw mut termios).addr())?;
+    Ok(termios)
+}
+
+pub fn tcsetattr(fd: FileDesc, termios: &Termios) -> Result<(), usize> {
+    ioctl(fd, TCSETS, (termios as *const Termios).addr()).map(|_| ())
+}
+
+pub fn tcgetpgrp(fd: FileDesc) -> Result<u32, usize> {
+    let mut pgid = 0u32;
+    ioctl(fd, TIOCGPGRP, (&raw mut pgid).addr())?;
+    Ok(pgid)
+}
+
+/// Make a process group the foreground group of a terminal, which
+/// receives the signals from its interrupt and suspend characters.
+pub fn tcsetpgrp(fd: FileDesc, pgid: u32) -> Result<(), usize> {
+    ioctl(fd, TIOCSPGRP, (&raw const pgid).addr()).map(|_| ())
+}
+
+pub fn get_winsize(fd: FileDesc) -> Result<WinSize, usize> {
+    let mut size = WinSize::default();
+    ioctl(fd, TIOCGWINSZ, (&raw mut size).addr())?;
+    Ok(size)
+}
+
+pub fn set_winsize(fd: FileDesc, size: &WinSize) -> Result<(), usize> {
+    ioctl(fd, TIOCSWINSZ, (size as *const WinSize).addr()).map(|_| ())
+}
+
+pub fn getpid() -> u32 {
+    unsafe { sys_getpid(u32::MAX as usize) as u32 }
+}
+
+/// The pid of the process a wait fd (from [`spawn_elf`]) refers to.
+pub fn wait_fd_pid(fd: FileDesc) -> Result<u32, usize> {
+    let res = unsafe { sys_getpid(fd as usize) };
+    int_to_error(res).map(|pid| pid as u32)
+}
+
+pub fn setpgid(pid: u32, pgid: u32) -> Result<(), usize> {
+    let res = unsafe { sys_setpgid(pid as usize, pgid as usize) };
+    int_to_error(res).map(|_| ())
+}
+
+pub fn getpgid(pid: u32) -> Result<u32, usize> {
+    let res = unsafe { sys_getpgid(pid as usize) };
+    int_to_error(res).map(|pgid| pgid as u32)
+}
+
+pub const SIGINT: u32 = 2;
+pub const SIGQUIT: u32 = 3;
+pub const SIGKILL: u32 = 9;
+pub const SIGTERM: u32 = 15;
+pub const SIGCONT: u32 = 18;
+pub const SIGSTOP: u32 = 19;
+pub const SIGTSTP: u32 = 20;
+
+/// Send a signal to a process, or to a process group if `pid` is
+/// negative.
+pub fn kill(pid: isize, signal: u32) -> Result<(), usize> {
+    let res = unsafe { sys_kill(pid, signal as usize) };
+    int_to_error(res).map(|_| ())
+}
+
+/// Ignore the given signals (a bitmask of signal numbers), returning
+/// the previously ignored ones.
+pub fn sigignore(mask: u64) -> u64 {
+    unsafe { sys_sigignore(mask) }
+}
+
+/// Set the largest core file (in bytes) this process writes if it
+/// crashes, returning the previous limit.  Core files are off (a limit
+/// of 0) unless asked for, and children inherit the limit.
+pub fn set_core_limit(limit: usize) -> usize {
+    unsafe { sys_set_core_limit(limit) }
+}
+
+/// Look up the IPv4 addresses of a host name (or parse a dotted quad),
+/// filling as many of `addrs` as there are.  Returns how many addresses
+/// the name has, which may be more than fit.
+pub fn resolve(name: &str, addrs: &mut [[u8; 4]]) -> Result<usize, usize> {
+    let res = unsafe { sys_resolve(name.as_ptr(), name.len(), addrs.as_mut_ptr(), addrs.len()) };
+    int_to_error(res)
+}
+
+/// The first IPv4 address of a host name.
+pub fn resolve_one(name: &str) -> Result<[u8; 4], usize> {
+    let mut addr = [[0; 4]];
+    match resolve(name, &mut addr)? {
+        0 => Err(1),
+        _ => Ok(addr[0]),
+    }
+}
+
+/// Empty the capture buffer once it has been read.
+pub const CAPTURE_CLEAR: usize = 1 << 0;
+/// Print the capture over the UART as hex dumps, instead of returning a
+/// file.
+pub const CAPTURE_UART: usize = 1 << 1;
+/// Stop capturing frames (on every interface).
+pub const CAPTURE_STOP: usize = 1 << 2;
+/// Start capturing frames again.
+pub const CAPTURE_START: usize = 1 << 3;
+
+/// The frames the kernel has captured on a network interface, as a pcap
+/// file.  With [`CAPTURE_UART`], nothing is returned.
+pub fn capture(interface: usize, flags: usize) -> Result<Option<FileDesc>, usize> {
+    let res = int_to_error(unsafe { sys_capture(interface, flags) })?;
+    if flags & CAPTURE_UART != 0 {
+        Ok(None)
+    } else {
+        Ok(Some(res as FileDesc))
+    }
+}
+
+/// The ICMP protocol number, for [`raw_socket`].
+pub const IPPROTO_ICMP: u8 = 1;
+
+/// Open a raw socket, which sends and receives whole messages of an IP
+/// protocol (only [`IPPROTO_ICMP`] for now), without the IP header.
+/// The kernel fills in the checksum of messages sent.
+pub fn raw_socket(protocol: u8) -> Result<FileDesc, usize> {
+    let res = unsafe { sys_raw_socket(protocol as usize) };
+    int_to_error(res).map(|fd| fd as FileDesc)
+}
+
+/// Send a message to an IPv4 address.
+pub fn sendto(fd: FileDesc, buf: &[u8], addr: [u8; 4]) -> Result<usize, usize> {
+    let res = unsafe { sys_sendto(fd as usize, buf.as_ptr(), buf.len(), &addr) };
+    int_to_error(res)
+}
+
+/// Wait for a message, for at most `timeout_ms` milliseconds (or forever
+/// if zero).  Returns the message's length, which may be more than fit
+/// in `buf`, and the address it came from.
+pub fn recvfrom(
+    fd: FileDesc,
+    buf: &mut [u8],
+    timeout_ms: usize,
+) -> Result<(usize, [u8; 4]), usize> {
+    let mut addr = [0; 4];
+    let res = unsafe {
+        sys_recvfrom(
+            fd as usize,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut addr,
+            timeout_ms,
+        )
+    };
+    int_to_error(res).map(|len| (len, addr))
+}
+
+/// Set the time to live of the packets a socket sends, or go back to the
+/// default with `None`.
+pub fn set_ttl(fd: FileDesc, ttl: Option<u8>) -> Result<(), usize> {
+    let res = unsafe { sys_set_ttl(fd as usize, ttl.unwrap_or(0) as usize) };
+    int_to_error(res).map(|_| ())
+}
+
 pub struct SpawnArgs<'a> {
     pub fd: FileDesc,
     pub stdin: Option<FileDesc>,
     pub stdout: Option<FileDesc>,
     pub stderr: Option<FileDesc>,
     pub args: &'a [ArgStr],
+    /// The process group to put the child in, where 0 starts a new
+    /// group named after the child.
+    pub pgid: Option<u32>,
 }
 
 pub fn spawn_elf(args: &SpawnArgs) -> Result<FileDesc, usize> {
@@ -394,6 +693,9 @@ extern "C" fn exec_child(spawn_args: *const SpawnArgs) -> ! {
     if let Some(fd) = spawn_args.stderr {
         dup3(fd, 2, 0).unwrap();
     }
+    if let Some(pgid) = spawn_args.pgid {
+        setpgid(0, pgid).unwrap();
+    }
 
     let flags = 0;
     let args = spawn_args.args;